@@ -9,10 +9,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
     let result = match &input.data {
         syn::Data::Struct(s) => derive_struct(&input, s),
-        syn::Data::Enum(e) => Err(syn::Error::new(
-            e.enum_token.span(),
-            "Lens implementations cannot be derived from enums",
-        )),
+        syn::Data::Enum(e) => derive_enum(&input, e),
         syn::Data::Union(u) => Err(syn::Error::new(
             u.union_token.span(),
             "Lens implementations cannot be derived from unions",
@@ -105,11 +102,19 @@ fn derive_struct(input: &syn::DeriveInput, s: &syn::DataStruct) -> syn::Result<T
                     Some(f(&mut data.#access))
                 }
 
+                fn get<'a>(&self, data: &'a #ty) -> &'a #lty {
+                    &data.#access
+                }
+
+                fn get_mut<'a>(&self, data: &'a mut #ty) -> &'a mut #lty {
+                    &mut data.#access
+                }
+
                 fn address(&self) -> Option<#addr_enum> {
                     Some(#addr_enum::#name(None))
                 }
 
-                fn concat<K, C: Data>(&self, rhs: &K) -> Option<#addr_enum>
+                fn concat<K, C: #CRATE::util::model::Data>(&self, rhs: &K) -> Option<#addr_enum>
                     where
                         K: #CRATE::util::model::Lens<#lty, C>
                         {
@@ -132,6 +137,15 @@ fn derive_struct(input: &syn::DeriveInput, s: &syn::DataStruct) -> syn::Result<T
             #vis const #name : #lens_ty_name = #lens_ty_name;
         };
         associated_items.push(assoc_item);
+
+        // lenses to the elements of collections
+        if let Some(collection) = collection_type(lty) {
+            let (decl, lens_impl, assoc_item) =
+                collection_element_lens(ty, vis, &addr_enum, &name, &access, &collection);
+            decls.push(decl);
+            impls.push(lens_impl);
+            associated_items.push(assoc_item);
+        }
     }
 
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
@@ -140,6 +154,7 @@ fn derive_struct(input: &syn::DeriveInput, s: &syn::DataStruct) -> syn::Result<T
         #(#decls)*
         #(#impls)*
 
+        #[allow(non_camel_case_types)]
         #[derive(Clone,Eq,PartialEq)]
         pub enum #addr_enum {
             #(#addr_variants),*
@@ -168,3 +183,330 @@ fn derive_struct(input: &syn::DeriveInput, s: &syn::DataStruct) -> syn::Result<T
 
     Ok(expanded)
 }
+
+/// Collection field types for which element lenses are generated.
+enum Collection<'a> {
+    /// `Vec<T>`: elements are accessed by index.
+    Vec { element: &'a syn::Type },
+    /// `HashMap<K, V>` (`std`, `im` or `imbl`): elements are accessed by key.
+    Map { key: &'a syn::Type, value: &'a syn::Type },
+}
+
+/// Recognizes `Vec` and `HashMap` field types by the last segment of their path.
+fn collection_type(ty: &syn::Type) -> Option<Collection> {
+    let segment = match ty {
+        syn::Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    let args: Vec<_> = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => return None,
+    };
+    match (segment.ident.to_string().as_str(), args.as_slice()) {
+        ("Vec", [element]) => Some(Collection::Vec { element }),
+        ("HashMap", [key, value]) => Some(Collection::Map { key, value }),
+        _ => None,
+    }
+}
+
+/// Generates a partial lens to an element of a collection field, and the associated function that creates it
+/// (`field_index(i)` for vectors, `field_key(k)` for maps).
+///
+/// The address of the element lens is the address of the whole collection.
+fn collection_element_lens(
+    ty: &syn::Ident,
+    vis: &syn::Visibility,
+    addr_enum: &syn::Ident,
+    name: &syn::Ident,
+    access: &TokenStream,
+    collection: &Collection,
+) -> (TokenStream, TokenStream, TokenStream) {
+    let (lens_ty_name, accessor, param, param_ty, elem_ty, get, get_mut) = match *collection {
+        Collection::Vec { element } => (
+            syn::Ident::new(&format!("{}IndexLens_{}", ty, name), Span::call_site()),
+            syn::Ident::new(&format!("{}_index", name), Span::call_site()),
+            quote! { index },
+            quote! { usize },
+            element,
+            quote! { data.#access.get(self.0) },
+            quote! { data.#access.get_mut(self.0) },
+        ),
+        Collection::Map { key, value } => (
+            syn::Ident::new(&format!("{}KeyLens_{}", ty, name), Span::call_site()),
+            syn::Ident::new(&format!("{}_key", name), Span::call_site()),
+            quote! { key },
+            quote! { #key },
+            value,
+            quote! { data.#access.get(&self.0) },
+            quote! { data.#access.get_mut(&self.0) },
+        ),
+    };
+    let missing = format!("no element in `{}::{}`", ty, name);
+
+    let decl = quote! {
+        #[allow(non_camel_case_types)]
+        #[derive(Clone)]
+        #vis struct #lens_ty_name(#param_ty);
+    };
+
+    let lens_impl = quote! {
+        impl #CRATE::util::model::Lens<#ty,#elem_ty> for #lens_ty_name {
+
+            fn with<R, F: FnOnce(&#elem_ty) -> R>(&self, data: &#ty, f: F) -> R {
+                f(#get.expect(#missing))
+            }
+
+            fn with_mut<R, F: FnOnce(&mut #elem_ty) -> R>(&self, data: &mut #ty, f: F) -> R {
+                f(#get_mut.expect(#missing))
+            }
+
+            fn try_with<R, F: FnOnce(&#elem_ty) -> R>(&self, data: &#ty, f: F) -> Option<R> {
+                #get.map(f)
+            }
+
+            fn try_with_mut<R, F: FnOnce(&mut #elem_ty) -> R>(&self, data: &mut #ty, f: F) -> Option<R> {
+                #get_mut.map(f)
+            }
+
+            fn get<'a>(&self, data: &'a #ty) -> &'a #elem_ty {
+                #get.expect(#missing)
+            }
+
+            fn get_mut<'a>(&self, data: &'a mut #ty) -> &'a mut #elem_ty {
+                #get_mut.expect(#missing)
+            }
+
+            fn address(&self) -> Option<#addr_enum> {
+                Some(#addr_enum::#name(None))
+            }
+
+            fn concat<K, C: #CRATE::util::model::Data>(&self, rhs: &K) -> Option<#addr_enum>
+                where
+                    K: #CRATE::util::model::Lens<#elem_ty, C>
+                    {
+                Some(#addr_enum::#name(None))
+            }
+
+            fn unprefix(&self, addr: <#ty as #CRATE::util::model::Data>::Address) -> Option<Option<<#elem_ty as #CRATE::util::model::Data>::Address>>
+            {
+                // any change to the collection may affect the element
+                if let #addr_enum::#name(_) = addr {
+                    Some(None)
+                } else {
+                    None
+                }
+            }
+        }
+    };
+
+    let assoc_item = quote! {
+        #vis fn #accessor(#param: #param_ty) -> #lens_ty_name {
+            #lens_ty_name(#param)
+        }
+    };
+
+    (decl, lens_impl, assoc_item)
+}
+
+/// Converts a variant name to snake case (`MyVariant` -> `my_variant`).
+fn to_snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i != 0 {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Generates partial lenses (prisms) focusing on the fields of each variant.
+///
+/// Accessing the fields of a variant through the lens fails (`try_with` returns `None`, `with` panics) if the
+/// data holds another variant.
+fn derive_enum(input: &syn::DeriveInput, e: &syn::DataEnum) -> syn::Result<TokenStream> {
+    let ty = &input.ident;
+    let vis = &input.vis;
+
+    let addr_enum = syn::Ident::new(&format!("DataAddress_{}", ty), Span::call_site());
+
+    let mut decls = Vec::new();
+    let mut impls = Vec::new();
+    let mut associated_items = Vec::new();
+    let mut addr_variants = Vec::new();
+    let mut addr_variant_debug_arms = Vec::new();
+
+    for variant in e.variants.iter() {
+        let variant_name = &variant.ident;
+        let variant_snake = to_snake_case(&variant_name.to_string());
+        let fields: Vec<_> = variant.fields.iter().collect();
+
+        for (i, f) in fields.iter().enumerate() {
+            let field_name = f
+                .ident
+                .clone()
+                .unwrap_or_else(|| syn::Ident::new(&format!("elem_{}", i), Span::call_site()));
+            // address variant and lens type are named after the variant and the field
+            let name = syn::Ident::new(&format!("{}_{}", variant_snake, field_name), Span::call_site());
+            let lens_ty_name = syn::Ident::new(&format!("{}Lens_{}", ty, name), Span::call_site());
+            let lty = &f.ty;
+
+            // pattern binding the field to `x`
+            let pattern = match &f.ident {
+                Some(ident) => quote! { #ty::#variant_name { #ident: x, .. } },
+                None => {
+                    let elems = (0..fields.len()).map(|j| {
+                        if j == i {
+                            quote! { x }
+                        } else {
+                            quote! { _ }
+                        }
+                    });
+                    quote! { #ty::#variant_name ( #(#elems),* ) }
+                }
+            };
+
+            addr_variants.push(quote! {
+                #name ( Option<<#lty as #CRATE::util::model::Data>::Address> )
+            });
+
+            addr_variant_debug_arms.push(quote! {
+                #addr_enum::#name ( addr ) => {
+                    write!(f, stringify!(#name))?;
+                    if let Some(addr) = addr {
+                        write!(f, ".{:?}", addr)?;
+                    }
+                }
+            });
+
+            decls.push(quote! {
+                #[allow(non_camel_case_types)]
+                #[derive(Copy,Clone)]
+                #vis struct #lens_ty_name;
+            });
+
+            let wrong_variant = format!("`{}` is not a `{}::{}`", ty, ty, variant_name);
+            impls.push(quote! {
+                impl #CRATE::util::model::Lens<#ty,#lty> for #lens_ty_name {
+
+                    fn with<R, F: FnOnce(&#lty) -> R>(&self, data: &#ty, f: F) -> R {
+                        match data {
+                            #pattern => f(x),
+                            _ => panic!(#wrong_variant),
+                        }
+                    }
+
+                    fn with_mut<R, F: FnOnce(&mut #lty) -> R>(&self, data: &mut #ty, f: F) -> R {
+                        match data {
+                            #pattern => f(x),
+                            _ => panic!(#wrong_variant),
+                        }
+                    }
+
+                    fn try_with<R, F: FnOnce(&#lty) -> R>(&self, data: &#ty, f: F) -> Option<R> {
+                        match data {
+                            #pattern => Some(f(x)),
+                            _ => None,
+                        }
+                    }
+
+                    fn try_with_mut<R, F: FnOnce(&mut #lty) -> R>(&self, data: &mut #ty, f: F) -> Option<R> {
+                        match data {
+                            #pattern => Some(f(x)),
+                            _ => None,
+                        }
+                    }
+
+                    fn get<'a>(&self, data: &'a #ty) -> &'a #lty {
+                        match data {
+                            #pattern => x,
+                            _ => panic!(#wrong_variant),
+                        }
+                    }
+
+                    fn get_mut<'a>(&self, data: &'a mut #ty) -> &'a mut #lty {
+                        match data {
+                            #pattern => x,
+                            _ => panic!(#wrong_variant),
+                        }
+                    }
+
+                    fn address(&self) -> Option<#addr_enum> {
+                        Some(#addr_enum::#name(None))
+                    }
+
+                    fn concat<K, C: #CRATE::util::model::Data>(&self, rhs: &K) -> Option<#addr_enum>
+                        where
+                            K: #CRATE::util::model::Lens<#lty, C>
+                            {
+                        Some(#addr_enum::#name(rhs.address()))
+                    }
+
+                    fn unprefix(&self, addr: <#ty as #CRATE::util::model::Data>::Address) -> Option<Option<<#lty as #CRATE::util::model::Data>::Address>>
+                    {
+                        if let #addr_enum::#name(rest) = addr {
+                            Some(rest)
+                        } else {
+                            None
+                        }
+                    }
+                }
+            });
+
+            // `Enum::variant_lens()` if the variant has only one field, `Enum::variant_field_lens()` otherwise
+            let accessor = if fields.len() == 1 {
+                syn::Ident::new(&format!("{}_lens", variant_snake), Span::call_site())
+            } else {
+                syn::Ident::new(&format!("{}_lens", name), Span::call_site())
+            };
+            associated_items.push(quote! {
+                #vis fn #accessor() -> #lens_ty_name {
+                    #lens_ty_name
+                }
+            });
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let expanded = quote! {
+        #(#decls)*
+        #(#impls)*
+
+        #[allow(non_camel_case_types)]
+        #[derive(Clone,Eq,PartialEq)]
+        pub enum #addr_enum {
+            #(#addr_variants),*
+        }
+
+        impl std::fmt::Debug for #addr_enum {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                match self {
+                    #(#addr_variant_debug_arms),*
+                }
+                Ok(())
+            }
+        }
+
+        impl #impl_generics #CRATE::util::model::Data for #ty #ty_generics #where_clause {
+            type Address = #addr_enum;
+        }
+
+        impl #impl_generics #ty #ty_generics #where_clause {
+            #(#associated_items)*
+        }
+    };
+
+    Ok(expanded)
+}
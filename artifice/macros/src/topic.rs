@@ -2,6 +2,19 @@ use crate::CRATE;
 use quote::quote;
 use syn::spanned::Spanned;
 
+/// Converts a method name (`after_change`) to the name of the corresponding event variant (`AfterChange`).
+fn to_camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
 pub fn topic(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // works only on trait declarations
     let trait_decl: syn::ItemTrait = syn::parse_macro_input!(item as syn::ItemTrait);
@@ -9,8 +22,18 @@ pub fn topic(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> pr
 
     let listener = &trait_decl.ident;
     let visibility = &trait_decl.vis;
+    // `FooEventListener` publishes `FooEvent`s
+    let listener_name = listener.to_string();
+    let event_name = match listener_name.strip_suffix("Listener") {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => format!("{}Event", topic),
+    };
+    let event = syn::Ident::new(&event_name, listener.span());
+    let adapter = syn::Ident::new(&format!("{}Subscriber", topic), topic.span());
 
     let mut publisher_methods = Vec::new();
+    let mut event_variants = Vec::new();
+    let mut adapter_methods = Vec::new();
     for item in trait_decl.items.iter() {
         if let syn::TraitItem::Method(method) = item {
             let sig = &method.sig;
@@ -42,14 +65,51 @@ pub fn topic(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> pr
                     })
                     .collect();
 
-                let method = quote! {
+                let publisher_method = quote! {
                     #sig {
                         self.listeners.for_each(|l| {
                             l.borrow_mut().#method_name(#(#args),*)
                         });
                     }
                 };
-                publisher_methods.push(method);
+                publisher_methods.push(publisher_method);
+
+                // each method corresponds to an event variant with owned copies of the arguments
+                let variant = syn::Ident::new(&to_camel_case(&method_name.to_string()), method_name.span());
+                let mut fields = Vec::new();
+                let mut values = Vec::new();
+                for arg in sig.inputs.iter().skip(1) {
+                    let arg = match arg {
+                        syn::FnArg::Typed(arg) => arg,
+                        _ => unreachable!(),
+                    };
+                    let name = match &*arg.pat {
+                        syn::Pat::Ident(pat) => &pat.ident,
+                        _ => {
+                            fields.push(syn::Error::new(arg.pat.span(), "expected an identifier").to_compile_error());
+                            continue;
+                        }
+                    };
+                    match &*arg.ty {
+                        syn::Type::Reference(r) => {
+                            let ty = &r.elem;
+                            fields.push(quote! { #name: #ty });
+                            values.push(quote! { #name: ::std::clone::Clone::clone(#name) });
+                        }
+                        ty => {
+                            fields.push(quote! { #name: #ty });
+                            values.push(quote! { #name: ::std::clone::Clone::clone(&#name) });
+                        }
+                    }
+                }
+                event_variants.push(quote! { #variant { #(#fields),* } });
+
+                let listener_sig = &method.sig;
+                adapter_methods.push(quote! {
+                    #listener_sig {
+                        self.0.send(#event::#variant { #(#values),* })
+                    }
+                });
             }
         } else {
             let err = syn::Error::new(item.span(), "unsupported trait item").to_compile_error();
@@ -84,6 +144,36 @@ pub fn topic(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> pr
             #(#publisher_methods)*
         }
 
+        /// Events published on the topic, as received by subscriptions.
+        #[derive(Clone)]
+        #visibility enum #event {
+            #(#event_variants),*
+        }
+
+        struct #adapter(#CRATE::util::EventSender<#event>);
+
+        impl #listener for #adapter {
+            #(#adapter_methods)*
+        }
+
+        impl #topic {
+            /// Returns a stream of all events published on the topic.
+            pub fn subscribe(&self) -> #CRATE::util::Subscription<#event> {
+                self.subscribe_filtered(|_| true)
+            }
+
+            /// Returns a stream of the events published on the topic that satisfy the predicate.
+            pub fn subscribe_filtered(
+                &self,
+                predicate: impl Fn(&#event) -> bool + 'static,
+            ) -> #CRATE::util::Subscription<#event> {
+                let (sender, receiver) = #CRATE::util::EventSender::new(predicate);
+                let subscriber = std::rc::Rc::new(std::cell::RefCell::new(#adapter(sender)));
+                self.listeners.add_listener(subscriber.clone());
+                #CRATE::util::Subscription::new(receiver, subscriber)
+            }
+        }
+
     };
 
     result.into()
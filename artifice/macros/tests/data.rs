@@ -1,5 +1,4 @@
-#![feature(specialization)]
-use artifice::ui::model::Lens;
+use artifice::util::model::Lens;
 use artifice_macros::Data;
 
 #[derive(Clone, Data)]
@@ -12,6 +11,12 @@ struct Fields {
 #[derive(Clone, Data)]
 struct Tuple(i32, f32, String);
 
+#[derive(Clone, Data)]
+struct Collections {
+    items: Vec<String>,
+    weights: imbl::HashMap<String, f32>,
+}
+
 #[test]
 fn struct_check() {
     let mut fields = Fields {
@@ -36,3 +41,49 @@ fn struct_check() {
     assert_eq!(Fields::c.get(&fields), "seventh");
     assert_eq!(Tuple::elem_2.get(&tup), "eighth");
 }
+
+#[derive(Clone, Data)]
+enum Shape {
+    Circle(f32),
+    Rect { width: f32, height: f32 },
+    Empty,
+}
+
+#[test]
+fn enum_check() {
+    let mut rect = Shape::Rect {
+        width: 2.0,
+        height: 3.0,
+    };
+    let circle = Shape::Circle(1.0);
+
+    assert_eq!(Shape::rect_width_lens().get(&rect), &2.0);
+    assert_eq!(Shape::rect_height_lens().get(&rect), &3.0);
+    assert_eq!(Shape::circle_lens().get(&circle), &1.0);
+
+    // lenses to the fields of another variant don't focus on anything
+    assert_eq!(Shape::circle_lens().try_with(&rect, |r| *r), None);
+    assert_eq!(Shape::rect_width_lens().try_with(&Shape::Empty, |w| *w), None);
+
+    *Shape::rect_height_lens().get_mut(&mut rect) = 4.0;
+    assert_eq!(Shape::rect_height_lens().get(&rect), &4.0);
+}
+
+#[test]
+fn collection_check() {
+    let mut data = Collections {
+        items: vec!["first".to_string(), "second".to_string()],
+        weights: imbl::HashMap::new(),
+    };
+    data.weights.insert("first".to_string(), 0.5);
+
+    assert_eq!(Collections::items_index(1).get(&data), "second");
+    assert_eq!(Collections::items_index(2).try_with(&data, |s| s.clone()), None);
+    assert_eq!(Collections::weights_key("first".to_string()).get(&data), &0.5);
+    assert_eq!(Collections::weights_key("second".to_string()).try_with(&data, |w| *w), None);
+
+    *Collections::items_index(0).get_mut(&mut data) = "third".to_string();
+    *Collections::weights_key("first".to_string()).get_mut(&mut data) = 1.0;
+    assert_eq!(data.items[0], "third");
+    assert_eq!(data.weights["first"], 1.0);
+}
@@ -1,5 +1,6 @@
 use artifice::util::MessageBus;
 use artifice_macros::topic;
+use futures::StreamExt;
 use std::{cell::RefCell, rc::Rc};
 
 #[topic(DocumentEvents)]
@@ -8,6 +9,11 @@ pub trait DocumentEventListener {
     fn after_change(&mut self);
 }
 
+#[topic(NodeEvents)]
+pub trait NodeEventListener {
+    fn node_renamed(&mut self, node: u32, name: &String);
+}
+
 #[derive(Debug)]
 struct TestEventListener;
 
@@ -32,7 +38,30 @@ fn test_simple() {
     let bus = MessageBus::new();
     let listener = Rc::new(RefCell::new(TestEventListener));
     DocumentEvents::listen(&bus, listener.clone());
-    let mut publisher = DocumentEvents::publisher(&bus);
+    let publisher = DocumentEvents::publisher(&bus);
     publisher.before_change();
     publisher.after_change();
 }
+
+#[test]
+fn test_subscribe() {
+    let bus = MessageBus::new();
+    let publisher = NodeEvents::publisher(&bus);
+    let mut all = publisher.subscribe();
+    let mut filtered = publisher.subscribe_filtered(|e| match e {
+        NodeEvent::NodeRenamed { node, .. } => *node == 2,
+    });
+
+    publisher.node_renamed(1, &"a".to_string());
+    publisher.node_renamed(2, &"b".to_string());
+
+    let renamed = |e: Option<NodeEvent>| match e {
+        Some(NodeEvent::NodeRenamed { node, name }) => Some((node, name)),
+        None => None,
+    };
+    futures::executor::block_on(async {
+        assert_eq!(renamed(all.next().await), Some((1, "a".to_string())));
+        assert_eq!(renamed(all.next().await), Some((2, "b".to_string())));
+        assert_eq!(renamed(filtered.next().await), Some((2, "b".to_string())));
+    });
+}
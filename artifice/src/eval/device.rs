@@ -1,15 +1,93 @@
 //! Device eval state
 
-use crate::eval::EvalError;
-use kyute::{graal, shell::application::Application};
+use crate::eval::{
+    imaging::{DeviceComputeImageResult, DeviceImagePlane, RequestWindow, TiRect},
+    EvalError, EvalKey,
+};
+use kyute::{
+    graal::{self, vk},
+    shell::application::Application,
+};
+use kyute_common::Atom;
 use parking_lot::Mutex;
-use std::{mem, sync::Arc, time::Duration};
+use std::{
+    mem,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::task::JoinHandle;
 
+/// An image that outlives the evaluation that produced it, and is owned by the device eval state.
+#[derive(Clone, Debug)]
+pub(crate) struct PersistentImage {
+    /// The evaluation that produced the image.
+    pub(crate) key: EvalKey,
+    /// The request window for which the image was computed.
+    pub(crate) request: RequestWindow,
+    /// Region that was computed.
+    pub(crate) region: TiRect,
+    /// Name of the plane in the evaluation result.
+    pub(crate) plane_name: Atom,
+    pub(crate) plane: DeviceImagePlane,
+    pub(crate) created: Instant,
+    /// Last time the image was produced or reused by an evaluation.
+    pub(crate) last_used: Instant,
+}
+
+impl PersistentImage {
+    /// Returns the approximate size in bytes of the image, if known.
+    pub(crate) fn byte_size(&self) -> Option<u64> {
+        let texel_size = format_texel_size(self.plane.format)?;
+        Some(self.plane.size.width as u64 * self.plane.size.height as u64 * texel_size)
+    }
+}
+
+/// Returns the size of a texel of the given format, for the formats used by imaging operators.
+fn format_texel_size(format: vk::Format) -> Option<u64> {
+    match format {
+        vk::Format::R8_UNORM => Some(1),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_SFLOAT => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_SFLOAT => Some(8),
+        vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        _ => None,
+    }
+}
+
+/// Default maximum size of the persistent images kept after evaluations.
+pub(crate) const DEFAULT_RESIDENT_IMAGE_BUDGET: u64 = 1 << 30;
+
+/// Returns the indices of the images to evict so that the total size of the images stays within `budget`,
+/// least recently used first.
+///
+/// Images used at or after `keep_since` (i.e. by the current evaluation) are never evicted, even if they
+/// exceed the budget on their own. Images of unknown size are not counted.
+fn select_lru_evictions(images: &[(Instant, Option<u64>)], budget: u64, keep_since: Instant) -> Vec<usize> {
+    let mut total: u64 = images.iter().filter_map(|&(_, size)| size).sum();
+    let mut candidates: Vec<usize> = (0..images.len()).filter(|&i| images[i].0 < keep_since).collect();
+    candidates.sort_by_key(|&i| images[i].0);
+    let mut evicted = vec![];
+    for i in candidates {
+        if total <= budget {
+            break;
+        }
+        total -= images[i].1.unwrap_or(0);
+        evicted.push(i);
+    }
+    evicted
+}
+
 struct DeviceEvalStateInner {
     frame: graal::Frame<'static, ()>,
     transient_images: Vec<graal::ImageId>,
     transient_buffers: Vec<graal::BufferId>,
+    persistent_images: Vec<PersistentImage>,
+    /// Maximum total size of the persistent images, in bytes.
+    resident_budget: u64,
 }
 
 impl DeviceEvalStateInner {
@@ -58,6 +136,8 @@ impl DeviceEvalState {
                 frame: Default::default(),
                 transient_images: vec![],
                 transient_buffers: vec![],
+                persistent_images: vec![],
+                resident_budget: DEFAULT_RESIDENT_IMAGE_BUDGET,
             }),
         }
     }
@@ -97,12 +177,183 @@ impl DeviceEvalState {
         Ok(())
     }
 
-    pub(crate) fn make_image_persistent(&self, image: graal::ImageId) {
+    /// Removes the image from the list of transient resources, so that it's not deleted on the next flush.
+    ///
+    /// The image is kept until it is evicted with `evict_persistent_images` or `evict_least_recently_used`,
+    /// or until the eval state is dropped.
+    pub(crate) fn make_image_persistent(
+        &self,
+        key: &EvalKey,
+        request: &RequestWindow,
+        region: TiRect,
+        plane_name: &Atom,
+        plane: &DeviceImagePlane,
+    ) {
         let mut inner = self.inner.lock();
-        if let Some(p) = inner.transient_images.iter().position(|x| *x == image) {
+        if let Some(p) = inner.transient_images.iter().position(|x| *x == plane.id) {
             inner.transient_images.swap_remove(p);
+            inner.persistent_images.push(PersistentImage {
+                key: key.clone(),
+                request: *request,
+                region,
+                plane_name: plane_name.clone(),
+                plane: plane.clone(),
+                created: Instant::now(),
+                last_used: Instant::now(),
+            });
         } else {
-            warn!("requested to make image {image:?} persistent but it was not found in the list of transient resources (already flushed?)");
+            warn!("requested to make image {:?} persistent but it was not found in the list of transient resources (already flushed?)", plane.id);
+        }
+    }
+
+    /// Returns the result of a previous evaluation of the specified key with the same request window,
+    /// if its images are still resident.
+    pub(crate) fn cached_image_result(
+        &self,
+        key: &EvalKey,
+        request: &RequestWindow,
+    ) -> Option<DeviceComputeImageResult> {
+        let mut inner = self.inner.lock();
+        let mut result = None;
+        for img in inner.persistent_images.iter_mut() {
+            if &img.key == key && img.request.roi == request.roi && img.request.resolution == request.resolution {
+                img.last_used = Instant::now();
+                let r = result.get_or_insert_with(|| DeviceComputeImageResult::new(img.region));
+                r.planes.push((img.plane_name.clone(), img.plane));
+            }
+        }
+        result
+    }
+
+    /// Removes the persistent images for which the predicate returns `true` and returns them,
+    /// without destroying them.
+    ///
+    /// Used to transfer ownership of the images to another eval state with `adopt_persistent_images`.
+    pub(crate) fn take_persistent_images(&self, mut f: impl FnMut(&PersistentImage) -> bool) -> Vec<PersistentImage> {
+        let mut inner = self.inner.lock();
+        let mut taken = vec![];
+        inner.persistent_images.retain(|img| {
+            if f(img) {
+                taken.push(img.clone());
+                false
+            } else {
+                true
+            }
+        });
+        taken
+    }
+
+    /// Takes ownership of persistent images from another eval state.
+    pub(crate) fn adopt_persistent_images(&self, images: Vec<PersistentImage>) {
+        self.inner.lock().persistent_images.extend(images);
+    }
+
+    /// Returns the list of persistent images.
+    pub(crate) fn persistent_images(&self) -> Vec<PersistentImage> {
+        self.inner.lock().persistent_images.clone()
+    }
+
+    /// Returns whether the specified image is a live persistent image.
+    pub(crate) fn is_persistent_image(&self, image: graal::ImageId) -> bool {
+        self.inner
+            .lock()
+            .persistent_images
+            .iter()
+            .any(|img| img.plane.id == image)
+    }
+
+    /// Destroys all persistent images for which the predicate returns `true`.
+    ///
+    /// Returns the number of images destroyed.
+    pub(crate) fn evict_persistent_images(&self, mut f: impl FnMut(&PersistentImage) -> bool) -> usize {
+        let mut inner = self.inner.lock();
+        let mut count = 0;
+        inner.persistent_images.retain(|img| {
+            if f(img) {
+                self.device.destroy_image(img.plane.id);
+                count += 1;
+                false
+            } else {
+                true
+            }
+        });
+        count
+    }
+}
+
+impl DeviceEvalState {
+    /// Returns the maximum total size of the persistent images, in bytes.
+    pub(crate) fn resident_budget(&self) -> u64 {
+        self.inner.lock().resident_budget
+    }
+
+    /// Sets the maximum total size of the persistent images, in bytes.
+    ///
+    /// The budget is enforced by `evict_least_recently_used`.
+    pub(crate) fn set_resident_budget(&self, budget: u64) {
+        self.inner.lock().resident_budget = budget;
+    }
+
+    /// Destroys the least recently used persistent images until their total size is within the budget.
+    ///
+    /// Images used at or after `keep_since` are kept. Returns the number of images destroyed.
+    pub(crate) fn evict_least_recently_used(&self, keep_since: Instant) -> usize {
+        let mut inner = self.inner.lock();
+        let usage: Vec<_> = inner
+            .persistent_images
+            .iter()
+            .map(|img| (img.last_used, img.byte_size()))
+            .collect();
+        let mut evicted = select_lru_evictions(&usage, inner.resident_budget, keep_since);
+        // remove from the back so that the indices stay valid
+        evicted.sort_unstable_by(|a, b| b.cmp(a));
+        for &i in evicted.iter() {
+            let img = inner.persistent_images.swap_remove(i);
+            trace!("evicting resident image for {:?}", img.key.path);
+            self.device.destroy_image(img.plane.id);
         }
+        evicted.len()
+    }
+}
+
+impl Drop for DeviceEvalState {
+    fn drop(&mut self) {
+        for img in self.inner.get_mut().persistent_images.iter() {
+            self.device.destroy_image(img.plane.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::select_lru_evictions;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_lru_evictions() {
+        let t0 = Instant::now();
+        let t1 = t0 + Duration::from_millis(10);
+        let t2 = t0 + Duration::from_millis(20);
+        const IMAGE: u64 = 1024;
+
+        // first evaluation: two images (e.g. a node and its input), within budget
+        let mut images = vec![(t0, Some(IMAGE)), (t0, Some(IMAGE))];
+        assert!(select_lru_evictions(&images, 2 * IMAGE, t0).is_empty());
+
+        // second evaluation at another time produces two more images: the images of the first evaluation
+        // are evicted instead of doubling the resident images
+        images.push((t1, Some(IMAGE)));
+        images.push((t1, Some(IMAGE)));
+        let mut evicted = select_lru_evictions(&images, 2 * IMAGE, t1);
+        evicted.sort_unstable();
+        assert_eq!(evicted, vec![0, 1]);
+
+        // least recently used first: an image reused by a later evaluation is kept
+        let images = vec![(t2, Some(IMAGE)), (t0, Some(IMAGE)), (t1, Some(IMAGE))];
+        assert_eq!(select_lru_evictions(&images, 2 * IMAGE, t2), vec![1]);
+
+        // images of the current evaluation are kept even over budget
+        let images = vec![(t2, Some(4 * IMAGE))];
+        assert!(select_lru_evictions(&images, IMAGE, t2).is_empty());
     }
 }
@@ -1,7 +1,7 @@
 //! Imaging evaluation context
 use crate::{
-    eval::{EvalError, EvalKey, GeneralEvalState, OpCtx, TaskMap},
-    model::{metadata, Document, Node, Path},
+    eval::{EvalError, EvalKey, EvalState, GeneralEvalState, OpCtx, TaskMap},
+    model::{metadata, Document, DocumentSettings, Node, Path},
 };
 use anyhow::anyhow;
 use async_trait::async_trait;
@@ -27,6 +27,10 @@ use tokio::task;
 
 pub struct ImagingOperatorRegistration {
     pub name: &'static str,
+    /// Short description of the operator, displayed in the UI.
+    pub description: &'static str,
+    /// Category of the operator, for grouping operators in the UI (e.g. "Input/Output").
+    pub category: &'static str,
     pub op: &'static (dyn OpImaging + Sync),
 }
 
@@ -98,6 +102,19 @@ pub struct RequestWindow {
 }
 
 impl RequestWindow {
+    /// Returns the window covering the whole output image defined by the document settings.
+    pub fn from_settings(settings: &DocumentSettings) -> RequestWindow {
+        let width = settings.resolution.width;
+        let height = settings.resolution.height;
+        RequestWindow {
+            roi: TiRect::new(
+                TiPoint::zero(),
+                TiSize::new(width as f64 * settings.pixel_aspect, height as f64),
+            ),
+            resolution: PxSizeI::new(width, height),
+        }
+    }
+
     /// Returns the aspect ratio of the window.
     pub fn aspect_ratio(&self) -> f64 {
         self.roi.width() / self.roi.height()
@@ -275,6 +292,18 @@ impl OpImagingCtx {
             .await
     }
 
+    /// Computes the image connected to the specified input on the device.
+    ///
+    /// Images still resident from a previous evaluation of the input are reused.
+    pub async fn device_evaluate_input(
+        &self,
+        input: impl Into<Atom>,
+        request: &RequestWindow,
+    ) -> Result<DeviceComputeImageResult, EvalError> {
+        let path = self.mandatory_connected_input(input)?;
+        EvalState::device_evaluate_image(self.eval.clone(), &path, self.time, request).await
+    }
+
     /*pub fn request_input(&mut self, path: &ModelPath, time: f64, roi: Rect) {
         // Get or create a request for the image
         let imaging_ctx = self.eval.imaging.as_mut().unwrap();
@@ -322,7 +351,7 @@ type RodFuture = EvalFuture<RegionOfDefinition>;
 /// Imaging context. Owned internally by `EvalSession`.
 pub(crate) struct ImagingEvalState {
     /// Tasks spawned by `compute_region_of_definition`.
-    rod_tasks: TaskMap<EvalKey, Result<RegionOfDefinition, EvalError>>,
+    pub(crate) rod_tasks: TaskMap<EvalKey, Result<RegionOfDefinition, EvalError>>,
 }
 
 impl ImagingEvalState {
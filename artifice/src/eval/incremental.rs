@@ -0,0 +1,120 @@
+//! Change detection between document revisions, for incremental re-evaluation.
+use crate::model::{Document, Node, Path};
+use std::collections::{HashMap, HashSet};
+
+/// Returns the paths of the nodes that were added, removed, or modified between two revisions of a document.
+///
+/// Documents are persistent data structures: an edit replaces the attribute (or metadata) map of the
+/// modified node, and leaves the maps of the other nodes shared between revisions. A node is considered
+/// modified if its maps are not shared with the previous revision.
+pub(crate) fn changed_nodes(old: &Document, new: &Document) -> HashSet<Path> {
+    let mut changed = HashSet::new();
+    diff_node(Some(old.root()), Some(new.root()), &mut changed);
+    changed
+}
+
+fn diff_node(old: Option<&Node>, new: Option<&Node>, changed: &mut HashSet<Path>) {
+    match (old, new) {
+        (Some(old), Some(new)) => {
+            if !(old.attributes.ptr_eq(&new.attributes) && old.metadata.ptr_eq(&new.metadata)) {
+                changed.insert(new.path.clone());
+            }
+            for (name, child) in new.children.iter() {
+                diff_node(old.children.get(name), Some(child), changed);
+            }
+            for (name, child) in old.children.iter() {
+                if !new.children.contains_key(name) {
+                    diff_node(Some(child), None, changed);
+                }
+            }
+        }
+        (Some(node), None) | (None, Some(node)) => {
+            changed.insert(node.path.clone());
+            for child in node.children.values() {
+                diff_node(Some(child), None, changed);
+            }
+        }
+        (None, None) => {}
+    }
+}
+
+fn collect_dependents(node: &Node, dependents: &mut HashMap<Path, Vec<Path>>) {
+    for attribute in node.attributes.values() {
+        if let Some(ref connection) = attribute.connection {
+            let source = if connection.is_attribute() {
+                connection.parent().unwrap()
+            } else {
+                connection.clone()
+            };
+            dependents.entry(source).or_default().push(node.path.clone());
+        }
+    }
+    for child in node.children.values() {
+        collect_dependents(child, dependents);
+    }
+}
+
+/// Returns the specified nodes, their ancestors, and all the nodes that depend on them through connections.
+///
+/// The results of the evaluation of all the other nodes are unaffected by changes to the specified nodes.
+pub(crate) fn invalidated_nodes(document: &Document, changed: HashSet<Path>) -> HashSet<Path> {
+    let mut dependents = HashMap::new();
+    collect_dependents(document.root(), &mut dependents);
+
+    let mut invalidated = HashSet::new();
+    let mut stack: Vec<Path> = changed.into_iter().collect();
+    while let Some(path) = stack.pop() {
+        if !invalidated.insert(path.clone()) {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            stack.push(parent);
+        }
+        if let Some(deps) = dependents.get(&path) {
+            stack.extend(deps.iter().cloned());
+        }
+    }
+    invalidated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changed_nodes, invalidated_nodes};
+    use crate::model::{Document, Path, Value};
+
+    const NETWORK: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read">
+        <string id="input:file">a.png</string>
+    </node>
+    <node id="b" op="read">
+        <string id="input:file">b.png</string>
+    </node>
+    <node id="c" op="display">
+        <texture2D id="input:image" connect="/b"/>
+    </node>
+</document>"#;
+
+    #[test]
+    fn test_invalidated_nodes() {
+        let old = Document::from_xml(NETWORK).unwrap();
+        let mut new = old.clone();
+        assert!(changed_nodes(&old, &new).is_empty());
+
+        let a = Path::parse("/a").unwrap();
+        let b = Path::parse("/b").unwrap();
+        let c = Path::parse("/c").unwrap();
+
+        new.set_attribute_value(&b.join_attribute("input:file"), Value::from("b2.png"))
+            .unwrap();
+        let changed = changed_nodes(&old, &new);
+        assert_eq!(changed.len(), 1);
+        assert!(changed.contains(&b));
+
+        let invalidated = invalidated_nodes(&new, changed);
+        assert!(invalidated.contains(&b));
+        assert!(invalidated.contains(&c));
+        assert!(invalidated.contains(&Path::root()));
+        assert!(!invalidated.contains(&a));
+    }
+}
@@ -1,13 +1,14 @@
 mod device;
 mod error;
 pub mod imaging;
+mod incremental;
 mod pipeline;
 mod shader;
 mod task_map;
 mod variability;
 
 pub use error::EvalError;
-pub use task_map::{TaskError, TaskMap};
+pub use task_map::{CacheStats, TaskEntryInfo, TaskError, TaskMap};
 pub use variability::Variability;
 
 use crate::{
@@ -18,8 +19,14 @@ use crate::{
             get_imaging_operator, DeviceComputeImageResult, ImagingEvalState, ImagingOperatorRegistration, OpImaging,
             OpImagingCtx, PxSizeI, RequestWindow,
         },
+        task_map::CacheCounters,
     },
-    model::{metadata, Document, Node, Param, Path, Value},
+    model::{
+        metadata,
+        typedesc::{ImageDimension, SampledImageType},
+        Document, DocumentSettings, Node, OperatorSchema, Param, Path, PrimitiveType, SchemaRegistry, TypeDesc, Value,
+    },
+    operators,
 };
 use async_trait::async_trait;
 use kyute::{
@@ -34,8 +41,11 @@ use std::{
     future::Future,
     hash::{Hash, Hasher},
     mem,
-    sync::Arc,
-    time::Duration,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 use tokio::task::JoinHandle;
 
@@ -52,6 +62,10 @@ pub trait OpGeneral {
 
 pub struct GeneralOperatorRegistration {
     pub name: &'static str,
+    /// Short description of the operator, displayed in the UI.
+    pub description: &'static str,
+    /// Category of the operator, for grouping operators in the UI.
+    pub category: &'static str,
     pub op: &'static (dyn OpGeneral + Sync),
 }
 
@@ -72,6 +86,70 @@ pub fn get_general_operator(node: &Node) -> Result<&'static (dyn OpGeneral + Syn
     find_general_operator(op_name.as_ref())
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Operator registry
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Describes a registered operator.
+#[derive(Copy, Clone, Debug)]
+pub struct OperatorInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub category: &'static str,
+    /// Whether this is an imaging operator.
+    pub imaging: bool,
+}
+
+/// Returns all the registered operators, sorted by category and name.
+pub fn registered_operators() -> Vec<OperatorInfo> {
+    let mut operators: Vec<_> = inventory::iter::<GeneralOperatorRegistration>
+        .into_iter()
+        .map(|op| OperatorInfo {
+            name: op.name,
+            description: op.description,
+            category: op.category,
+            imaging: false,
+        })
+        .chain(
+            inventory::iter::<ImagingOperatorRegistration>
+                .into_iter()
+                .map(|op| OperatorInfo {
+                    name: op.name,
+                    description: op.description,
+                    category: op.category,
+                    imaging: true,
+                }),
+        )
+        .collect();
+    operators.sort_by_key(|op| (op.category, op.name));
+    operators
+}
+
+/// Returns a schema registry with the registered operators, for validating documents.
+///
+/// Imaging operators produce images, and read them from their `input:image` attribute. The `read` operator reads
+/// its image from the file in its `input:file` attribute instead.
+pub fn operator_schemas() -> SchemaRegistry {
+    let image = TypeDesc::SampledImage(Arc::new(SampledImageType {
+        sampled_ty: PrimitiveType::Float,
+        dim: ImageDimension::Dim2D,
+        ms: false,
+    }));
+    let mut schemas = SchemaRegistry::new();
+    for op in registered_operators() {
+        let mut schema = OperatorSchema::new(op.name);
+        if op.imaging {
+            schema = schema.output(image.clone());
+            schema = match op.name {
+                "read" => schema.mandatory("input:file", TypeDesc::String),
+                _ => schema.mandatory("input:image", image.clone()),
+            };
+        }
+        schemas.register(schema);
+    }
+    schemas
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // EvalKey
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -108,6 +186,12 @@ pub struct EvalState {
     general: GeneralEvalState,
     imaging: ImagingEvalState,
     device_state: DeviceEvalState,
+    /// Lookups of resident device images.
+    image_counters: CacheCounters,
+    /// Duration of the last call to `Evaluation::device_evaluate_image`.
+    last_evaluation_time: Mutex<Option<Duration>>,
+    /// Number of calls to `Evaluation::device_evaluate_image`.
+    evaluation_count: AtomicU64,
 }
 
 impl EvalState {
@@ -117,6 +201,17 @@ impl EvalState {
         time: f64,
         request: &RequestWindow,
     ) -> Result<DeviceComputeImageResult, EvalError> {
+        let key = EvalKey {
+            path: path.clone(),
+            time,
+        };
+        if let Some(result) = this.device_state.cached_image_result(&key, request) {
+            trace!("reusing resident images for {:?}", path);
+            this.image_counters.record(true);
+            return Ok(result);
+        }
+        this.image_counters.record(false);
+
         let node = this.document.node(path).ok_or(EvalError::PathNotFound)?;
         let op = get_imaging_operator(&node)?;
 
@@ -129,10 +224,95 @@ impl EvalState {
             transform: Transform::identity(),
         };
 
-        op.device_compute_image(&ctx, request).await
+        let result = op.device_compute_image(&ctx, request).await?;
+        // keep the images alive after the evaluation, so that they can be reused by subsequent evaluations
+        for (name, plane) in result.planes.iter() {
+            this.device_state
+                .make_image_persistent(&key, request, result.region, name, plane);
+        }
+        Ok(result)
+    }
+
+    /// Creates the eval state for a new revision of the document.
+    ///
+    /// The cached results of the nodes that are not affected by the changes are carried over to the new state.
+    async fn update(&self, document: Document) -> EvalState {
+        let changed = incremental::changed_nodes(&self.document, &document);
+        let invalidated = incremental::invalidated_nodes(&document, changed);
+        // the settings (e.g. the frame rate or the resolution) may affect the results of any node
+        let settings_changed = self.document.settings() != document.settings();
+        if settings_changed {
+            trace!("document settings changed, re-evaluating all nodes");
+        } else {
+            trace!("re-evaluating {} nodes", invalidated.len());
+        }
+        let is_valid = |key: &EvalKey| !settings_changed && !invalidated.contains(&key_node_path(key));
+
+        let general = GeneralEvalState {
+            tasks: self.general.tasks.clone_filtered(|key| is_valid(key)).await,
+        };
+        let imaging = ImagingEvalState {
+            rod_tasks: self.imaging.rod_tasks.clone_filtered(|key| is_valid(key)).await,
+        };
+        let device_state = DeviceEvalState::new(self.device_state.device.clone());
+        device_state.set_resident_budget(self.device_state.resident_budget());
+        device_state.adopt_persistent_images(self.device_state.take_persistent_images(|img| is_valid(&img.key)));
+
+        EvalState {
+            document,
+            general,
+            imaging,
+            device_state,
+            image_counters: CacheCounters::from_stats(self.image_counters.stats()),
+            last_evaluation_time: Mutex::new(*self.last_evaluation_time.lock()),
+            evaluation_count: AtomicU64::new(self.evaluation_count.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Returns all the cached evaluation results.
+    async fn cache_entries(&self) -> Vec<CacheEntry> {
+        let mut entries = Vec::new();
+        for task in self.general.tasks.entries().await {
+            entries.push(CacheEntry::from_task(CacheEntryKind::Value, task));
+        }
+        for task in self.imaging.rod_tasks.entries().await {
+            entries.push(CacheEntry::from_task(CacheEntryKind::RegionOfDefinition, task));
+        }
+        for image in self.device_state.persistent_images() {
+            entries.push(CacheEntry {
+                kind: CacheEntryKind::DeviceImage,
+                byte_size: image.byte_size(),
+                key: image.key,
+                location: CacheLocation::Device,
+                age: image.created.elapsed(),
+                pending: false,
+            });
+        }
+        entries
+    }
+
+    /// Removes a cached evaluation result.
+    async fn evict(&self, kind: CacheEntryKind, key: &EvalKey) -> bool {
+        match kind {
+            CacheEntryKind::Value => self.general.tasks.remove(key).await,
+            CacheEntryKind::RegionOfDefinition => self.imaging.rod_tasks.remove(key).await,
+            CacheEntryKind::DeviceImage => self.device_state.evict_persistent_images(|img| &img.key == key) != 0,
+        }
+    }
+
+    /// Removes all cached evaluation results produced by the specified node.
+    async fn clear_node_cache(&self, node: &Path) -> usize {
+        let mut count = 0;
+        count += self.general.tasks.retain(|key| &key_node_path(key) != node).await;
+        count += self.imaging.rod_tasks.retain(|key| &key_node_path(key) != node).await;
+        count += self
+            .device_state
+            .evict_persistent_images(|img| &key_node_path(&img.key) == node);
+        count
     }
 }
 
+#[derive(Clone)]
 pub struct Evaluation(Arc<EvalState>);
 
 impl Evaluation {
@@ -142,6 +322,9 @@ impl Evaluation {
             general: GeneralEvalState::new(),
             imaging: ImagingEvalState::new(),
             device_state: DeviceEvalState::new(device),
+            image_counters: CacheCounters::default(),
+            last_evaluation_time: Mutex::new(None),
+            evaluation_count: AtomicU64::new(0),
         });
         Evaluation(state)
     }
@@ -153,15 +336,201 @@ impl Evaluation {
         time: f64,
         request: &RequestWindow,
     ) -> Result<DeviceComputeImageResult, EvalError> {
+        let start = Instant::now();
         let runtime_handle = tokio::runtime::Handle::current();
         let result = runtime_handle.block_on(EvalState::device_evaluate_image(self.0.clone(), path, time, request))?;
-        // before flushing, extract the final outputs from the transient resource list
-        for (_, plane) in result.planes.iter() {
-            self.0.device_state.make_image_persistent(plane.id);
-        }
         self.0.device_state.flush();
+        // the images produced or reused by this evaluation are more recent than `start`, and are kept
+        self.0.device_state.evict_least_recently_used(start);
+        *self.0.last_evaluation_time.lock() = Some(start.elapsed());
+        self.0.evaluation_count.fetch_add(1, Ordering::Relaxed);
         Ok(result)
     }
+
+    /// Evaluates an imaging operator at the specified frame, over the output image defined in the document settings.
+    ///
+    /// This is how the viewer and exporters render frames: the frame is converted to a time with the frame rate
+    /// of the document.
+    pub fn device_evaluate_frame(&self, path: &Path, frame: i64) -> Result<DeviceComputeImageResult, EvalError> {
+        let time = self.document().settings().frame_to_time(frame);
+        self.device_evaluate_image(path, time, &self.default_request_window())
+    }
+
+    /// Sets the maximum total size, in bytes, of the device images kept resident after evaluations.
+    ///
+    /// When the budget is exceeded, the least recently used images are evicted after each evaluation.
+    pub fn set_resident_image_budget(&self, bytes: u64) {
+        self.0.device_state.set_resident_budget(bytes);
+    }
+
+    /// Returns statistics about the evaluation, for diagnostics.
+    pub fn stats(&self) -> EvalStats {
+        EvalStats {
+            last_evaluation_time: *self.0.last_evaluation_time.lock(),
+            evaluation_count: self.0.evaluation_count.load(Ordering::Relaxed),
+            values: self.0.general.tasks.stats(),
+            regions_of_definition: self.0.imaging.rod_tasks.stats(),
+            images: self.0.image_counters.stats(),
+            resident_image_bytes: self
+                .0
+                .device_state
+                .persistent_images()
+                .iter()
+                .filter_map(|img| img.byte_size())
+                .sum(),
+        }
+    }
+
+    /// Switches to a new revision of the evaluated document.
+    ///
+    /// Only the nodes affected by the changes, and the nodes downstream of them, are re-evaluated on the next
+    /// request: the other results, including resident device images, are reused. This makes re-evaluation
+    /// cheap when a single parameter changes (e.g. during a slider drag).
+    pub fn update(&mut self, document: Document) {
+        let runtime_handle = tokio::runtime::Handle::current();
+        let state = runtime_handle.block_on(self.0.update(document));
+        self.0 = Arc::new(state);
+    }
+
+    /// Returns whether the two objects refer to the same evaluation state.
+    pub fn same_state(&self, other: &Evaluation) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Returns the document being evaluated.
+    pub fn document(&self) -> &Document {
+        &self.0.document
+    }
+
+    /// Returns the request window for the output image defined in the document settings.
+    pub fn default_request_window(&self) -> RequestWindow {
+        RequestWindow::from_settings(self.0.document.settings())
+    }
+
+    /// Returns all the cached evaluation results.
+    pub fn cache_entries(&self) -> Vec<CacheEntry> {
+        let runtime_handle = tokio::runtime::Handle::current();
+        runtime_handle.block_on(self.0.cache_entries())
+    }
+
+    /// Removes a cached evaluation result.
+    ///
+    /// Evicting a device image destroys it: it must not be used after this call.
+    /// Returns whether the entry was found.
+    pub fn evict_cache_entry(&self, kind: CacheEntryKind, key: &EvalKey) -> bool {
+        let runtime_handle = tokio::runtime::Handle::current();
+        runtime_handle.block_on(self.0.evict(kind, key))
+    }
+
+    /// Removes all cached evaluation results produced by the node at the specified path.
+    ///
+    /// Returns the number of entries removed.
+    pub fn clear_node_cache(&self, node: &Path) -> usize {
+        let runtime_handle = tokio::runtime::Handle::current();
+        runtime_handle.block_on(self.0.clear_node_cache(node))
+    }
+
+    /// Reloads a file read by nodes of the document after it was modified externally (see `model::FileWatcher`).
+    ///
+    /// Forgets the loaded contents of the file and removes the cached evaluation results of the nodes reading
+    /// it. Returns the number of entries removed.
+    pub fn reload_file(&self, file: &std::path::Path, nodes: &[Path]) -> usize {
+        let runtime_handle = tokio::runtime::Handle::current();
+        runtime_handle.block_on(async {
+            let mut count = operators::read::forget_image_file(file).await as usize;
+            for node in nodes {
+                count += self.0.clear_node_cache(node).await;
+            }
+            count
+        })
+    }
+
+    /// Returns whether the specified image is a cached evaluation result that hasn't been evicted.
+    pub fn is_device_image_cached(&self, image: graal::ImageId) -> bool {
+        self.0.device_state.is_persistent_image(image)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Cache introspection
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Statistics about an evaluation, returned by `Evaluation::stats`.
+///
+/// Cache statistics are cumulated over all revisions of the document.
+#[derive(Copy, Clone, Debug)]
+pub struct EvalStats {
+    /// Duration of the last image evaluation, up to the submission of the work to the device.
+    pub last_evaluation_time: Option<Duration>,
+    /// Number of image evaluations, which changes when `last_evaluation_time` is updated.
+    pub evaluation_count: u64,
+    /// Lookups of attribute values.
+    pub values: CacheStats,
+    /// Lookups of regions of definition.
+    pub regions_of_definition: CacheStats,
+    /// Lookups of resident device images.
+    pub images: CacheStats,
+    /// Approximate device memory used by resident images, in bytes.
+    pub resident_image_bytes: u64,
+}
+
+/// Kind of cached evaluation result.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CacheEntryKind {
+    /// Value of an attribute.
+    Value,
+    /// Region of definition of an image.
+    RegionOfDefinition,
+    /// Image on the device.
+    DeviceImage,
+}
+
+/// Where a cached evaluation result is stored.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CacheLocation {
+    Host,
+    Device,
+}
+
+/// Describes a cached evaluation result, as returned by `Evaluation::cache_entries`.
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    pub kind: CacheEntryKind,
+    pub key: EvalKey,
+    pub location: CacheLocation,
+    /// Approximate size in bytes of the result, if known.
+    pub byte_size: Option<u64>,
+    /// Time elapsed since the evaluation was started.
+    pub age: Duration,
+    /// Whether the evaluation is still in progress.
+    pub pending: bool,
+}
+
+impl CacheEntry {
+    fn from_task(kind: CacheEntryKind, task: TaskEntryInfo<EvalKey>) -> CacheEntry {
+        CacheEntry {
+            kind,
+            key: task.key,
+            location: CacheLocation::Host,
+            byte_size: None,
+            age: task.age,
+            pending: !task.complete,
+        }
+    }
+
+    /// Returns the path of the node that produced this result.
+    pub fn node_path(&self) -> Path {
+        key_node_path(&self.key)
+    }
+}
+
+/// Returns the path of the node owning the evaluated path.
+fn key_node_path(key: &EvalKey) -> Path {
+    if key.path.is_attribute() {
+        key.path.parent().unwrap()
+    } else {
+        key.path.clone()
+    }
 }
 
 /// Context passed to operators.
@@ -195,6 +564,16 @@ impl OpCtx {
         OpCtx { eval, time, node }
     }
 
+    /// Returns the settings of the document being evaluated.
+    pub fn settings(&self) -> &DocumentSettings {
+        self.eval.document.settings()
+    }
+
+    /// Resolves the path of a file read by the current node, relative to the document file.
+    pub fn resolve_file(&self, file: &str) -> std::path::PathBuf {
+        self.eval.document.resolve_file(file)
+    }
+
     /// Returns the path to the attribute connected to the current node's specified input.
     ///
     /// Returns None if the specified input is unconnected.
@@ -236,7 +615,7 @@ impl OpCtx {
 
         let attribute = document.attribute(&path).ok_or(EvalError::PathNotFound)?;
         if let Some(ref value) = attribute.value {
-            Ok(value.clone())
+            Ok(value.sample(time))
         } else {
             // no value, evaluate attribute using the operator defined on the parent node
             let node = document.node(&path.parent().unwrap()).unwrap();
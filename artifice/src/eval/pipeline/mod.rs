@@ -27,7 +27,7 @@ use crate::{
         },
         EvalError, OpCtx, Variability,
     },
-    model::typedesc::{Field, ImageDimension},
+    model::typedesc::{Field, ImageDimension, PrimitiveType},
 };
 pub use program::{Program, ProgramError, ProgramInterface};
 
@@ -326,6 +326,21 @@ pub enum InterpolationMode {
     Smooth,
 }
 
+impl InterpolationMode {
+    /// Returns the default interpolation mode for values of the given type.
+    ///
+    /// Integer and boolean values can't be interpolated and use `Flat`, everything else uses `Smooth`.
+    pub fn default_for_type(ty: &TypeDesc) -> InterpolationMode {
+        match ty {
+            TypeDesc::Primitive(elem_ty) | TypeDesc::Vector { elem_ty, .. } => match elem_ty {
+                PrimitiveType::Int | PrimitiveType::UnsignedInt | PrimitiveType::Bool => InterpolationMode::Flat,
+                PrimitiveType::Float | PrimitiveType::Double => InterpolationMode::Smooth,
+            },
+            _ => InterpolationMode::Smooth,
+        }
+    }
+}
+
 #[derive(Clone)]
 struct InterpolatedVariable {
     in_: Arc<str>,
@@ -415,6 +430,7 @@ pub struct ProgramNodeBuilder {
     variabilities: HashSet<Variability>,
     bindings: Vec<Binding>,
     vars: VarMap,
+    auto_interpolation: bool,
 }
 
 impl ProgramNodeBuilder {
@@ -427,9 +443,20 @@ impl ProgramNodeBuilder {
             variabilities: Default::default(),
             bindings: vec![Binding::Default; num_interface_vars],
             vars,
+            auto_interpolation: false,
         }
     }
 
+    /// Enables or disables automatic insertion of interpolation nodes.
+    ///
+    /// When enabled, and the program has fragment variability but binds vertex-varying variables,
+    /// an interpolation node is inserted before the program node, with the default interpolation
+    /// mode for each variable (see `InterpolationMode::default_for_type`).
+    /// Otherwise, mixing vertex and fragment variabilities is an error.
+    pub fn set_auto_interpolation(&mut self, enabled: bool) {
+        self.auto_interpolation = enabled;
+    }
+
     /// Exposes a program interface as a pipeline uniform.
     pub fn bind_uniform(&mut self, interface_name: &str, uniform_name: &str) -> Result<(), PipelineError> {
         let i = self
@@ -473,10 +500,72 @@ impl ProgramNodeBuilder {
         }
 
         self.variabilities.insert(pvar.variability);
-        self.bindings.push(Binding::Variable {
+        self.bindings[i] = Binding::Variable {
             name: pvar.name.clone(),
             ssa_index: pvar.ssa_index,
-        });
+        };
+        Ok(())
+    }
+
+    /// Inserts an interpolation node between the predecessor and this node for all vertex-varying
+    /// variables bound to the program inputs, and rebinds the inputs to the interpolated variables.
+    fn insert_interpolation(&mut self) -> Result<(), PipelineError> {
+        let mut builder = InterpolationNodeBuilder::new(self.pred.clone());
+        // interpolated variable for each vertex-varying variable
+        let mut interpolated: HashMap<Arc<str>, Arc<str>> = HashMap::new();
+        // variables created by `bind_output`, which may shadow vertex-varying variables
+        let mut outputs = HashSet::new();
+
+        for (i, binding) in self.bindings.iter_mut().enumerate() {
+            if self.program.interface()[i].output {
+                if let Binding::Variable { name, .. } = binding {
+                    outputs.insert(name.clone());
+                }
+                continue;
+            }
+            if let Binding::Variable { name, .. } = binding {
+                let var = self.pred.variable(name.clone())?;
+                if var.variability != Variability::Vertex {
+                    continue;
+                }
+                // the same variable may be bound to more than one input
+                let out = match interpolated.get(name) {
+                    Some(out) => out.clone(),
+                    None => {
+                        // pick a name that doesn't collide with existing variables
+                        let mut out: Arc<str> = Arc::from(format!("{}_interp", name));
+                        let mut n = 1;
+                        while self.vars.contains_key(&out) || interpolated.values().any(|v| *v == out) {
+                            out = Arc::from(format!("{}_interp{}", name, n));
+                            n += 1;
+                        }
+                        builder.interpolate(name, out.clone(), InterpolationMode::default_for_type(&var.ty))?;
+                        interpolated.insert(name.clone(), out.clone());
+                        out
+                    }
+                };
+                *binding = Binding::Variable {
+                    name: out,
+                    ssa_index: 0,
+                };
+            }
+        }
+
+        let interpolation_node = builder.finish();
+
+        // vertex-varying variables are not visible past the interpolation node, except for the outputs of the
+        // program, whose variability is set in `finish`
+        self.vars
+            .retain(|name, v| v.variability != Variability::Vertex || outputs.contains(name));
+        for (name, var) in interpolation_node.vars.iter() {
+            if var.variability == Variability::Fragment {
+                self.vars.entry(name.clone()).or_insert_with(|| var.clone());
+            }
+        }
+
+        self.pred = interpolation_node;
+        self.variabilities.remove(&Variability::Vertex);
+        self.variabilities.insert(Variability::Fragment);
         Ok(())
     }
 
@@ -518,6 +607,13 @@ impl ProgramNodeBuilder {
     }
 
     pub fn finish(mut self) -> Result<Arc<PipelineNode>, PipelineError> {
+        if self.auto_interpolation
+            && self.variabilities.contains(&Variability::Vertex)
+            && self.variabilities.contains(&Variability::Fragment)
+        {
+            self.insert_interpolation()?;
+        }
+
         let vs: Vec<_> = self.variabilities.into_iter().collect();
         let min_variability = check_ordered_variabilities(&vs)?;
         let stage = shader_stage_from_variability(min_variability);
@@ -1013,8 +1109,8 @@ pub trait OpProgram {
 #[cfg(test)]
 mod tests {
     use crate::eval::pipeline::{
-        program, InterpolationMode, InterpolationNodeBuilder, PipelineNode, Program, ProgramNodeBuilder, ShaderStage,
-        TypeDesc,
+        program, InterpolationMode, InterpolationNodeBuilder, PipelineNode, PipelineNodeKind, Program,
+        ProgramNodeBuilder, ShaderStage, TypeDesc,
     };
     use artifice::eval::{pipeline::PipelineEntryNodeBuilder, Variability};
     use stats_alloc::{Region, StatsAlloc, INSTRUMENTED_SYSTEM};
@@ -1053,6 +1149,13 @@ mod tests {
     out vec4 o_color = color + bluenoise(fragCoord);
     "#;
 
+    const COLOR_FROM_UV: &str = r#"
+        in vec2 fragCoord;
+        in vec2 uv;
+        in int instance;
+        out vec4 color = vec4(fragCoord + uv, float(instance), 1.0);
+        "#;
+
     /*
 
     in vec3 position;
@@ -1136,4 +1239,101 @@ mod tests {
         eprintln!("====== SRI: ====== \n {:#?}", shader.sri);
         //drop(prog_2_node);
     }
+
+    #[test]
+    fn test_auto_interpolation() {
+        let vfs = program::Vfs::new();
+        let mut preprocessor = program::Preprocessor::new_with_fs(vfs);
+
+        let entry = {
+            let mut builder = PipelineEntryNodeBuilder::new();
+            builder.add_gl_vertex_builtins();
+            builder.gl_fragment_builtins();
+            builder.add_variable("uv", TypeDesc::VEC2, Variability::Vertex);
+            builder.add_variable("instance", TypeDesc::INT, Variability::Vertex);
+            builder.finish().unwrap()
+        };
+
+        let bind_all = |builder: &mut ProgramNodeBuilder| {
+            builder.bind("fragCoord", "gl_FragCoord").unwrap();
+            builder.bind("uv", "uv").unwrap();
+            builder.bind("instance", "instance").unwrap();
+            builder.bind_output("color", "color").unwrap();
+        };
+
+        // without auto-interpolation, mixing vertex and fragment variables is an error
+        {
+            let program = Program::new(COLOR_FROM_UV, "color_from_uv", &mut preprocessor).unwrap();
+            let mut builder = ProgramNodeBuilder::new(entry.clone(), program);
+            bind_all(&mut builder);
+            assert!(builder.finish().is_err());
+        }
+
+        let program = Program::new(COLOR_FROM_UV, "color_from_uv", &mut preprocessor).unwrap();
+        let mut builder = ProgramNodeBuilder::new(entry, program);
+        builder.set_auto_interpolation(true);
+        bind_all(&mut builder);
+        let node = builder.finish().unwrap();
+
+        assert_eq!(node.stage, Some(ShaderStage::Fragment));
+        assert!(node.variable("uv").is_err());
+        assert_eq!(node.variable("color").unwrap().variability, Variability::Fragment);
+        match node.parents[0].kind {
+            PipelineNodeKind::Interpolation { ref vars } => {
+                assert_eq!(vars.len(), 2);
+                for v in vars.iter() {
+                    let expected = if &*v.in_ == "instance" {
+                        InterpolationMode::Flat
+                    } else {
+                        InterpolationMode::Smooth
+                    };
+                    assert_eq!(v.mode, expected);
+                }
+            }
+            _ => panic!("expected an interpolation node"),
+        }
+    }
+
+    #[test]
+    fn test_auto_interpolation_shadowing() {
+        let vfs = program::Vfs::new();
+        let mut preprocessor = program::Preprocessor::new_with_fs(vfs);
+
+        let entry = {
+            let mut builder = PipelineEntryNodeBuilder::new();
+            builder.add_gl_vertex_builtins();
+            builder.gl_fragment_builtins();
+            builder.add_variable("uv", TypeDesc::VEC2, Variability::Vertex);
+            builder.add_variable("instance", TypeDesc::INT, Variability::Vertex);
+            // collides with the default name of the interpolated `uv`
+            builder.add_variable("uv_interp", TypeDesc::VEC2, Variability::TimeVarying);
+            builder.finish().unwrap()
+        };
+
+        let program = Program::new(COLOR_FROM_UV, "color_from_uv", &mut preprocessor).unwrap();
+        let mut builder = ProgramNodeBuilder::new(entry, program);
+        builder.set_auto_interpolation(true);
+        builder.bind("fragCoord", "gl_FragCoord").unwrap();
+        builder.bind("uv", "uv").unwrap();
+        builder.bind("instance", "instance").unwrap();
+        // the output shadows a vertex-varying input
+        builder.bind_output("color", "uv").unwrap();
+        let node = builder.finish().unwrap();
+
+        let uv = node.variable("uv").unwrap();
+        assert_eq!(uv.ssa_index, 1);
+        assert_eq!(uv.ty, TypeDesc::VEC4);
+        assert_eq!(uv.variability, Variability::Fragment);
+        assert_eq!(
+            node.variable("uv_interp").unwrap().variability,
+            Variability::TimeVarying
+        );
+        match node.parents[0].kind {
+            PipelineNodeKind::Interpolation { ref vars } => {
+                let uv = vars.iter().find(|v| &*v.in_ == "uv").unwrap();
+                assert_ne!(&*uv.out, "uv_interp");
+            }
+            _ => panic!("expected an interpolation node"),
+        }
+    }
 }
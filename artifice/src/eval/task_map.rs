@@ -5,8 +5,12 @@ use std::{
     future::Future,
     hash::Hash,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 use tokio::{sync::RwLock, task};
 
@@ -16,6 +20,59 @@ use tokio::{sync::RwLock, task};
 
 //type TaskFuture = future::Shared<future::Map<>>
 
+/// Number of hits and misses of a cache.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Returns the ratio of hits over the total number of lookups, or `None` if there were no lookups.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / total as f64)
+        }
+    }
+}
+
+/// Hit and miss counters of a cache.
+#[derive(Default)]
+pub(crate) struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounters {
+    /// Creates counters starting from the given values.
+    pub(crate) fn from_stats(stats: CacheStats) -> CacheCounters {
+        CacheCounters {
+            hits: AtomicU64::new(stats.hits),
+            misses: AtomicU64::new(stats.misses),
+        }
+    }
+
+    /// Records a cache lookup.
+    pub(crate) fn record(&self, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the current values of the counters.
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum TaskError {
     #[error("task was cancelled")]
@@ -44,16 +101,39 @@ impl<V> Future for TaskFuture<V> {
     }
 }
 
+struct TaskEntry<V> {
+    future: future::Shared<TaskFuture<V>>,
+    /// When the task was spawned.
+    created: Instant,
+}
+
+/// Information about an entry of a `TaskMap`, returned by `TaskMap::entries`.
+#[derive(Clone, Debug)]
+pub struct TaskEntryInfo<K> {
+    pub key: K,
+    /// Time elapsed since the task was spawned.
+    pub age: Duration,
+    /// Whether the task has finished.
+    pub complete: bool,
+}
+
 pub struct TaskMap<K, V> {
-    tasks: RwLock<HashMap<K, future::Shared<TaskFuture<V>>>>,
+    tasks: RwLock<HashMap<K, TaskEntry<V>>>,
+    counters: CacheCounters,
 }
 
 impl<K, V> TaskMap<K, V> {
     pub fn new() -> TaskMap<K, V> {
         TaskMap {
             tasks: RwLock::new(HashMap::new()),
+            counters: CacheCounters::default(),
         }
     }
+
+    /// Returns the number of lookups that found an existing task, and the number of tasks spawned.
+    pub fn stats(&self) -> CacheStats {
+        self.counters.stats()
+    }
 }
 
 impl<K, V> TaskMap<K, V>
@@ -68,13 +148,21 @@ where
     {
         {
             let mut tasks = self.tasks.read().await;
-            if let Some(fut) = tasks.get(&key) {
-                return fut.clone().await;
+            if let Some(entry) = tasks.get(&key) {
+                self.counters.record(true);
+                return entry.future.clone().await;
             }
         }
+        self.counters.record(false);
 
         let fut = TaskFuture(task::spawn(fut)).shared();
-        self.tasks.write().await.insert(key, fut.clone());
+        self.tasks.write().await.insert(
+            key,
+            TaskEntry {
+                future: fut.clone(),
+                created: Instant::now(),
+            },
+        );
         fut.await
     }
 
@@ -85,13 +173,85 @@ where
     {
         {
             let mut tasks = self.tasks.read().await;
-            if let Some(fut) = tasks.get(&key) {
-                return fut.clone().await;
+            if let Some(entry) = tasks.get(&key) {
+                self.counters.record(true);
+                return entry.future.clone().await;
             }
         }
+        self.counters.record(false);
 
         let fut = TaskFuture(task::spawn_blocking(f)).shared();
-        self.tasks.write().await.insert(key, fut.clone());
+        self.tasks.write().await.insert(
+            key,
+            TaskEntry {
+                future: fut.clone(),
+                created: Instant::now(),
+            },
+        );
         fut.await
     }
+
+    /// Removes the task with the specified key from the map.
+    ///
+    /// If the task is still running, callers already waiting on it still receive the result, but it won't be
+    /// returned to subsequent callers. Returns whether the key was present in the map.
+    pub async fn remove(&self, key: &K) -> bool {
+        self.tasks.write().await.remove(key).is_some()
+    }
+
+    /// Removes all tasks for which the predicate returns `false`.
+    ///
+    /// Returns the number of tasks removed.
+    pub async fn retain(&self, mut f: impl FnMut(&K) -> bool) -> usize {
+        let mut tasks = self.tasks.write().await;
+        let len = tasks.len();
+        tasks.retain(|key, _| f(key));
+        len - tasks.len()
+    }
+}
+
+impl<K, V> TaskMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Send + 'static,
+{
+    /// Returns information about all the tasks in the map.
+    pub async fn entries(&self) -> Vec<TaskEntryInfo<K>> {
+        self.tasks
+            .read()
+            .await
+            .iter()
+            .map(|(key, entry)| TaskEntryInfo {
+                key: key.clone(),
+                age: entry.created.elapsed(),
+                complete: entry.future.peek().is_some(),
+            })
+            .collect()
+    }
+
+    /// Returns a new map containing the tasks for which the predicate returns `true`.
+    ///
+    /// The tasks are shared between the two maps: a task still running is not spawned again.
+    pub async fn clone_filtered(&self, mut f: impl FnMut(&K) -> bool) -> TaskMap<K, V> {
+        let tasks = self
+            .tasks
+            .read()
+            .await
+            .iter()
+            .filter(|(key, _)| f(key))
+            .map(|(key, entry)| {
+                (
+                    key.clone(),
+                    TaskEntry {
+                        future: entry.future.clone(),
+                        created: entry.created,
+                    },
+                )
+            })
+            .collect();
+        TaskMap {
+            tasks: RwLock::new(tasks),
+            counters: CacheCounters::from_stats(self.counters.stats()),
+        }
+    }
 }
@@ -1,19 +1,52 @@
 use crate::{
     model,
-    model::{metadata, param::Param, Node, Path, ShareGroup},
+    model::{
+        layer::{compose_node, compose_properties, find_node, find_node_mut, Layer},
+        metadata,
+        param::Param,
+        reference::{expand_references, ExpansionCache, Reference},
+        undo::{EditAction, EditGroup, UndoStack},
+        DocumentSettings, Error, Metadata, Node, Path, ShareGroup, TypeDesc, Value,
+    },
 };
 
-use imbl::{HashMap, Vector};
+use imbl::{HashMap, OrdMap, Vector};
 use kyute_common::{Atom, Data};
-use std::{fmt, sync::Arc};
+use std::{
+    cell::RefCell,
+    fmt, mem,
+    path::{Path as FilePath, PathBuf},
+    sync::Arc,
+};
 
 /// The root object of artifice documents.
+///
+/// A document is composed from a stack of layers (see `Layer`). Its nodes are the result of the composition,
+/// and edits are made to the current edit target layer.
 #[derive(Clone)]
 pub struct Document {
     /// Document revision index
     pub(crate) revision: usize,
-    /// Root node
+    /// Root node of the composed document, with references expanded
     pub(crate) root: Node,
+    /// Root node of the composition of the layers, before the expansion of references
+    pub(crate) composed_root: Node,
+    /// Root layer, followed by the sublayers, strongest first
+    pub(crate) layers: Vector<Layer>,
+    /// Root nodes of the documents that can be referenced by name
+    pub(crate) referenced_documents: OrdMap<Atom, Node>,
+    /// Expansions of the references, reused when the referencing and referenced nodes don't change
+    pub(crate) expansions: ExpansionCache,
+    /// File the document was loaded from
+    pub(crate) file: Option<PathBuf>,
+    /// Index of the layer receiving the edits
+    pub(crate) edit_target: usize,
+    /// Document settings
+    pub(crate) settings: DocumentSettings,
+    /// Revision index of the settings, incremented every time they change
+    pub(crate) settings_revision: usize,
+    /// Inverse actions of the edits made to the document
+    pub(crate) undo_stack: UndoStack,
     //nodes: HashMap<Path, Node>,
     // Share groups
     //pub share_groups: Vector<ShareGroup>,
@@ -25,14 +58,107 @@ pub struct Document {
     }
 }*/
 
+/// Resolves a file path relative to the directory of a document file. Absolute paths are returned as is.
+pub(crate) fn resolve_file(document_file: Option<&FilePath>, file: &str) -> PathBuf {
+    match document_file.and_then(FilePath::parent) {
+        Some(dir) => dir.join(file),
+        None => PathBuf::from(file),
+    }
+}
+
 impl Document {
     /// Returns a new document.
     pub fn new() -> Document {
-        Document {
+        Document::from_root(Node::new(0, Path::root()), DocumentSettings::default())
+    }
+
+    /// Returns a document with a single layer.
+    pub(crate) fn from_root(root: Node, settings: DocumentSettings) -> Document {
+        Document::from_root_and_file(root, settings, None)
+    }
+
+    /// Returns a document with a single layer, loaded from the specified file.
+    fn from_root_and_file(root: Node, settings: DocumentSettings, file: Option<PathBuf>) -> Document {
+        let mut layer = Layer::new("root");
+        layer.root = root.clone();
+        let mut document = Document {
             revision: 0,
             //nodes: Default::default(),
-            root: Node::new(0, Path::root()),
+            root: root.clone(),
+            composed_root: root,
+            layers: Vector::unit(layer),
+            referenced_documents: OrdMap::new(),
+            expansions: ExpansionCache::new(),
+            file,
+            edit_target: 0,
+            settings,
+            settings_revision: 0,
+            undo_stack: UndoStack::new(),
+        };
+        document.expand_references();
+        document
+    }
+
+    /// Loads a document from a file.
+    ///
+    /// References to other files (`file@/path`) are resolved relative to the directory of the file.
+    pub fn open(file: &FilePath) -> Result<Document, Error> {
+        thread_local! {
+            /// Files being opened, to detect cyclic references between files
+            static OPENING: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
         }
+
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        if OPENING.with(|opening| opening.borrow().contains(&canonical)) {
+            return Err(Error::FileError(anyhow::anyhow!(
+                "cyclic reference to `{}`",
+                file.display()
+            )));
+        }
+        OPENING.with(|opening| opening.borrow_mut().push(canonical));
+        let result = (|| {
+            let xml = std::fs::read_to_string(file).map_err(anyhow::Error::from)?;
+            let (root, settings) = Document::read_xml(&xml).map_err(anyhow::Error::from)?;
+            Ok(Document::from_root_and_file(root, settings, Some(file.to_path_buf())))
+        })();
+        OPENING.with(|opening| opening.borrow_mut().pop());
+        result
+    }
+
+    /// Returns the file the document was loaded from.
+    pub fn file(&self) -> Option<&FilePath> {
+        self.file.as_deref()
+    }
+
+    /// Resolves the path of a file referenced by the document (e.g. read by a node).
+    ///
+    /// Relative paths are relative to the directory of the document file, or to the current directory if the
+    /// document wasn't loaded from a file.
+    pub fn resolve_file(&self, file: &str) -> PathBuf {
+        resolve_file(self.file(), file)
+    }
+
+    /// Returns the document settings.
+    pub fn settings(&self) -> &DocumentSettings {
+        &self.settings
+    }
+
+    /// Replaces the document settings.
+    ///
+    /// The document and settings revisions are only incremented if the settings are different. The settings
+    /// must be valid (see `DocumentSettings::check`).
+    pub fn set_settings(&mut self, settings: DocumentSettings) {
+        debug_assert!(settings.check().is_ok(), "invalid document settings");
+        if self.settings != settings {
+            self.edit(EditAction::SetSettings(settings)).unwrap();
+        }
+    }
+
+    /// Returns the revision index of the settings.
+    ///
+    /// Use this to detect changes to the settings since the last time they were read.
+    pub fn settings_revision(&self) -> usize {
+        self.settings_revision
     }
 
     /// Returns the node with the given path.
@@ -45,6 +171,9 @@ impl Document {
     }
 
     /// Returns a mutable reference to the node with the given path.
+    ///
+    /// Modifications made through this reference bypass the layers and the undo stack, and are lost
+    /// when the node is composed again. Use `edit` instead.
     pub fn node_mut(&mut self, path: &Path) -> Option<&mut Node> {
         if path.is_root() {
             Some(&mut self.root)
@@ -63,6 +192,479 @@ impl Document {
         self.node(&path.parent()?)?.attribute(&path.name())
     }
 
+    /// Creates a new node evaluated by the specified operator, as a child of `parent`.
+    ///
+    /// The name of the node is derived from `stem`, and made unique among the children of `parent`.
+    /// Returns the path of the new node.
+    pub fn create_node(&mut self, parent: &Path, stem: &str, operator: &str) -> Result<Path, Error> {
+        let parent_node = self.node(parent).ok_or(Error::NoObjectAtPath)?;
+        let name = parent_node.make_unique_child_name(stem);
+        let path = parent.join(name);
+        let mut node = Node::new(0, path.clone());
+        node.metadata
+            .insert(Atom::from(metadata::OPERATOR.name), Value::Token(Atom::from(operator)));
+        self.edit(EditAction::InsertNode(node))?;
+        Ok(path)
+    }
+
+    /// Removes the node at the given path, with its attributes and children.
+    pub fn remove_node(&mut self, path: &Path) -> Result<(), Error> {
+        self.edit(EditAction::RemoveNode { path: path.clone() })
+    }
+
+    /// Sets a metadata entry on the node at the given path.
+    pub fn set_node_metadata<T: Into<Value>>(
+        &mut self,
+        path: &Path,
+        metadata: Metadata<T>,
+        value: T,
+    ) -> Result<(), Error> {
+        self.edit(EditAction::SetNodeMetadata {
+            path: path.clone(),
+            name: Atom::from(metadata.name),
+            value: Some(value.into()),
+        })
+    }
+
+    /// Sets the value of the attribute at the given path.
+    pub fn set_attribute_value(&mut self, path: &Path, value: Value) -> Result<(), Error> {
+        self.edit(EditAction::SetAttributeValue {
+            path: path.clone(),
+            value: Some(value),
+        })
+    }
+
+    /// Connects the input attribute at `input` to `source`.
+    ///
+    /// If the node doesn't have the input attribute, it is created with the specified type.
+    pub fn connect(&mut self, input: &Path, ty: TypeDesc, source: &Path) -> Result<(), Error> {
+        if self.node(source).is_none() && self.attribute(source).is_none() {
+            return Err(Error::NoObjectAtPath);
+        }
+        if let Some(attribute) = self.attribute(input) {
+            if attribute.ty != ty {
+                return Err(Error::MismatchedTypes);
+            }
+            self.edit(EditAction::SetConnection {
+                path: input.clone(),
+                connection: Some(source.clone()),
+            })
+        } else {
+            self.edit(EditAction::InsertAttribute(Param::new(
+                0,
+                input.clone(),
+                ty,
+                None,
+                Some(source.clone()),
+            )))
+        }
+    }
+
+    /// Removes the connection of the input attribute at `input`.
+    pub fn disconnect(&mut self, input: &Path) -> Result<(), Error> {
+        self.edit(EditAction::SetConnection {
+            path: input.clone(),
+            connection: None,
+        })
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Layers
+
+    /// Returns the layers of the document: the root layer, followed by the sublayers, strongest first.
+    pub fn layers(&self) -> &Vector<Layer> {
+        &self.layers
+    }
+
+    /// Returns the index of the layer that receives the edits.
+    pub fn edit_target(&self) -> usize {
+        self.edit_target
+    }
+
+    /// Sets the layer that receives the edits.
+    ///
+    /// Editing an attribute or a node defined in a weaker layer creates an override in the target layer.
+    pub fn set_edit_target(&mut self, layer: usize) {
+        assert!(layer < self.layers.len(), "invalid layer index");
+        self.edit_target = layer;
+    }
+
+    /// Inserts a sublayer, weaker than the root layer and the sublayers before `index`.
+    ///
+    /// The undo stack is cleared.
+    pub fn insert_sublayer(&mut self, index: usize, layer: Layer) {
+        assert!(index < self.layers.len(), "invalid sublayer index");
+        self.layers.insert(index + 1, layer);
+        if self.edit_target > index {
+            self.edit_target += 1;
+        }
+        self.layers_changed();
+    }
+
+    /// Adds a sublayer, weaker than all the other layers.
+    ///
+    /// The undo stack is cleared.
+    pub fn add_sublayer(&mut self, layer: Layer) {
+        self.insert_sublayer(self.layers.len() - 1, layer);
+    }
+
+    /// Removes a sublayer and returns it.
+    ///
+    /// If the sublayer was the edit target, the root layer becomes the edit target. The undo stack is cleared.
+    pub fn remove_sublayer(&mut self, index: usize) -> Layer {
+        assert!(index + 1 < self.layers.len(), "invalid sublayer index");
+        let layer = self.layers.remove(index + 1);
+        if self.edit_target == index + 1 {
+            self.edit_target = 0;
+        } else if self.edit_target > index + 1 {
+            self.edit_target -= 1;
+        }
+        self.layers_changed();
+        layer
+    }
+
+    fn layers_changed(&mut self) {
+        // the recorded edits refer to layers by index
+        self.undo_stack.clear();
+        self.recompose(&Path::root(), true);
+        self.revision += 1;
+    }
+
+    /// Composes the node at the given path from the layers.
+    ///
+    /// If `deep` is false, only the attributes and metadata of the node are composed.
+    fn recompose(&mut self, path: &Path, deep: bool) {
+        if let Some(parent) = path.parent() {
+            if find_node(&self.composed_root, &parent).is_none() {
+                // the parent was created in a layer to override a node of an instance
+                return self.recompose(&parent, true);
+            }
+        }
+        let deep = deep || find_node(&self.composed_root, path).is_none();
+        let nodes: Vec<Node> = self
+            .layers
+            .iter()
+            .filter_map(|layer| layer.node(path).cloned())
+            .collect();
+        let nodes: Vec<&Node> = nodes.iter().collect();
+        if !deep {
+            if let Some(composed) = find_node_mut(&mut self.composed_root, path) {
+                compose_properties(composed, &nodes);
+            }
+        } else {
+            let composed = compose_node(&nodes);
+            if path.is_root() {
+                self.composed_root = composed.unwrap_or_else(|| Node::new(0, Path::root()));
+            } else if let Some(parent) = find_node_mut(&mut self.composed_root, &path.parent().unwrap()) {
+                match composed {
+                    Some(node) => {
+                        parent.children.insert(path.name(), node);
+                    }
+                    None => {
+                        parent.children.remove(&path.name());
+                    }
+                }
+            }
+        }
+        self.expand_references();
+    }
+
+    fn expand_references(&mut self) {
+        self.load_referenced_files();
+        let (root, expansions) = expand_references(&self.composed_root, &self.referenced_documents, &self.expansions);
+        self.root = root;
+        self.expansions = expansions;
+    }
+
+    /// Loads the files designated by references to documents that were not added with
+    /// `add_referenced_document`.
+    fn load_referenced_files(&mut self) {
+        fn collect(node: &Node, names: &mut Vec<Atom>) {
+            if let Some(document) = node
+                .metadata(metadata::REFERENCE)
+                .and_then(|reference| Reference::parse(&reference))
+                .and_then(|reference| reference.document)
+            {
+                names.push(document);
+            }
+            for child in node.children.values() {
+                collect(child, names);
+            }
+        }
+
+        let mut names = Vec::new();
+        collect(&self.composed_root, &mut names);
+        for name in names {
+            if self.referenced_documents.contains_key(&name) {
+                continue;
+            }
+            let file = self.resolve_file(&*name);
+            match Document::open(&file) {
+                Ok(document) => {
+                    self.referenced_documents.insert(name, document.root);
+                }
+                Err(err) => {
+                    warn!("could not load referenced document `{}`: {}", file.display(), err);
+                }
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the node at the given path in a layer.
+    ///
+    /// If the node is not defined in the layer but exists in the composed document, an empty node overriding
+    /// it is created in the layer.
+    fn layer_node_mut(&mut self, layer: usize, path: &Path) -> Result<&mut Node, Error> {
+        if self.layers[layer].node(path).is_none() {
+            if self.node(path).is_none() {
+                return Err(Error::NoObjectAtPath);
+            }
+            // the root node exists in all layers
+            let node = Node::new(0, path.clone());
+            self.layer_node_mut(layer, &path.parent().unwrap())?
+                .children
+                .insert(path.name(), node);
+        }
+        Ok(self.layers[layer].node_mut(path).unwrap())
+    }
+
+    /// Makes sure that the attribute at the given path is defined in a layer, by creating an attribute
+    /// without value or connection overriding the attribute of the composed document if necessary.
+    ///
+    /// Returns whether the attribute was created.
+    fn override_attribute(&mut self, layer: usize, path: &Path) -> Result<bool, Error> {
+        let node_path = path.parent().ok_or(Error::NoObjectAtPath)?;
+        if let Some(node) = self.layers[layer].node(&node_path) {
+            if node.attribute(&path.name()).is_some() {
+                return Ok(false);
+            }
+        }
+        let ty = self.attribute(path).ok_or(Error::NoObjectAtPath)?.ty.clone();
+        let node = self.layer_node_mut(layer, &node_path)?;
+        node.attributes
+            .insert(path.name(), Param::new(0, path.clone(), ty, None, None));
+        Ok(true)
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // References
+
+    /// Makes the nodes of another document available to references, under the specified name.
+    ///
+    /// The contents of the document are copied: later changes to it are not visible in this document until
+    /// it is added again.
+    pub fn add_referenced_document(&mut self, name: impl Into<Atom>, document: &Document) {
+        self.referenced_documents.insert(name.into(), document.root.clone());
+        self.expand_references();
+        self.revision += 1;
+    }
+
+    /// Creates a node that instances the node designated by `reference`, as a child of `parent`.
+    ///
+    /// See `Reference::parse` for the syntax of references. The instanced nodes appear under the new node,
+    /// and can be overridden by editing them. Returns the path of the new node.
+    pub fn create_instance(&mut self, parent: &Path, stem: &str, reference: &str) -> Result<Path, Error> {
+        let reference = Reference::parse(reference).ok_or(Error::PathSyntax)?;
+        let parent_node = self.node(parent).ok_or(Error::NoObjectAtPath)?;
+        let name = parent_node.make_unique_child_name(stem);
+        let path = parent.join(name);
+        let mut node = Node::new(0, path.clone());
+        node.metadata.insert(
+            Atom::from(metadata::REFERENCE.name),
+            Value::Token(Atom::from(reference.to_string())),
+        );
+        self.edit(EditAction::InsertNode(node))?;
+        Ok(path)
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Undo/redo
+
+    /// Applies an edit action to a layer, and returns the action that reverts it.
+    ///
+    /// Increments the revisions of the modified nodes and attributes, but not the revision of the document.
+    fn apply_action(&mut self, layer: usize, action: EditAction) -> Result<EditAction, Error> {
+        let (inverse, path, deep) = match action {
+            EditAction::SetAttributeValue { path, value } => {
+                let created = self.override_attribute(layer, &path)?;
+                let node_path = path.parent().unwrap();
+                let node = self.layer_node_mut(layer, &node_path)?;
+                let attribute = node.attribute_mut(&path.name()).unwrap();
+                let value = mem::replace(&mut attribute.value, value);
+                attribute.rev += 1;
+                node.rev += 1;
+                let inverse = if created {
+                    EditAction::RemoveAttribute { path }
+                } else {
+                    EditAction::SetAttributeValue { path, value }
+                };
+                (inverse, node_path, false)
+            }
+            EditAction::SetConnection { path, connection } => {
+                let created = self.override_attribute(layer, &path)?;
+                let node_path = path.parent().unwrap();
+                let node = self.layer_node_mut(layer, &node_path)?;
+                let attribute = node.attribute_mut(&path.name()).unwrap();
+                let connection = mem::replace(&mut attribute.connection, connection);
+                attribute.rev += 1;
+                node.rev += 1;
+                let inverse = if created {
+                    EditAction::RemoveAttribute { path }
+                } else {
+                    EditAction::SetConnection { path, connection }
+                };
+                (inverse, node_path, false)
+            }
+            EditAction::InsertAttribute(attribute) => {
+                let path = attribute.path.clone();
+                let node_path = path.parent().ok_or(Error::NoObjectAtPath)?;
+                let node = self.layer_node_mut(layer, &node_path)?;
+                if node.attributes.contains_key(&path.name()) {
+                    return Err(Error::AlreadyExists);
+                }
+                node.attributes.insert(path.name(), attribute);
+                node.rev += 1;
+                (EditAction::RemoveAttribute { path }, node_path, false)
+            }
+            EditAction::RemoveAttribute { path } => {
+                let node_path = path.parent().ok_or(Error::NoObjectAtPath)?;
+                let node = self.layers[layer].node_mut(&node_path).ok_or(Error::NoObjectAtPath)?;
+                let attribute = node.attributes.remove(&path.name()).ok_or(Error::NoObjectAtPath)?;
+                node.rev += 1;
+                (EditAction::InsertAttribute(attribute), node_path, false)
+            }
+            EditAction::InsertNode(node) => {
+                let path = node.path.clone();
+                let parent = self.layer_node_mut(layer, &path.parent().ok_or(Error::AlreadyExists)?)?;
+                if parent.children.contains_key(&path.name()) {
+                    return Err(Error::AlreadyExists);
+                }
+                parent.children.insert(path.name(), node);
+                (EditAction::RemoveNode { path: path.clone() }, path, true)
+            }
+            EditAction::RemoveNode { path } => {
+                // the root node has no parent and can't be removed
+                let parent = self.layers[layer]
+                    .node_mut(&path.parent().ok_or(Error::NoObjectAtPath)?)
+                    .ok_or(Error::NoObjectAtPath)?;
+                let node = parent.children.remove(&path.name()).ok_or(Error::NoObjectAtPath)?;
+                (EditAction::InsertNode(node), path, true)
+            }
+            EditAction::SetNodeMetadata { path, name, value } => {
+                let node = self.layer_node_mut(layer, &path)?;
+                let value = match value {
+                    Some(value) => node.metadata.insert(name.clone(), value),
+                    None => node.metadata.remove(&name),
+                };
+                (
+                    EditAction::SetNodeMetadata {
+                        path: path.clone(),
+                        name,
+                        value,
+                    },
+                    path,
+                    false,
+                )
+            }
+            EditAction::SetSettings(settings) => {
+                let settings = mem::replace(&mut self.settings, settings);
+                self.settings_revision += 1;
+                return Ok(EditAction::SetSettings(settings));
+            }
+        };
+        self.recompose(&path, deep);
+        Ok(inverse)
+    }
+
+    /// Applies the actions of a recorded group, and returns the actions that revert them.
+    fn apply_group(&mut self, group: EditGroup) -> EditGroup {
+        group
+            .into_iter()
+            .map(|(layer, action)| {
+                let inverse = self.apply_action(layer, action).expect("could not apply recorded edit");
+                (layer, inverse)
+            })
+            .collect()
+    }
+
+    /// Applies an edit action to the edit target layer.
+    ///
+    /// The inverse of the action is recorded in the undo stack, and the document revision is incremented.
+    pub fn edit(&mut self, action: EditAction) -> Result<(), Error> {
+        let layer = self.edit_target;
+        let inverse = self.apply_action(layer, action)?;
+        self.undo_stack.record(layer, inverse);
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// Returns the history of the edits made to the document.
+    pub fn undo_stack(&self) -> &UndoStack {
+        &self.undo_stack
+    }
+
+    /// Starts a group of edits that are undone and redone as a single step.
+    ///
+    /// Groups can be nested: the edits are grouped until the outermost group ends.
+    pub fn begin_edit_group(&mut self) {
+        self.undo_stack.begin_group();
+    }
+
+    /// Ends a group of edits started with `begin_edit_group`.
+    pub fn end_edit_group(&mut self) {
+        self.undo_stack.end_group();
+    }
+
+    /// Applies a set of edits atomically.
+    ///
+    /// The edits made by `f` are applied to a copy of the document, which replaces the document if `f` returns
+    /// `Ok`. The revision of the document is incremented only once, and the edits are undone as a single step.
+    /// If `f` returns an error, the document is left unchanged.
+    ///
+    /// `f` must not call `undo` or `redo`.
+    pub fn transaction<R, E>(&mut self, f: impl FnOnce(&mut Document) -> Result<R, E>) -> Result<R, E> {
+        let mut staged = self.clone();
+        staged.begin_edit_group();
+        let result = f(&mut staged)?;
+        staged.end_edit_group();
+        if staged.revision != self.revision {
+            staged.revision = self.revision + 1;
+        }
+        if staged.settings_revision != self.settings_revision {
+            staged.settings_revision = self.settings_revision + 1;
+        }
+        *self = staged;
+        Ok(result)
+    }
+
+    /// Reverts the last edit or group of edits.
+    ///
+    /// Returns false if there was nothing to undo. Increments the document revision otherwise.
+    pub fn undo(&mut self) -> bool {
+        let group = match self.undo_stack.pop_undo() {
+            Some(group) => group,
+            None => return false,
+        };
+        let redo = self.apply_group(group);
+        self.undo_stack.push_redo(redo);
+        self.revision += 1;
+        true
+    }
+
+    /// Re-applies the last undone edit or group of edits.
+    ///
+    /// Returns false if there was nothing to redo. Increments the document revision otherwise.
+    pub fn redo(&mut self) -> bool {
+        let group = match self.undo_stack.pop_redo() {
+            Some(group) => group,
+            None => return false,
+        };
+        let undo = self.apply_group(group);
+        self.undo_stack.push_undo(undo);
+        self.revision += 1;
+        true
+    }
+
     /// Prints a textual representation of this document.
     pub fn dump(&self, out: &mut dyn std::fmt::Write) {
         let mut printer = DocumentPrettyPrinter::new(out);
@@ -160,3 +762,214 @@ impl<'a> DocumentPrettyPrinter<'a> {
         writeln!(self.output);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Document, Error, Layer, Path, TypeDesc, Value};
+    use std::convert::TryFrom;
+
+    fn string_value(document: &Document, path: &Path) -> Option<String> {
+        String::try_from(document.attribute(path)?.value.clone()?).ok()
+    }
+
+    #[test]
+    fn test_transaction() {
+        let mut document = Document::new();
+        let revision = document.revision;
+
+        let (source, node) = document
+            .transaction(|tx| {
+                let source = tx.create_node(&Path::root(), "read", "read")?;
+                let node = tx.create_node(&Path::root(), "blur", "blur")?;
+                tx.connect(&node.join_attribute("input:image"), TypeDesc::FLOAT, &source)?;
+                Ok::<_, Error>((source, node))
+            })
+            .unwrap();
+        assert_eq!(document.revision, revision + 1);
+        let input = node.join_attribute("input:image");
+        assert_eq!(document.attribute(&input).unwrap().connection, Some(source.clone()));
+
+        // edits are rolled back on error
+        let result = document.transaction(|tx| {
+            tx.disconnect(&input)?;
+            tx.remove_node(&source)?;
+            tx.set_attribute_value(&source.join_attribute("input:file"), Value::from("a.png"))
+        });
+        assert!(matches!(result, Err(Error::NoObjectAtPath)));
+        assert_eq!(document.revision, revision + 1);
+        assert!(document.node(&source).is_some());
+        assert_eq!(document.attribute(&input).unwrap().connection, Some(source.clone()));
+
+        // a transaction is undone as a single step
+        assert!(document.undo());
+        assert!(document.node(&source).is_none());
+        assert!(document.node(&node).is_none());
+        assert!(!document.undo_stack().can_undo());
+    }
+
+    #[test]
+    fn test_layers() {
+        let asset = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read">
+        <string id="input:file">a.png</string>
+    </node>
+</document>"#,
+        )
+        .unwrap();
+        let mut document = Document::new();
+        document.add_sublayer(Layer::from_document("asset.xml", &asset));
+        let a = Path::parse("/a").unwrap();
+        let file = a.join_attribute("input:file");
+        assert_eq!(string_value(&document, &file).as_deref(), Some("a.png"));
+
+        // edits go to the root layer, and override the value of the sublayer
+        document.set_attribute_value(&file, Value::from("b.png")).unwrap();
+        assert_eq!(string_value(&document, &file).as_deref(), Some("b.png"));
+        let asset_file = document.layers()[1].node(&a).unwrap().attribute(&file.name()).unwrap();
+        assert_eq!(String::try_from(asset_file.value.clone().unwrap()).unwrap(), "a.png");
+
+        // the override hides edits made in the sublayer
+        document.set_edit_target(1);
+        document.set_attribute_value(&file, Value::from("c.png")).unwrap();
+        assert_eq!(string_value(&document, &file).as_deref(), Some("b.png"));
+        document.undo();
+        document.undo();
+        assert_eq!(string_value(&document, &file).as_deref(), Some("a.png"));
+
+        document.remove_sublayer(0);
+        assert_eq!(document.edit_target(), 0);
+        assert!(document.attribute(&file).is_none());
+    }
+
+    #[test]
+    fn test_references() {
+        let mut document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="network">
+        <node id="read" op="read">
+            <string id="input:file">a.png</string>
+        </node>
+        <node id="blur" op="blur">
+            <texture2D id="input:image" connect="/network/read"/>
+        </node>
+    </node>
+    <node id="instance" reference="/network"/>
+</document>"#,
+        )
+        .unwrap();
+
+        // the referenced nodes are moved under the referencing node, with their connections
+        let read = Path::parse("/instance/read").unwrap();
+        let file = read.join_attribute("input:file");
+        let input = Path::parse("/instance/blur").unwrap().join_attribute("input:image");
+        assert_eq!(string_value(&document, &file).as_deref(), Some("a.png"));
+        assert_eq!(document.attribute(&input).unwrap().connection, Some(read.clone()));
+
+        // instanced nodes can be overridden
+        document.set_attribute_value(&file, Value::from("b.png")).unwrap();
+        assert_eq!(string_value(&document, &file).as_deref(), Some("b.png"));
+        let source_file = Path::parse("/network/read").unwrap().join_attribute("input:file");
+        assert_eq!(string_value(&document, &source_file).as_deref(), Some("a.png"));
+
+        // changes to the referenced nodes are visible in the instances
+        document.undo();
+        document
+            .set_attribute_value(&source_file, Value::from("c.png"))
+            .unwrap();
+        assert_eq!(string_value(&document, &file).as_deref(), Some("c.png"));
+
+        // references to other documents: connections inside the instance are moved along with it
+        let mut other = Document::new();
+        other.add_referenced_document("asset", &document);
+        let instance = other
+            .create_instance(&Path::root(), "network", "asset@/network")
+            .unwrap();
+        let input = instance.join("blur").join_attribute("input:image");
+        assert_eq!(other.attribute(&input).unwrap().connection, Some(instance.join("read")));
+
+        // connections to nodes outside of the instance don't exist in this document, and are removed
+        let instance = other
+            .create_instance(&Path::root(), "blur", "asset@/network/blur")
+            .unwrap();
+        let input = instance.join_attribute("input:image");
+        assert!(other.node(&instance).unwrap().operator().is_some());
+        assert!(other.attribute(&input).unwrap().connection.is_none());
+    }
+
+    #[test]
+    fn test_reuse_expanded_references() {
+        let mut document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="network">
+        <node id="read" op="read">
+            <string id="input:file">a.png</string>
+        </node>
+    </node>
+    <node id="other" op="read">
+        <string id="input:file">b.png</string>
+    </node>
+    <node id="instance" reference="/network"/>
+</document>"#,
+        )
+        .unwrap();
+
+        let read = Path::parse("/instance/read").unwrap();
+        let before = document.node(&read).unwrap().clone();
+
+        // unrelated edits don't rebuild the instanced nodes
+        let other = Path::parse("/other").unwrap().join_attribute("input:file");
+        document.set_attribute_value(&other, Value::from("c.png")).unwrap();
+        assert!(document.node(&read).unwrap().attributes.ptr_eq(&before.attributes));
+
+        // edits to the referenced nodes do
+        let source_file = Path::parse("/network/read").unwrap().join_attribute("input:file");
+        document
+            .set_attribute_value(&source_file, Value::from("d.png"))
+            .unwrap();
+        assert!(!document.node(&read).unwrap().attributes.ptr_eq(&before.attributes));
+        assert_eq!(
+            string_value(&document, &read.join_attribute("input:file")).as_deref(),
+            Some("d.png")
+        );
+    }
+
+    #[test]
+    fn test_file_references() {
+        let dir = std::env::temp_dir().join(format!("artifice-references-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("asset.xml"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="read" op="read">
+        <string id="input:file">a.png</string>
+    </node>
+</document>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.xml"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="instance" reference="asset.xml@/read"/>
+    <node id="cycle" reference="main.xml@/instance"/>
+</document>"#,
+        )
+        .unwrap();
+
+        // files are resolved relative to the referencing document, not the working directory
+        let document = Document::open(&dir.join("main.xml")).unwrap();
+        let file = Path::parse("/instance").unwrap().join_attribute("input:file");
+        assert_eq!(string_value(&document, &file).as_deref(), Some("a.png"));
+        // a document referencing itself is not loaded again
+        let cycle = Path::parse("/cycle").unwrap();
+        assert!(document.node(&cycle).is_some());
+        assert!(document.attribute(&cycle.join_attribute("input:file")).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
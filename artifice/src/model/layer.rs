@@ -0,0 +1,133 @@
+//! Layers of a composed document
+use crate::model::{Atom, Document, Node, Path};
+use imbl::OrdMap;
+
+/// A tree of nodes that contributes attributes, metadata and child nodes to a document.
+///
+/// A document is composed from a stack of layers: its root layer, followed by its sublayers. Values
+/// and connections of attributes, and metadata entries, defined in a layer override the ones defined in
+/// the layers after it in the stack.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    /// Name of the layer, for display (e.g. the path of the file it was loaded from).
+    pub name: Atom,
+    pub(crate) root: Node,
+}
+
+impl Layer {
+    /// Creates an empty layer.
+    pub fn new(name: impl Into<Atom>) -> Layer {
+        Layer {
+            name: name.into(),
+            root: Node::new(0, Path::root()),
+        }
+    }
+
+    /// Creates a layer with the contents of a document (e.g. a shared asset file).
+    pub fn from_document(name: impl Into<Atom>, document: &Document) -> Layer {
+        Layer {
+            name: name.into(),
+            root: document.root().clone(),
+        }
+    }
+
+    /// Returns the root node of the layer.
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// Returns the node of the layer with the given path.
+    pub fn node(&self, path: &Path) -> Option<&Node> {
+        find_node(&self.root, path)
+    }
+
+    /// Returns a mutable reference to the node of the layer with the given path.
+    pub(crate) fn node_mut(&mut self, path: &Path) -> Option<&mut Node> {
+        find_node_mut(&mut self.root, path)
+    }
+}
+
+/// Returns the node with the given path in the tree under `root`.
+pub(crate) fn find_node<'a>(root: &'a Node, path: &Path) -> Option<&'a Node> {
+    if path.is_root() {
+        Some(root)
+    } else {
+        find_node(root, &path.parent().unwrap())?.children.get(&path.name())
+    }
+}
+
+/// Returns a mutable reference to the node with the given path in the tree under `root`.
+pub(crate) fn find_node_mut<'a>(root: &'a mut Node, path: &Path) -> Option<&'a mut Node> {
+    if path.is_root() {
+        Some(root)
+    } else {
+        find_node_mut(root, &path.parent().unwrap())?
+            .children
+            .get_mut(&path.name())
+    }
+}
+
+/// Overrides the attributes and metadata of `weaker` with the ones of `stronger`.
+fn override_properties(weaker: &mut Node, stronger: &Node) {
+    for (name, attribute) in stronger.attributes.iter() {
+        match weaker.attributes.get_mut(name) {
+            Some(weaker_attribute) => {
+                if attribute.value.is_some() {
+                    weaker_attribute.value = attribute.value.clone();
+                }
+                if attribute.connection.is_some() {
+                    weaker_attribute.connection = attribute.connection.clone();
+                }
+                // `union` keeps the entries of the map it's called on
+                weaker_attribute.metadata = attribute.metadata.clone().union(weaker_attribute.metadata.clone());
+                weaker_attribute.rev += attribute.rev;
+            }
+            None => {
+                weaker.attributes.insert(name.clone(), attribute.clone());
+            }
+        }
+    }
+    weaker.metadata = stronger.metadata.clone().union(weaker.metadata.clone());
+    weaker.rev += stronger.rev;
+}
+
+/// Composes the nodes at the same path in a stack of layers, strongest first.
+///
+/// If only one layer has a node at this path, the node is returned as is, sharing its maps with the layer.
+pub(crate) fn compose_node(nodes: &[&Node]) -> Option<Node> {
+    let (weakest, stronger) = nodes.split_last()?;
+    if stronger.is_empty() {
+        return Some((*weakest).clone());
+    }
+
+    let mut composed = (*weakest).clone();
+    for node in stronger.iter().rev() {
+        override_properties(&mut composed, node);
+    }
+
+    let mut names: Vec<&Atom> = nodes.iter().flat_map(|node| node.children.keys()).collect();
+    names.sort();
+    names.dedup();
+    let mut children = OrdMap::new();
+    for name in names {
+        let child_nodes: Vec<&Node> = nodes.iter().filter_map(|node| node.children.get(name)).collect();
+        if let Some(child) = compose_node(&child_nodes) {
+            children.insert(name.clone(), child);
+        }
+    }
+    composed.children = children;
+    Some(composed)
+}
+
+/// Composes the attributes and metadata of the nodes at the same path in a stack of layers, keeping the
+/// children of `composed`.
+pub(crate) fn compose_properties(composed: &mut Node, nodes: &[&Node]) {
+    if let Some((weakest, stronger)) = nodes.split_last() {
+        let children = composed.children.clone();
+        *composed = (*weakest).clone();
+        for node in stronger.iter().rev() {
+            override_properties(composed, node);
+        }
+        composed.children = children;
+    }
+}
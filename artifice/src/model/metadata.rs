@@ -1,4 +1,4 @@
-use crate::model::Value;
+use crate::model::{value::Vec2, Value};
 use kyute_common::Atom;
 use std::{fmt, fmt::Formatter, marker::PhantomData};
 
@@ -44,3 +44,7 @@ impl<T> fmt::Debug for Metadata<T> {
 
 pub const SCHEMA: Metadata<Atom> = Metadata::new("schema");
 pub const OPERATOR: Metadata<Atom> = Metadata::new("operator");
+/// Position of the node in the network editor.
+pub const POSITION: Metadata<Vec2> = Metadata::new("position");
+/// Node instanced by the node, as a string parsed by `Reference::parse` (e.g. `/network` or `asset@/network`).
+pub const REFERENCE: Metadata<Atom> = Metadata::new("reference");
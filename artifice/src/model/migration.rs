@@ -0,0 +1,291 @@
+//! Migration of documents saved with older versions of the file format
+use crate::model::{metadata, parser::ReadError, Atom, Node, Path, Value};
+
+/// Version of the file format of the documents, stored in the `version` attribute of the `<document>` element.
+///
+/// Documents without a version are version 0. Increment this when operators or attributes are renamed, and
+/// register a `Migration` from the previous version.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A step upgrading documents from a version of the file format to the next.
+///
+/// Migrations are registered with `inventory::submit!`.
+pub struct Migration {
+    /// Version of the documents upgraded by this step, to `version + 1`.
+    pub version: u32,
+    /// Position of this step among the steps upgrading the same version: steps are applied by increasing
+    /// order. Two steps can't have the same version and order.
+    pub order: u32,
+    /// Short description of the changes, for logging.
+    pub description: &'static str,
+    /// Upgrades the tree of a document, from its root node.
+    pub migrate: fn(&mut Node),
+}
+
+inventory::collect!(Migration);
+
+/// Returns the steps upgrading the specified version, sorted by `Migration::order`.
+fn migration_steps<'a>(
+    migrations: impl IntoIterator<Item = &'a Migration>,
+    version: u32,
+) -> Result<Vec<&'a Migration>, ReadError> {
+    let mut steps: Vec<_> = migrations.into_iter().filter(|m| m.version == version).collect();
+    if steps.is_empty() {
+        return Err(ReadError::MissingMigration(version));
+    }
+    steps.sort_by_key(|m| m.order);
+    if steps.windows(2).any(|w| w[0].order == w[1].order) {
+        return Err(ReadError::ConflictingMigrations(version));
+    }
+    Ok(steps)
+}
+
+/// Upgrades the tree of a document saved with the specified version of the file format to the current version.
+///
+/// The steps registered for each version are applied by increasing `Migration::order`.
+pub(crate) fn migrate(root: &mut Node, version: u32) -> Result<(), ReadError> {
+    if version > FORMAT_VERSION {
+        return Err(ReadError::UnsupportedVersion(version));
+    }
+    for version in version..FORMAT_VERSION {
+        for migration in migration_steps(inventory::iter::<Migration>, version)? {
+            debug!(
+                "migrating document from version {} to {}: {}",
+                version,
+                version + 1,
+                migration.description
+            );
+            (migration.migrate)(root);
+        }
+    }
+    Ok(())
+}
+
+/// Renames an operator in the nodes of a tree.
+pub fn rename_operator(node: &mut Node, from: &str, to: &str) {
+    if node.operator() == Some(Atom::from(from)) {
+        node.metadata
+            .insert(Atom::from(metadata::OPERATOR.name), Value::Token(Atom::from(to)));
+    }
+    for child in node.children.values_mut() {
+        rename_operator(child, from, to);
+    }
+}
+
+/// Renames the attribute `from` of the nodes with the specified operator in a tree, and updates the connections
+/// to the renamed attributes.
+pub fn rename_attribute(root: &mut Node, operator: &str, from: &str, to: &str) {
+    let mut renamed = Vec::new();
+    rename_attribute_rec(
+        root,
+        &Atom::from(operator),
+        &Atom::from(from),
+        &Atom::from(to),
+        &mut renamed,
+    );
+    if !renamed.is_empty() {
+        update_connections(root, &renamed);
+    }
+}
+
+fn rename_attribute_rec(node: &mut Node, operator: &Atom, from: &Atom, to: &Atom, renamed: &mut Vec<(Path, Path)>) {
+    if node.operator().as_ref() == Some(operator) {
+        if let Some(mut attribute) = node.attributes.remove(from) {
+            let path = node.path.join_attribute(to.clone());
+            renamed.push((attribute.path.clone(), path.clone()));
+            attribute.path = path;
+            node.attributes.insert(to.clone(), attribute);
+        }
+    }
+    for child in node.children.values_mut() {
+        rename_attribute_rec(child, operator, from, to, renamed);
+    }
+}
+
+/// Replaces connections to the first path of each pair with connections to the second.
+fn update_connections(node: &mut Node, renamed: &[(Path, Path)]) {
+    for attribute in node.attributes.values_mut() {
+        if let Some(ref connection) = attribute.connection {
+            if let Some((_, to)) = renamed.iter().find(|(from, _)| from == connection) {
+                attribute.connection = Some(to.clone());
+            }
+        }
+    }
+    for child in node.children.values_mut() {
+        update_connections(child, renamed);
+    }
+}
+
+/// Documents saved before the introduction of format versions don't need changes.
+fn migrate_unversioned(_root: &mut Node) {}
+
+inventory::submit! {
+    Migration {
+        version: 0,
+        order: 0,
+        description: "documents saved before the introduction of format versions",
+        migrate: migrate_unversioned,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migration_steps, Migration};
+    use crate::model::{parser::ReadError, Document, Layer, Node, Path, PresetLibrary};
+
+    fn migrate_test_operator(root: &mut Node) {
+        super::rename_operator(root, "test:legacy", "test:current");
+    }
+
+    inventory::submit! {
+        Migration {
+            version: 0,
+            order: 1,
+            description: "test migration",
+            migrate: migrate_test_operator,
+        }
+    }
+
+    #[test]
+    fn test_migrate_on_load() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="test:legacy"/>
+</document>"#;
+        let a = Path::parse("/a").unwrap();
+
+        // unversioned documents are migrated
+        let document = Document::from_xml(xml).unwrap();
+        assert_eq!(document.node(&a).unwrap().operator(), Some("test:current".into()));
+
+        // documents saved with the current version aren't
+        let document = Document::from_xml(&xml.replace("<document>", r#"<document version="1">"#)).unwrap();
+        assert_eq!(document.node(&a).unwrap().operator(), Some("test:legacy".into()));
+
+        // saved documents are tagged with the current version
+        let saved = document.to_xml();
+        assert!(saved.contains(&format!(r#"<document version="{}">"#, super::FORMAT_VERSION)));
+        let document = Document::from_xml(&saved).unwrap();
+        assert_eq!(document.node(&a).unwrap().operator(), Some("test:legacy".into()));
+    }
+
+    #[test]
+    fn test_migrate_sublayer() {
+        // layers are migrated when their document is read, before they are composed
+        let asset = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="test:legacy"/>
+</document>"#,
+        )
+        .unwrap();
+        let mut document = Document::from_xml(r#"<document version="1"/>"#).unwrap();
+        document.add_sublayer(Layer::from_document("asset.xml", &asset));
+
+        let a = Path::parse("/a").unwrap();
+        assert_eq!(document.node(&a).unwrap().operator(), Some("test:current".into()));
+        assert_eq!(
+            document.layers()[1].node(&a).unwrap().operator(),
+            Some("test:current".into())
+        );
+    }
+
+    #[test]
+    fn test_migrate_presets() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<presets>
+    <preset name="legacy">
+        <node id="a" op="test:legacy"/>
+    </preset>
+</presets>"#;
+
+        // unversioned libraries are migrated
+        let library = PresetLibrary::from_xml(xml).unwrap();
+        let preset = library.get(&"legacy".into()).unwrap();
+        assert_eq!(preset.operator(), Some("test:current".into()));
+        assert_eq!(preset.node().path, Path::parse("/a").unwrap());
+
+        // saved libraries are tagged with the current version, and aren't migrated again
+        let saved = library.to_xml();
+        assert!(saved.contains(&format!(r#"<presets version="{}">"#, super::FORMAT_VERSION)));
+        let library = PresetLibrary::from_xml(&saved.replace("test:current", "test:legacy")).unwrap();
+        assert_eq!(
+            library.get(&"legacy".into()).unwrap().operator(),
+            Some("test:legacy".into())
+        );
+
+        let result = PresetLibrary::from_xml(r#"<presets version="1000"/>"#);
+        assert!(matches!(result, Err(ReadError::UnsupportedVersion(1000))));
+
+        let result = PresetLibrary::from_xml(r#"<presets><preset name="empty"/></presets>"#);
+        assert!(matches!(result, Err(ReadError::MissingPresetNode(name)) if name == "empty"));
+    }
+
+    #[test]
+    fn test_migration_order() {
+        fn noop(_root: &mut Node) {}
+        let step = |version, order, description| Migration {
+            version,
+            order,
+            description,
+            migrate: noop,
+        };
+
+        let migrations = [step(0, 2, "second"), step(1, 0, "other version"), step(0, 1, "first")];
+        let steps = migration_steps(&migrations, 0).unwrap();
+        assert_eq!(
+            steps.iter().map(|m| m.description).collect::<Vec<_>>(),
+            vec!["first", "second"]
+        );
+        assert!(matches!(
+            migration_steps(&migrations, 2),
+            Err(ReadError::MissingMigration(2))
+        ));
+
+        // steps with the same version and order are rejected
+        let migrations = [step(0, 1, "a"), step(0, 1, "b")];
+        assert!(matches!(
+            migration_steps(&migrations, 0),
+            Err(ReadError::ConflictingMigrations(0))
+        ));
+    }
+
+    #[test]
+    fn test_migrate_renamed_attribute() {
+        let mut document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read">
+        <string id="input:file">a.png</string>
+        <texture2D id="output:image"/>
+    </node>
+    <node id="b" op="blur">
+        <texture2D id="input:image" connect="/a.output:image"/>
+    </node>
+</document>"#,
+        )
+        .unwrap();
+
+        let mut root = document.root().clone();
+        super::rename_operator(&mut root, "read", "load");
+        super::rename_attribute(&mut root, "load", "output:image", "output:pixels");
+        document = Document::from_root(root, document.settings().clone());
+
+        let a = Path::parse("/a").unwrap();
+        let b = Path::parse("/b").unwrap();
+        assert_eq!(document.node(&a).unwrap().operator(), Some("load".into()));
+        assert!(document.attribute(&a.join_attribute("output:image")).is_none());
+        let input = document.attribute(&b.join_attribute("input:image")).unwrap();
+        assert_eq!(input.connection, Some(a.join_attribute("output:pixels")));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let result = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document version="1000"/>"#,
+        );
+        assert!(matches!(result, Err(ReadError::UnsupportedVersion(1000))));
+        assert!(Document::from_xml(r#"<document version="1"/>"#).is_ok());
+    }
+}
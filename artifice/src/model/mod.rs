@@ -1,24 +1,43 @@
 //! Application data model
 mod document;
 mod error;
+mod layer;
 pub mod metadata;
+pub mod migration;
 mod node;
 mod param;
 mod parser;
 mod path;
+mod preset;
+mod reference;
 mod sampler;
+mod schema;
+mod settings;
 mod share_group;
 pub mod typedesc;
+mod undo;
+mod validate;
 mod value;
+mod watch;
+mod writer;
 
 pub use document::Document;
 pub use error::Error;
 pub use kyute_common::Atom;
+pub use layer::Layer;
 pub use metadata::Metadata;
+pub use migration::{Migration, FORMAT_VERSION};
 pub use node::Node;
 pub use param::Param;
 pub use path::Path;
+pub use preset::{Preset, PresetLibrary};
+pub use reference::Reference;
 pub use sampler::{SamplerFilter, SamplerParameters, SamplerWrapMode};
+pub use schema::{AttributeSchema, OperatorSchema, SchemaRegistry};
+pub use settings::{DocumentSettings, ResolutionPreset, RESOLUTION_PRESETS};
 pub use share_group::ShareGroup;
 pub use typedesc::{PrimitiveType, TypeDesc};
-pub use value::{TryFromValueError, Value};
+pub use undo::{EditAction, UndoStack};
+pub use validate::{Diagnostic, DiagnosticKind};
+pub use value::{AnimCurve, Interpolation, Keyframe, TryFromValueError, Value};
+pub use watch::{FileEvent, FileWatcher};
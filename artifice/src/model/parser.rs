@@ -1,8 +1,9 @@
 use crate::{
     model,
     model::{
-        metadata, typedesc, typedesc::ImageDimension, Document, Node, Param, Path, PrimitiveType, SamplerParameters,
-        SamplerWrapMode, TypeDesc, Value,
+        metadata, migration, typedesc, typedesc::ImageDimension, AnimCurve, Document, DocumentSettings, Interpolation,
+        Keyframe, Node, Param, Path, Preset, PresetLibrary, PrimitiveType, SamplerParameters, SamplerWrapMode,
+        TypeDesc, Value,
     },
 };
 use anyhow::{anyhow, bail};
@@ -48,6 +49,16 @@ pub enum ReadError {
     ParseBoolError(#[from] ParseBoolError),
     #[error("invalid value format")]
     InvalidValueFormat,
+    #[error("unsupported document version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("no migration from document version {0}")]
+    MissingMigration(u32),
+    #[error("several migrations from document version {0} have the same order")]
+    ConflictingMigrations(u32),
+    #[error("preset `{0}` has no <node> element")]
+    MissingPresetNode(String),
+    #[error("invalid document settings: {0}")]
+    InvalidSettings(&'static str),
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -151,6 +162,52 @@ fn parse_wrap_mode(text: &str) -> Result<SamplerWrapMode, ReadError> {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Animation curves
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Reads the keyframes of an animated value.
+///
+/// Keyframes are represented as `<key>` elements in the element of the value:
+/// `<key time="0" value="1" interpolation="bezier" inSlope="0" outSlope="2"/>`. The interpolation
+/// defaults to linear, and the slopes to zero. `ty` is the type of the animated value. Returns `None` if the
+/// element has no keyframes.
+fn read_anim_curve(xml_node: roxmltree::Node, ty: &TypeDesc) -> Result<Option<AnimCurve>, ReadError> {
+    let mut curve = None;
+    for key in xml_node.children() {
+        if !key.is_element() {
+            continue;
+        }
+        if key.tag_name().name() != "key" {
+            return Err(ReadError::UnexpectedElement {
+                tag: key.tag_name().name().to_string(),
+            });
+        }
+        let time = key.attribute("time").ok_or(ReadError::MissingAttribute)?.parse()?;
+        let value = key.attribute("value").ok_or(ReadError::MissingAttribute)?.parse()?;
+        let interpolation = match key.attribute("interpolation") {
+            None | Some("linear") => Interpolation::Linear,
+            Some("hold") => Interpolation::Hold,
+            Some("bezier") => Interpolation::Bezier,
+            Some(other) => {
+                error!("invalid keyframe interpolation: `{}`", other);
+                return Err(ReadError::InvalidValueFormat);
+            }
+        };
+        let mut keyframe = Keyframe::new(time, value, interpolation);
+        if let Some(slope) = key.attribute("inSlope") {
+            keyframe.in_slope = slope.parse()?;
+        }
+        if let Some(slope) = key.attribute("outSlope") {
+            keyframe.out_slope = slope.parse()?;
+        }
+        curve
+            .get_or_insert_with(|| AnimCurve::with_type(ty.clone()))
+            .insert(keyframe);
+    }
+    Ok(curve)
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Nodes
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -161,6 +218,7 @@ impl Node {
     fn read(parent_path: Path, xml_node: roxmltree::Node) -> Result<Node, ReadError> {
         let mut name = Atom::default();
         let mut op = Atom::default();
+        let mut reference = None;
 
         let tag_name = xml_node.tag_name().name();
         assert_eq!(tag_name, "node");
@@ -172,6 +230,9 @@ impl Node {
                 "op" => {
                     op = attr.value().into();
                 }
+                "reference" => {
+                    reference = Some(Atom::from(attr.value()));
+                }
                 _ => {
                     warn!("unrecognized node attribute: {}=\"{}\"", attr.name(), attr.value());
                 }
@@ -182,6 +243,7 @@ impl Node {
         //let mut ports = vec![];
         let mut params = OrdMap::new();
         let mut children = OrdMap::new();
+        let mut metadata = OrdMap::new();
 
         for n in xml_node.children() {
             if !n.is_element() {
@@ -195,6 +257,10 @@ impl Node {
                     let child = Node::read(node_path.clone(), n)?;
                     children.insert(child.name(), child);
                 }
+                "metadata" => {
+                    let id = n.attribute("id").ok_or(ReadError::MissingAttribute)?;
+                    metadata.insert(Atom::from(id), Value::Token(text_content(n)?.into()));
+                }
                 ty_name => {
                     // this is a parameter
                     let mut param_name = Atom::default();
@@ -220,13 +286,17 @@ impl Node {
 
                     match ty_name {
                         "float" => {
-                            let text = text_content(n)?;
-                            value = Value::Float(text.parse()?);
+                            value = match read_anim_curve(n, &TypeDesc::FLOAT)? {
+                                Some(curve) => curve.into(),
+                                None => Value::Float(text_content(n)?.parse()?),
+                            };
                             ty = TypeDesc::FLOAT;
                         }
                         "double" => {
-                            let text = text_content(n)?;
-                            value = Value::Double(text.parse()?);
+                            value = match read_anim_curve(n, &TypeDesc::DOUBLE)? {
+                                Some(curve) => curve.into(),
+                                None => Value::Double(text_content(n)?.parse()?),
+                            };
                             ty = TypeDesc::DOUBLE;
                         }
                         "vec2" | "float2" => {
@@ -328,10 +398,12 @@ impl Node {
             }
         }
 
-        let mut metadata = OrdMap::new();
         if !op.is_empty() {
             metadata.insert(Atom::from(metadata::OPERATOR.name), Value::from(op));
         }
+        if let Some(reference) = reference {
+            metadata.insert(Atom::from(metadata::REFERENCE.name), Value::from(reference));
+        }
 
         Ok(Node {
             rev: 0,
@@ -344,11 +416,39 @@ impl Node {
     }
 }
 
+impl DocumentSettings {
+    fn read(xml_node: roxmltree::Node) -> Result<DocumentSettings, ReadError> {
+        let mut settings = DocumentSettings::default();
+        for attr in xml_node.attributes() {
+            match attr.name() {
+                "fps" => settings.fps = attr.value().parse()?,
+                "width" => settings.resolution.width = attr.value().parse()?,
+                "height" => settings.resolution.height = attr.value().parse()?,
+                "pixelAspect" => settings.pixel_aspect = attr.value().parse()?,
+                "colorspace" => settings.colorspace = attr.value().into(),
+                _ => {
+                    warn!("unrecognized settings attribute: {}=\"{}\"", attr.name(), attr.value());
+                }
+            }
+        }
+        settings.check().map_err(ReadError::InvalidSettings)?;
+        Ok(settings)
+    }
+}
+
 impl Document {
     pub fn from_xml(xml: &str) -> Result<Document, ReadError> {
+        let (root, settings) = Document::read_xml(xml)?;
+        Ok(Document::from_root(root, settings))
+    }
+
+    /// Reads the root node and the settings of a document.
+    pub(crate) fn read_xml(xml: &str) -> Result<(Node, DocumentSettings), ReadError> {
         let xml = roxmltree::Document::parse(xml)?;
         let mut seen_document = false;
         let mut root = Node::new(0, Path::root());
+        let mut settings = DocumentSettings::default();
+        let mut version = 0;
 
         for child in xml.root().children() {
             if !child.is_element() {
@@ -361,6 +461,10 @@ impl Document {
                     }
                     seen_document = true;
 
+                    if let Some(v) = child.attribute("version") {
+                        version = v.parse()?;
+                    }
+
                     // load root nodes
                     for node in child.children() {
                         if !node.is_element() {
@@ -371,6 +475,9 @@ impl Document {
                                 let n = Node::read(root.path.clone(), node)?;
                                 root.children.insert(n.name(), n);
                             }
+                            "settings" => {
+                                settings = DocumentSettings::read(node)?;
+                            }
                             other => {
                                 warn!("unknown element tag: `<{}>`", other)
                             }
@@ -388,9 +495,128 @@ impl Document {
             return Err(ReadError::MissingDocumentElement);
         }
 
-        Ok(Document { revision: 0, root })
+        migration::migrate(&mut root, version)?;
+        Ok((root, settings))
+    }
+}
+
+impl PresetLibrary {
+    /// Reads a preset library from its XML representation (see `PresetLibrary::to_xml`).
+    ///
+    /// Presets saved with an older version of the file format are migrated.
+    pub fn from_xml(xml: &str) -> Result<PresetLibrary, ReadError> {
+        let xml = roxmltree::Document::parse(xml)?;
+        let presets = xml.root_element();
+        if presets.tag_name().name() != "presets" {
+            return Err(ReadError::UnexpectedElement {
+                tag: presets.tag_name().name().to_string(),
+            });
+        }
+        let version = match presets.attribute("version") {
+            Some(v) => v.parse()?,
+            None => 0,
+        };
+
+        let mut library = PresetLibrary::new();
+        for preset in presets.children() {
+            if !preset.is_element() {
+                continue;
+            }
+            if preset.tag_name().name() != "preset" {
+                warn!("unknown element: `<{}>`", preset.tag_name().name());
+                continue;
+            }
+            let name = preset.attribute("name").ok_or(ReadError::MissingAttribute)?;
+            let node = preset
+                .children()
+                .find(|n| n.is_element() && n.tag_name().name() == "node")
+                .ok_or_else(|| ReadError::MissingPresetNode(name.to_string()))?;
+            let node = Node::read(Path::root(), node)?;
+
+            // migrations apply to a whole tree: migrate the template as the only node of a document
+            let node_name = node.name();
+            let mut root = Node::new(0, Path::root());
+            root.children.insert(node_name.clone(), node);
+            migration::migrate(&mut root, version)?;
+            library.insert(Preset {
+                name: name.into(),
+                node: root.children.remove(&node_name).unwrap(),
+            });
+        }
+        Ok(library)
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::ReadError;
+    use crate::model::{Document, Path, TypeDesc, Value};
+
+    #[test]
+    fn test_read_anim_curve() {
+        let document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="blur" op="blur">
+        <float id="param:radius">
+            <key time="0" value="1"/>
+            <key time="1" value="3" interpolation="bezier" inSlope="0" outSlope="1"/>
+            <key time="2" value="2"/>
+        </float>
+    </node>
+</document>"#,
+        )
+        .unwrap();
+        let radius = Path::parse("/blur").unwrap().join_attribute("param:radius");
+        let value = document.attribute(&radius).unwrap().value.clone().unwrap();
+        let curve = match value {
+            Value::AnimCurve(ref curve) => curve.clone(),
+            _ => panic!("expected an animation curve"),
+        };
+        assert_eq!(curve.keyframes().len(), 3);
+        assert_eq!(curve.keyframes()[1].out_slope, 1.0);
+        assert_eq!(value.type_desc(), &TypeDesc::FLOAT);
+        assert!(matches!(value.sample(0.5), Value::Float(v) if v == 2.0));
+        assert!(matches!(value.sample(5.0), Value::Float(v) if v == 2.0));
+    }
+
+    #[test]
+    fn test_read_settings() {
+        let read = |settings: &str| {
+            Document::from_xml(&format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <settings {}/>
+</document>"#,
+                settings
+            ))
+        };
+
+        let document = read(r#"fps="25" width="1920" height="1080" pixelAspect="2" colorspace="acescg""#).unwrap();
+        let settings = document.settings();
+        assert_eq!(settings.fps, 25.0);
+        assert_eq!(settings.resolution.width, 1920);
+        assert_eq!(settings.resolution.height, 1080);
+        assert_eq!(settings.pixel_aspect, 2.0);
+        assert_eq!(&*settings.colorspace, "acescg");
+
+        // the settings are written back
+        let reloaded = Document::from_xml(&document.to_xml()).unwrap();
+        assert_eq!(reloaded.settings(), settings);
+
+        for invalid in [
+            r#"fps="0""#,
+            r#"fps="-24""#,
+            r#"fps="NaN""#,
+            r#"width="0""#,
+            r#"height="0""#,
+            r#"pixelAspect="0""#,
+        ] {
+            assert!(
+                matches!(read(invalid), Err(ReadError::InvalidSettings(_))),
+                "{} was accepted",
+                invalid
+            );
+        }
+    }
+}
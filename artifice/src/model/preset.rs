@@ -0,0 +1,209 @@
+//! Node presets
+use crate::model::{
+    metadata,
+    writer::{escape, write_node},
+    Atom, Document, EditAction, Error, Node, Path, FORMAT_VERSION,
+};
+use imbl::OrdMap;
+use std::fmt::Write;
+
+/// A configured node saved under a name: its operator, the values of its attributes and its metadata.
+#[derive(Clone, Debug)]
+pub struct Preset {
+    pub name: Atom,
+    /// Template of the nodes created from the preset, without connections or children.
+    pub(crate) node: Node,
+}
+
+impl Preset {
+    /// Creates a preset from a node of a document.
+    ///
+    /// Connections, child nodes and the position of the node are not saved in the preset.
+    pub fn from_node(name: impl Into<Atom>, node: &Node) -> Preset {
+        let mut template = Node::new(0, Path::root().join(node.name()));
+        for (attr_name, attribute) in node.attributes.iter() {
+            let mut attribute = attribute.clone();
+            attribute.rev = 0;
+            attribute.path = template.path.join_attribute(attr_name.clone());
+            attribute.connection = None;
+            template.attributes.insert(attr_name.clone(), attribute);
+        }
+        template.metadata = node.metadata.without(&Atom::from(metadata::POSITION.name));
+        Preset {
+            name: name.into(),
+            node: template,
+        }
+    }
+
+    /// Returns the operator of the nodes created from the preset.
+    pub fn operator(&self) -> Option<Atom> {
+        self.node.operator()
+    }
+
+    /// Returns the template of the nodes created from the preset.
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    /// Returns a node created from the preset, at the specified path.
+    fn instantiate(&self, path: Path) -> Node {
+        let mut node = self.node.clone();
+        node.attributes = self
+            .node
+            .attributes
+            .iter()
+            .map(|(name, attribute)| {
+                let mut attribute = attribute.clone();
+                attribute.path = path.join_attribute(name.clone());
+                (name.clone(), attribute)
+            })
+            .collect();
+        node.path = path;
+        node
+    }
+}
+
+/// A collection of presets, usually saved in a file of the user.
+#[derive(Clone, Debug, Default)]
+pub struct PresetLibrary {
+    pub(crate) presets: OrdMap<Atom, Preset>,
+}
+
+impl PresetLibrary {
+    /// Creates an empty library.
+    pub fn new() -> PresetLibrary {
+        PresetLibrary::default()
+    }
+
+    /// Loads a library from a file.
+    pub fn load(file: &std::path::Path) -> Result<PresetLibrary, Error> {
+        let xml = std::fs::read_to_string(file).map_err(anyhow::Error::from)?;
+        PresetLibrary::from_xml(&xml).map_err(|e| Error::FileError(e.into()))
+    }
+
+    /// Saves the library to a file.
+    pub fn save(&self, file: &std::path::Path) -> Result<(), Error> {
+        std::fs::write(file, self.to_xml()).map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+
+    /// Adds a preset to the library, replacing the preset with the same name.
+    pub fn insert(&mut self, preset: Preset) {
+        self.presets.insert(preset.name.clone(), preset);
+    }
+
+    /// Removes the preset with the given name.
+    pub fn remove(&mut self, name: &Atom) -> Option<Preset> {
+        self.presets.remove(name)
+    }
+
+    /// Returns the preset with the given name.
+    pub fn get(&self, name: &Atom) -> Option<&Preset> {
+        self.presets.get(name)
+    }
+
+    /// Returns the presets of the library, sorted by name.
+    pub fn presets(&self) -> impl Iterator<Item = &Preset> {
+        self.presets.values()
+    }
+
+    /// Returns the presets for the specified operator, sorted by name.
+    pub fn presets_for_operator<'a>(&'a self, operator: &'a Atom) -> impl Iterator<Item = &'a Preset> + 'a {
+        self.presets
+            .values()
+            .filter(move |preset| preset.operator().as_ref() == Some(operator))
+    }
+
+    /// Serializes the library to XML, in the format read by `PresetLibrary::from_xml`.
+    ///
+    /// The library is tagged with the current version of the file format (`FORMAT_VERSION`), so that presets
+    /// are migrated like documents when the format changes.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(out, r#"<presets version="{}">"#, FORMAT_VERSION).unwrap();
+        for preset in self.presets.values() {
+            writeln!(out, r#"    <preset name="{}">"#, escape(&preset.name)).unwrap();
+            write_node(&mut out, &preset.node, 2);
+            writeln!(out, "    </preset>").unwrap();
+        }
+        writeln!(out, "</presets>").unwrap();
+        out
+    }
+}
+
+impl Document {
+    /// Creates a node from a preset under the node at `parent`. Returns the path of the new node.
+    pub fn create_node_from_preset(&mut self, parent: &Path, preset: &Preset) -> Result<Path, Error> {
+        let parent_node = self.node(parent).ok_or(Error::NoObjectAtPath)?;
+        let name = parent_node.make_unique_child_name(preset.node.name());
+        let path = parent.join(name);
+        self.edit(EditAction::InsertNode(preset.instantiate(path.clone())))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Document, Path, Preset, PresetLibrary, Value};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_presets() {
+        let mut document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read">
+        <string id="input:file">a.png</string>
+        <texture2D id="output:image"/>
+    </node>
+    <node id="blur" op="blur">
+        <float id="stddev">4.5</float>
+        <texture2D id="input:image" connect="/a.output:image"/>
+        <metadata id="label">Soft</metadata>
+    </node>
+</document>"#,
+        )
+        .unwrap();
+
+        let blur = Path::parse("/blur").unwrap();
+        let mut library = PresetLibrary::new();
+        library.insert(Preset::from_node("soft <blur>", document.node(&blur).unwrap()));
+        library.insert(Preset::from_node(
+            "image",
+            document.node(&Path::parse("/a").unwrap()).unwrap(),
+        ));
+
+        // save and reload the library
+        let library = PresetLibrary::from_xml(&library.to_xml()).unwrap();
+        assert_eq!(library.presets().count(), 2);
+
+        let preset = library.get(&"soft <blur>".into()).unwrap();
+        assert_eq!(preset.operator(), Some("blur".into()));
+        let path = document.create_node_from_preset(&Path::root(), preset).unwrap();
+        assert_ne!(path, blur);
+
+        let node = document.node(&path).unwrap();
+        assert_eq!(
+            node.metadata
+                .get(&"label".into())
+                .cloned()
+                .map(String::try_from)
+                .unwrap()
+                .ok(),
+            Some("Soft".to_string())
+        );
+        let stddev = document.attribute(&path.join_attribute("stddev")).unwrap();
+        assert!(matches!(stddev.value, Some(Value::Float(v)) if v == 4.5));
+        let input = document.attribute(&path.join_attribute("input:image")).unwrap();
+        assert!(input.connection.is_none());
+
+        let preset = library.get(&"image".into()).unwrap();
+        let path = document.create_node_from_preset(&Path::root(), preset).unwrap();
+        let file = document.attribute(&path.join_attribute("input:file")).unwrap();
+        assert_eq!(
+            String::try_from(file.value.clone().unwrap()).ok(),
+            Some("a.png".to_string())
+        );
+    }
+}
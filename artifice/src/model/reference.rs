@@ -0,0 +1,245 @@
+//! Node references and instancing
+use crate::model::{
+    layer::{compose_node, find_node},
+    metadata, Atom, Node, Path,
+};
+use imbl::OrdMap;
+use std::fmt;
+
+/// Target of a node reference (see `metadata::REFERENCE`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reference {
+    /// Name of the referenced document, or `None` for a node of the same document.
+    pub document: Option<Atom>,
+    /// Path of the referenced node in its document.
+    pub path: Path,
+}
+
+impl Reference {
+    /// Parses a reference.
+    ///
+    /// References are either paths to nodes of the same document (`/network`), or paths to nodes of a
+    /// referenced document prefixed by the name of the document (`asset@/network`).
+    pub fn parse(reference: &str) -> Option<Reference> {
+        let (document, path) = match reference.split_once('@') {
+            Some((document, path)) => (Some(Atom::from(document)), path),
+            None => (None, reference),
+        };
+        let path = Path::parse(path)?;
+        if path.is_attribute() {
+            return None;
+        }
+        Some(Reference { document, path })
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref document) = self.document {
+            write!(f, "{}@", document)?;
+        }
+        write!(f, "{}", self.path.to_string())
+    }
+}
+
+/// Returns `path` with the prefix `from` replaced by `to`, or `None` if `from` is not a prefix of `path`.
+fn rebase(path: &Path, from: &Path, to: &Path) -> Option<Path> {
+    if path == from {
+        return Some(to.clone());
+    }
+    let (parent, name) = path.split_last()?;
+    let parent = rebase(&parent, from, to)?;
+    if path.is_attribute() {
+        Some(parent.join_attribute(name))
+    } else {
+        Some(parent.join(name))
+    }
+}
+
+/// Copies a node and its subtree from `from` to `to`.
+///
+/// Connections to objects of the subtree are moved along with it. Connections to other objects are kept if the
+/// node is copied from the same document, and removed if it's copied from another document, since the
+/// connected objects don't exist in the document receiving the copy.
+fn instantiate(node: &Node, from: &Path, to: &Path, external: bool) -> Node {
+    let path = rebase(&node.path, from, to).unwrap();
+    let mut instance = node.clone();
+    instance.attributes = node
+        .attributes
+        .iter()
+        .map(|(name, attribute)| {
+            let mut attribute = attribute.clone();
+            attribute.path = path.join_attribute(name.clone());
+            if let Some(ref connection) = attribute.connection {
+                match rebase(connection, from, to) {
+                    Some(connection) => attribute.connection = Some(connection),
+                    None if external => {
+                        warn!(
+                            "{}: connection to `{}` is outside of the referenced node",
+                            attribute.path.to_string(),
+                            connection.to_string()
+                        );
+                        attribute.connection = None;
+                    }
+                    None => {}
+                }
+            }
+            (name.clone(), attribute)
+        })
+        .collect();
+    instance.children = node
+        .children
+        .iter()
+        .map(|(name, child)| (name.clone(), instantiate(child, from, to, external)))
+        .collect();
+    instance.path = path;
+    instance
+}
+
+/// Returns whether two nodes are the same, i.e. whether their attributes, metadata and descendants are shared.
+///
+/// This doesn't compare values: nodes with equal but separately allocated maps are considered different.
+fn same_node(a: &Node, b: &Node) -> bool {
+    a.path == b.path
+        && a.attributes.ptr_eq(&b.attributes)
+        && a.metadata.ptr_eq(&b.metadata)
+        && (a.children.ptr_eq(&b.children)
+            || (a.children.len() == b.children.len()
+                && a.children
+                    .iter()
+                    .zip(b.children.iter())
+                    .all(|((name_a, a), (name_b, b))| name_a == name_b && same_node(a, b))))
+}
+
+/// The expansion of a reference, reused by the next expansion if the referencing node and the referenced
+/// node don't change.
+#[derive(Clone, Debug)]
+pub(crate) struct Expansion {
+    /// The referencing node, with the references of its descendants expanded.
+    node: Node,
+    /// The referenced node, with its references expanded.
+    source: Node,
+    /// Copy of the referenced node moved under the path of the referencing node.
+    instance: Node,
+    /// Composition of the referencing node and the instance.
+    expanded: Node,
+}
+
+/// Expansions of the references of a document, by path of the referencing node.
+pub(crate) type ExpansionCache = OrdMap<Path, Expansion>;
+
+struct Expander<'a> {
+    root: &'a Node,
+    documents: &'a OrdMap<Atom, Node>,
+    /// References being expanded, to detect cycles
+    visiting: Vec<Path>,
+    previous: &'a ExpansionCache,
+    next: ExpansionCache,
+}
+
+impl<'a> Expander<'a> {
+    /// Expands the references of a node and of its descendants.
+    ///
+    /// Returns `None` if the subtree doesn't contain references.
+    fn expand_node(&mut self, node: &Node) -> Option<Node> {
+        let mut expanded: Option<Node> = None;
+        for (name, child) in node.children.iter() {
+            if let Some(child) = self.expand_node(child) {
+                expanded
+                    .get_or_insert_with(|| node.clone())
+                    .children
+                    .insert(name.clone(), child);
+            }
+        }
+
+        let reference = match node.metadata(metadata::REFERENCE) {
+            Some(reference) => reference,
+            None => return expanded,
+        };
+        let reference = match Reference::parse(&reference) {
+            Some(reference) => reference,
+            None => {
+                warn!("{}: invalid reference `{}`", node.path.to_string(), reference);
+                return expanded;
+            }
+        };
+
+        let source = match reference.document {
+            // referenced documents are expanded already
+            Some(ref document) => self
+                .documents
+                .get(document)
+                .and_then(|root| find_node(root, &reference.path))
+                .cloned(),
+            None => {
+                if self.visiting.contains(&reference.path) {
+                    warn!("{}: cyclic reference to `{}`", node.path.to_string(), reference);
+                    return expanded;
+                }
+                self.visiting.push(reference.path.clone());
+                let root = self.root;
+                let source = find_node(root, &reference.path)
+                    .map(|source| self.expand_node(source).unwrap_or_else(|| source.clone()));
+                self.visiting.pop();
+                source
+            }
+        };
+        let source = match source {
+            Some(source) => source,
+            None => {
+                warn!("{}: unresolved reference `{}`", node.path.to_string(), reference);
+                return expanded;
+            }
+        };
+
+        let node = expanded.unwrap_or_else(|| node.clone());
+        let previous = self.previous;
+        let previous = previous.get(&node.path);
+        let expansion = match previous {
+            Some(previous) if same_node(&previous.node, &node) && same_node(&previous.source, &source) => {
+                // nothing changed: share the nodes of the previous expansion, so that they are not seen as modified
+                previous.clone()
+            }
+            _ => {
+                let instance = match previous {
+                    Some(previous) if same_node(&previous.source, &source) => previous.instance.clone(),
+                    _ => instantiate(&source, &reference.path, &node.path, reference.document.is_some()),
+                };
+                // the attributes, metadata and children of the referencing node override the ones of the instance
+                let expanded = compose_node(&[&node, &instance]).unwrap();
+                Expansion {
+                    node,
+                    source,
+                    instance,
+                    expanded,
+                }
+            }
+        };
+        let expanded = expansion.expanded.clone();
+        self.next.insert(expanded.path.clone(), expansion);
+        Some(expanded)
+    }
+}
+
+/// Expands the references in a document tree.
+///
+/// Each node with a `metadata::REFERENCE` entry receives a copy of the attributes, metadata and children of
+/// the referenced node, moved under its own path. Nodes without references are shared with `root`.
+///
+/// Expansions in `previous` are reused when the referencing and referenced nodes haven't changed. Returns the
+/// expanded tree and the expansions to pass to the next call.
+pub(crate) fn expand_references(
+    root: &Node,
+    documents: &OrdMap<Atom, Node>,
+    previous: &ExpansionCache,
+) -> (Node, ExpansionCache) {
+    let mut expander = Expander {
+        root,
+        documents,
+        visiting: Vec::new(),
+        previous,
+        next: ExpansionCache::new(),
+    };
+    let expanded = expander.expand_node(root).unwrap_or_else(|| root.clone());
+    (expanded, expander.next)
+}
@@ -0,0 +1,92 @@
+//! Descriptions of the attributes expected on the nodes of each operator
+use crate::model::{Atom, TypeDesc};
+use imbl::OrdMap;
+
+/// Describes an attribute of the nodes of an operator.
+#[derive(Clone, Debug)]
+pub struct AttributeSchema {
+    pub name: Atom,
+    pub ty: TypeDesc,
+    /// Whether the attribute must have a value or a connection.
+    pub mandatory: bool,
+}
+
+/// Describes the attributes of the nodes of an operator.
+#[derive(Clone, Debug)]
+pub struct OperatorSchema {
+    pub name: Atom,
+    pub attributes: Vec<AttributeSchema>,
+    /// Type of the value produced by the nodes themselves, for connections made to a node rather than to one
+    /// of its attributes (e.g. the image of an imaging operator).
+    pub output: Option<TypeDesc>,
+}
+
+impl OperatorSchema {
+    /// Creates the schema of an operator without attributes.
+    pub fn new(name: impl Into<Atom>) -> OperatorSchema {
+        OperatorSchema {
+            name: name.into(),
+            attributes: vec![],
+            output: None,
+        }
+    }
+
+    /// Sets the type of the value produced by the nodes.
+    pub fn output(mut self, ty: TypeDesc) -> OperatorSchema {
+        self.output = Some(ty);
+        self
+    }
+
+    /// Adds an attribute that must have a value or a connection.
+    pub fn mandatory(mut self, name: impl Into<Atom>, ty: TypeDesc) -> OperatorSchema {
+        self.attributes.push(AttributeSchema {
+            name: name.into(),
+            ty,
+            mandatory: true,
+        });
+        self
+    }
+
+    /// Adds an optional attribute.
+    pub fn optional(mut self, name: impl Into<Atom>, ty: TypeDesc) -> OperatorSchema {
+        self.attributes.push(AttributeSchema {
+            name: name.into(),
+            ty,
+            mandatory: false,
+        });
+        self
+    }
+
+    /// Returns the schema of the attribute with the given name.
+    pub fn attribute(&self, name: &Atom) -> Option<&AttributeSchema> {
+        self.attributes.iter().find(|a| &a.name == name)
+    }
+}
+
+/// The set of known operators, with the attributes of their nodes.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaRegistry {
+    operators: OrdMap<Atom, OperatorSchema>,
+}
+
+impl SchemaRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> SchemaRegistry {
+        SchemaRegistry::default()
+    }
+
+    /// Registers an operator, replacing the schema previously registered under the same name.
+    pub fn register(&mut self, schema: OperatorSchema) {
+        self.operators.insert(schema.name.clone(), schema);
+    }
+
+    /// Returns the schema of the operator with the given name.
+    pub fn operator(&self, name: &Atom) -> Option<&OperatorSchema> {
+        self.operators.get(name)
+    }
+
+    /// Returns the registered operators, sorted by name.
+    pub fn operators(&self) -> impl Iterator<Item = &OperatorSchema> {
+        self.operators.values()
+    }
+}
@@ -0,0 +1,147 @@
+use kyute_common::{Atom, SizeI};
+
+/// A named output resolution.
+#[derive(Copy, Clone, Debug)]
+pub struct ResolutionPreset {
+    pub name: &'static str,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Common output resolutions.
+pub const RESOLUTION_PRESETS: &[ResolutionPreset] = &[
+    ResolutionPreset {
+        name: "HD 720",
+        width: 1280,
+        height: 720,
+    },
+    ResolutionPreset {
+        name: "HD 1080",
+        width: 1920,
+        height: 1080,
+    },
+    ResolutionPreset {
+        name: "UHD 4K",
+        width: 3840,
+        height: 2160,
+    },
+    ResolutionPreset {
+        name: "DCI 2K",
+        width: 2048,
+        height: 1080,
+    },
+    ResolutionPreset {
+        name: "DCI 4K",
+        width: 4096,
+        height: 2160,
+    },
+];
+
+/// Document-wide settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentSettings {
+    /// Frames per second.
+    pub fps: f64,
+    /// Output resolution, in pixels.
+    pub resolution: SizeI,
+    /// Width of a pixel divided by its height.
+    pub pixel_aspect: f64,
+    /// Name of the working colorspace.
+    pub colorspace: Atom,
+}
+
+impl Default for DocumentSettings {
+    fn default() -> Self {
+        DocumentSettings {
+            fps: 24.0,
+            resolution: SizeI::new(1280, 720),
+            pixel_aspect: 1.0,
+            colorspace: Atom::from("linear-srgb"),
+        }
+    }
+}
+
+impl DocumentSettings {
+    /// Checks that the settings can be used to evaluate the document: the frame rate and the pixel aspect ratio
+    /// must be positive, and the resolution non-empty.
+    ///
+    /// Returns a description of the first invalid setting found.
+    pub fn check(&self) -> Result<(), &'static str> {
+        // NaN fails the comparisons
+        if !(self.fps > 0.0 && self.fps.is_finite()) {
+            return Err("the frame rate must be positive");
+        }
+        if self.resolution.width <= 0 || self.resolution.height <= 0 {
+            return Err("the resolution must be non-empty");
+        }
+        if !(self.pixel_aspect > 0.0 && self.pixel_aspect.is_finite()) {
+            return Err("the pixel aspect ratio must be positive");
+        }
+        Ok(())
+    }
+
+    /// Returns the time in seconds at the start of the specified frame.
+    ///
+    /// The settings must be valid (see `check`).
+    pub fn frame_to_time(&self, frame: i64) -> f64 {
+        frame as f64 / self.fps
+    }
+
+    /// Returns the frame that contains the specified time.
+    ///
+    /// The settings must be valid (see `check`).
+    pub fn time_to_frame(&self, time: f64) -> i64 {
+        (time * self.fps).floor() as i64
+    }
+
+    /// Sets the output resolution from a preset.
+    pub fn set_resolution_preset(&mut self, preset: &ResolutionPreset) {
+        self.resolution = SizeI::new(preset.width, preset.height);
+    }
+
+    /// Returns the preset matching the current resolution, if any.
+    pub fn resolution_preset(&self) -> Option<&'static ResolutionPreset> {
+        RESOLUTION_PRESETS
+            .iter()
+            .find(|p| p.width == self.resolution.width && p.height == self.resolution.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DocumentSettings;
+    use kyute_common::SizeI;
+
+    #[test]
+    fn test_check_settings() {
+        let settings = DocumentSettings::default();
+        assert!(settings.check().is_ok());
+        assert_eq!(settings.frame_to_time(48), 2.0);
+        assert_eq!(settings.time_to_frame(2.01), 48);
+        assert_eq!(settings.time_to_frame(-0.01), -1);
+
+        for fps in [0.0, -24.0, f64::NAN, f64::INFINITY] {
+            assert!(DocumentSettings {
+                fps,
+                ..settings.clone()
+            }
+            .check()
+            .is_err());
+        }
+        for (width, height) in [(0, 720), (1280, 0), (-1, 720)] {
+            let resolution = SizeI::new(width, height);
+            assert!(DocumentSettings {
+                resolution,
+                ..settings.clone()
+            }
+            .check()
+            .is_err());
+        }
+        assert!(DocumentSettings {
+            pixel_aspect: 0.0,
+            ..settings.clone()
+        }
+        .check()
+        .is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Undo/redo history of document edits
+use crate::model::{Atom, DocumentSettings, Node, Param, Path, Value};
+use imbl::Vector;
+
+/// An elementary modification of a document.
+///
+/// Applying an action to a document returns the action that reverts it, which is what `UndoStack` records.
+#[derive(Clone, Debug)]
+pub enum EditAction {
+    /// Sets (or clears) the value of the attribute at `path`.
+    SetAttributeValue { path: Path, value: Option<Value> },
+    /// Sets (or removes) the connection of the attribute at `path`.
+    SetConnection { path: Path, connection: Option<Path> },
+    /// Inserts an attribute in the node designated by the parent of its path.
+    InsertAttribute(Param),
+    /// Removes the attribute at `path`.
+    RemoveAttribute { path: Path },
+    /// Inserts a node, with its attributes and children, in the node designated by the parent of its path.
+    InsertNode(Node),
+    /// Removes the node at `path`, with its attributes and children.
+    RemoveNode { path: Path },
+    /// Sets (or removes) a metadata entry of the node at `path`.
+    SetNodeMetadata {
+        path: Path,
+        name: Atom,
+        value: Option<Value>,
+    },
+    /// Replaces the document settings.
+    SetSettings(DocumentSettings),
+}
+
+/// Inverse actions of a group of edits undone or redone together, in the order of the edits, with the index
+/// of the layer they apply to.
+pub(crate) type EditGroup = Vec<(usize, EditAction)>;
+
+/// History of the edits made to a document.
+///
+/// Every edit made through the methods of `Document` records its inverse action. Edits made between
+/// `Document::begin_edit_group` and `Document::end_edit_group` are undone and redone as a single step.
+#[derive(Clone, Debug, Default)]
+pub struct UndoStack {
+    undo: Vector<EditGroup>,
+    redo: Vector<EditGroup>,
+    /// Inverse actions of the edits of the current group, in the order in which the edits were made.
+    group: EditGroup,
+    group_depth: usize,
+}
+
+impl UndoStack {
+    /// Creates an empty history.
+    pub fn new() -> UndoStack {
+        UndoStack::default()
+    }
+
+    /// Returns whether there are edits to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Returns whether there are undone edits to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Returns whether edits are currently grouped.
+    pub fn in_group(&self) -> bool {
+        self.group_depth > 0
+    }
+
+    /// Forgets all recorded edits.
+    pub fn clear(&mut self) {
+        assert!(!self.in_group(), "cannot clear the undo stack inside an edit group");
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Records the inverse of an edit. Discards the edits that were undone.
+    pub(crate) fn record(&mut self, layer: usize, inverse: EditAction) {
+        self.redo.clear();
+        if self.in_group() {
+            self.group.push((layer, inverse));
+        } else {
+            self.undo.push_back(vec![(layer, inverse)]);
+        }
+    }
+
+    pub(crate) fn begin_group(&mut self) {
+        self.group_depth += 1;
+    }
+
+    pub(crate) fn end_group(&mut self) {
+        assert!(self.in_group(), "unbalanced call to end_edit_group");
+        self.group_depth -= 1;
+        if self.group_depth == 0 && !self.group.is_empty() {
+            let group = std::mem::take(&mut self.group);
+            self.undo.push_back(group);
+        }
+    }
+
+    /// Removes the last group of recorded edits. The actions are returned in the order in which they must be
+    /// applied to revert the edits.
+    pub(crate) fn pop_undo(&mut self) -> Option<EditGroup> {
+        assert!(!self.in_group(), "cannot undo inside an edit group");
+        let mut group = self.undo.pop_back()?;
+        group.reverse();
+        Some(group)
+    }
+
+    /// Removes the last group of undone edits. The actions are returned in the order in which they must be
+    /// applied to redo the edits.
+    pub(crate) fn pop_redo(&mut self) -> Option<EditGroup> {
+        assert!(!self.in_group(), "cannot redo inside an edit group");
+        let mut group = self.redo.pop_back()?;
+        group.reverse();
+        Some(group)
+    }
+
+    /// Records the inverse actions of an undone group of edits.
+    pub(crate) fn push_redo(&mut self, group: EditGroup) {
+        self.redo.push_back(group);
+    }
+
+    /// Records the inverse actions of a redone group of edits, without discarding the other undone edits.
+    pub(crate) fn push_undo(&mut self, group: EditGroup) {
+        self.undo.push_back(group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{metadata, Document, Path, TypeDesc, Value};
+    use std::convert::TryFrom;
+
+    const NETWORK: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read">
+        <string id="input:file">a.png</string>
+    </node>
+</document>"#;
+
+    fn file(document: &Document) -> Option<String> {
+        let path = Path::parse("/a").unwrap().join_attribute("input:file");
+        String::try_from(document.attribute(&path)?.value.clone()?).ok()
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut document = Document::from_xml(NETWORK).unwrap();
+        let a = Path::parse("/a").unwrap();
+        let attribute = a.join_attribute("input:file");
+
+        document.set_attribute_value(&attribute, Value::from("b.png")).unwrap();
+        let node = document.create_node(&Path::root(), "blur", "blur").unwrap();
+        assert!(document.undo_stack().can_undo());
+
+        let revision = document.revision;
+        assert!(document.undo());
+        assert!(document.node(&node).is_none());
+        assert!(document.revision > revision);
+        assert!(document.undo());
+        assert_eq!(file(&document), Some("a.png".to_string()));
+        assert!(!document.undo());
+
+        assert!(document.redo());
+        assert_eq!(file(&document), Some("b.png".to_string()));
+        assert!(document.redo());
+        assert!(document.node(&node).is_some());
+        assert!(!document.redo());
+
+        // a new edit discards the undone edits
+        document.undo();
+        document.remove_node(&a).unwrap();
+        assert!(!document.undo_stack().can_redo());
+        document.undo();
+        assert_eq!(file(&document), Some("b.png".to_string()));
+    }
+
+    #[test]
+    fn test_edit_groups() {
+        let mut document = Document::from_xml(NETWORK).unwrap();
+        let a = Path::parse("/a").unwrap();
+
+        document.begin_edit_group();
+        let node = document.create_node(&Path::root(), "blur", "blur").unwrap();
+        document
+            .set_node_metadata(&node, metadata::OPERATOR, "blur".into())
+            .unwrap();
+        document
+            .connect(&node.join_attribute("input:image"), TypeDesc::FLOAT, &a)
+            .unwrap();
+        document.end_edit_group();
+
+        assert!(document.undo());
+        assert!(document.node(&node).is_none());
+        assert!(!document.undo_stack().can_undo());
+        assert!(document.redo());
+        let input = document.attribute(&node.join_attribute("input:image")).unwrap();
+        assert_eq!(input.connection, Some(a));
+    }
+}
@@ -0,0 +1,274 @@
+//! Validation of documents against the schemas of their operators
+use crate::model::{metadata, schema::SchemaRegistry, Atom, Document, Node, Path, Reference, TypeDesc};
+use std::fmt;
+
+/// A problem found in a document.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiagnosticKind {
+    /// The operator of the node isn't registered.
+    UnknownOperator(Atom),
+    /// A mandatory attribute of the node has neither a value nor a connection.
+    MissingInput,
+    /// The type of the source of a connection doesn't match the type of the input. Nodes that don't produce
+    /// a value are of type `TypeDesc::Void`.
+    IncompatibleConnection { expected: TypeDesc, found: TypeDesc },
+    /// A connection or a reference points to an object that doesn't exist.
+    DanglingPath(Path),
+    /// A reference can't be parsed.
+    InvalidReference(Atom),
+}
+
+/// A problem found in a document, with the path of the offending node or attribute.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub path: Path,
+    pub kind: DiagnosticKind,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.path.to_string())?;
+        match self.kind {
+            DiagnosticKind::UnknownOperator(ref operator) => write!(f, "unknown operator `{}`", operator),
+            DiagnosticKind::MissingInput => write!(f, "missing input"),
+            DiagnosticKind::IncompatibleConnection {
+                ref expected,
+                ref found,
+            } => write!(f, "incompatible connection: expected {:?}, found {:?}", expected, found),
+            DiagnosticKind::DanglingPath(ref path) => write!(f, "`{}` doesn't exist", path.to_string()),
+            DiagnosticKind::InvalidReference(ref reference) => write!(f, "invalid reference `{}`", reference),
+        }
+    }
+}
+
+impl Document {
+    /// Checks the nodes of the document against the schemas of their operators.
+    ///
+    /// Returns the problems found, in document order. Nodes without operators (e.g. groups) aren't checked against
+    /// a schema, but their connections and references are.
+    pub fn validate(&self, schemas: &SchemaRegistry) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        self.validate_node(&self.root, schemas, &mut diagnostics);
+        diagnostics
+    }
+
+    fn validate_node(&self, node: &Node, schemas: &SchemaRegistry, diagnostics: &mut Vec<Diagnostic>) {
+        let schema = match node.operator() {
+            Some(operator) => match schemas.operator(&operator) {
+                Some(schema) => Some(schema),
+                None => {
+                    diagnostics.push(Diagnostic {
+                        path: node.path.clone(),
+                        kind: DiagnosticKind::UnknownOperator(operator),
+                    });
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if let Some(reference) = node.metadata(metadata::REFERENCE) {
+            match Reference::parse(&reference) {
+                // references to other documents are resolved when the documents are added
+                Some(Reference { document: None, path }) if self.node(&path).is_none() => {
+                    diagnostics.push(Diagnostic {
+                        path: node.path.clone(),
+                        kind: DiagnosticKind::DanglingPath(path),
+                    });
+                }
+                Some(_) => {}
+                None => diagnostics.push(Diagnostic {
+                    path: node.path.clone(),
+                    kind: DiagnosticKind::InvalidReference(reference),
+                }),
+            }
+        }
+
+        if let Some(schema) = schema {
+            for attribute in schema.attributes.iter().filter(|a| a.mandatory) {
+                let set = node.attribute(&attribute.name).map_or(false, |a| {
+                    a.connection.is_some() || a.value.as_ref().map_or(false, |v| !v.is_null())
+                });
+                if !set {
+                    diagnostics.push(Diagnostic {
+                        path: node.path.join_attribute(attribute.name.clone()),
+                        kind: DiagnosticKind::MissingInput,
+                    });
+                }
+            }
+        }
+
+        for (name, attribute) in node.attributes.iter() {
+            let source = match attribute.connection {
+                Some(ref source) => source,
+                None => continue,
+            };
+            let found = if source.is_attribute() {
+                self.attribute(source)
+                    .map(|source_attribute| source_attribute.ty.clone())
+            } else {
+                // connections to a node are connections to the value produced by its operator: only check them
+                // if the operator is known
+                self.node(source).map(|source_node| {
+                    source_node
+                        .operator()
+                        .and_then(|operator| schemas.operator(&operator))
+                        .map_or(TypeDesc::Unknown, |schema| {
+                            schema.output.clone().unwrap_or(TypeDesc::Void)
+                        })
+                })
+            };
+            let found = match found {
+                Some(found) => found,
+                None => {
+                    diagnostics.push(Diagnostic {
+                        path: attribute.path.clone(),
+                        kind: DiagnosticKind::DanglingPath(source.clone()),
+                    });
+                    continue;
+                }
+            };
+            let expected = schema
+                .and_then(|schema| schema.attribute(name))
+                .map_or(&attribute.ty, |a| &a.ty);
+            if *expected != TypeDesc::Unknown && found != TypeDesc::Unknown && *expected != found {
+                diagnostics.push(Diagnostic {
+                    path: attribute.path.clone(),
+                    kind: DiagnosticKind::IncompatibleConnection {
+                        expected: expected.clone(),
+                        found,
+                    },
+                });
+            }
+        }
+
+        for child in node.children.values() {
+            self.validate_node(child, schemas, diagnostics);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiagnosticKind;
+    use crate::{
+        eval::operator_schemas,
+        model::{
+            schema::{OperatorSchema, SchemaRegistry},
+            Document, Path, TypeDesc,
+        },
+    };
+
+    #[test]
+    fn test_validate() {
+        let document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read">
+        <texture2D id="output:image"/>
+    </node>
+    <node id="b" op="blur">
+        <texture2D id="input:image" connect="/a.output:image"/>
+        <texture2D id="input:mask" connect="/c.output:image"/>
+    </node>
+    <node id="c" op="noise"/>
+</document>"#,
+        )
+        .unwrap();
+
+        let mut schemas = SchemaRegistry::new();
+        schemas.register(OperatorSchema::new("read").mandatory("input:file", TypeDesc::String));
+        schemas.register(OperatorSchema::new("blur").mandatory("input:image", TypeDesc::FLOAT));
+
+        let a = Path::parse("/a").unwrap();
+        let b = Path::parse("/b").unwrap();
+        let c = Path::parse("/c").unwrap();
+        let kinds: Vec<_> = document
+            .validate(&schemas)
+            .into_iter()
+            .map(|d| (d.path, d.kind))
+            .collect();
+        assert_eq!(kinds.len(), 4);
+        assert!(kinds.contains(&(a.join_attribute("input:file"), DiagnosticKind::MissingInput)));
+        assert!(kinds.iter().any(|(path, kind)| path == &b.join_attribute("input:image")
+            && matches!(kind, DiagnosticKind::IncompatibleConnection { .. })));
+        assert!(kinds.contains(&(
+            b.join_attribute("input:mask"),
+            DiagnosticKind::DanglingPath(c.join_attribute("output:image"))
+        )));
+        assert!(kinds.contains(&(c, DiagnosticKind::UnknownOperator("noise".into()))));
+    }
+
+    #[test]
+    fn test_validate_node_connections() {
+        let document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read">
+        <string id="input:file">a.png</string>
+    </node>
+    <node id="b" op="blur">
+        <texture2D id="input:image" connect="/a"/>
+    </node>
+    <node id="c" op="blur">
+        <texture2D id="input:image" connect="/d"/>
+    </node>
+    <node id="d" op="constant"/>
+    <node id="e" op="blur">
+        <texture2D id="input:image" connect="/f"/>
+    </node>
+</document>"#,
+        )
+        .unwrap();
+
+        let mut schemas = operator_schemas();
+        let image = document
+            .attribute(&Path::parse("/b").unwrap().join_attribute("input:image"))
+            .unwrap()
+            .ty
+            .clone();
+        schemas.register(OperatorSchema::new("blur").mandatory("input:image", image.clone()));
+        schemas.register(OperatorSchema::new("constant"));
+
+        let kinds: Vec<_> = document
+            .validate(&schemas)
+            .into_iter()
+            .map(|d| (d.path, d.kind))
+            .collect();
+        // the `read` operator produces images
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    Path::parse("/c").unwrap().join_attribute("input:image"),
+                    DiagnosticKind::IncompatibleConnection {
+                        expected: image,
+                        found: TypeDesc::Void
+                    }
+                ),
+                (
+                    Path::parse("/e").unwrap().join_attribute("input:image"),
+                    DiagnosticKind::DanglingPath(Path::parse("/f").unwrap())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_builtin_operators() {
+        let document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read"/>
+</document>"#,
+        )
+        .unwrap();
+        let diagnostics = document.validate(&operator_schemas());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].path,
+            Path::parse("/a").unwrap().join_attribute("input:file")
+        );
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::MissingInput);
+    }
+}
@@ -99,6 +99,8 @@ pub enum Value {
     Map(Map),
     Array(Array),
     Custom(Arc<dyn ValueType>),
+    /// Scalar value varying over time.
+    AnimCurve(Arc<AnimCurve>),
     Null,
 }
 
@@ -157,6 +159,9 @@ impl fmt::Debug for Value {
                 write!(f, "(custom value)")
                 //write!(f, "{:?}", v)
             }
+            Value::AnimCurve(v) => {
+                write!(f, "animcurve({} keys)", v.keyframes().len())
+            }
             Value::Null => {
                 write!(f, "(null)")
             }
@@ -193,6 +198,7 @@ impl Value {
                 todo!()
             }
             Value::Custom(v) => v.type_desc().unwrap_or(&TypeDesc::Unknown),
+            Value::AnimCurve(curve) => curve.ty(),
             Value::Null => &TypeDesc::Void,
             Value::IVec2(_) => &TypeDesc::IVEC2,
             Value::IVec4(_) => &TypeDesc::IVEC3,
@@ -273,6 +279,154 @@ impl Value {
     pub fn is_null(&self) -> bool {
         matches!(self, Value::Null)
     }
+
+    /// Returns whether the value varies over time.
+    pub fn is_animated(&self) -> bool {
+        matches!(self, Value::AnimCurve(_))
+    }
+
+    /// Returns the value at the specified time.
+    ///
+    /// Animation curves are evaluated to a `Value::Float` or a `Value::Double` depending on the type of the curve,
+    /// other values are returned as is.
+    pub fn sample(&self, time: f64) -> Value {
+        match self {
+            Value::AnimCurve(curve) if *curve.ty() == TypeDesc::FLOAT => Value::Float(curve.eval(time) as f32),
+            Value::AnimCurve(curve) => Value::Double(curve.eval(time)),
+            other => other.clone(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Animation curves
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Interpolation of an animation curve between a keyframe and the next.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Interpolation {
+    /// The value of the keyframe is held until the next keyframe.
+    Hold,
+    /// Linear interpolation between the values of the keyframes.
+    Linear,
+    /// Cubic bezier curve, whose control points are placed along the slopes of the keyframes.
+    Bezier,
+}
+
+/// A keyframe of an animation curve.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f64,
+    pub value: f64,
+    /// Interpolation between this keyframe and the next.
+    pub interpolation: Interpolation,
+    /// Slope of the curve (change of value per unit of time) when arriving at the keyframe.
+    pub in_slope: f64,
+    /// Slope of the curve when leaving the keyframe.
+    pub out_slope: f64,
+}
+
+impl Keyframe {
+    /// Creates a keyframe with flat slopes.
+    pub fn new(time: f64, value: f64, interpolation: Interpolation) -> Keyframe {
+        Keyframe {
+            time,
+            value,
+            interpolation,
+            in_slope: 0.0,
+            out_slope: 0.0,
+        }
+    }
+}
+
+/// A scalar value defined by keyframes.
+///
+/// The value is constant before the first keyframe and after the last one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimCurve {
+    /// Type of the values of the curve, either `TypeDesc::FLOAT` or `TypeDesc::DOUBLE`.
+    ty: TypeDesc,
+    /// Sorted by time.
+    keyframes: Vec<Keyframe>,
+}
+
+impl Default for AnimCurve {
+    fn default() -> Self {
+        AnimCurve::with_type(TypeDesc::DOUBLE)
+    }
+}
+
+impl AnimCurve {
+    /// Creates a curve of doubles without keyframes.
+    pub fn new() -> AnimCurve {
+        AnimCurve::default()
+    }
+
+    /// Creates a curve without keyframes, evaluating to values of the specified type (`TypeDesc::FLOAT` or
+    /// `TypeDesc::DOUBLE`).
+    pub fn with_type(ty: TypeDesc) -> AnimCurve {
+        AnimCurve { ty, keyframes: vec![] }
+    }
+
+    /// Returns the type of the values of the curve.
+    pub fn ty(&self) -> &TypeDesc {
+        &self.ty
+    }
+
+    /// Returns the keyframes of the curve, sorted by time.
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Inserts a keyframe, replacing the one at the same time if there's one.
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        let index = self.keyframes.partition_point(|k| k.time < keyframe.time);
+        match self.keyframes.get_mut(index) {
+            Some(k) if k.time == keyframe.time => *k = keyframe,
+            _ => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    /// Removes the keyframe at the specified time.
+    pub fn remove(&mut self, time: f64) -> Option<Keyframe> {
+        let index = self.keyframes.iter().position(|k| k.time == time)?;
+        Some(self.keyframes.remove(index))
+    }
+
+    /// Evaluates the curve at the specified time.
+    ///
+    /// Returns zero if the curve has no keyframes.
+    pub fn eval(&self, time: f64) -> f64 {
+        let keyframes = &self.keyframes;
+        let next = keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return keyframes.first().map_or(0.0, |k| k.value);
+        }
+        if next == keyframes.len() {
+            return keyframes[next - 1].value;
+        }
+
+        let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+        let duration = b.time - a.time;
+        let t = (time - a.time) / duration;
+        match a.interpolation {
+            Interpolation::Hold => a.value,
+            Interpolation::Linear => a.value + (b.value - a.value) * t,
+            Interpolation::Bezier => {
+                // control points at one third of the segment
+                let p1 = a.value + a.out_slope * duration / 3.0;
+                let p2 = b.value - b.in_slope * duration / 3.0;
+                let u = 1.0 - t;
+                u * u * u * a.value + 3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t * b.value
+            }
+        }
+    }
+}
+
+impl From<AnimCurve> for Value {
+    fn from(v: AnimCurve) -> Self {
+        Value::AnimCurve(Arc::new(v))
+    }
 }
 
 impl Default for Value {
@@ -299,6 +453,12 @@ impl From<bool> for Value {
     }
 }
 
+impl From<Vec2> for Value {
+    fn from(v: Vec2) -> Self {
+        Value::Vec2(v)
+    }
+}
+
 impl From<String> for Value {
     fn from(v: String) -> Self {
         Value::String(v.into())
@@ -339,6 +499,16 @@ impl TryFrom<Value> for f64 {
     }
 }
 
+impl TryFrom<Value> for Vec2 {
+    type Error = TryFromValueError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Vec2(v) => Ok(v),
+            _ => Err(TryFromValueError),
+        }
+    }
+}
+
 impl TryFrom<Value> for Atom {
     type Error = TryFromValueError;
     fn try_from(value: Value) -> Result<Self, Self::Error> {
@@ -360,3 +530,38 @@ impl TryFrom<Value> for String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AnimCurve, Interpolation, Keyframe};
+
+    #[test]
+    fn test_anim_curve() {
+        let mut curve = AnimCurve::new();
+        assert_eq!(curve.eval(1.0), 0.0);
+
+        curve.insert(Keyframe::new(2.0, 4.0, Interpolation::Hold));
+        curve.insert(Keyframe::new(0.0, 0.0, Interpolation::Linear));
+        curve.insert(Keyframe::new(1.0, 2.0, Interpolation::Bezier));
+        curve.insert(Keyframe::new(3.0, 8.0, Interpolation::Linear));
+        assert_eq!(curve.keyframes().len(), 4);
+
+        // constant outside of the keyframes
+        assert_eq!(curve.eval(-1.0), 0.0);
+        assert_eq!(curve.eval(4.0), 8.0);
+
+        assert_eq!(curve.eval(0.5), 1.0);
+        // flat slopes: symmetric around the middle of the segment
+        assert_eq!(curve.eval(1.5), 3.0);
+        assert!(curve.eval(1.25) < 2.5);
+        assert_eq!(curve.eval(2.5), 4.0);
+        assert_eq!(curve.eval(3.0), 8.0);
+
+        // replaces the keyframe at the same time
+        curve.insert(Keyframe::new(3.0, 6.0, Interpolation::Linear));
+        assert_eq!(curve.keyframes().len(), 4);
+        assert_eq!(curve.eval(3.0), 6.0);
+        assert!(curve.remove(3.0).is_some());
+        assert_eq!(curve.eval(3.0), 4.0);
+    }
+}
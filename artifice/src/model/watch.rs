@@ -0,0 +1,203 @@
+//! Detection of external modifications to the files of a document
+use crate::model::{document::resolve_file, Atom, Document, Node, Path};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    path::{Path as FilePath, PathBuf},
+    time::SystemTime,
+};
+
+/// Name of the attribute holding the file read by `read` nodes.
+const FILE_ATTRIBUTE: &str = "input:file";
+
+/// A modification of a watched file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FileEvent {
+    /// The document file was modified, created or removed. The UI should prompt to reload the document.
+    DocumentChanged { file: PathBuf },
+    /// A file read by nodes of the document was modified, created or removed. The nodes should be re-evaluated
+    /// (see `Evaluation::reload_file`).
+    InputChanged { file: PathBuf, nodes: Vec<Path> },
+}
+
+/// Returns the modification time of a file, or `None` if it doesn't exist.
+fn modified_time(file: &FilePath) -> Option<SystemTime> {
+    std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Collects the files read by the nodes of a tree, with the nodes reading them.
+///
+/// Relative paths are resolved against the directory of `document_file`.
+fn collect_input_files(node: &Node, document_file: Option<&FilePath>, files: &mut BTreeMap<PathBuf, Vec<Path>>) {
+    if node.operator() == Some(Atom::from("read")) {
+        let file = node
+            .attribute(&FILE_ATTRIBUTE.into())
+            .and_then(|attribute| attribute.value.clone())
+            .and_then(|value| String::try_from(value).ok());
+        if let Some(file) = file {
+            files
+                .entry(resolve_file(document_file, &file))
+                .or_default()
+                .push(node.path.clone());
+        }
+    }
+    for child in node.children.values() {
+        collect_input_files(child, document_file, files);
+    }
+}
+
+/// A watched file.
+#[derive(Clone, Debug)]
+struct WatchedFile {
+    modified: Option<SystemTime>,
+    /// Nodes reading the file
+    nodes: Vec<Path>,
+}
+
+/// Watches the file of a document, and the files read by its nodes, for modifications.
+///
+/// Files are polled: call `poll` periodically (e.g. from a background thread) to retrieve the modifications
+/// made since the last call.
+#[derive(Clone, Debug)]
+pub struct FileWatcher {
+    /// Path of the document file, if it was loaded from a file
+    document_file: Option<PathBuf>,
+    document_modified: Option<SystemTime>,
+    inputs: BTreeMap<PathBuf, WatchedFile>,
+}
+
+impl FileWatcher {
+    /// Creates a watcher for a document and the file it was loaded from (`Document::file`).
+    pub fn new(document: &Document) -> FileWatcher {
+        let document_file = document.file();
+        let mut watcher = FileWatcher {
+            document_file: document_file.map(FilePath::to_path_buf),
+            document_modified: document_file.and_then(modified_time),
+            inputs: BTreeMap::new(),
+        };
+        watcher.update_inputs(document);
+        watcher
+    }
+
+    /// Returns the path of the watched document file.
+    pub fn document_file(&self) -> Option<&FilePath> {
+        self.document_file.as_deref()
+    }
+
+    /// Returns the watched input files.
+    pub fn input_files(&self) -> impl Iterator<Item = &FilePath> {
+        self.inputs.keys().map(PathBuf::as_path)
+    }
+
+    /// Updates the set of watched input files after the document has changed.
+    ///
+    /// Files that were already watched keep their last known modification time, so that modifications made
+    /// before the update are still reported by the next call to `poll`.
+    pub fn update_inputs(&mut self, document: &Document) {
+        let mut files = BTreeMap::new();
+        collect_input_files(document.root(), self.document_file.as_deref(), &mut files);
+        let mut inputs = BTreeMap::new();
+        for (file, nodes) in files {
+            let modified = match self.inputs.get(&file) {
+                Some(watched) => watched.modified,
+                None => modified_time(&file),
+            };
+            inputs.insert(file, WatchedFile { modified, nodes });
+        }
+        self.inputs = inputs;
+    }
+
+    /// Marks the document file as up-to-date, e.g. after saving or reloading the document.
+    pub fn reset_document_file(&mut self) {
+        self.document_modified = self.document_file.as_deref().and_then(modified_time);
+    }
+
+    /// Returns the modifications of the watched files since the last call.
+    pub fn poll(&mut self) -> Vec<FileEvent> {
+        let mut events = Vec::new();
+        if let Some(ref file) = self.document_file {
+            let modified = modified_time(file);
+            if modified != self.document_modified {
+                self.document_modified = modified;
+                events.push(FileEvent::DocumentChanged { file: file.clone() });
+            }
+        }
+        for (file, watched) in self.inputs.iter_mut() {
+            let modified = modified_time(file);
+            if modified != watched.modified {
+                watched.modified = modified;
+                events.push(FileEvent::InputChanged {
+                    file: file.clone(),
+                    nodes: watched.nodes.clone(),
+                });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileEvent, FileWatcher};
+    use crate::model::{Document, Path, Value};
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn test_file_watcher() {
+        let dir = std::env::temp_dir().join(format!("artifice-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let document_file = dir.join("network.xml");
+        let image_file = dir.join("image.png");
+        fs::write(&document_file, "").unwrap();
+        fs::write(&image_file, "").unwrap();
+
+        let mut document = Document::new();
+        let node = document.create_node(&Path::root(), "read", "read").unwrap();
+        document
+            .set_attribute_value(&node.join_attribute("input:file"), Value::from("image.png"))
+            .unwrap();
+
+        // relative to the directory of the document
+        document.file = Some(document_file.clone());
+        let mut watcher = FileWatcher::new(&document);
+        assert_eq!(watcher.input_files().collect::<Vec<_>>(), vec![image_file.as_path()]);
+        assert!(watcher.poll().is_empty());
+
+        // explicitly change the modification time, the resolution of the filesystem clock may be coarse
+        let touch = |file: &std::path::Path| {
+            fs::File::options()
+                .write(true)
+                .open(file)
+                .unwrap()
+                .set_modified(SystemTime::now() + Duration::from_secs(10))
+                .unwrap();
+        };
+
+        touch(&image_file);
+        assert_eq!(
+            watcher.poll(),
+            vec![FileEvent::InputChanged {
+                file: image_file.clone(),
+                nodes: vec![node.clone()]
+            }]
+        );
+        assert!(watcher.poll().is_empty());
+
+        fs::remove_file(&document_file).unwrap();
+        assert_eq!(
+            watcher.poll(),
+            vec![FileEvent::DocumentChanged {
+                file: document_file.clone()
+            }]
+        );
+
+        document.remove_node(&node).unwrap();
+        watcher.update_inputs(&document);
+        assert_eq!(watcher.input_files().count(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Serialization of documents to XML, in the format read by the parser
+use crate::model::{
+    metadata, typedesc::ImageDimension, AnimCurve, Document, Interpolation, Node, Param, TypeDesc, Value,
+    FORMAT_VERSION,
+};
+use std::fmt::Write;
+
+impl Document {
+    /// Serializes the document to XML, in the format read by `Document::from_xml`.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(out, r#"<document version="{}">"#, FORMAT_VERSION).unwrap();
+        let settings = self.settings();
+        writeln!(
+            out,
+            r#"    <settings fps="{}" width="{}" height="{}" pixelAspect="{}" colorspace="{}"/>"#,
+            settings.fps,
+            settings.resolution.width,
+            settings.resolution.height,
+            settings.pixel_aspect,
+            escape(&settings.colorspace)
+        )
+        .unwrap();
+        for node in self.root().children.values() {
+            write_node(&mut out, node, 1);
+        }
+        writeln!(out, "</document>").unwrap();
+        out
+    }
+}
+
+/// Escapes the special characters of XML text and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a node, its metadata, attributes and children, indented by `indent` levels.
+pub(crate) fn write_node(out: &mut String, node: &Node, indent: usize) {
+    let pad = "    ".repeat(indent);
+    write!(out, r#"{}<node id="{}""#, pad, escape(&node.name())).unwrap();
+    if let Some(operator) = node.operator() {
+        write!(out, r#" op="{}""#, escape(&operator)).unwrap();
+    }
+    if let Some(reference) = node.metadata(metadata::REFERENCE) {
+        write!(out, r#" reference="{}""#, escape(&reference)).unwrap();
+    }
+    writeln!(out, ">").unwrap();
+
+    for (name, value) in node.metadata.iter() {
+        if &**name == metadata::OPERATOR.name || &**name == metadata::REFERENCE.name {
+            continue;
+        }
+        let text = match value {
+            Value::Token(v) => escape(v),
+            Value::String(v) => escape(v),
+            _ => {
+                warn!("{}: metadata `{}` can't be saved", node.path.to_string(), name);
+                continue;
+            }
+        };
+        writeln!(out, r#"{}    <metadata id="{}">{}</metadata>"#, pad, escape(name), text).unwrap();
+    }
+    for attribute in node.attributes.values() {
+        write_attribute(out, attribute, indent + 1);
+    }
+    for child in node.children.values() {
+        write_node(out, child, indent + 1);
+    }
+    writeln!(out, "{}</node>", pad).unwrap();
+}
+
+fn write_anim_curve(out: &mut String, curve: &AnimCurve, pad: &str) {
+    for key in curve.keyframes() {
+        let interpolation = match key.interpolation {
+            Interpolation::Hold => "hold",
+            Interpolation::Linear => "linear",
+            Interpolation::Bezier => "bezier",
+        };
+        writeln!(
+            out,
+            r#"{}    <key time="{}" value="{}" interpolation="{}" inSlope="{}" outSlope="{}"/>"#,
+            pad, key.time, key.value, interpolation, key.in_slope, key.out_slope
+        )
+        .unwrap();
+    }
+}
+
+fn write_attribute(out: &mut String, attribute: &Param, indent: usize) {
+    let pad = "    ".repeat(indent);
+    let name = escape(&attribute.name());
+    let value = attribute.value.as_ref().unwrap_or(&Value::Null);
+    let (tag, text) = match (value, &attribute.ty) {
+        (Value::AnimCurve(curve), _) => {
+            let tag = if *curve.ty() == TypeDesc::FLOAT {
+                "float"
+            } else {
+                "double"
+            };
+            writeln!(out, r#"{}<{} id="{}">"#, pad, tag, name).unwrap();
+            write_anim_curve(out, curve, &pad);
+            writeln!(out, "{}</{}>", pad, tag).unwrap();
+            return;
+        }
+        (Value::Float(v), _) => ("float", v.to_string()),
+        (Value::Double(v), _) => ("double", v.to_string()),
+        (Value::Int(v), _) => ("int", v.to_string()),
+        (Value::UnsignedInt(v), _) => ("uint", v.to_string()),
+        (Value::Bool(v), _) => ("bool", v.to_string()),
+        (Value::Vec2(v), _) => ("vec2", format!("{}, {}", v.x, v.y)),
+        (Value::Vec3(v), _) => ("vec3", format!("{}, {}, {}", v.x, v.y, v.z)),
+        (Value::Vec4(v), _) => ("vec4", format!("{}, {}, {}, {}", v.x, v.y, v.z, v.w)),
+        (Value::String(v), _) => ("string", escape(v)),
+        (Value::Token(v), _) => ("string", escape(v)),
+        (Value::Null, TypeDesc::SampledImage(image)) => {
+            let tag = match image.dim {
+                ImageDimension::Dim1D => "texture1D",
+                ImageDimension::Dim3D => "texture3D",
+                _ => "texture2D",
+            };
+            writeln!(out, r#"{}<{} id="{}"/>"#, pad, tag, name).unwrap();
+            return;
+        }
+        _ => {
+            warn!("{}: the value can't be saved", attribute.path.to_string());
+            return;
+        }
+    };
+    writeln!(out, r#"{}<{} id="{}">{}</{}>"#, pad, tag, name, text, tag).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Document, Path, TypeDesc, Value};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_write_document() {
+        let document = Document::from_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<document>
+    <node id="a" op="read">
+        <string id="input:file">a &amp; b.png</string>
+        <texture2D id="output:image"/>
+    </node>
+    <node id="blur" op="blur">
+        <float id="param:radius">
+            <key time="0" value="1"/>
+            <key time="1" value="3" interpolation="bezier" inSlope="0" outSlope="1"/>
+            <key time="2" value="2" interpolation="hold"/>
+        </float>
+        <double id="param:scale">0.5</double>
+        <texture2D id="input:image" connect="/a.output:image"/>
+    </node>
+</document>"#,
+        )
+        .unwrap();
+
+        let reloaded = Document::from_xml(&document.to_xml()).unwrap();
+        let a = Path::parse("/a").unwrap();
+        let blur = Path::parse("/blur").unwrap();
+        assert_eq!(reloaded.node(&blur).unwrap().operator(), Some("blur".into()));
+        let file = reloaded.attribute(&a.join_attribute("input:file")).unwrap();
+        assert_eq!(
+            file.value.clone().map(String::try_from).unwrap().ok(),
+            Some("a & b.png".to_string())
+        );
+        let input = reloaded.attribute(&blur.join_attribute("input:image")).unwrap();
+        assert_eq!(input.connection, Some(a.join_attribute("output:image")));
+
+        // the animated attribute keeps its keyframes and its type
+        let radius = blur.join_attribute("param:radius");
+        let original = document.attribute(&radius).unwrap().value.clone().unwrap();
+        let value = reloaded.attribute(&radius).unwrap().value.clone().unwrap();
+        assert_eq!(reloaded.attribute(&radius).unwrap().ty, TypeDesc::FLOAT);
+        assert_eq!(value.type_desc(), &TypeDesc::FLOAT);
+        match (&original, &value) {
+            (Value::AnimCurve(original), Value::AnimCurve(curve)) => assert_eq!(original, curve),
+            _ => panic!("expected an animation curve"),
+        }
+        assert!(matches!(value.sample(0.5), Value::Float(_)));
+
+        let scale = reloaded.attribute(&blur.join_attribute("param:scale")).unwrap();
+        assert!(matches!(scale.value, Some(Value::Double(v)) if v == 0.5));
+    }
+}
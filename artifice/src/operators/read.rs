@@ -44,6 +44,16 @@ type ImageHeaderLoadResult = Result<ImageHeaders, EvalError>;
 static IMAGE_READ_TASKS: Lazy<TaskMap<PathBuf, ImageDataLoadResult>> = Lazy::new(|| TaskMap::new());
 static IMAGE_READ_HEADER_TASKS: Lazy<TaskMap<PathBuf, ImageHeaderLoadResult>> = Lazy::new(|| TaskMap::new());
 
+/// Forgets the loaded contents of an image file, so that it's read again the next time it's needed.
+///
+/// Returns whether the file was loaded.
+pub async fn forget_image_file(path: &Path) -> bool {
+    let path = path.to_path_buf();
+    let data = IMAGE_READ_TASKS.remove(&path).await;
+    let headers = IMAGE_READ_HEADER_TASKS.remove(&path).await;
+    data || headers
+}
+
 pub struct OpRead;
 
 impl OpRead {
@@ -112,7 +122,7 @@ impl OpImaging for OpRead {
 
     async fn compute_region_of_definition(&self, ctx: &OpImagingCtx) -> Result<RegionOfDefinition, EvalError> {
         let file_path: String = ctx.eval_attribute("input:file", ctx.time).await?;
-        let header = self.get_image_headers(file_path).await?;
+        let header = self.get_image_headers(ctx.resolve_file(&file_path)).await?;
 
         // for images without physical dimensions, assume that their physical size happens to be
         // equal to their pixel size (equivalently, assume a DPI of 96).
@@ -134,6 +144,7 @@ impl OpImaging for OpRead {
 
         // open the image file
         let file_path: String = ctx.eval_attribute("input:file", ctx.time).await?;
+        let file_path = ctx.resolve_file(&file_path);
 
         // TODO: open may fail due to an I/O error, but OpenImageIO doesn't return I/O errors, unfortunately
         let image_input = openimageio::ImageInput::open(&file_path).map_err(|e| EvalError::general(e.to_string()))?;
@@ -269,6 +280,8 @@ impl OpImaging for OpRead {
 inventory::submit! {
     ImagingOperatorRegistration {
         name: "read",
+        description: "Reads an image file",
+        category: "Input/Output",
         op: &OpRead
     }
 }
@@ -1,8 +1,11 @@
+use futures::{channel::mpsc, Stream};
 use std::{
     any::Any,
     cell::RefCell,
     marker::PhantomData,
+    pin::Pin,
     rc::{Rc, Weak},
+    task::{Context, Poll},
 };
 use typemap::TypeMap;
 
@@ -70,9 +73,128 @@ impl<L: ?Sized> TopicListeners<L> {
     }
 
     pub fn for_each(&self, mut f: impl FnMut(Rc<RefCell<L>>)) {
-        let listeners = self.inner.try_borrow().expect("reentrant event submission");
-        for l in listeners.iter() {
-            f(l.upgrade().expect("listener deleted"))
+        let mut has_deleted = false;
+        {
+            let listeners = self.inner.try_borrow().expect("reentrant event submission");
+            for l in listeners.iter() {
+                match l.upgrade() {
+                    Some(l) => f(l),
+                    None => has_deleted = true,
+                }
+            }
         }
+        // remove deleted listeners (e.g. dropped subscriptions), unless we're called from another listener
+        if has_deleted {
+            if let Ok(mut listeners) = self.inner.try_borrow_mut() {
+                listeners.retain(|l| l.strong_count() > 0);
+            }
+        }
+    }
+}
+
+/// Sends the events published on a topic to a `Subscription`, if they pass a filter.
+pub struct EventSender<E> {
+    sender: mpsc::UnboundedSender<E>,
+    filter: Box<dyn Fn(&E) -> bool>,
+}
+
+impl<E> EventSender<E> {
+    /// Creates a sender that forwards the events for which `filter` returns true, and the receiving end of
+    /// its channel.
+    pub fn new(filter: impl Fn(&E) -> bool + 'static) -> (EventSender<E>, mpsc::UnboundedReceiver<E>) {
+        let (sender, receiver) = mpsc::unbounded();
+        (
+            EventSender {
+                sender,
+                filter: Box::new(filter),
+            },
+            receiver,
+        )
+    }
+
+    pub fn send(&self, event: E) {
+        if (self.filter)(&event) {
+            // the subscription may have been dropped, in which case the listener will be removed shortly
+            let _ = self.sender.unbounded_send(event);
+        }
+    }
+}
+
+/// Stream of the events published on a topic, returned by the `subscribe` methods generated by `#[topic]`.
+///
+/// The subscription is removed from the topic when the stream is dropped.
+pub struct Subscription<E> {
+    receiver: mpsc::UnboundedReceiver<E>,
+    // keeps the listener that forwards events to the channel alive
+    _listener: Rc<dyn Any>,
+}
+
+impl<E> Subscription<E> {
+    /// Creates a subscription from the receiving end of the channel to which `listener` sends events.
+    pub fn new(receiver: mpsc::UnboundedReceiver<E>, listener: Rc<dyn Any>) -> Subscription<E> {
+        Subscription {
+            receiver,
+            _listener: listener,
+        }
+    }
+}
+
+impl<E> Stream for Subscription<E> {
+    type Item = E;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<E>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::MessageBus;
+    use artifice_macros::topic;
+    use futures::StreamExt;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[topic(RenameEvents)]
+    trait RenameListener {
+        fn renamed(&mut self, id: u32, name: &String);
+    }
+
+    #[derive(Default)]
+    struct RecordingListener {
+        renamed: Vec<(u32, String)>,
+    }
+
+    impl RenameListener for RecordingListener {
+        fn renamed(&mut self, id: u32, name: &String) {
+            self.renamed.push((id, name.clone()));
+        }
+    }
+
+    #[test]
+    fn test_topic() {
+        let bus = MessageBus::new();
+        let listener = Rc::new(RefCell::new(RecordingListener::default()));
+        RenameEvents::listen(&bus, listener.clone());
+
+        let publisher = RenameEvents::publisher(&bus);
+        let mut subscription = publisher.subscribe_filtered(|e| match e {
+            Rename::Renamed { id, .. } => *id != 0,
+        });
+        publisher.renamed(0, &"a".to_string());
+        publisher.renamed(1, &"b".to_string());
+
+        assert_eq!(
+            listener.borrow().renamed,
+            vec![(0, "a".to_string()), (1, "b".to_string())]
+        );
+        match futures::executor::block_on(subscription.next()) {
+            Some(Rename::Renamed { id, name }) => assert_eq!((id, name), (1, "b".to_string())),
+            None => panic!("no event received"),
+        }
+
+        // dropped listeners are removed from the topic
+        drop(subscription);
+        drop(listener);
+        publisher.renamed(2, &"c".to_string());
     }
 }
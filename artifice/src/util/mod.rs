@@ -1,3 +1,4 @@
-//mod messaging;
+mod messaging;
+pub mod model;
 
-//pub use messaging::{MessageBus, Topic, TopicListeners};
+pub use messaging::{EventSender, MessageBus, Subscription, Topic, TopicListeners};
@@ -0,0 +1,76 @@
+//! Addressable data and lenses, implemented by `#[derive(Data)]`
+use std::{collections::HashMap, convert::Infallible, fmt};
+
+/// Data whose parts can be focused on by lenses.
+pub trait Data: Clone + 'static {
+    /// Identifies a part of the data: for structs, a field and optionally the address of a part of that field.
+    ///
+    /// Types without addressable parts use `Infallible`.
+    type Address: Clone + Eq + fmt::Debug;
+}
+
+macro_rules! impl_leaf_data {
+    ($($t:ty),*) => {
+        $(impl Data for $t {
+            type Address = Infallible;
+        })*
+    };
+}
+
+impl_leaf_data!(bool, i8, i16, i32, i64, u8, u16, u32, u64, usize, isize, f32, f64, String);
+
+// Elements of collections are focused on by the `field_index` and `field_key` lenses generated for collection
+// fields, whose address is the address of the whole collection.
+
+impl<T: Data> Data for Vec<T> {
+    type Address = Infallible;
+}
+
+impl<K, V, S> Data for HashMap<K, V, S>
+where
+    K: Clone + 'static,
+    V: Data,
+    S: Clone + 'static,
+{
+    type Address = Infallible;
+}
+
+impl<K, V, S> Data for imbl::HashMap<K, V, S>
+where
+    K: Clone + 'static,
+    V: Data,
+    S: 'static,
+    imbl::HashMap<K, V, S>: Clone,
+{
+    type Address = Infallible;
+}
+
+/// Focuses on a part of type `U` of a value of type `T`.
+///
+/// Partial lenses, e.g. to the fields of a variant of an enum, may not focus on anything: `try_with` and
+/// `try_with_mut` then return `None`, and the other accessors panic.
+pub trait Lens<T: Data, U: Data> {
+    fn with<R, F: FnOnce(&U) -> R>(&self, data: &T, f: F) -> R;
+
+    fn with_mut<R, F: FnOnce(&mut U) -> R>(&self, data: &mut T, f: F) -> R;
+
+    fn try_with<R, F: FnOnce(&U) -> R>(&self, data: &T, f: F) -> Option<R>;
+
+    fn try_with_mut<R, F: FnOnce(&mut U) -> R>(&self, data: &mut T, f: F) -> Option<R>;
+
+    fn get<'a>(&self, data: &'a T) -> &'a U;
+
+    fn get_mut<'a>(&self, data: &'a mut T) -> &'a mut U;
+
+    /// Returns the address of the part focused by the lens.
+    fn address(&self) -> Option<T::Address>;
+
+    /// Returns the address of the part focused by `rhs` inside the part focused by this lens.
+    fn concat<K, V: Data>(&self, rhs: &K) -> Option<T::Address>
+    where
+        K: Lens<U, V>;
+
+    /// If `addr` designates the part focused by the lens or a part of it, returns the address relative to the
+    /// focused part (`None` for the whole part). Otherwise returns `None`.
+    fn unprefix(&self, addr: T::Address) -> Option<Option<U::Address>>;
+}
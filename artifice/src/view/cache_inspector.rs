@@ -0,0 +1,104 @@
+//! Debug panel listing the cached evaluation results.
+use crate::eval::{CacheEntry, CacheEntryKind, CacheLocation, Evaluation};
+use kyute::{
+    cache, composable,
+    widget::{Button, Grid, Text, WidgetExt},
+    UnitExt, Widget,
+};
+use std::time::Duration;
+
+fn format_byte_size(byte_size: Option<u64>) -> String {
+    match byte_size {
+        None => "?".to_string(),
+        Some(s) if s < 1 << 10 => format!("{} B", s),
+        Some(s) if s < 1 << 20 => format!("{:.1} KiB", s as f64 / (1u64 << 10) as f64),
+        Some(s) => format!("{:.1} MiB", s as f64 / (1u64 << 20) as f64),
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, (secs / 60) % 60)
+    }
+}
+
+fn kind_label(entry: &CacheEntry) -> String {
+    let kind = match entry.kind {
+        CacheEntryKind::Value => "value",
+        CacheEntryKind::RegionOfDefinition => "RoD",
+        CacheEntryKind::DeviceImage => "image",
+    };
+    let location = match entry.location {
+        CacheLocation::Host => "host",
+        CacheLocation::Device => "device",
+    };
+    format!("{} ({})", kind, location)
+}
+
+/// Lists the cached evaluation results, grouped by node, with buttons to evict individual entries or all
+/// entries of a node.
+#[composable]
+pub fn cache_inspector(evaluation: &Evaluation) -> impl Widget {
+    let mut entries = evaluation.cache_entries();
+    entries.sort_by_cached_key(|e| (format!("{:?}", e.node_path()), format!("{:?}", e.key.path)));
+
+    let total_size: u64 = entries.iter().filter_map(|e| e.byte_size).sum();
+
+    // columns: path, time, kind & location, size, age, action
+    let mut grid = Grid::with_template("{20} / 1fr 50 100 70 60 60 / 2 4");
+    grid.insert((
+        Text::new(format!("{} entries", entries.len())),
+        (),
+        (),
+        Text::new(format_byte_size(Some(total_size))),
+        (),
+        (),
+    ));
+
+    let mut current_node = None;
+    for entry in entries.iter() {
+        let node_path = entry.node_path();
+        if current_node.as_ref() != Some(&node_path) {
+            // node header
+            cache::scoped(format!("{:?}", node_path), || {
+                let clear = Button::new("Clear".to_string());
+                if clear.clicked() {
+                    let count = evaluation.clear_node_cache(&node_path);
+                    trace!("cleared {} cache entries of {:?}", count, node_path);
+                }
+                grid.insert((Text::new(format!("{:?}", node_path)), (), (), (), (), clear));
+            });
+            current_node = Some(node_path);
+        }
+
+        cache::scoped(
+            format!("{:?}/{:?}@{}", entry.kind, entry.key.path, entry.key.time),
+            || {
+                let evict = Button::new("Evict".to_string());
+                if evict.clicked() {
+                    evaluation.evict_cache_entry(entry.kind, &entry.key);
+                }
+                let age = if entry.pending {
+                    format!("{} (pending)", format_age(entry.age))
+                } else {
+                    format_age(entry.age)
+                };
+                grid.insert((
+                    Text::new(format!("  {:?}", entry.key.path)),
+                    Text::new(format!("{:.2}", entry.key.time)),
+                    Text::new(kind_label(entry)),
+                    Text::new(format_byte_size(entry.byte_size)),
+                    Text::new(age),
+                    evict,
+                ));
+            },
+        );
+    }
+
+    grid.fix_width(100.percent())
+}
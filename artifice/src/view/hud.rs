@@ -0,0 +1,120 @@
+//! Performance HUD.
+use crate::eval::{CacheStats, Evaluation};
+use kyute::{
+    cache, composable,
+    widget::{Grid, Text, WidgetExt},
+    UnitExt, Widget,
+};
+use std::{collections::VecDeque, time::Instant};
+
+/// Number of samples kept in the history of each metric.
+const HISTORY_LEN: usize = 60;
+
+/// Fixed-size history of the values of a metric.
+#[derive(Clone, Default)]
+struct History {
+    samples: VecDeque<f64>,
+}
+
+impl History {
+    fn push(&mut self, value: f64) {
+        if self.samples.len() == HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+
+    /// Renders the history as a line of block characters, scaled to the maximum value.
+    fn sparkline(&self) -> String {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let max = self.samples.iter().cloned().fold(0.0, f64::max);
+        self.samples
+            .iter()
+            .map(|&v| {
+                if max <= 0.0 {
+                    BARS[0]
+                } else {
+                    BARS[((v / max) * (BARS.len() - 1) as f64).round() as usize]
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Default)]
+struct HudHistory {
+    ui_frame_time_ms: History,
+    eval_time_ms: History,
+    last_frame: Option<Instant>,
+    /// `EvalStats::evaluation_count` when the last evaluation time was pushed to the history.
+    last_evaluation_count: u64,
+}
+
+fn format_hit_rate(stats: &CacheStats) -> String {
+    match stats.hit_rate() {
+        Some(rate) => format!("{:.0}% ({}/{})", rate * 100.0, stats.hits, stats.hits + stats.misses),
+        None => "-".to_string(),
+    }
+}
+
+/// Overlay showing the evaluation time of the last frame, the memory used by resident images,
+/// the cache hit rates and the UI frame time, with their recent history.
+#[composable]
+pub fn performance_hud(evaluation: &Evaluation) -> impl Widget {
+    let history_state = cache::state(HudHistory::default);
+    let mut history = history_state.get();
+    let stats = evaluation.stats();
+
+    let now = Instant::now();
+    if let Some(last_frame) = history.last_frame {
+        history.ui_frame_time_ms.push((now - last_frame).as_secs_f64() * 1000.0);
+    }
+    history.last_frame = Some(now);
+    let eval_time_ms = stats.last_evaluation_time.map(|t| t.as_secs_f64() * 1000.0);
+    if stats.evaluation_count != history.last_evaluation_count {
+        // only record new evaluations, not every recomposition of the HUD
+        if let Some(t) = eval_time_ms {
+            history.eval_time_ms.push(t);
+        }
+        history.last_evaluation_count = stats.evaluation_count;
+    }
+
+    let mut grid = Grid::with_template("{18} / 120 1fr / 2 8");
+    grid.insert((
+        Text::new("UI frame".to_string()),
+        Text::new(format!(
+            "{:.1} ms {}",
+            history.ui_frame_time_ms.samples.back().cloned().unwrap_or(0.0),
+            history.ui_frame_time_ms.sparkline()
+        )),
+    ));
+    grid.insert((
+        Text::new("Evaluation".to_string()),
+        Text::new(match eval_time_ms {
+            Some(t) => format!("{:.1} ms {}", t, history.eval_time_ms.sparkline()),
+            None => "-".to_string(),
+        }),
+    ));
+    grid.insert((
+        Text::new("Resident images".to_string()),
+        Text::new(format!(
+            "{:.1} MiB",
+            stats.resident_image_bytes as f64 / (1u64 << 20) as f64
+        )),
+    ));
+    grid.insert((
+        Text::new("Value cache".to_string()),
+        Text::new(format_hit_rate(&stats.values)),
+    ));
+    grid.insert((
+        Text::new("RoD cache".to_string()),
+        Text::new(format_hit_rate(&stats.regions_of_definition)),
+    ));
+    grid.insert((
+        Text::new("Image cache".to_string()),
+        Text::new(format_hit_rate(&stats.images)),
+    ));
+
+    history_state.set_without_invalidation(history);
+    grid.fix_width(360.dip())
+}
@@ -1,41 +1,39 @@
+mod cache_inspector;
+mod hud;
+mod node_palette;
+
 use crate::{
-    eval::{
-        imaging::{PxSizeI, RequestWindow, TiPoint, TiRect, TiSize},
-        EvalState, Evaluation,
-    },
-    model::{metadata, Document},
+    eval::{EvalState, Evaluation},
+    model::{metadata, Document, FileEvent, FileWatcher, Path},
 };
+use glam::Vec2;
 use kyute::{
-    cache, composable, graal,
+    cache, composable,
+    event::Key,
+    graal,
     graal::{vk, Frame, PassBuilder, SubmitInfo},
     shell::{animation::Layer, application::Application, winit::window::WindowBuilder},
     style::Shape,
     text::FormattedTextExt,
-    widget::{Retained, RetainedWidget, Text, WidgetExt},
+    widget::{Grid, Retained, RetainedWidget, Text, WidgetExt},
     Alignment, Environment, Event, EventCtx, Geometry, LayoutCtx, LayoutParams, Measurements, PaintCtx, UnitExt,
     Widget, WidgetId, Window,
 };
 use kyute_common::{Atom, SizeI};
-use std::fs;
+use parking_lot::Mutex;
+use std::{mem, path::PathBuf, sync::Arc, thread, time::Duration};
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Native vulkan view
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub struct NativeLayerWidget {
+    /// Evaluation that produced the displayed image, and owns it.
+    evaluation: Evaluation,
     image_id: Option<graal::ImageId>,
     image_handle: Option<vk::Image>,
     image_size: SizeI,
 }
 
-impl Drop for NativeLayerWidget {
-    fn drop(&mut self) {
-        if let Some(image_id) = self.image_id {
-            let gpu_device = Application::instance().gpu_device();
-            gpu_device.destroy_image(image_id);
-        }
-    }
-}
-
 impl NativeLayerWidget {
     /// Renders the current view.
     fn render(&mut self, layer: &Layer, scale_factor: f64) {
@@ -46,6 +44,10 @@ impl NativeLayerWidget {
         trace!("NativeLayerWidget::render");
 
         let image_id = self.image_id.unwrap();
+        if !self.evaluation.is_device_image_cached(image_id) {
+            // evicted from the evaluation cache
+            return;
+        }
         let image_handle = self.image_handle.unwrap();
 
         let mut gpu_context = Application::instance().lock_gpu_context();
@@ -129,9 +131,10 @@ impl NativeLayerWidget {
 }
 
 impl RetainedWidget for NativeLayerWidget {
-    type Args = Document;
+    type Args = Evaluation;
 
-    fn new(document: &Self::Args) -> Self {
+    fn new(evaluation: &Self::Args) -> Self {
+        let document = evaluation.document();
         // find the display node
         let mut display_image = None;
         for node in document.root().children.values() {
@@ -151,18 +154,7 @@ impl RetainedWidget for NativeLayerWidget {
 
         if let Some(display_image) = display_image {
             // evaluate the input of the display node
-            let device = Application::instance().gpu_device().clone();
-            let eval = Evaluation::new(device, document.clone());
-            let images = eval
-                .device_evaluate_image(
-                    &display_image,
-                    0.0,
-                    &RequestWindow {
-                        roi: TiRect::new(TiPoint::zero(), TiSize::new(1280.0, 720.0)),
-                        resolution: PxSizeI::new(1280, 720),
-                    },
-                )
-                .unwrap();
+            let images = evaluation.device_evaluate_frame(&display_image, 0).unwrap();
             let image = images.planes.first().unwrap().1;
             image_id = Some(image.id);
             image_size = SizeI::new(image.size.width, image.size.height);
@@ -174,14 +166,18 @@ impl RetainedWidget for NativeLayerWidget {
         }
 
         NativeLayerWidget {
+            evaluation: evaluation.clone(),
             image_id,
             image_handle,
             image_size,
         }
     }
 
-    fn update(&mut self, args: &Self::Args) {
-        // nothing
+    fn update(&mut self, evaluation: &Self::Args) {
+        if !self.evaluation.same_state(evaluation) {
+            // the document has changed: re-evaluate (unaffected results are reused)
+            *self = Self::new(evaluation);
+        }
     }
 
     fn widget_id(&self) -> Option<WidgetId> {
@@ -213,9 +209,236 @@ impl RetainedWidget for NativeLayerWidget {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Network view
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Horizontal offset between a node and the nodes created from it.
+const NODE_SPACING: f32 = 150.0;
+
+/// State of the network view: the network being edited, the selected node, and the position where new nodes
+/// are created.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct NetworkViewState {
+    /// Node containing the edited network.
+    pub(crate) parent: Path,
+    pub(crate) selection: Option<Path>,
+    /// Position of the cursor in the network.
+    pub(crate) cursor: Vec2,
+}
+
+impl NetworkViewState {
+    /// Creates the view of the root network, with the displayed node selected.
+    fn new(document: &Document) -> NetworkViewState {
+        let mut view = NetworkViewState {
+            parent: Path::root(),
+            selection: None,
+            cursor: Vec2::ZERO,
+        };
+        let displayed = document
+            .root()
+            .children
+            .values()
+            .filter(|node| node.metadata(metadata::OPERATOR).map_or(false, |op| &*op == "display"))
+            .find_map(|node| node.attribute(&Atom::from("input:image"))?.connection.clone())
+            .and_then(|source| {
+                if source.is_attribute() {
+                    source.parent()
+                } else {
+                    Some(source)
+                }
+            });
+        if let Some(displayed) = displayed {
+            view.select(document, displayed);
+        }
+        view
+    }
+
+    /// Selects a node, and moves the cursor next to it.
+    pub(crate) fn select(&mut self, document: &Document, path: Path) {
+        let position = document
+            .node(&path)
+            .and_then(|node| node.metadata(metadata::POSITION))
+            .unwrap_or(self.cursor);
+        self.cursor = position + Vec2::new(NODE_SPACING, 0.0);
+        self.selection = Some(path);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// File watching
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Interval between two polls of the files of the document.
+const FILE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Files watched by the file watcher thread.
+#[derive(Debug)]
+struct WatchedFiles {
+    watcher: FileWatcher,
+    /// Modifications detected by the thread, not handled yet.
+    events: Vec<FileEvent>,
+}
+
+/// Watches the file of the displayed document and the files read by its nodes.
+///
+/// The files are polled every `FILE_POLL_INTERVAL` by a background thread, which invalidates a state of the
+/// window when it detects modifications so that they are handled on the next recomposition. The thread stops
+/// once the watch is dropped.
+#[derive(Clone, Debug)]
+struct DocumentFileWatch {
+    files: Arc<Mutex<WatchedFiles>>,
+    /// Revision of the document when it was loaded from its file.
+    loaded_revision: usize,
+    /// Revision of the document when the set of watched input files was last updated.
+    inputs_revision: usize,
+}
+
+impl DocumentFileWatch {
+    /// Starts watching the files of a document. `notify` is invalidated when files are modified.
+    fn new(document: &Document, notify: cache::State<u64>) -> DocumentFileWatch {
+        let files = Arc::new(Mutex::new(WatchedFiles {
+            watcher: FileWatcher::new(document),
+            events: vec![],
+        }));
+
+        let watched = Arc::downgrade(&files);
+        thread::Builder::new()
+            .name("file watcher".to_string())
+            .spawn(move || {
+                let mut notifications = 0;
+                loop {
+                    thread::sleep(FILE_POLL_INTERVAL);
+                    let files = match watched.upgrade() {
+                        Some(files) => files,
+                        None => break,
+                    };
+                    let mut files = files.lock();
+                    let events = files.watcher.poll();
+                    if !events.is_empty() {
+                        files.events.extend(events);
+                        notifications += 1;
+                        notify.set(notifications);
+                    }
+                }
+            })
+            .expect("failed to spawn file watcher thread");
+
+        DocumentFileWatch {
+            files,
+            loaded_revision: document.revision,
+            inputs_revision: document.revision,
+        }
+    }
+
+    /// Handles the modifications detected by the file watcher thread.
+    ///
+    /// Reloads the document if its file was modified and the document has no edits since it was loaded. Returns
+    /// the modified input files, with the nodes reading them.
+    fn handle_events(&mut self, document: &mut Document) -> Vec<(PathBuf, Vec<Path>)> {
+        let mut files = self.files.lock();
+        if document.revision != self.inputs_revision {
+            files.watcher.update_inputs(document);
+            self.inputs_revision = document.revision;
+        }
+
+        let mut inputs = Vec::new();
+        for event in mem::take(&mut files.events) {
+            match event {
+                FileEvent::DocumentChanged { file } => {
+                    if document.revision != self.loaded_revision {
+                        warn!(
+                            "`{}` was modified externally, but the document has unsaved edits",
+                            file.display()
+                        );
+                        continue;
+                    }
+                    match Document::open(&file) {
+                        Ok(mut reloaded) => {
+                            // bump the revision so that the evaluation switches to the reloaded document
+                            reloaded.revision = document.revision + 1;
+                            *document = reloaded;
+                            self.loaded_revision = document.revision;
+                            self.inputs_revision = document.revision;
+                            files.watcher.update_inputs(document);
+                        }
+                        Err(err) => warn!("could not reload `{}`: {}", file.display(), err),
+                    }
+                }
+                FileEvent::InputChanged { file, nodes } => inputs.push((file, nodes)),
+            }
+        }
+        inputs
+    }
+}
+
 #[composable]
-fn document_window_contents(document: &Document) -> impl Widget {
-    Retained::<NativeLayerWidget>::new(document)
+fn document_window_contents(document: &mut Document) -> impl Widget {
+    // set by the file watcher thread to recompose the window when files are modified
+    let file_notify_state = cache::state(|| 0u64);
+    let file_watch_state = cache::state(|| DocumentFileWatch::new(document, file_notify_state.clone()));
+    let mut file_watch = file_watch_state.get();
+    let modified_inputs = file_watch.handle_events(document);
+    file_watch_state.set_without_invalidation(file_watch);
+
+    let evaluation_state = cache::state(|| {
+        let device = Application::instance().gpu_device().clone();
+        Evaluation::new(device, document.clone())
+    });
+    let mut evaluation = evaluation_state.get();
+    for (file, nodes) in modified_inputs.iter() {
+        evaluation.reload_file(file, nodes);
+    }
+    if evaluation.document().revision != document.revision || !modified_inputs.is_empty() {
+        evaluation.update(document.clone());
+        evaluation_state.set(evaluation.clone());
+    }
+
+    let network_view_state = cache::state(|| NetworkViewState::new(document));
+    let previous_network_view = network_view_state.get();
+    let mut network_view = previous_network_view.clone();
+    if network_view
+        .selection
+        .as_ref()
+        .map_or(false, |path| document.node(path).is_none())
+    {
+        // the selected node was removed
+        network_view.selection = None;
+    }
+
+    let palette_open_state = cache::state(|| false);
+    let mut palette_open = palette_open_state.get();
+
+    // the performance HUD is hidden by default, and toggled with F3
+    let hud_visible_state = cache::state(|| false);
+    let mut hud_visible = hud_visible_state.get();
+
+    let view = Retained::<NativeLayerWidget>::new(&evaluation);
+    if view.key_pressed(Key::Tab) {
+        palette_open = true;
+    }
+    if view.key_pressed(Key::F3) {
+        hud_visible = !hud_visible;
+        hud_visible_state.set(hud_visible);
+    }
+
+    let mut grid = Grid::with_template("1fr auto / 1fr 360");
+    grid.insert((view, cache_inspector::cache_inspector(&evaluation)));
+
+    if palette_open {
+        let palette = node_palette::node_palette(document, &mut network_view, &mut palette_open);
+        grid.insert((palette, ()));
+    }
+    palette_open_state.set(palette_open);
+    if network_view != previous_network_view {
+        network_view_state.set(network_view);
+    }
+
+    if hud_visible {
+        grid.insert(((), hud::performance_hud(&evaluation)));
+    }
+
+    grid
 
     /*Text::new("-- NO SIGNAL --".font_size(40.0).font_family("MS 33558"))
     .centered()
@@ -225,7 +448,7 @@ fn document_window_contents(document: &Document) -> impl Widget {
 
 /// Native window displaying a document.
 #[composable]
-pub fn document_window(document: &Document) -> Window {
+pub fn document_window(document: &mut Document) -> Window {
     Window::new(
         WindowBuilder::new().with_title("Document"),
         document_window_contents(document),
@@ -234,8 +457,7 @@ pub fn document_window(document: &Document) -> Window {
 }
 
 fn try_open_document() -> anyhow::Result<Document> {
-    let xml = fs::read_to_string("data/networks/simple.xml")?;
-    let document = Document::from_xml(&xml)?;
+    let document = Document::open(std::path::Path::new("data/networks/simple.xml"))?;
     eprintln!("{:?}", document);
     Ok(document)
 }
@@ -248,7 +470,7 @@ pub fn application_root() -> impl Widget {
     let mut doc = document_file_state.take_without_invalidation().unwrap();
 
     let rev = doc.revision;
-    let window = document_window(&doc);
+    let window = document_window(&mut doc);
     if doc.revision != rev {
         document_file_state.set(Some(doc));
     } else {
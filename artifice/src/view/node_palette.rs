@@ -0,0 +1,216 @@
+//! Keyboard-driven node creation palette.
+use crate::{
+    eval::{registered_operators, OperatorInfo},
+    model::{
+        metadata,
+        typedesc::{ImageDimension, SampledImageType},
+        Document, Path, PrimitiveType, TypeDesc,
+    },
+    view::NetworkViewState,
+};
+use glam::Vec2;
+use kyute::{
+    cache, composable,
+    event::Key,
+    widget::{Button, Grid, Text, TextEdit, WidgetExt},
+    UnitExt, Widget,
+};
+use std::sync::Arc;
+
+/// Maximum number of operators listed in the palette.
+const MAX_RESULTS: usize = 12;
+
+/// Scores an operator against a search query (lower is better).
+///
+/// The query matches if its characters appear in order in the operator name (case-insensitive),
+/// or if it's a substring of the description or category. Returns `None` if it doesn't match.
+fn match_score(query: &str, op: &OperatorInfo) -> Option<usize> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let query = query.to_lowercase();
+    let name = op.name.to_lowercase();
+
+    if name.starts_with(&query) {
+        return Some(0);
+    }
+
+    // subsequence match on the name; the score is the number of skipped characters
+    let mut skipped = 0;
+    let mut name_chars = name.chars();
+    let mut matched = true;
+    for qc in query.chars() {
+        loop {
+            match name_chars.next() {
+                Some(c) if c == qc => break,
+                Some(_) => skipped += 1,
+                None => {
+                    matched = false;
+                    break;
+                }
+            }
+        }
+        if !matched {
+            break;
+        }
+    }
+    if matched {
+        return Some(1 + skipped);
+    }
+
+    if op.description.to_lowercase().contains(&query) || op.category.to_lowercase().contains(&query) {
+        return Some(1000);
+    }
+    None
+}
+
+/// Returns the registered operators matching the query, best matches first.
+pub(crate) fn search_operators(query: &str) -> Vec<OperatorInfo> {
+    rank_operators(query, registered_operators())
+}
+
+/// Returns the operators matching the query, best matches first.
+fn rank_operators(query: &str, operators: Vec<OperatorInfo>) -> Vec<OperatorInfo> {
+    let mut results: Vec<_> = operators
+        .into_iter()
+        .filter_map(|op| Some((match_score(query, &op)?, op)))
+        .collect();
+    // stable sort: operators with the same score stay sorted by category and name
+    results.sort_by_key(|(score, _)| *score);
+    results.into_iter().map(|(_, op)| op).collect()
+}
+
+/// Creates a node evaluated by the specified operator at the given position in the network.
+///
+/// If `selection` is set and the operator is an imaging operator, the `input:image` input of the new node
+/// is connected to the selected node. Returns the path of the new node.
+pub(crate) fn create_node_from_palette(
+    document: &mut Document,
+    parent: &Path,
+    op: &OperatorInfo,
+    position: Vec2,
+    selection: Option<&Path>,
+) -> Result<Path, crate::model::Error> {
+    document.transaction(|document| {
+        let path = document.create_node(parent, op.name, op.name)?;
+        document.set_node_metadata(&path, metadata::POSITION, position)?;
+        if let Some(selection) = selection {
+            if op.imaging {
+                let ty = TypeDesc::SampledImage(Arc::new(SampledImageType {
+                    sampled_ty: PrimitiveType::Float,
+                    dim: ImageDimension::Dim2D,
+                    ms: false,
+                }));
+                document.connect(&path.join_attribute("input:image"), ty, selection)?;
+            }
+        }
+        Ok(path)
+    })
+}
+
+/// Searchable list of the registered operators.
+///
+/// Opened by pressing Tab in the network view. Choosing an operator creates a node under the cursor of the view,
+/// connected to the output of the selected node, and closes the palette. The new node becomes the selection.
+#[composable]
+pub fn node_palette(document: &mut Document, view: &mut NetworkViewState, open: &mut bool) -> impl Widget {
+    let query_state = cache::state(String::new);
+    let mut query = query_state.get();
+
+    let mut grid = Grid::with_template("{24} / 1fr 100 / 2 4");
+
+    let edit = TextEdit::new(query.clone());
+    if let Some(text) = edit.text_changed() {
+        query = text.to_string();
+        query_state.set(query.clone());
+    }
+    let enter_pressed = edit.key_pressed(Key::Enter);
+    let escape_pressed = edit.key_pressed(Key::Escape);
+    grid.insert((edit, ()));
+
+    let mut chosen = None;
+    let results = search_operators(&query);
+    for op in results.iter().take(MAX_RESULTS) {
+        cache::scoped(op.name, || {
+            let button = Button::new(op.name.to_string());
+            if button.clicked() {
+                chosen = Some(*op);
+            }
+            grid.insert((button, Text::new(op.category.to_string())));
+            grid.insert((Text::new(op.description.to_string()), ()));
+        });
+    }
+
+    if enter_pressed {
+        chosen = results.first().copied();
+    }
+    if escape_pressed {
+        *open = false;
+        query_state.set(String::new());
+    }
+
+    if let Some(op) = chosen {
+        match create_node_from_palette(document, &view.parent, &op, view.cursor, view.selection.as_ref()) {
+            Ok(path) => {
+                trace!("created node {:?}", path);
+                view.select(document, path);
+            }
+            Err(err) => error!("failed to create `{}` node: {}", op.name, err),
+        }
+        *open = false;
+        query_state.set(String::new());
+    }
+
+    grid.fix_width(400.dip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{match_score, rank_operators};
+    use crate::eval::OperatorInfo;
+
+    fn operator(name: &'static str, description: &'static str, category: &'static str) -> OperatorInfo {
+        OperatorInfo {
+            name,
+            description,
+            category,
+            imaging: true,
+        }
+    }
+
+    #[test]
+    fn test_match_score() {
+        let blur = operator("blur", "Gaussian blur", "filter");
+        assert_eq!(match_score("", &blur), Some(0));
+        assert_eq!(match_score("BL", &blur), Some(0));
+        // subsequence: `u` and `r` are skipped
+        assert_eq!(match_score("bl", &operator("bilateral", "", "filter")), Some(2));
+        assert_eq!(match_score("gauss", &blur), Some(1000));
+        assert_eq!(match_score("filt", &blur), Some(1000));
+        assert_eq!(match_score("sharpen", &blur), None);
+    }
+
+    #[test]
+    fn test_rank_operators() {
+        // sorted by category and name, as returned by `registered_operators`
+        let operators = vec![
+            operator("bilateral", "Edge-preserving blur", "filter"),
+            operator("blur", "Gaussian blur", "filter"),
+            operator("read", "Reads an image file", "io"),
+            operator("rotate", "Rotates an image", "transform"),
+        ];
+        let names = |query: &str| -> Vec<&str> {
+            rank_operators(query, operators.clone())
+                .iter()
+                .map(|op| op.name)
+                .collect()
+        };
+
+        assert_eq!(names(""), vec!["bilateral", "blur", "read", "rotate"]);
+        // prefix matches first, then subsequences by number of skipped characters, then descriptions
+        assert_eq!(names("bl"), vec!["blur", "bilateral"]);
+        assert_eq!(names("r"), vec!["read", "rotate", "blur", "bilateral"]);
+        assert_eq!(names("image"), vec!["read", "rotate"]);
+        assert!(names("xyz").is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{spirv, CRATE};
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
+use std::path::Path;
+use syn::{
+    parse::{Parse, ParseStream},
+    spanned::Spanned,
+};
+
+/// `check_interface!(Type, "path/to/shader.spv", set = N)`
+struct CheckInterfaceInput {
+    ty: syn::Type,
+    path: syn::LitStr,
+    set: u32,
+}
+
+impl Parse for CheckInterfaceInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ty = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let path = input.parse()?;
+        let mut set = 0;
+        if input.parse::<Option<syn::Token![,]>>()?.is_some() && !input.is_empty() {
+            let key: syn::Ident = input.parse()?;
+            if key != "set" {
+                return Err(syn::Error::new(key.span(), "expected `set`"));
+            }
+            input.parse::<syn::Token![=]>()?;
+            set = input.parse::<syn::LitInt>()?.base10_parse()?;
+            input.parse::<Option<syn::Token![,]>>()?;
+        }
+        Ok(CheckInterfaceInput { ty, path, set })
+    }
+}
+
+pub fn expand(input: proc_macro::TokenStream) -> TokenStream {
+    let input: CheckInterfaceInput = match syn::parse(input) {
+        Ok(input) => input,
+        Err(e) => return e.into_compile_error(),
+    };
+
+    // paths are relative to the root of the crate, like the ones of build scripts
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let path = Path::new(&manifest_dir).join(input.path.value());
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return syn::Error::new(input.path.span(), format!("could not read `{}`: {}", path.display(), e))
+                .into_compile_error()
+        }
+    };
+    let module = match spirv::Module::parse(&bytes) {
+        Ok(module) => module,
+        Err(e) => {
+            return syn::Error::new(
+                input.path.span(),
+                format!("invalid SPIR-V module `{}`: {}", path.display(), e),
+            )
+            .into_compile_error()
+        }
+    };
+
+    let ty = &input.ty;
+    let type_name = match ty {
+        syn::Type::Path(path) => path.path.segments.last().unwrap().ident.to_string(),
+        _ => return syn::Error::new(ty.span(), "expected a struct type").into_compile_error(),
+    };
+    let shader = input.path.value();
+
+    let checks = if let Some(members) = module.struct_members(&type_name) {
+        // a block or struct of the shader has the same name as the type: check the offsets of its members
+        members
+            .iter()
+            .map(|m| {
+                let member = syn::Ident::new(&m.name, ty.span());
+                let offset = Literal::usize_unsuffixed(m.offset as usize);
+                let msg = format!(
+                    "offset of `{}::{}` doesn't match the shader `{}` (expected {})",
+                    type_name, m.name, shader, m.offset
+                );
+                quote! {
+                    assert!(<#ty>::LAYOUT.#member.offset == #offset, #msg);
+                }
+            })
+            .collect::<Vec<_>>()
+    } else {
+        // otherwise, the type should be `Arguments` for the specified set
+        module
+            .descriptor_bindings()
+            .iter()
+            .filter(|b| b.set == input.set)
+            .map(|b| {
+                let binding = b.binding;
+                let descriptor_type = syn::Ident::new(b.descriptor_type, ty.span());
+                let missing_msg = format!(
+                    "`{}` has no binding for (set={}, binding={}) declared in the shader `{}`",
+                    type_name, b.set, binding, shader
+                );
+                let type_msg = format!(
+                    "descriptor type of binding {} of `{}` doesn't match the shader `{}` (expected {})",
+                    binding, type_name, shader, b.descriptor_type
+                );
+                let count_check = b.count.map(|count| {
+                    let count_msg = format!(
+                        "not enough descriptors in binding {} of `{}` for the shader `{}` (expected {})",
+                        binding, type_name, shader, count
+                    );
+                    quote! { assert!(b.descriptor_count >= #count, #count_msg); }
+                });
+                quote! {
+                    match #CRATE::shader::find_layout_binding(<#ty>::DESCRIPTOR_SET_LAYOUT_BINDINGS, #binding) {
+                        Some(b) => {
+                            assert!(
+                                #CRATE::shader::is_compatible_descriptor_type(
+                                    #CRATE::vk::DescriptorType::#descriptor_type,
+                                    b.descriptor_type
+                                ),
+                                #type_msg
+                            );
+                            #count_check
+                        }
+                        None => panic!(#missing_msg),
+                    }
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let path = path.to_string_lossy();
+    quote! {
+        const _: () = {
+            // recompile when the shader changes
+            const _: &[u8] = include_bytes!(#path);
+            #(#checks)*
+        };
+    }
+}
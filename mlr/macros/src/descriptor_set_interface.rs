@@ -81,8 +81,14 @@ struct ArgumentAttr {
     binding: Option<u32>,
     #[darling(default)]
     runtime_array: Option<SpannedValue<RuntimeArrayMeta>>,
+    /// Maximum number of descriptors of a variable-count binding (e.g. `textures: &[SampledImage2D]`).
+    #[darling(default)]
+    variable_count: Option<u32>,
     #[darling(default)]
     stages: Option<StagesMeta>,
+    /// Push constants (`PushConstants`), set with `vkCmdPushConstants` instead of a descriptor.
+    #[darling(default)]
+    push_constant: Flag,
 }
 
 #[derive(Default, FromMeta)]
@@ -209,6 +215,7 @@ struct Binding<'a> {
     field: &'a syn::Field,
     field_index: usize,
     binding: u32,
+    variable_count: Option<u32>,
 }
 
 /// A uniform variable directly specified in the structure instead of going through a uniform buffer.
@@ -254,10 +261,12 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
     };
     let struct_name = &derive_input.ident;
     let (impl_generics, ty_generics, where_clause) = s.generics.split_for_impl();
+    let vis = &s.vis;
 
     //let mut runtime_array_writes = Vec::new();
     let mut bindings = Vec::new();
     let mut direct_uniform_fields = Vec::new();
+    let mut push_constant_fields = Vec::new();
     //let mut default_uniform_buffer_generics = Vec::new();
     let mut attrib_errors = Vec::new();
 
@@ -267,12 +276,23 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
             Ok(attr) => {
                 let attr: &ArgumentAttr = &attr;
                 // TODO verify attrs
-                if let Some(binding) = attr.binding {
+                if attr.push_constant.is_some() {
+                    if attr.binding.is_some() {
+                        Diagnostic::spanned(
+                            field.span().unwrap(),
+                            Level::Error,
+                            "push constants can't have a binding number",
+                        )
+                        .emit();
+                    }
+                    push_constant_fields.push((field, field_index));
+                } else if let Some(binding) = attr.binding {
                     // this is an actual binding
                     bindings.push(Binding {
                         field,
                         field_index,
                         binding,
+                        variable_count: attr.variable_count,
                     })
                 } else {
                     // assume this is a primary uniform
@@ -283,6 +303,68 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
         }
     }
 
+    // --- Check the variable-count binding: there can be only one, and it must be the last binding ---
+    let variable_count_bindings: Vec<_> = bindings.iter().filter(|b| b.variable_count.is_some()).collect();
+    if variable_count_bindings.len() > 1 {
+        Diagnostic::spanned(
+            variable_count_bindings[1].field.span().unwrap(),
+            Level::Error,
+            "only one binding can have a variable descriptor count",
+        )
+        .emit();
+    }
+    let variable_count_binding = variable_count_bindings.first().copied();
+    if let Some(vb) = variable_count_binding {
+        if bindings.iter().any(|b| b.binding > vb.binding) {
+            Diagnostic::spanned(
+                vb.field.span().unwrap(),
+                Level::Error,
+                "the variable-count binding must have the highest binding number",
+            )
+            .emit();
+        }
+    }
+
+    // --- Push constants: there can be only one push constant block ---
+    if push_constant_fields.len() > 1 {
+        Diagnostic::spanned(
+            push_constant_fields[1].0.span().unwrap(),
+            Level::Error,
+            "only one field can contain push constants",
+        )
+        .emit();
+    }
+    let (push_constant_range, push_constant_methods) = if let Some(&(field, field_index)) = push_constant_fields.first()
+    {
+        let ty = &field.ty;
+        let member = if let Some(ref ident) = field.ident {
+            quote! { self.#ident }
+        } else {
+            let index = syn::Index::from(field_index);
+            quote! { self.#index }
+        };
+        (
+            quote! {
+                Some(#CRATE::vk::PushConstantRange {
+                    stage_flags: <#ty as #CRATE::PushConstants>::STAGES,
+                    offset: <#ty as #CRATE::PushConstants>::OFFSET,
+                    size: <#ty as #CRATE::PushConstants>::SIZE,
+                })
+            },
+            quote! {
+                fn push_constant_range(&self) -> Option<#CRATE::vk::PushConstantRange> {
+                    Self::PUSH_CONSTANT_RANGE
+                }
+
+                fn push_constant_data(&self) -> Option<&[u8]> {
+                    Some(#CRATE::push_constants::push_constant_data(&#member))
+                }
+            },
+        )
+    } else {
+        (quote! { None }, quote! {})
+    };
+
     // --- Collect generics of each primary uniform field ---
     let type_params = derive_input.generics.declared_type_params();
     let direct_uniform_type_param_idents = direct_uniform_fields
@@ -300,6 +382,11 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
         .map(|b| {
             let ty = &b.field.ty;
             let binding = b.binding;
+            let descriptor_count = match b.variable_count {
+                // maximum count, the actual count is specified when allocating the descriptor set
+                Some(max_count) => quote! { #max_count },
+                None => quote! { <#ty as #CRATE::DescriptorBinding>::DESCRIPTOR_COUNT },
+            };
             if binding == 0 && !direct_uniform_fields.is_empty() {
                 // binding #0 is reserved for the direct uniform buffer is there's one: ensure it's not used
                 Diagnostic::spanned(
@@ -315,7 +402,7 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
                         binding              : #binding,
                         stage_flags          : <#ty as #CRATE::DescriptorBinding>::SHADER_STAGES,
                         descriptor_type      : <#ty as #CRATE::DescriptorBinding>::DESCRIPTOR_TYPE,
-                        descriptor_count     : <#ty as #CRATE::DescriptorBinding>::DESCRIPTOR_COUNT,
+                        descriptor_count     : #descriptor_count,
                         p_immutable_samplers : ::std::ptr::null()
                     }
                 }
@@ -323,6 +410,54 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
         })
         .collect();
 
+    // --- Binding flags (only if there's a variable-count binding) ---
+    let binding_flags_method = if let Some(vb) = variable_count_binding {
+        let direct_ubo_flags = if !direct_uniform_fields.is_empty() {
+            quote! { #CRATE::vk::DescriptorBindingFlags::empty(), }
+        } else {
+            quote! {}
+        };
+        let flags = bindings.iter().map(|b| {
+            if b.variable_count.is_some() {
+                quote! {
+                    #CRATE::vk::DescriptorBindingFlags::from_raw(
+                        #CRATE::vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT.as_raw()
+                            | #CRATE::vk::DescriptorBindingFlags::PARTIALLY_BOUND.as_raw())
+                }
+            } else {
+                quote! { #CRATE::vk::DescriptorBindingFlags::empty() }
+            }
+        });
+        let max_count = vb.variable_count.unwrap();
+        let (field, field_name) = if let Some(ref ident) = vb.field.ident {
+            (quote! { self.#ident }, ident.to_string())
+        } else {
+            let index = syn::Index::from(vb.field_index);
+            (quote! { self.#index }, vb.field_index.to_string())
+        };
+        quote! {
+            fn get_descriptor_set_layout_binding_flags(&self) -> &[#CRATE::vk::DescriptorBindingFlags] {
+                // const item because calls to `from_raw` are not promoted to 'static
+                const FLAGS: &[#CRATE::vk::DescriptorBindingFlags] = &[#direct_ubo_flags #(#flags,)*];
+                FLAGS
+            }
+
+            fn variable_descriptor_count(&self) -> Option<u32> {
+                let count = #field.len();
+                assert!(
+                    count <= #max_count as usize,
+                    "too many descriptors in variable-count binding `{}` ({}, max {})",
+                    #field_name,
+                    count,
+                    #max_count
+                );
+                Some(count as u32)
+            }
+        }
+    } else {
+        quote! {}
+    };
+
     // --- Direct UBO binding (binding=#0) ---
     let direct_ubo = if !direct_uniform_fields.is_empty() {
         quote! {
@@ -369,6 +504,45 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
         })
         .collect();
 
+    // --- Descriptor resources (for caching) ---
+    let descriptor_resources_expr = if !direct_uniform_fields.is_empty() {
+        // direct uniforms are uploaded on every use
+        quote! { false }
+    } else {
+        let resources_exprs: Vec<_> = bindings
+            .iter()
+            .map(|b| {
+                let binding = b.binding;
+                if let Some(ref ident) = b.field.ident {
+                    quote! { #CRATE::DescriptorBinding::resources(&self.#ident, #binding, resources) }
+                } else {
+                    let index = syn::Index::from(b.field_index);
+                    quote! { #CRATE::DescriptorBinding::resources(&self.#index, #binding, resources) }
+                }
+            })
+            .collect();
+        quote! { true #(&& #resources_exprs)* }
+    };
+
+    // --- Buffer ranges (for validation against shaders) ---
+    let buffer_range_statements: Vec<_> = bindings
+        .iter()
+        .map(|b| {
+            let binding = b.binding;
+            let field = if let Some(ref ident) = b.field.ident {
+                quote! { self.#ident }
+            } else {
+                let index = syn::Index::from(b.field_index);
+                quote! { self.#index }
+            };
+            quote! {
+                if let Some(range) = #CRATE::DescriptorBinding::buffer_range(&#field) {
+                    ranges.push((#binding, range));
+                }
+            }
+        })
+        .collect();
+
     // --- direct uniform upload block
     let direct_uniforms_upload_stmts = if !direct_uniform_fields.is_empty() {
         // --- direct uniform struct ---
@@ -459,6 +633,14 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
 
         #impl_resource_access
 
+        impl #impl_generics #struct_name #ty_generics #where_clause {
+            /// Bindings of the descriptor set layout (also returned by `get_descriptor_set_layout_bindings`).
+            #vis const DESCRIPTOR_SET_LAYOUT_BINDINGS: &'static [#CRATE::vk::DescriptorSetLayoutBinding] =
+                &[#direct_ubo #(#descriptor_set_layout_bindings,)*];
+            /// Range of the push constants of the arguments, to declare in the pipeline layout.
+            #vis const PUSH_CONSTANT_RANGE: Option<#CRATE::vk::PushConstantRange> = #push_constant_range;
+        }
+
         impl #impl_generics #CRATE::arguments::Arguments for #struct_name #ty_generics #where_clause {
 
             fn unique_type_id(&self) -> Option<::std::any::TypeId> {
@@ -467,9 +649,13 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
 
             fn get_descriptor_set_layout_bindings(&self) -> &[#CRATE::vk::DescriptorSetLayoutBinding]
             {
-                &[#direct_ubo #(#descriptor_set_layout_bindings,)*]
+                Self::DESCRIPTOR_SET_LAYOUT_BINDINGS
             }
 
+            #binding_flags_method
+
+            #push_constant_methods
+
             fn get_descriptor_set_update_template_entries(
                 &self,
             ) -> Option<&[#CRATE::vk::DescriptorUpdateTemplateEntry]>
@@ -487,6 +673,14 @@ pub(crate) fn derive(input: proc_macro::TokenStream) -> TokenStream {
                 #direct_uniforms_upload_stmts
                 #(#descriptor_write_statements)*
             }
+
+            fn descriptor_resources(&self, resources: &mut ::std::vec::Vec<(u32, #CRATE::descriptor_cache::DescriptorResource)>) -> bool {
+                #descriptor_resources_expr
+            }
+
+            fn buffer_ranges(&self, ranges: &mut ::std::vec::Vec<(u32, #CRATE::vk::DeviceSize)>) {
+                #(#buffer_range_statements)*
+            }
         }
     }
 }
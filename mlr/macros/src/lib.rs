@@ -31,11 +31,14 @@ impl ToTokens for CrateName {
 type FieldList = syn::punctuated::Punctuated<syn::Field, syn::Token![,]>;
 
 //--------------------------------------------------------------------------------------------------
+mod check_interface;
 mod descriptor_set_interface;
-mod vertex_data;
-//mod fragment_output_interface;
+mod fragment_output_interface;
 mod pipeline_interface;
+mod push_constants;
+mod spirv;
 mod struct_layout;
+mod vertex_data;
 //mod vertex_input_interface;
 //mod pipeline_interface;
 
@@ -44,16 +47,40 @@ pub fn arguments_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStre
     descriptor_set_interface::derive(input).into()
 }
 
-#[proc_macro_derive(VertexData)]
+#[proc_macro_derive(VertexData, attributes(vertex))]
 pub fn vertex_data_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     vertex_data::derive(input).into()
 }
 
+#[proc_macro_derive(FragmentOutputInterface, attributes(attachment))]
+pub fn fragment_output_interface_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    fragment_output_interface::derive(input).into()
+}
+
+#[proc_macro_derive(PushConstants, attributes(push_constants))]
+pub fn push_constants_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    push_constants::derive(input).into()
+}
+
 #[proc_macro_derive(StructLayout)]
 pub fn struct_layout_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     struct_layout::derive(input).into()
 }
 
+/// Checks at compile time that a type matches the interface of a SPIR-V shader.
+///
+/// `check_interface!(MyArguments, "shaders/foo.spv")` checks that the `Arguments` provide compatible
+/// descriptors for all the bindings of set #0 declared in the shader (use `set = N` as a third argument for
+/// another set). If the shader contains a struct or block with the same name as the type (this requires debug
+/// information), the type is instead expected to derive `StructLayout`, and the offsets of its fields are
+/// checked against the offsets of the members of the struct in the shader.
+///
+/// Paths are relative to the root of the crate.
+#[proc_macro]
+pub fn check_interface(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    check_interface::expand(input).into()
+}
+
 /*#[proc_macro_attribute]
 pub fn pipeline_interface(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     generate_pipeline_interface(attr, item)
@@ -66,15 +93,6 @@ pub fn vertex_input_interface_derive(input: proc_macro::TokenStream) -> proc_mac
         input,
         vertex_input_interface::generate,
     )
-}
-
-#[proc_macro_derive(FragmentOutputInterface, attributes(attachment))]
-pub fn fragment_output_interface_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    derive_struct(
-        "FragmentOutputInterface",
-        input,
-        fragment_output_interface::generate,
-    )
 }*/
 
 /*
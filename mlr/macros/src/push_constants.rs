@@ -0,0 +1,65 @@
+use crate::{struct_layout::has_repr_c_attr, CRATE};
+use darling::FromDeriveInput;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+
+#[derive(FromDeriveInput)]
+#[darling(attributes(push_constants))]
+struct PushConstantsAttrs {
+    /// Offset of the push constants in the push constant block (`#[push_constants(offset = N)]`).
+    #[darling(default)]
+    offset: Option<u32>,
+}
+
+pub fn derive(input: proc_macro::TokenStream) -> TokenStream {
+    let derive_input: syn::DeriveInput = match syn::parse(input) {
+        Ok(input) => input,
+        Err(e) => return e.into_compile_error(),
+    };
+
+    // check for struct
+    if !matches!(derive_input.data, syn::Data::Struct(_)) {
+        return syn::Error::new(derive_input.span(), "`PushConstants` can only be derived on structs")
+            .into_compile_error();
+    }
+
+    // check for `#[repr(C)]`
+    let repr_c_check = if !has_repr_c_attr(&derive_input) {
+        syn::Error::new(
+            derive_input.span(),
+            format!("`PushConstants` can only be derived on `repr(C)` structs"),
+        )
+        .into_compile_error()
+    } else {
+        quote! {}
+    };
+
+    let attrs = match PushConstantsAttrs::from_derive_input(&derive_input) {
+        Ok(attrs) => attrs,
+        Err(e) => return e.write_errors(),
+    };
+    let offset = attrs.offset.map(|offset| {
+        quote! {
+            const OFFSET: u32 = {
+                assert!(#offset % 4 == 0, "the offset of push constants must be a multiple of 4");
+                #offset
+            };
+        }
+    });
+
+    let struct_name = &derive_input.ident;
+    let (impl_generics, ty_generics, where_clause) = derive_input.generics.split_for_impl();
+
+    quote! {
+        #repr_c_check
+        unsafe impl #impl_generics #CRATE::PushConstants for #struct_name #ty_generics #where_clause {
+            const SIZE: u32 = {
+                let size = ::std::mem::size_of::<Self>();
+                assert!(size % 4 == 0, "the size of push constants must be a multiple of 4");
+                size as u32
+            };
+            #offset
+        }
+    }
+}
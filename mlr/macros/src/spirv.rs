@@ -0,0 +1,265 @@
+//! Minimal SPIR-V reflection, for checking shader interfaces at compile time
+use std::collections::HashMap;
+
+const MAGIC: u32 = 0x07230203;
+const HEADER_LEN: usize = 5;
+
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+enum Type {
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { member_count: u32 },
+    Pointer { pointee: u32 },
+}
+
+/// A descriptor binding declared in a shader.
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    /// Name of the `vk::DescriptorType` constant.
+    pub descriptor_type: &'static str,
+    /// Number of descriptors, or `None` for runtime-sized arrays.
+    pub count: Option<u32>,
+}
+
+/// A member of a struct with an explicit layout.
+pub struct StructMember {
+    pub name: String,
+    pub offset: u32,
+}
+
+/// Names, types and decorations of a SPIR-V module.
+#[derive(Default)]
+pub struct Module {
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    names: HashMap<u32, String>,
+    member_names: HashMap<(u32, u32), String>,
+    member_offsets: HashMap<(u32, u32), u32>,
+    /// (pointer type, id, storage class) of each variable
+    variables: Vec<(u32, u32, u32)>,
+    bindings: HashMap<u32, u32>,
+    descriptor_sets: HashMap<u32, u32>,
+    buffer_blocks: Vec<u32>,
+}
+
+/// Decodes a nul-terminated literal string.
+fn literal_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .take_while(|&b| b != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+    /// Parses a SPIR-V module from its binary representation.
+    pub fn parse(bytes: &[u8]) -> Result<Module, String> {
+        if bytes.len() % 4 != 0 {
+            return Err("the size of the module is not a multiple of 4".to_string());
+        }
+        let spirv: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        if spirv.len() < HEADER_LEN || spirv[0] != MAGIC {
+            return Err("not a SPIR-V module".to_string());
+        }
+
+        let mut module = Module::default();
+        let mut words = &spirv[HEADER_LEN..];
+        while !words.is_empty() {
+            let word_count = (words[0] >> 16) as usize;
+            let opcode = words[0] & 0xFFFF;
+            if word_count == 0 || word_count > words.len() {
+                return Err("invalid instruction length".to_string());
+            }
+            let ops = &words[1..word_count];
+            match opcode {
+                OP_NAME if ops.len() >= 2 => {
+                    module.names.insert(ops[0], literal_string(&ops[1..]));
+                }
+                OP_MEMBER_NAME if ops.len() >= 3 => {
+                    module.member_names.insert((ops[0], ops[1]), literal_string(&ops[2..]));
+                }
+                OP_TYPE_IMAGE if ops.len() >= 7 => {
+                    module.types.insert(
+                        ops[0],
+                        Type::Image {
+                            dim: ops[2],
+                            sampled: ops[6],
+                        },
+                    );
+                }
+                OP_TYPE_SAMPLER if !ops.is_empty() => {
+                    module.types.insert(ops[0], Type::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE if !ops.is_empty() => {
+                    module.types.insert(ops[0], Type::SampledImage);
+                }
+                OP_TYPE_ARRAY if ops.len() >= 3 => {
+                    module.types.insert(
+                        ops[0],
+                        Type::Array {
+                            element: ops[1],
+                            length: ops[2],
+                        },
+                    );
+                }
+                OP_TYPE_RUNTIME_ARRAY if ops.len() >= 2 => {
+                    module.types.insert(ops[0], Type::RuntimeArray { element: ops[1] });
+                }
+                OP_TYPE_STRUCT if !ops.is_empty() => {
+                    module.types.insert(
+                        ops[0],
+                        Type::Struct {
+                            member_count: (ops.len() - 1) as u32,
+                        },
+                    );
+                }
+                OP_TYPE_POINTER if ops.len() >= 3 => {
+                    module.types.insert(ops[0], Type::Pointer { pointee: ops[2] });
+                }
+                OP_CONSTANT if ops.len() >= 3 => {
+                    module.constants.insert(ops[1], ops[2]);
+                }
+                OP_VARIABLE if ops.len() >= 3 => {
+                    module.variables.push((ops[0], ops[1], ops[2]));
+                }
+                OP_DECORATE if ops.len() >= 2 => match ops[1] {
+                    DECORATION_BUFFER_BLOCK => module.buffer_blocks.push(ops[0]),
+                    DECORATION_BINDING if ops.len() >= 3 => {
+                        module.bindings.insert(ops[0], ops[2]);
+                    }
+                    DECORATION_DESCRIPTOR_SET if ops.len() >= 3 => {
+                        module.descriptor_sets.insert(ops[0], ops[2]);
+                    }
+                    _ => {}
+                },
+                OP_MEMBER_DECORATE if ops.len() >= 4 && ops[2] == DECORATION_OFFSET => {
+                    module.member_offsets.insert((ops[0], ops[1]), ops[3]);
+                }
+                _ => {}
+            }
+            words = &words[word_count..];
+        }
+        Ok(module)
+    }
+
+    /// Returns the descriptor type and count of a variable with the specified type and storage class.
+    fn descriptor_info(&self, ty: u32, storage_class: u32) -> Option<(&'static str, Option<u32>)> {
+        let (ty, count) = match *self.types.get(&ty)? {
+            Type::Array { element, length } => (element, Some(*self.constants.get(&length)?)),
+            Type::RuntimeArray { element } => (element, None),
+            _ => (ty, Some(1)),
+        };
+        let descriptor_type = match *self.types.get(&ty)? {
+            Type::Sampler => "SAMPLER",
+            Type::SampledImage => "COMBINED_IMAGE_SAMPLER",
+            Type::Image { dim, sampled } => match (dim, sampled) {
+                (DIM_SUBPASS_DATA, _) => "INPUT_ATTACHMENT",
+                (DIM_BUFFER, 2) => "STORAGE_TEXEL_BUFFER",
+                (DIM_BUFFER, _) => "UNIFORM_TEXEL_BUFFER",
+                (_, 2) => "STORAGE_IMAGE",
+                _ => "SAMPLED_IMAGE",
+            },
+            Type::Struct { .. } => match storage_class {
+                STORAGE_CLASS_UNIFORM if self.buffer_blocks.contains(&ty) => "STORAGE_BUFFER",
+                STORAGE_CLASS_UNIFORM => "UNIFORM_BUFFER",
+                STORAGE_CLASS_STORAGE_BUFFER => "STORAGE_BUFFER",
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some((descriptor_type, count))
+    }
+
+    /// Returns the descriptor bindings declared in the module, sorted by set and binding number.
+    pub fn descriptor_bindings(&self) -> Vec<DescriptorBinding> {
+        let mut bindings: Vec<_> = self
+            .variables
+            .iter()
+            .filter_map(|&(result_type, id, storage_class)| {
+                if !matches!(
+                    storage_class,
+                    STORAGE_CLASS_UNIFORM_CONSTANT | STORAGE_CLASS_UNIFORM | STORAGE_CLASS_STORAGE_BUFFER
+                ) {
+                    return None;
+                }
+                let pointee = match *self.types.get(&result_type)? {
+                    Type::Pointer { pointee } => pointee,
+                    _ => return None,
+                };
+                let (descriptor_type, count) = self.descriptor_info(pointee, storage_class)?;
+                Some(DescriptorBinding {
+                    set: self.descriptor_sets.get(&id).cloned().unwrap_or(0),
+                    binding: *self.bindings.get(&id)?,
+                    descriptor_type,
+                    count,
+                })
+            })
+            .collect();
+        bindings.sort_by_key(|b| (b.set, b.binding));
+        bindings
+    }
+
+    /// Returns the named members of the struct type with the specified name and an explicit layout (i.e. used
+    /// in a uniform, storage or push constant block), or `None` if there's no such struct.
+    ///
+    /// Requires debug information in the module.
+    pub fn struct_members(&self, name: &str) -> Option<Vec<StructMember>> {
+        let mut ids: Vec<_> = self
+            .types
+            .iter()
+            .filter_map(|(&id, ty)| match *ty {
+                Type::Struct { member_count } => Some((id, member_count)),
+                _ => None,
+            })
+            .filter(|&(id, member_count)| {
+                self.names.get(&id).map(String::as_str) == Some(name)
+                    && (0..member_count).any(|i| self.member_offsets.contains_key(&(id, i)))
+            })
+            .collect();
+        ids.sort();
+        let (id, member_count) = ids.first().cloned()?;
+        Some(
+            (0..member_count)
+                .filter_map(|i| {
+                    Some(StructMember {
+                        name: self.member_names.get(&(id, i))?.clone(),
+                        offset: *self.member_offsets.get(&(id, i))?,
+                    })
+                })
+                .collect(),
+        )
+    }
+}
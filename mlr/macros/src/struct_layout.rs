@@ -150,7 +150,105 @@ pub(crate) fn generate_repr_c_struct_layout(
     })
 }
 
+/// Generates an implementation of `BlockLayout`, with the std140 and std430 layouts of the struct
+/// computed from the layouts of its fields, and a struct containing the layout of each member.
+fn generate_block_layout(derive_input: &syn::DeriveInput, fields: &Fields) -> TokenStream {
+    let struct_name = &derive_input.ident;
+    let vis = &derive_input.vis;
+    let (impl_generics, ty_generics, where_clause) = derive_input.generics.split_for_impl();
+    // `quote!` binds the variables interpolated in repetitions with `let`, which can't shadow the `CRATE` constant
+    let krate = &CRATE;
+
+    // `<vis> struct __MyStruct_BlockLayout { <vis> field: MemberLayout<Members of field> ... }`
+    let members_struct_name = Ident::new(
+        &format!("__{}_BlockLayout", struct_name.to_string()),
+        Span::call_site(),
+    );
+    let field_types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+    let member_types = field_types.iter().map(|ty| {
+        quote! { #krate::layout::MemberLayout<<#ty as #krate::layout::BlockLayout>::Members> }
+    });
+    let members_struct = match fields {
+        Fields::Named(_) => {
+            let names = fields.iter().map(|f| f.ident.as_ref().unwrap());
+            quote! {
+                #[doc(hidden)]
+                #[derive(Copy, Clone, Debug)]
+                #vis struct #members_struct_name {
+                    #(pub #names: #member_types,)*
+                }
+            }
+        }
+        _ => quote! {
+            #[doc(hidden)]
+            #[derive(Copy, Clone, Debug)]
+            #vis struct #members_struct_name(#(pub #member_types,)*);
+        },
+    };
+
+    // initializer of the members struct, for one of the `STD140` or `STD430` layouts
+    let members_init = |layout: &Ident, members: &Ident| {
+        let inits = fields.iter().enumerate().map(|(i, f)| {
+            let ty = &f.ty;
+            let member = quote! {
+                #krate::layout::MemberLayout {
+                    offset: offsets[#i],
+                    size: <#ty as #krate::layout::BlockLayout>::#layout.size,
+                    members: <#ty as #krate::layout::BlockLayout>::#members,
+                }
+            };
+            if let Some(ident) = &f.ident {
+                quote! { #ident: #member }
+            } else {
+                let index = syn::Index::from(i);
+                quote! { #index: #member }
+            }
+        });
+        quote! {
+            {
+                let offsets = #krate::layout::member_offsets(
+                    [#(<#field_types as #krate::layout::BlockLayout>::#layout,)*]
+                );
+                #members_struct_name { #(#inits,)* }
+            }
+        }
+    };
+    let std140 = Ident::new("STD140", Span::call_site());
+    let std430 = Ident::new("STD430", Span::call_site());
+    let std140_members_init = members_init(&std140, &Ident::new("STD140_MEMBERS", Span::call_site()));
+    let std430_members_init = members_init(&std430, &Ident::new("STD430_MEMBERS", Span::call_site()));
+
+    quote! {
+        #members_struct
+
+        impl #impl_generics #krate::layout::BlockLayout for #struct_name #ty_generics #where_clause {
+            type Members = #members_struct_name;
+            const STD140: #krate::layout::TypeLayout = #krate::layout::std140_struct_layout(
+                &[#(<#field_types as #krate::layout::BlockLayout>::#std140,)*]
+            );
+            const STD430: #krate::layout::TypeLayout = #krate::layout::std430_struct_layout(
+                &[#(<#field_types as #krate::layout::BlockLayout>::#std430,)*]
+            );
+            const STD140_MEMBERS: #members_struct_name = #std140_members_init;
+            const STD430_MEMBERS: #members_struct_name = #std430_members_init;
+        }
+
+        impl #impl_generics #struct_name #ty_generics #where_clause {
+            /// Offsets and sizes of the fields in a uniform block (std140), including the members of
+            /// nested structs.
+            #vis const STD140_LAYOUT: #members_struct_name =
+                <Self as #krate::layout::BlockLayout>::STD140_MEMBERS;
+            /// Offsets and sizes of the fields in a storage block (std430), including the members of
+            /// nested structs.
+            #vis const STD430_LAYOUT: #members_struct_name =
+                <Self as #krate::layout::BlockLayout>::STD430_MEMBERS;
+        }
+    }
+}
+
 // Not exactly a derive, but adds an inherent impl block with a `LAYOUT` associated constant.
+//
+// Also implements `BlockLayout`, with `STD140_LAYOUT` and `STD430_LAYOUT` associated constants.
 pub fn derive(input: proc_macro::TokenStream) -> TokenStream {
     let derive_input: syn::DeriveInput = match syn::parse(input) {
         Ok(input) => input,
@@ -190,14 +288,17 @@ pub fn derive(input: proc_macro::TokenStream) -> TokenStream {
     let struct_name = &derive_input.ident;
     let (impl_generics, ty_generics, where_clause) = derive_input.generics.split_for_impl();
     let layout_struct = &struct_layout.layout_struct;
+    let layout_struct_name = &layout_struct.ident;
     let layout_const_fn = &struct_layout.layout_const_fn;
-    let vis = &derive_input.vis;
+    let block_layout = generate_block_layout(&derive_input, fields);
 
     quote! {
         #repr_c_check
         #layout_struct
         impl #impl_generics #struct_name #ty_generics #where_clause {
             #layout_const_fn
+            const LAYOUT: #layout_struct_name = Self::layout();
         }
+        #block_layout
     }
 }
@@ -2,10 +2,30 @@ use crate::{
     struct_layout::{ensure_repr_c_derive_input, generate_repr_c_struct_layout, has_repr_c_attr},
     CRATE,
 };
+use darling::{util::SpannedValue, FromDeriveInput, FromField};
 use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{__private::str, spanned::Spanned};
 
+#[derive(FromDeriveInput)]
+#[darling(attributes(vertex))]
+struct VertexStruct {
+    /// `"vertex"` (the default) or `"instance"`.
+    #[darling(default)]
+    input_rate: Option<SpannedValue<String>>,
+}
+
+#[derive(FromField)]
+#[darling(attributes(vertex))]
+struct VertexAttr {
+    /// Explicit shader location of the attribute.
+    #[darling(default)]
+    location: Option<u32>,
+    /// Name of the `vk::Format` of the attribute, overriding the format of the field type.
+    #[darling(default)]
+    format: Option<syn::Ident>,
+}
+
 /*pub fn generate_structured_buffer_data(
     derive_input: &syn::DeriveInput,
     fields: &FieldList,
@@ -83,6 +103,20 @@ pub fn derive(input: proc_macro::TokenStream) -> TokenStream {
         }
     };
 
+    let s = match <VertexStruct as FromDeriveInput>::from_derive_input(&derive_input) {
+        Ok(s) => s,
+        Err(e) => return e.write_errors(),
+    };
+    let input_rate = match s.input_rate.as_ref().map(|rate| rate.as_str()) {
+        None | Some("vertex") => quote! { #CRATE::vk::VertexInputRate::VERTEX },
+        Some("instance") => quote! { #CRATE::vk::VertexInputRate::INSTANCE },
+        Some(_) => {
+            let span = s.input_rate.as_ref().unwrap().span();
+            return syn::Error::new(span, "expected `\"vertex\"` or `\"instance\"`")
+                .into_compile_error();
+        }
+    };
+
     // check for `#[repr(C)]`
     let repr_c_check = if !has_repr_c_attr(&derive_input) {
         syn::Error::new(
@@ -101,28 +135,44 @@ pub fn derive(input: proc_macro::TokenStream) -> TokenStream {
     };
 
     // `VertexAttribute { format: <FieldType as VertexAttributeType>::FORMAT, offset: OFFSET_field_index }`
-    let attribs = fields.iter().enumerate().map(|(i, f)| {
-        let field_ty = &f.ty;
-        match f.ident {
-            Some(ref ident) => {
-                quote! {
-                    #CRATE::VertexAttribute {
-                        format: <#field_ty as #CRATE::VertexAttributeType>::FORMAT,
-                        offset: Self::layout().#ident.offset as u32,
-                    }
-                }
+    let mut errors = Vec::new();
+    let mut attribs = Vec::new();
+    for (i, f) in fields.iter().enumerate() {
+        let attr = match <VertexAttr as FromField>::from_field(f) {
+            Ok(attr) => attr,
+            Err(e) => {
+                errors.push(e.write_errors());
+                continue;
             }
+        };
+        let field_ty = &f.ty;
+        let format = match attr.format {
+            Some(ref format) => quote! { #CRATE::vk::Format::#format },
+            None => quote! { <#field_ty as #CRATE::VertexAttributeType>::FORMAT },
+        };
+        let location = match attr.location {
+            Some(location) => quote! { Some(#location) },
+            None => quote! { None },
+        };
+        let member = match f.ident {
+            Some(ref ident) => quote! { #ident },
             None => {
                 let index = syn::Index::from(i);
-                quote! {
-                    #CRATE::VertexAttribute {
-                        format: <#field_ty as #CRATE::VertexAttributeType>::FORMAT,
-                        offset: Self::layout().#index.offset as u32,
-                    }
-                }
+                quote! { #index }
             }
-        }
-    });
+        };
+        attribs.push(quote! {
+            #CRATE::VertexAttribute {
+                format: #format,
+                offset: Self::layout().#member.offset as u32,
+                location: #location,
+            }
+        });
+    }
+
+    if !errors.is_empty() {
+        return quote! { #(#errors)* };
+    }
 
     let struct_name = &derive_input.ident;
     let (impl_generics, ty_generics, where_clause) = derive_input.generics.split_for_impl();
@@ -139,6 +189,7 @@ pub fn derive(input: proc_macro::TokenStream) -> TokenStream {
             const ATTRIBUTES: &'static [#CRATE::VertexAttribute] = {
                 &[#(#attribs,)*]
             };
+            const INPUT_RATE: #CRATE::vk::VertexInputRate = #input_rate;
         }
     }
 }
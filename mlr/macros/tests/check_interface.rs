@@ -0,0 +1,41 @@
+use mlr::{check_interface, sampler::Linear_ClampToEdge, CombinedImageSampler2D, StructLayout};
+
+// `shaders/check_interface.spv` declares:
+//
+//     layout(set = 0, binding = 0) uniform Globals { float time; float exposure; vec4 tint; } globals;
+//     layout(set = 0, binding = 1) uniform texture2D t_color;
+//     layout(set = 1, binding = 0) uniform sampler2D shadow_maps[4];
+
+#[derive(mlr::Arguments)]
+#[repr(C)]
+struct MaterialArguments<'a> {
+    u_color: [f32; 4],
+    #[argument(binding = 1, stages(vertex, fragment))]
+    t_color: mlr::SampledImage2D<'a>,
+}
+
+#[derive(mlr::Arguments)]
+#[repr(C)]
+struct ShadowArguments<'a> {
+    #[argument(binding = 0)]
+    shadow_maps: [CombinedImageSampler2D<'a, Linear_ClampToEdge>; 4],
+}
+
+#[repr(C)]
+#[derive(StructLayout, Copy, Clone)]
+struct Globals {
+    time: f32,
+    exposure: f32,
+    _padding: [f32; 2],
+    tint: [f32; 4],
+}
+
+check_interface!(MaterialArguments, "tests/shaders/check_interface.spv");
+check_interface!(ShadowArguments, "tests/shaders/check_interface.spv", set = 1);
+check_interface!(Globals, "tests/shaders/check_interface.spv");
+
+#[test]
+fn test_check_interface() {
+    assert_eq!(MaterialArguments::DESCRIPTOR_SET_LAYOUT_BINDINGS.len(), 2);
+    assert_eq!(ShadowArguments::DESCRIPTOR_SET_LAYOUT_BINDINGS[0].descriptor_count, 4);
+}
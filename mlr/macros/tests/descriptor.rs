@@ -1,4 +1,4 @@
-use mlr::Arguments;
+use mlr::{sampler::Linear_ClampToEdge, vk, Arguments, CombinedImageSampler2D, DescriptorBinding, PushConstants};
 use std::{marker::PhantomData, mem};
 
 /*#[derive(Copy, Clone, Debug)]
@@ -18,6 +18,37 @@ struct MaterialArguments<'a> {
     t_color: mlr::SampledImage2D<'a>,
 }
 
+#[derive(mlr::Arguments)]
+#[repr(C)]
+struct ShadowArguments<'a> {
+    #[argument(binding = 0)]
+    shadow_maps: [CombinedImageSampler2D<'a, Linear_ClampToEdge>; 4],
+}
+
+#[derive(mlr::Arguments)]
+#[repr(C)]
+struct BindlessArguments<'a> {
+    #[argument(binding = 1, variable_count = 1024)]
+    textures: &'a [mlr::SampledImage2D<'a>],
+}
+
+#[repr(C)]
+#[derive(mlr::PushConstants, Copy, Clone)]
+struct DrawParams {
+    transform: [[f32; 4]; 4],
+    texture_index: u32,
+    _pad: [u32; 3],
+}
+
+#[derive(mlr::Arguments)]
+#[repr(C)]
+struct DrawArguments<'a> {
+    #[argument(push_constant)]
+    params: DrawParams,
+    #[argument(binding = 1, variable_count = 16)]
+    textures: &'a [mlr::SampledImage2D<'a>],
+}
+
 #[repr(C)]
 struct PerObjectData {
     resolution: [f32; 2],
@@ -30,3 +61,51 @@ fn test_descriptor() {
     //eprintln!("GlobalResources: {:#?}", GlobalResources::DESC);
     //eprintln!("PerObjectResources: {:#?}", PerObjectResources::DESC);
 }
+
+#[test]
+fn test_descriptor_array() {
+    type ShadowMaps<'a> = [CombinedImageSampler2D<'a, Linear_ClampToEdge>; 4];
+    assert_eq!(<ShadowMaps as DescriptorBinding>::DESCRIPTOR_COUNT, 4);
+    assert_eq!(
+        <ShadowMaps as DescriptorBinding>::DESCRIPTOR_TYPE,
+        vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+    );
+}
+
+#[test]
+fn test_variable_count_binding() {
+    let args = BindlessArguments { textures: &[] };
+    let bindings = args.get_descriptor_set_layout_bindings();
+    assert_eq!(bindings.len(), 1);
+    assert_eq!(bindings[0].binding, 1);
+    assert_eq!(bindings[0].descriptor_count, 1024);
+    assert_eq!(bindings[0].descriptor_type, vk::DescriptorType::SAMPLED_IMAGE);
+    assert_eq!(
+        args.get_descriptor_set_layout_binding_flags(),
+        &[vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT | vk::DescriptorBindingFlags::PARTIALLY_BOUND]
+    );
+    assert_eq!(args.variable_descriptor_count(), Some(0));
+}
+
+#[test]
+fn test_push_constant_argument() {
+    let args = DrawArguments {
+        params: DrawParams {
+            transform: [[0.0; 4]; 4],
+            texture_index: 7,
+            _pad: [0; 3],
+        },
+        textures: &[],
+    };
+    // push constants don't take a binding
+    assert_eq!(args.get_descriptor_set_layout_bindings().len(), 1);
+
+    let range = args.push_constant_range().unwrap();
+    assert_eq!(range.size, <DrawParams as PushConstants>::SIZE);
+    assert_eq!(range.stage_flags, vk::ShaderStageFlags::ALL);
+    assert_eq!(DrawArguments::PUSH_CONSTANT_RANGE.unwrap().size, 80);
+
+    let data = args.push_constant_data().unwrap();
+    assert_eq!(data.len(), 80);
+    assert_eq!(&data[64..68], &7u32.to_ne_bytes());
+}
@@ -1,195 +1,132 @@
-use graal::{vk, FragmentOutputInterface};
-use std::ptr;
+use mlr::{
+    image::ImageAny,
+    render_pass::{AttachmentLoadOp, AttachmentStoreOp, ClearColorValue},
+    vk, FragmentOutputInterface,
+};
 
 #[derive(FragmentOutputInterface)]
-struct GBuffers {
+struct GBuffers<'a> {
     /// Color buffer, R16G16B16A16_SFLOAT
-    #[attachment(
-        color,
-        format = "R16G16B16A16_SFLOAT",
-        samples = 1,
-        load_op = "CLEAR",
-        store_op = "STORE",
-        layout = "COLOR_ATTACHMENT_OPTIMAL"
-    )]
-    color: graal::ImageInfo,
+    #[attachment(color, format = "R16G16B16A16_SFLOAT", load_op = "CLEAR", store_op = "STORE")]
+    color: &'a ImageAny,
 
     /// Normals, RG16_SFLOAT
     #[attachment(
         color,
         format = "R16G16_SFLOAT",
-        samples = 1,
         load_op = "CLEAR",
-        store_op = "STORE",
-        layout = "COLOR_ATTACHMENT_OPTIMAL"
+        clear_value = "[0.5, 0.5, 0.0, 0.0]",
+        store_op = "STORE"
     )]
-    normal: graal::ImageInfo,
+    normal: &'a ImageAny,
 
     /// Tangents: RG16_SFLOAT
+    #[attachment(color, format = "R16G16_SFLOAT", load_op = "LOAD", store_op = "STORE")]
+    tangent: &'a ImageAny,
+
+    /// Object IDs: R32_UINT
     #[attachment(
         color,
-        format = "R16G16_SFLOAT",
-        samples = 1,
+        format = "R32_UINT",
         load_op = "CLEAR",
-        store_op = "STORE",
-        layout = "COLOR_ATTACHMENT_OPTIMAL"
+        clear_value = "[0u32, 0, 0, 0]",
+        store_op = "STORE"
     )]
-    tangent: graal::ImageInfo,
+    object_id: &'a ImageAny,
 
     /// Depth: D32_SFLOAT
-    #[attachment(
-        depth,
-        format = "D32_SFLOAT",
-        samples = 1,
-        load_op = "CLEAR",
-        store_op = "STORE",
-        layout = "DEPTH_STENCIL_ATTACHMENT_OPTIMAL"
-    )]
-    depth: graal::ImageInfo,
-
-    #[framebuffer]
-    framebuffer: vk::Framebuffer,
+    #[attachment(depth, format = "D32_SFLOAT", load_op = "CLEAR")]
+    depth: &'a ImageAny,
 }
 
 #[test]
 fn test_fragment_output() {
-    let attachments = <GBuffers as FragmentOutputInterface>::ATTACHMENTS;
-
-    assert_eq!(
-        attachments[0].flags,
-        vk::AttachmentDescriptionFlags::empty()
-    );
-    assert_eq!(attachments[0].format, vk::Format::R16G16B16A16_SFLOAT);
-    assert_eq!(attachments[0].samples, vk::SampleCountFlags::TYPE_1);
-    assert_eq!(attachments[0].load_op, vk::AttachmentLoadOp::CLEAR);
-    assert_eq!(attachments[0].store_op, vk::AttachmentStoreOp::STORE);
     assert_eq!(
-        attachments[0].stencil_load_op,
-        vk::AttachmentLoadOp::DONT_CARE
+        <GBuffers as FragmentOutputInterface>::COLOR_ATTACHMENT_FORMATS,
+        &[
+            vk::Format::R16G16B16A16_SFLOAT,
+            vk::Format::R16G16_SFLOAT,
+            vk::Format::R16G16_SFLOAT,
+            vk::Format::R32_UINT
+        ]
     );
     assert_eq!(
-        attachments[0].stencil_store_op,
-        vk::AttachmentStoreOp::DONT_CARE
-    );
-    assert_eq!(
-        attachments[0].initial_layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
-    );
-    assert_eq!(
-        attachments[0].final_layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        <GBuffers as FragmentOutputInterface>::DEPTH_ATTACHMENT_FORMAT,
+        Some(vk::Format::D32_SFLOAT)
     );
+    assert_eq!(<GBuffers as FragmentOutputInterface>::STENCIL_ATTACHMENT_FORMAT, None);
 
     assert_eq!(
-        attachments[1].flags,
-        vk::AttachmentDescriptionFlags::empty()
-    );
-    assert_eq!(attachments[1].format, vk::Format::R16G16_SFLOAT);
-    assert_eq!(attachments[1].samples, vk::SampleCountFlags::TYPE_1);
-    assert_eq!(attachments[1].load_op, vk::AttachmentLoadOp::CLEAR);
-    assert_eq!(attachments[1].store_op, vk::AttachmentStoreOp::STORE);
-    assert_eq!(
-        attachments[1].stencil_load_op,
-        vk::AttachmentLoadOp::DONT_CARE
-    );
-    assert_eq!(
-        attachments[1].stencil_store_op,
-        vk::AttachmentStoreOp::DONT_CARE
-    );
-    assert_eq!(
-        attachments[1].initial_layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
-    );
-    assert_eq!(
-        attachments[1].final_layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        <GBuffers as FragmentOutputInterface>::color_attachment_ops(),
+        vec![
+            (
+                AttachmentLoadOp::Clear {
+                    value: ClearColorValue::Float([0.0, 0.0, 0.0, 0.0])
+                },
+                AttachmentStoreOp::Store
+            ),
+            (
+                AttachmentLoadOp::Clear {
+                    value: ClearColorValue::Float([0.5, 0.5, 0.0, 0.0])
+                },
+                AttachmentStoreOp::Store
+            ),
+            (AttachmentLoadOp::Load, AttachmentStoreOp::Store),
+            (
+                AttachmentLoadOp::Clear {
+                    value: ClearColorValue::Uint([0, 0, 0, 0])
+                },
+                AttachmentStoreOp::Store
+            ),
+        ]
+    );
+    assert_eq!(
+        <GBuffers as FragmentOutputInterface>::depth_stencil_attachment_ops(),
+        Some((AttachmentLoadOp::Clear { value: 1.0 }, AttachmentStoreOp::DontCare))
     );
+}
 
-    assert_eq!(
-        attachments[2].flags,
-        vk::AttachmentDescriptionFlags::empty()
-    );
-    assert_eq!(attachments[2].format, vk::Format::R16G16_SFLOAT);
-    assert_eq!(attachments[2].samples, vk::SampleCountFlags::TYPE_1);
-    assert_eq!(attachments[2].load_op, vk::AttachmentLoadOp::CLEAR);
-    assert_eq!(attachments[2].store_op, vk::AttachmentStoreOp::STORE);
-    assert_eq!(
-        attachments[2].stencil_load_op,
-        vk::AttachmentLoadOp::DONT_CARE
-    );
-    assert_eq!(
-        attachments[2].stencil_store_op,
-        vk::AttachmentStoreOp::DONT_CARE
-    );
-    assert_eq!(
-        attachments[2].initial_layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
-    );
-    assert_eq!(
-        attachments[2].final_layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
-    );
+#[derive(FragmentOutputInterface)]
+struct ColorDepthStencil<'a> {
+    #[attachment(color, format = "R8G8B8A8_UNORM")]
+    color: &'a ImageAny,
+    #[attachment(depth, format = "D24_UNORM_S8_UINT", load_op = "LOAD", store_op = "STORE")]
+    depth_stencil: &'a ImageAny,
+}
 
+#[derive(FragmentOutputInterface)]
+struct StencilOnly<'a> {
+    #[attachment(depth, format = "S8_UINT", load_op = "CLEAR", clear_value = "0.0")]
+    stencil: &'a ImageAny,
+}
+
+#[test]
+fn test_depth_stencil_output() {
     assert_eq!(
-        attachments[3].flags,
-        vk::AttachmentDescriptionFlags::empty()
-    );
-    assert_eq!(attachments[3].format, vk::Format::D32_SFLOAT);
-    assert_eq!(attachments[3].samples, vk::SampleCountFlags::TYPE_1);
-    assert_eq!(attachments[3].load_op, vk::AttachmentLoadOp::CLEAR);
-    assert_eq!(attachments[3].store_op, vk::AttachmentStoreOp::STORE);
-    assert_eq!(
-        attachments[3].stencil_load_op,
-        vk::AttachmentLoadOp::DONT_CARE
+        <ColorDepthStencil as FragmentOutputInterface>::color_attachment_ops(),
+        vec![(AttachmentLoadOp::DontCare, AttachmentStoreOp::DontCare)]
     );
     assert_eq!(
-        attachments[3].stencil_store_op,
-        vk::AttachmentStoreOp::DONT_CARE
+        <ColorDepthStencil as FragmentOutputInterface>::DEPTH_ATTACHMENT_FORMAT,
+        Some(vk::Format::D24_UNORM_S8_UINT)
     );
     assert_eq!(
-        attachments[3].initial_layout,
-        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        <ColorDepthStencil as FragmentOutputInterface>::STENCIL_ATTACHMENT_FORMAT,
+        Some(vk::Format::D24_UNORM_S8_UINT)
     );
     assert_eq!(
-        attachments[3].final_layout,
-        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        <ColorDepthStencil as FragmentOutputInterface>::depth_stencil_attachment_ops(),
+        Some((AttachmentLoadOp::Load, AttachmentStoreOp::Store))
     );
 
-    let color_att = <GBuffers as FragmentOutputInterface>::COLOR_ATTACHMENTS;
-    assert_eq!(color_att[0].attachment, 0);
+    assert!(<StencilOnly as FragmentOutputInterface>::COLOR_ATTACHMENT_FORMATS.is_empty());
+    assert_eq!(<StencilOnly as FragmentOutputInterface>::DEPTH_ATTACHMENT_FORMAT, None);
     assert_eq!(
-        color_att[0].layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        <StencilOnly as FragmentOutputInterface>::STENCIL_ATTACHMENT_FORMAT,
+        Some(vk::Format::S8_UINT)
     );
-    assert_eq!(color_att[1].attachment, 1);
     assert_eq!(
-        color_att[1].layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        <StencilOnly as FragmentOutputInterface>::depth_stencil_attachment_ops(),
+        Some((AttachmentLoadOp::Clear { value: 0.0 }, AttachmentStoreOp::DontCare))
     );
-    assert_eq!(color_att[2].attachment, 2);
-    assert_eq!(
-        color_att[2].layout,
-        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
-    );
-
-    let depth_att = <GBuffers as FragmentOutputInterface>::DEPTH_ATTACHMENT;
-    assert!(depth_att.is_some());
-    assert_eq!(depth_att.unwrap().attachment, 3);
-    assert_eq!(
-        depth_att.unwrap().layout,
-        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
-    );
-
-    /*assert_eq!(
-        <GBuffers as FragmentOutputInterface>::RENDER_PASS_CREATE_INFO,
-        vk::RenderPassCreateInfo {
-            attachment_count: <GBuffers as FragmentOutputInterface>::COLOR_ATTACHMENTS.len(),
-            p_attachments: <GBuffers as FragmentOutputInterface>::COLOR_ATTACHMENTS.as_ptr(),
-            subpass_count: 1,
-            p_subpasses: <GBuffers as FragmentOutputInterface>::RENDER_PASS_CREATE_INFO.p_subpasses, // TODO
-            dependency_count: 0,
-            p_dependencies: ptr::null(),
-            ..Default::default()
-        }
-    );*/
 }
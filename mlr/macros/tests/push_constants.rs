@@ -0,0 +1,34 @@
+use mlr::{vk, PushConstants};
+
+#[repr(C)]
+#[derive(PushConstants, Copy, Clone)]
+struct DrawParams {
+    transform: [[f32; 4]; 4],
+    color: [f32; 4],
+    index: u32,
+    _pad: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(PushConstants, Copy, Clone)]
+#[push_constants(offset = 96)]
+struct MaterialParams {
+    base_color: [f32; 4],
+}
+
+#[test]
+fn test_push_constants() {
+    assert_eq!(<DrawParams as PushConstants>::SIZE, 96);
+    let range = DrawParams::range();
+    assert_eq!(range.offset, 0);
+    assert_eq!(range.size, 96);
+    assert_eq!(range.stage_flags, vk::ShaderStageFlags::ALL);
+}
+
+#[test]
+fn test_push_constants_offset() {
+    assert_eq!(<MaterialParams as PushConstants>::OFFSET, 96);
+    let range = MaterialParams::range();
+    assert_eq!(range.offset, 96);
+    assert_eq!(range.size, 16);
+}
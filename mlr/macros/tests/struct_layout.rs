@@ -1,4 +1,8 @@
-use mlr::utils::FieldLayout;
+use mlr::{
+    layout::{BlockLayout, MatrixLayout},
+    utils::FieldLayout,
+    StructLayout,
+};
 
 #[repr(C)]
 #[derive(StructLayout, Copy, Clone)]
@@ -8,7 +12,106 @@ struct TestLayout2 {
     c: [f32; 3],
 }
 
+#[repr(C)]
+#[derive(StructLayout, Copy, Clone)]
+struct Light {
+    position: [f32; 3],
+    intensity: f32,
+    color: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(StructLayout, Copy, Clone)]
+struct SceneUniforms {
+    time: f32,
+    light: Light,
+    exposure: f32,
+    view: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(StructLayout, Copy, Clone)]
+struct Transforms {
+    normal_matrix: [[f32; 3]; 3],
+    uv_transform: [[f32; 2]; 2],
+    model: [[f32; 4]; 4],
+}
+
 #[test]
 fn test_vertex_layout() {
     assert_eq!(TestLayout2::LAYOUT.a.offset, 0);
 }
+
+#[test]
+fn test_std140_layout() {
+    // vec3 followed by a float packs into the same vec4 slot
+    assert_eq!(TestLayout2::STD140_LAYOUT.a.offset, 0);
+    assert_eq!(TestLayout2::STD140_LAYOUT.b.offset, 12);
+    assert_eq!(TestLayout2::STD140_LAYOUT.c.offset, 16);
+
+    assert_eq!(Light::STD140.align, 16);
+    assert_eq!(Light::STD140.size, 32);
+    assert_eq!(Light::STD430.align, 16);
+
+    let layout = SceneUniforms::STD140_LAYOUT;
+    assert_eq!(layout.time.offset, 0);
+    // structs are aligned to 16 bytes
+    assert_eq!(layout.light.offset, 16);
+    assert_eq!(layout.light.size, 32);
+    assert_eq!(layout.light.members.intensity.offset, 12);
+    assert_eq!(layout.light.members.color.offset, 16);
+    // the member following a struct starts after its padding
+    assert_eq!(layout.exposure.offset, 48);
+    assert_eq!(layout.view.offset, 64);
+    assert_eq!(SceneUniforms::STD140.size, 128);
+}
+
+#[test]
+fn test_std430_layout() {
+    #[repr(C)]
+    #[derive(StructLayout, Copy, Clone)]
+    struct Particle {
+        velocity: [f32; 2],
+    }
+
+    #[repr(C)]
+    #[derive(StructLayout, Copy, Clone)]
+    struct Emitter {
+        rate: f32,
+        particle: Particle,
+    }
+
+    // no rounding of struct alignment to 16 bytes in std430
+    assert_eq!(Particle::STD430.align, 8);
+    assert_eq!(Emitter::STD430_LAYOUT.particle.offset, 8);
+    assert_eq!(Emitter::STD140_LAYOUT.particle.offset, 16);
+    // the Rust layout differs: arrays of floats are only 4-byte aligned
+    assert_eq!(Emitter::LAYOUT.particle, FieldLayout { offset: 4, size: 8 });
+}
+
+#[test]
+fn test_matrix_layout() {
+    let std140 = Transforms::STD140_LAYOUT;
+    // vec3 columns are padded to 16 bytes
+    assert_eq!(
+        std140.normal_matrix.members,
+        MatrixLayout {
+            columns: 3,
+            rows: 3,
+            stride: 16
+        }
+    );
+    assert_eq!(std140.normal_matrix.size, 48);
+    assert_eq!(std140.uv_transform.offset, 48);
+    assert_eq!(std140.uv_transform.members.stride, 16);
+    assert_eq!(std140.model.offset, 80);
+
+    // std430 packs the columns of mat2 but not those of mat3
+    let std430 = Transforms::STD430_LAYOUT;
+    assert_eq!(std430.normal_matrix.members.stride, 16);
+    assert_eq!(std430.uv_transform.offset, 48);
+    assert_eq!(std430.uv_transform.members.stride, 8);
+    assert_eq!(std430.uv_transform.size, 16);
+    assert_eq!(std430.model.offset, 64);
+    assert_eq!(Transforms::STD430.size, 128);
+}
@@ -0,0 +1,134 @@
+use mlr::{vk, Norm, VertexAttribute, VertexAttributeType, VertexData, VertexInputLayout};
+
+/// A user-defined attribute type.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+struct Color([Norm<u8>; 4]);
+
+unsafe impl VertexAttributeType for Color {
+    const FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+}
+
+#[repr(C)]
+#[derive(VertexData, Copy, Clone)]
+struct Vertex {
+    pos: [f32; 3],
+    color: Color,
+    uv: [Norm<u16>; 2],
+}
+
+#[repr(C)]
+#[derive(VertexData, Copy, Clone)]
+struct PackedVertex {
+    pos: [f32; 3],
+    normal: [Norm<i8>; 4],
+    color: [Norm<u8>; 4],
+    uv: [Norm<u16>; 2],
+}
+
+#[repr(C)]
+#[derive(VertexData, Copy, Clone)]
+struct SkinnedVertex {
+    pos: [f32; 3],
+    // location 1 is unused in the shader
+    #[vertex(location = 2, format = "R16G16_SNORM")]
+    normal: [i16; 2],
+    joints: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(VertexData, Copy, Clone)]
+#[vertex(input_rate = "instance")]
+struct InstanceData {
+    offset: [f32; 3],
+    scale: f32,
+}
+
+#[test]
+fn test_vertex_attribute_types() {
+    assert_eq!(<Color as VertexAttributeType>::SIZE, 4);
+    assert_eq!(<[f32; 3] as VertexAttributeType>::ALIGN, 4);
+    assert_eq!(
+        <Vertex as VertexData>::ATTRIBUTES,
+        &[
+            VertexAttribute {
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 0,
+                location: None
+            },
+            VertexAttribute {
+                format: vk::Format::R8G8B8A8_UNORM,
+                offset: 12,
+                location: None
+            },
+            VertexAttribute {
+                format: vk::Format::R16G16_UNORM,
+                offset: 16,
+                location: None
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_vertex_attribute_overrides() {
+    assert_eq!(
+        <SkinnedVertex as VertexData>::ATTRIBUTES[1],
+        VertexAttribute {
+            format: vk::Format::R16G16_SNORM,
+            offset: 12,
+            location: Some(2)
+        }
+    );
+
+    let layout = VertexInputLayout::new()
+        .per_vertex::<SkinnedVertex>()
+        .per_instance::<Vertex>();
+    let locations: Vec<_> = layout.attributes.iter().map(|a| (a.location, a.binding)).collect();
+    // attributes following an explicit location continue from it
+    assert_eq!(locations, [(0, 0), (2, 0), (3, 0), (4, 1), (5, 1), (6, 1)]);
+}
+
+#[test]
+fn test_instance_input_rate() {
+    assert_eq!(<Vertex as VertexData>::INPUT_RATE, vk::VertexInputRate::VERTEX);
+    assert_eq!(<InstanceData as VertexData>::INPUT_RATE, vk::VertexInputRate::INSTANCE);
+
+    let layout = VertexInputLayout::new().buffer::<Vertex>().buffer::<InstanceData>();
+    let bindings: Vec<_> = layout
+        .bindings
+        .iter()
+        .map(|b| (b.binding, b.stride, b.input_rate))
+        .collect();
+    assert_eq!(
+        bindings,
+        [
+            (0, 20, vk::VertexInputRate::VERTEX),
+            (1, 16, vk::VertexInputRate::INSTANCE)
+        ]
+    );
+    assert_eq!(layout.attributes.len(), 5);
+}
+
+#[test]
+fn test_normalized_attributes() {
+    let formats: Vec<_> = <PackedVertex as VertexData>::ATTRIBUTES
+        .iter()
+        .map(|a| (a.format, a.offset))
+        .collect();
+    assert_eq!(
+        formats,
+        [
+            (vk::Format::R32G32B32_SFLOAT, 0),
+            (vk::Format::R8G8B8A8_SNORM, 12),
+            (vk::Format::R8G8B8A8_UNORM, 16),
+            (vk::Format::R16G16_UNORM, 20),
+        ]
+    );
+
+    assert_eq!(Norm::<u8>::from_f32(1.0), Norm(255));
+    assert_eq!(Norm::<u8>::from_f32(-0.5), Norm(0));
+    assert_eq!(Norm::<i8>::from_f32(-1.0), Norm(-127));
+    assert_eq!(Norm::<i16>::from(0.5), Norm(16384));
+    assert_eq!(Norm::<i8>(-128).to_f32(), -1.0);
+}
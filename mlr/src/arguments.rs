@@ -1,4 +1,10 @@
-use crate::{buffer::BufferAny, image::ImageAny, sampler::SamplerType, vk};
+use crate::{
+    buffer::BufferAny,
+    descriptor_cache::DescriptorResource,
+    image::ImageAny,
+    sampler::{SamplerCache, SamplerType},
+    vk,
+};
 use graal::{Device, PassBuilder};
 use graal_spirv::typedesc::TypeDesc;
 use kyute_common::Atom;
@@ -116,6 +122,32 @@ pub trait Arguments: ResourceAccess {
     /// Returns the descriptor set layout for this argument.
     fn get_descriptor_set_layout_bindings(&self) -> &[vk::DescriptorSetLayoutBinding];
 
+    /// Returns the flags of each binding returned by `get_descriptor_set_layout_bindings`, or an empty slice if
+    /// no binding has flags.
+    fn get_descriptor_set_layout_binding_flags(&self) -> &[vk::DescriptorBindingFlags] {
+        &[]
+    }
+
+    /// Returns the number of descriptors to allocate for the variable-count binding of the layout
+    /// (`VK_DESCRIPTOR_BINDING_VARIABLE_DESCRIPTOR_COUNT_BIT`), if there's one.
+    fn variable_descriptor_count(&self) -> Option<u32> {
+        None
+    }
+
+    /// Returns the push constant range of the `#[argument(push_constant)]` field, if there's one.
+    ///
+    /// The range must be declared in the pipeline layout (`GraphicsPipelineConfig::push_constant_range`).
+    fn push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        None
+    }
+
+    /// Returns the bytes of the `#[argument(push_constant)]` field, if there's one.
+    ///
+    /// They are set with `vkCmdPushConstants` when the arguments are bound.
+    fn push_constant_data(&self) -> Option<&[u8]> {
+        None
+    }
+
     /// Updates a descriptor set with the data contained in the arguments.
     unsafe fn update_descriptor_set(
         &mut self,
@@ -123,6 +155,54 @@ pub trait Arguments: ResourceAccess {
         descriptor_set_builder: &mut DescriptorSetBuilder,
         update_template: Option<vk::DescriptorUpdateTemplate>,
     );
+
+    /// Collects the resources referenced by each binding, used as the key of the descriptor set cache.
+    ///
+    /// Returns false if the descriptors can't be reused (e.g. if the arguments contain uniforms that are
+    /// uploaded on every use).
+    fn descriptor_resources(&self, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        let _ = resources;
+        false
+    }
+
+    /// Collects the size of the buffer ranges bound to each buffer binding, used to validate the arguments
+    /// against the uniform blocks declared in shaders.
+    fn buffer_ranges(&self, ranges: &mut Vec<(u32, vk::DeviceSize)>) {
+        let _ = ranges;
+    }
+}
+
+enum DescriptorInfoKind {
+    Image,
+    Buffer,
+    TexelBufferView,
+}
+
+fn descriptor_info_kind(descriptor_type: vk::DescriptorType) -> DescriptorInfoKind {
+    match descriptor_type {
+        vk::DescriptorType::SAMPLER
+        | vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+        | vk::DescriptorType::SAMPLED_IMAGE
+        | vk::DescriptorType::STORAGE_IMAGE
+        | vk::DescriptorType::INPUT_ATTACHMENT => DescriptorInfoKind::Image,
+        vk::DescriptorType::UNIFORM_TEXEL_BUFFER | vk::DescriptorType::STORAGE_TEXEL_BUFFER => {
+            DescriptorInfoKind::TexelBufferView
+        }
+        _ => DescriptorInfoKind::Buffer,
+    }
+}
+
+/// Returns the offset of the first descriptor info of a write in the info array of `DescriptorSetBuilder`,
+/// before the pointers are resolved by `into_writes`.
+///
+/// The offsets are stored in the info pointers, so the first info of an array is a null pointer: the descriptor
+/// type determines which pointer holds the offset.
+fn descriptor_info_offset(write: &vk::WriteDescriptorSet) -> usize {
+    match descriptor_info_kind(write.descriptor_type) {
+        DescriptorInfoKind::Image => write.p_image_info as usize,
+        DescriptorInfoKind::Buffer => write.p_buffer_info as usize,
+        DescriptorInfoKind::TexelBufferView => write.p_texel_buffer_view as usize,
+    }
 }
 
 pub struct DescriptorSetBuilder {
@@ -131,17 +211,59 @@ pub struct DescriptorSetBuilder {
     image_infos: Vec<vk::DescriptorImageInfo>,
     buffer_infos: Vec<vk::DescriptorBufferInfo>,
     texel_buffer_views: Vec<vk::BufferView>,
+    /// Image views owned by the descriptor set, or `None` if the descriptors are only used in the current frame.
+    image_views: Option<Vec<vk::ImageView>>,
+    sampler_cache: Arc<SamplerCache>,
 }
 
 impl DescriptorSetBuilder {
-    fn new(set: vk::DescriptorSet) -> DescriptorSetBuilder {
+    pub(crate) fn new(set: vk::DescriptorSet, sampler_cache: &Arc<SamplerCache>) -> DescriptorSetBuilder {
         DescriptorSetBuilder {
             set,
             writes: vec![],
             image_infos: vec![],
             buffer_infos: vec![],
             texel_buffer_views: vec![],
+            image_views: None,
+            sampler_cache: sampler_cache.clone(),
+        }
+    }
+
+    /// Creates a builder for a descriptor set that is reused across frames.
+    ///
+    /// The image views created by the descriptors are returned by `finish`, and must be destroyed
+    /// along with the descriptor set.
+    pub(crate) fn new_persistent(set: vk::DescriptorSet, sampler_cache: &Arc<SamplerCache>) -> DescriptorSetBuilder {
+        DescriptorSetBuilder {
+            image_views: Some(vec![]),
+            ..DescriptorSetBuilder::new(set, sampler_cache)
+        }
+    }
+
+    /// Returns a sampler object for a descriptor, shared with all other descriptors using the same parameters.
+    pub fn get_or_create_sampler(&self, create_info: &vk::SamplerCreateInfo) -> vk::Sampler {
+        self.sampler_cache.get_or_create(create_info)
+    }
+
+    /// Creates an image view for a descriptor.
+    ///
+    /// The view is deleted at the end of the frame, or along with the descriptor set if the descriptor set is
+    /// reused across frames.
+    pub unsafe fn create_image_view(
+        &mut self,
+        device: &graal::Device,
+        create_info: &vk::ImageViewCreateInfo,
+    ) -> vk::ImageView {
+        let image_view = device
+            .device
+            .create_image_view(create_info, None)
+            .expect("could not create image view");
+        match self.image_views {
+            Some(ref mut image_views) => image_views.push(image_view),
+            // immediately schedule deletion since it will be used only in this frame
+            None => device.destroy_image_view(image_view),
         }
+        image_view
     }
 
     pub fn write_image_descriptor(
@@ -190,20 +312,68 @@ impl DescriptorSetBuilder {
         })
     }
 
-    fn finish(mut self, device: &graal::Device) {
+    /// Merges the writes starting at `first_write` that update consecutive array elements of the same binding
+    /// with consecutive descriptor infos into a single write.
+    pub(crate) fn merge_writes(&mut self, first_write: usize) {
+        let mut merged: Vec<vk::WriteDescriptorSet> = Vec::new();
+        for w in self.writes.drain(first_write..) {
+            if let Some(last) = merged.last_mut() {
+                if last.dst_binding == w.dst_binding
+                    && last.descriptor_type == w.descriptor_type
+                    && last.dst_array_element + last.descriptor_count == w.dst_array_element
+                    && descriptor_info_offset(last) + last.descriptor_count as usize == descriptor_info_offset(&w)
+                {
+                    last.descriptor_count += w.descriptor_count;
+                    continue;
+                }
+            }
+            merged.push(w);
+        }
+        self.writes.extend(merged);
+    }
+
+    /// Resolves the descriptor info pointers of the writes.
+    pub(crate) fn into_writes(mut self) -> DescriptorWrites {
         unsafe {
             for w in self.writes.iter_mut() {
-                if !w.p_image_info.is_null() {
-                    w.p_image_info = self.image_infos.as_ptr().add(w.p_image_info as usize);
-                } else if !w.p_buffer_info.is_null() {
-                    w.p_buffer_info = self.buffer_infos.as_ptr().add(w.p_buffer_info as usize);
-                } else if !w.p_texel_buffer_view.is_null() {
-                    w.p_texel_buffer_view = self.texel_buffer_views.as_ptr().add(w.p_texel_buffer_view as usize);
+                match descriptor_info_kind(w.descriptor_type) {
+                    DescriptorInfoKind::Image => {
+                        w.p_image_info = self.image_infos.as_ptr().add(w.p_image_info as usize);
+                    }
+                    DescriptorInfoKind::Buffer => {
+                        w.p_buffer_info = self.buffer_infos.as_ptr().add(w.p_buffer_info as usize);
+                    }
+                    DescriptorInfoKind::TexelBufferView => {
+                        w.p_texel_buffer_view = self.texel_buffer_views.as_ptr().add(w.p_texel_buffer_view as usize);
+                    }
                 }
             }
-            device.device.update_descriptor_sets(&self.writes, &[]);
+        }
+        DescriptorWrites {
+            writes: self.writes,
+            _image_infos: self.image_infos,
+            _buffer_infos: self.buffer_infos,
+            _texel_buffer_views: self.texel_buffer_views,
         }
     }
+
+    /// Writes the descriptors, and returns the image views owned by the descriptor set.
+    pub(crate) fn finish(mut self, device: &graal::Device) -> Vec<vk::ImageView> {
+        let image_views = self.image_views.take().unwrap_or_default();
+        let writes = self.into_writes();
+        unsafe {
+            device.device.update_descriptor_sets(&writes.writes, &[]);
+        }
+        image_views
+    }
+}
+
+/// Descriptor writes, along with the descriptor infos they point to.
+pub(crate) struct DescriptorWrites {
+    pub(crate) writes: Vec<vk::WriteDescriptorSet>,
+    _image_infos: Vec<vk::DescriptorImageInfo>,
+    _buffer_infos: Vec<vk::DescriptorBufferInfo>,
+    _texel_buffer_views: Vec<vk::BufferView>,
 }
 
 ///
@@ -230,6 +400,19 @@ pub unsafe trait DescriptorBinding: ResourceAccess {
         binding: u32,
         descriptor_set_builder: &mut DescriptorSetBuilder,
     );
+
+    /// Adds the resources referenced by the descriptors to `resources`.
+    ///
+    /// Returns false if the descriptors can't be reused across frames.
+    fn resources(&self, binding: u32, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        let _ = (binding, resources);
+        false
+    }
+
+    /// Returns the size in bytes of the buffer range bound by this descriptor, for buffer descriptors.
+    fn buffer_range(&self) -> Option<vk::DeviceSize> {
+        None
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -269,14 +452,7 @@ unsafe impl<'a> DescriptorBinding for SampledImage2D<'a> {
                 },
                 ..Default::default()
             };
-            /// FIXME: this should probably be cached into the image
-            let image_view = device
-                .device
-                .create_image_view(&create_info, None)
-                .expect("could not create image view");
-            // immediately schedule deletion since it will be used only in this frame
-            device.destroy_image_view(image_view);
-            image_view
+            descriptor_set_builder.create_image_view(device, &create_info)
         };
 
         descriptor_set_builder.write_image_descriptor(
@@ -291,6 +467,11 @@ unsafe impl<'a> DescriptorBinding for SampledImage2D<'a> {
             },
         );
     }
+
+    fn resources(&self, binding: u32, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        resources.push((binding, DescriptorResource::Image(self.image.id())));
+        true
+    }
 }
 
 impl<'a> ResourceAccess for SampledImage2D<'a> {
@@ -349,17 +530,10 @@ unsafe impl<'a, S: SamplerType> DescriptorBinding for CombinedImageSampler2D<'a,
                 },
                 ..Default::default()
             };
-            /// FIXME: this should probably be cached into the image
-            let image_view = device
-                .device
-                .create_image_view(&create_info, None)
-                .expect("could not create image view");
-            // immediately schedule deletion since it will be used only in this frame
-            device.destroy_image_view(image_view);
-            image_view
+            descriptor_set_builder.create_image_view(device, &create_info)
         };
 
-        let sampler = self.sampler.to_sampler(&device.device);
+        let sampler = descriptor_set_builder.get_or_create_sampler(&self.sampler.create_info());
 
         descriptor_set_builder.write_image_descriptor(
             binding,
@@ -373,6 +547,16 @@ unsafe impl<'a, S: SamplerType> DescriptorBinding for CombinedImageSampler2D<'a,
             },
         );
     }
+
+    fn resources(&self, binding: u32, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        if let Some(sampler_type_id) = self.sampler.unique_type_id() {
+            resources.push((binding, DescriptorResource::Image(self.image.id())));
+            resources.push((binding, DescriptorResource::Sampler(sampler_type_id)));
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl<'a, S: SamplerType> ResourceAccess for CombinedImageSampler2D<'a, S> {
@@ -389,6 +573,77 @@ impl<'a, S: SamplerType> ResourceAccess for CombinedImageSampler2D<'a, S> {
 
 //--------------------------------------------------------------------------------------------------
 
+/// Storage image descriptor, for images written by shaders (e.g. the output of a compute shader).
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct StorageImage2D<'a> {
+    pub(crate) image: &'a ImageAny,
+}
+
+unsafe impl<'a> DescriptorBinding for StorageImage2D<'a> {
+    const DESCRIPTOR_TYPE: vk::DescriptorType = vk::DescriptorType::STORAGE_IMAGE;
+    const SHADER_STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::ALL;
+    const DESCRIPTOR_COUNT: u32 = 1;
+
+    fn write_descriptors(
+        &self,
+        device: &graal::Device,
+        binding: u32,
+        descriptor_set_builder: &mut DescriptorSetBuilder,
+    ) {
+        // SAFETY: TODO
+        let image_view = unsafe {
+            let create_info = vk::ImageViewCreateInfo {
+                flags: vk::ImageViewCreateFlags::empty(),
+                image: self.image.handle(),
+                view_type: vk::ImageViewType::TYPE_2D,
+                format: self.image.format(),
+                components: vk::ComponentMapping::default(),
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                ..Default::default()
+            };
+            descriptor_set_builder.create_image_view(device, &create_info)
+        };
+
+        descriptor_set_builder.write_image_descriptor(
+            binding,
+            0,
+            1,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorImageInfo {
+                sampler: Default::default(),
+                image_view,
+                image_layout: vk::ImageLayout::GENERAL,
+            },
+        );
+    }
+
+    fn resources(&self, binding: u32, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        resources.push((binding, DescriptorResource::Image(self.image.id())));
+        true
+    }
+}
+
+impl<'a> ResourceAccess for StorageImage2D<'a> {
+    fn register(&self, pass: &mut PassBuilder<()>) {
+        pass.add_image_dependency(
+            self.image.image.id,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::GENERAL,
+        )
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+
 /// Uniform buffer slice.
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
@@ -416,6 +671,22 @@ unsafe impl<'a> DescriptorBinding for UniformBuffer<'a> {
             },
         );
     }
+
+    fn resources(&self, binding: u32, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        resources.push((
+            binding,
+            DescriptorResource::Buffer {
+                id: self.buffer.id(),
+                offset: self.offset,
+                range: self.range,
+            },
+        ));
+        true
+    }
+
+    fn buffer_range(&self) -> Option<vk::DeviceSize> {
+        Some(self.range)
+    }
 }
 
 impl<'a> ResourceAccess for UniformBuffer<'a> {
@@ -429,3 +700,141 @@ impl<'a> ResourceAccess for UniformBuffer<'a> {
 }
 
 //--------------------------------------------------------------------------------------------------
+
+/// Dynamic uniform buffer: a buffer holding many instances of uniform data of `range` bytes each.
+///
+/// The offset of the instance to use is supplied when binding the arguments
+/// (see `RenderPass::bind_arguments`), so that the same descriptor set can be used for many draws.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct DynamicUniformBuffer<'a> {
+    pub(crate) buffer: &'a BufferAny,
+    pub(crate) range: vk::DeviceSize,
+}
+
+unsafe impl<'a> DescriptorBinding for DynamicUniformBuffer<'a> {
+    const DESCRIPTOR_TYPE: vk::DescriptorType = vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC;
+    const SHADER_STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::ALL;
+    const DESCRIPTOR_COUNT: u32 = 1;
+
+    fn write_descriptors(&self, device: &Device, binding: u32, descriptor_set_builder: &mut DescriptorSetBuilder) {
+        descriptor_set_builder.write_buffer_descriptor(
+            binding,
+            0,
+            1,
+            vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            vk::DescriptorBufferInfo {
+                buffer: self.buffer.handle(),
+                offset: 0,
+                range: self.range,
+            },
+        );
+    }
+
+    fn resources(&self, binding: u32, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        resources.push((
+            binding,
+            DescriptorResource::Buffer {
+                id: self.buffer.id(),
+                offset: 0,
+                range: self.range,
+            },
+        ));
+        true
+    }
+
+    fn buffer_range(&self) -> Option<vk::DeviceSize> {
+        Some(self.range)
+    }
+}
+
+impl<'a> ResourceAccess for DynamicUniformBuffer<'a> {
+    fn register(&self, pass: &mut PassBuilder<()>) {
+        pass.add_buffer_dependency(
+            self.buffer.id(),
+            vk::AccessFlags::UNIFORM_READ,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+        )
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+
+/// Writes the descriptors of each element of an array to consecutive array elements of a binding.
+fn write_array_descriptors<T: DescriptorBinding>(
+    elements: &[T],
+    device: &graal::Device,
+    binding: u32,
+    descriptor_set_builder: &mut DescriptorSetBuilder,
+) {
+    let first_write = descriptor_set_builder.writes.len();
+    for (i, element) in elements.iter().enumerate() {
+        let element_first_write = descriptor_set_builder.writes.len();
+        element.write_descriptors(device, binding, descriptor_set_builder);
+        for write in descriptor_set_builder.writes[element_first_write..].iter_mut() {
+            write.dst_array_element += i as u32 * T::DESCRIPTOR_COUNT;
+        }
+    }
+    descriptor_set_builder.merge_writes(first_write);
+}
+
+/// Array of descriptors, bound to consecutive array elements of a single binding
+/// (e.g. `uniform sampler2D shadowMaps[4];` in GLSL).
+unsafe impl<T: DescriptorBinding, const N: usize> DescriptorBinding for [T; N] {
+    const DESCRIPTOR_TYPE: vk::DescriptorType = T::DESCRIPTOR_TYPE;
+    const SHADER_STAGES: vk::ShaderStageFlags = T::SHADER_STAGES;
+    const DESCRIPTOR_COUNT: u32 = N as u32 * T::DESCRIPTOR_COUNT;
+
+    fn write_descriptors(
+        &self,
+        device: &graal::Device,
+        binding: u32,
+        descriptor_set_builder: &mut DescriptorSetBuilder,
+    ) {
+        write_array_descriptors(self, device, binding, descriptor_set_builder)
+    }
+
+    fn resources(&self, binding: u32, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        self.iter().all(|element| element.resources(binding, resources))
+    }
+}
+
+impl<T: ResourceAccess, const N: usize> ResourceAccess for [T; N] {
+    fn register(&self, pass: &mut PassBuilder<()>) {
+        for element in self.iter() {
+            element.register(pass);
+        }
+    }
+}
+
+/// Runtime-sized array of descriptors, for variable-count bindings
+/// (e.g. `uniform sampler2D textures[];` in GLSL).
+///
+/// The number of descriptors is not known statically: `DESCRIPTOR_COUNT` is zero, and the maximum number of
+/// descriptors is specified on the binding (`#[argument(variable_count = N)]` with the derive macro).
+unsafe impl<'a, T: DescriptorBinding> DescriptorBinding for &'a [T] {
+    const DESCRIPTOR_TYPE: vk::DescriptorType = T::DESCRIPTOR_TYPE;
+    const SHADER_STAGES: vk::ShaderStageFlags = T::SHADER_STAGES;
+    const DESCRIPTOR_COUNT: u32 = 0;
+
+    fn write_descriptors(
+        &self,
+        device: &graal::Device,
+        binding: u32,
+        descriptor_set_builder: &mut DescriptorSetBuilder,
+    ) {
+        write_array_descriptors(self, device, binding, descriptor_set_builder)
+    }
+
+    fn resources(&self, binding: u32, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+        self.iter().all(|element| element.resources(binding, resources))
+    }
+}
+
+impl<'a, T: ResourceAccess> ResourceAccess for &'a [T] {
+    fn register(&self, pass: &mut PassBuilder<()>) {
+        for element in self.iter() {
+            element.register(pass);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Bindless texture tables
+use crate::{
+    arguments::ResourceAccess,
+    deferred::{DeferredDestroyQueue, RetiredObject},
+    descriptor_cache::MAX_FRAMES_IN_FLIGHT,
+    device::Device,
+    image::ImageAny,
+    sampler::{SamplerCache, SamplerType},
+    utils::set_debug_object_name,
+    vk,
+};
+use std::{ptr, sync::Arc};
+
+/// Index of a texture in a `TextureArray`, used by shaders to look up the texture in the table.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[repr(transparent)]
+pub struct TextureIndex(pub u32);
+
+struct TextureSlot {
+    image: graal::ImageId,
+    image_view: vk::ImageView,
+}
+
+/// Global table of textures (combined image samplers) referenced by index in shaders.
+///
+/// The table is a descriptor set with a single binding (#0) containing a variable-size array of combined
+/// image samplers (e.g. `layout(set=N, binding=0) uniform sampler2D textures[];` in GLSL). It is backed by
+/// descriptor indexing: the descriptors are updated after the set is bound, and unused entries are left
+/// unwritten. This requires the `descriptorBindingPartiallyBound`, `descriptorBindingVariableDescriptorCount`,
+/// `descriptorBindingSampledImageUpdateAfterBind` and `runtimeDescriptorArray` device features.
+///
+/// Bind it with `RenderPass::bind_texture_array`. The descriptor set layout, returned by
+/// `descriptor_set_layout`, must be part of the layout of the pipelines that use the table
+/// (see `PipelineInterfaceDesc::with_descriptor_set_layouts`).
+///
+/// When the table is dropped, its descriptor set is destroyed once the frames that may still use it have
+/// completed (see `Device::collect`).
+pub struct TextureArray {
+    device: Arc<graal::Device>,
+    deferred: DeferredDestroyQueue,
+    sampler_cache: Arc<SamplerCache>,
+    layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    capacity: u32,
+    slots: Vec<Option<TextureSlot>>,
+    /// Indices that can be reused.
+    free_indices: Vec<u32>,
+    /// Indices removed from the table, with the frame on which they were removed. They can't be reused
+    /// until the frames that may still reference them have completed.
+    retired_indices: Vec<(u64, u32)>,
+    /// Number of calls to `collect`.
+    frame: u64,
+}
+
+impl TextureArray {
+    /// Creates an empty table that can hold up to `capacity` textures.
+    pub fn new(device: &Device, capacity: u32) -> TextureArray {
+        let vk_device = device.vulkan_device();
+
+        unsafe {
+            let binding_flags = vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT;
+            let binding_flags_create_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo {
+                binding_count: 1,
+                p_binding_flags: &binding_flags,
+                ..Default::default()
+            };
+            let binding = vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: capacity,
+                stage_flags: vk::ShaderStageFlags::ALL,
+                p_immutable_samplers: ptr::null(),
+            };
+            let layout = vk_device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo {
+                        p_next: &binding_flags_create_info as *const _ as *const _,
+                        flags: vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+                        binding_count: 1,
+                        p_bindings: &binding,
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .expect("failed to create descriptor set layout");
+
+            let pool_size = vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: capacity,
+            };
+            let pool = vk_device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo {
+                        flags: vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+                        max_sets: 1,
+                        pool_size_count: 1,
+                        p_pool_sizes: &pool_size,
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .expect("failed to create descriptor pool");
+
+            let variable_count_allocate_info = vk::DescriptorSetVariableDescriptorCountAllocateInfo {
+                descriptor_set_count: 1,
+                p_descriptor_counts: &capacity,
+                ..Default::default()
+            };
+            let set = vk_device
+                .allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo {
+                    p_next: &variable_count_allocate_info as *const _ as *const _,
+                    descriptor_pool: pool,
+                    descriptor_set_count: 1,
+                    p_set_layouts: &layout,
+                    ..Default::default()
+                })
+                .expect("failed to allocate descriptor set")[0];
+            device.stats.count_descriptor_set_allocation();
+
+            TextureArray {
+                device: device.backend().clone(),
+                deferred: device.deferred.clone(),
+                sampler_cache: device.sampler_cache().clone(),
+                layout,
+                pool,
+                set,
+                capacity,
+                slots: vec![],
+                free_indices: vec![],
+                retired_indices: vec![],
+                frame: 0,
+            }
+        }
+    }
+
+    /// Sets the debug name of the descriptor set of the table, shown by debugging tools like RenderDoc.
+    pub fn set_name(&self, name: &str) {
+        set_debug_object_name(&self.device, self.set, name)
+    }
+
+    /// Returns the layout of the descriptor set of the table.
+    pub fn descriptor_set_layout(&self) -> vk::DescriptorSetLayout {
+        self.layout
+    }
+
+    /// Returns the descriptor set of the table.
+    pub fn descriptor_set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    /// Returns the maximum number of textures in the table.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Returns the number of textures in the table.
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    /// Returns whether the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds a texture to the table and returns its index.
+    ///
+    /// Panics if the table is full.
+    pub fn insert(&mut self, image: &ImageAny, sampler: &impl SamplerType) -> TextureIndex {
+        let index = if let Some(index) = self.free_indices.pop() {
+            index
+        } else {
+            assert!((self.slots.len() as u32) < self.capacity, "texture array is full");
+            self.slots.push(None);
+            self.slots.len() as u32 - 1
+        };
+
+        let vk_device = &self.device.device;
+        unsafe {
+            let image_view = vk_device
+                .create_image_view(
+                    &vk::ImageViewCreateInfo {
+                        flags: vk::ImageViewCreateFlags::empty(),
+                        image: image.handle(),
+                        view_type: vk::ImageViewType::TYPE_2D,
+                        format: image.format(),
+                        components: vk::ComponentMapping::default(),
+                        subresource_range: vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: 0,
+                            level_count: vk::REMAINING_MIP_LEVELS,
+                            base_array_layer: 0,
+                            layer_count: vk::REMAINING_ARRAY_LAYERS,
+                        },
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .expect("could not create image view");
+
+            let image_info = vk::DescriptorImageInfo {
+                sampler: self.sampler_cache.get_or_create_for_type(sampler),
+                image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            };
+            vk_device.update_descriptor_sets(
+                &[vk::WriteDescriptorSet {
+                    dst_set: self.set,
+                    dst_binding: 0,
+                    dst_array_element: index,
+                    descriptor_count: 1,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    p_image_info: &image_info,
+                    ..Default::default()
+                }],
+                &[],
+            );
+
+            self.slots[index as usize] = Some(TextureSlot {
+                image: image.id(),
+                image_view,
+            });
+        }
+
+        TextureIndex(index)
+    }
+
+    /// Removes a texture from the table.
+    ///
+    /// The index is reused once the frames that may reference it have completed (see `collect`).
+    pub fn remove(&mut self, index: TextureIndex) {
+        let slot = self
+            .slots
+            .get_mut(index.0 as usize)
+            .and_then(Option::take)
+            .expect("invalid texture index");
+        // deletion is deferred until the frames using the view have completed
+        unsafe { self.device.destroy_image_view(slot.image_view) }
+        self.retired_indices.push((self.frame, index.0));
+    }
+
+    /// Makes the indices removed at least `MAX_FRAMES_IN_FLIGHT` frames ago available again.
+    ///
+    /// Should be called once per frame.
+    pub fn collect(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        let free_indices = &mut self.free_indices;
+        self.retired_indices.retain(|&(retired_frame, index)| {
+            if retired_frame + MAX_FRAMES_IN_FLIGHT <= frame {
+                free_indices.push(index);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+impl ResourceAccess for TextureArray {
+    fn register(&self, pass: &mut graal::PassBuilder<()>) {
+        for slot in self.slots.iter().flatten() {
+            pass.add_image_dependency(
+                slot.image,
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+        }
+    }
+}
+
+impl Drop for TextureArray {
+    fn drop(&mut self) {
+        unsafe {
+            for slot in self.slots.drain(..).flatten() {
+                self.device.destroy_image_view(slot.image_view);
+            }
+        }
+        self.deferred.destroy(RetiredObject::DescriptorPool(self.pool));
+        self.deferred.destroy(RetiredObject::DescriptorSetLayout(self.layout));
+    }
+}
@@ -1,4 +1,12 @@
-use std::sync::Arc;
+use crate::{
+    arguments::{DynamicUniformBuffer, UniformBuffer},
+    device::Device,
+    stats::StatsCounters,
+    utils::set_debug_object_name,
+    vertex::{IndexBufferView, IndexData, VertexBufferView, VertexData},
+    vk,
+};
+use std::{marker::PhantomData, mem, ptr, sync::Arc};
 
 /// Marker trait for data that can be uploaded to a GPU buffer
 pub trait BufferData: 'static {
@@ -29,16 +37,24 @@ pub struct BufferAny {
 
 impl BufferAny {
     /// Creates a new, uninitialized buffer resource.
+    ///
+    /// `name` is the name of the resource in the frame graph and the debug name of the buffer (can be empty).
     pub fn new(
         device: &Arc<graal::Device>,
+        name: &str,
         location: graal::MemoryLocation,
         create_info: graal::BufferResourceCreateInfo,
     ) -> BufferAny {
         let device = device.clone();
-        let buffer = device.create_buffer("", location, &create_info);
+        let buffer = device.create_buffer(name, location, &create_info);
         BufferAny { device, buffer }
     }
 
+    /// Sets the debug name of the buffer, shown by debugging tools like RenderDoc.
+    pub fn set_name(&self, name: &str) {
+        set_debug_object_name(&self.device, self.buffer.handle, name)
+    }
+
     pub fn group_id(&self) -> Option<graal::ResourceGroupId> {
         self.device.get_buffer_state(self.buffer.id).map(|s| s.group_id)
     }
@@ -52,6 +68,16 @@ impl BufferAny {
     pub fn id(&self) -> graal::BufferId {
         self.buffer.id
     }
+
+    /// Returns a pointer to the start of the buffer if it is mapped in host memory.
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        self.buffer.mapped_ptr.map(|ptr| ptr.as_ptr() as *mut u8)
+    }
+
+    /// Returns a dynamic uniform buffer descriptor for this buffer, with instances of `range` bytes.
+    pub fn to_dynamic_uniform_buffer(&self, range: graal::vk::DeviceSize) -> DynamicUniformBuffer {
+        DynamicUniformBuffer { buffer: self, range }
+    }
 }
 
 impl Drop for BufferAny {
@@ -59,3 +85,156 @@ impl Drop for BufferAny {
         self.device.destroy_buffer(self.buffer.id)
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+
+/// A host-visible GPU buffer containing elements of type `T`.
+pub struct Buffer<T> {
+    buffer: BufferAny,
+    /// Number of elements that the buffer can hold.
+    len: usize,
+    /// Statistics of the device, updated by `upload`.
+    stats: Arc<StatsCounters>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: Copy + 'static> Buffer<T> {
+    /// Creates an uninitialized buffer that can hold `len` elements.
+    pub fn new(device: &Device, usage: vk::BufferUsageFlags, len: usize) -> Buffer<T> {
+        // zero-sized buffers are not allowed
+        let byte_size = (len * mem::size_of::<T>()).max(1);
+        let buffer = BufferAny::new(
+            device.backend(),
+            "",
+            graal::MemoryLocation::CpuToGpu,
+            graal::BufferResourceCreateInfo {
+                usage,
+                byte_size: byte_size as u64,
+                map_on_create: true,
+            },
+        );
+        Buffer {
+            buffer,
+            len,
+            stats: device.stats.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Creates a buffer initialized with the contents of a slice.
+    pub fn from_slice(device: &Device, usage: vk::BufferUsageFlags, data: &[T]) -> Buffer<T> {
+        let mut buffer = Buffer::new(device, usage, data.len());
+        buffer.upload(data);
+        buffer
+    }
+
+    /// Copies elements to the start of the buffer.
+    ///
+    /// The buffer is written immediately: it must not be in use by passes that haven't finished executing
+    /// on the GPU.
+    pub fn upload(&mut self, data: &[T]) {
+        assert!(
+            data.len() <= self.len,
+            "too many elements ({}) for a buffer of length {}",
+            data.len(),
+            self.len
+        );
+        let ptr = self.buffer.mapped_ptr().expect("buffer was not mapped in memory");
+        // SAFETY: the buffer is mapped and large enough
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut T, data.len()) }
+        self.stats.count_bytes_uploaded(mem::size_of_val(data) as u64);
+    }
+
+    /// Returns the number of elements that the buffer can hold.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the buffer holds no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the size of the elements of the buffer in bytes.
+    pub fn byte_size(&self) -> vk::DeviceSize {
+        (self.len * mem::size_of::<T>()) as vk::DeviceSize
+    }
+
+    /// Sets the debug name of the buffer, shown by debugging tools like RenderDoc.
+    pub fn set_name(&self, name: &str) {
+        self.buffer.set_name(name)
+    }
+
+    /// Returns the untyped buffer.
+    pub fn untyped(&self) -> &BufferAny {
+        &self.buffer
+    }
+
+    /// Returns a uniform buffer descriptor for the whole buffer.
+    pub fn to_uniform_buffer(&self) -> UniformBuffer {
+        UniformBuffer {
+            buffer: &self.buffer,
+            offset: 0,
+            range: self.byte_size(),
+        }
+    }
+
+    /// Returns a dynamic uniform buffer descriptor whose instances are the elements of this buffer.
+    ///
+    /// The size of `T` must be a multiple of `minUniformBufferOffsetAlignment`.
+    pub fn to_dynamic_uniform_buffer(&self) -> DynamicUniformBuffer {
+        self.buffer
+            .to_dynamic_uniform_buffer(mem::size_of::<T>() as vk::DeviceSize)
+    }
+}
+
+impl<T: VertexData> Buffer<T> {
+    /// Returns a view of all elements of the buffer, for use as vertex or instance data.
+    pub fn vertex_view(&self) -> VertexBufferView<T> {
+        VertexBufferView::new(&self.buffer, 0, self.len as u32)
+    }
+}
+
+impl<T: IndexData> Buffer<T> {
+    /// Returns a view of all elements of the buffer, for use as vertex indices.
+    pub fn index_view(&self) -> IndexBufferView {
+        IndexBufferView {
+            buffer: &self.buffer,
+            offset: 0,
+            format: T::FORMAT,
+            len: self.len as u32,
+        }
+    }
+}
+
+/// Buffer of draw commands for `RenderPass::draw_indirect`.
+pub type DrawIndirectCommandBuffer = Buffer<vk::DrawIndirectCommand>;
+
+/// Buffer of indexed draw commands for `RenderPass::draw_indexed_indirect`.
+pub type DrawIndexedIndirectCommandBuffer = Buffer<vk::DrawIndexedIndirectCommand>;
+
+impl Buffer<vk::DrawIndirectCommand> {
+    /// Creates a buffer of draw commands.
+    ///
+    /// The buffer can also be bound as a storage buffer, so that the commands can be written by compute shaders.
+    pub fn from_commands(device: &Device, commands: &[vk::DrawIndirectCommand]) -> Self {
+        Buffer::from_slice(
+            device,
+            vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            commands,
+        )
+    }
+}
+
+impl Buffer<vk::DrawIndexedIndirectCommand> {
+    /// Creates a buffer of indexed draw commands.
+    ///
+    /// The buffer can also be bound as a storage buffer, so that the commands can be written by compute shaders.
+    pub fn from_commands(device: &Device, commands: &[vk::DrawIndexedIndirectCommand]) -> Self {
+        Buffer::from_slice(
+            device,
+            vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            commands,
+        )
+    }
+}
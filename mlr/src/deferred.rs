@@ -0,0 +1,114 @@
+//! Deferred destruction of objects that may still be used by frames in flight
+use crate::{descriptor_cache::MAX_FRAMES_IN_FLIGHT, vk};
+use std::sync::{Arc, Mutex};
+
+/// A Vulkan object that is not used anymore by the application.
+pub(crate) enum RetiredObject {
+    Pipeline(vk::Pipeline),
+    PipelineLayout(vk::PipelineLayout),
+    DescriptorPool(vk::DescriptorPool),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+}
+
+unsafe fn destroy_object(device: &graal::Device, object: RetiredObject) {
+    let vk_device = &device.device;
+    match object {
+        RetiredObject::Pipeline(pipeline) => vk_device.destroy_pipeline(pipeline, None),
+        RetiredObject::PipelineLayout(layout) => vk_device.destroy_pipeline_layout(layout, None),
+        RetiredObject::DescriptorPool(pool) => vk_device.destroy_descriptor_pool(pool, None),
+        RetiredObject::DescriptorSetLayout(layout) => vk_device.destroy_descriptor_set_layout(layout, None),
+    }
+}
+
+/// Removes and returns the elements retired at least `MAX_FRAMES_IN_FLIGHT` frames before `frame`.
+fn take_expired<T>(retired: &mut Vec<(u64, T)>, frame: u64) -> Vec<T> {
+    let mut expired = vec![];
+    let mut i = 0;
+    while i < retired.len() {
+        if retired[i].0 + MAX_FRAMES_IN_FLIGHT <= frame {
+            expired.push(retired.swap_remove(i).1);
+        } else {
+            i += 1;
+        }
+    }
+    expired
+}
+
+struct RetiredObjects {
+    device: Arc<graal::Device>,
+    /// Objects not in use anymore, with the frame on which they were retired.
+    objects: Vec<(u64, RetiredObject)>,
+    /// Number of calls to `collect`.
+    frame: u64,
+}
+
+impl Drop for RetiredObjects {
+    fn drop(&mut self) {
+        for (_, object) in self.objects.drain(..) {
+            unsafe { destroy_object(&self.device, object) }
+        }
+    }
+}
+
+/// Destroys the objects dropped by the application once the frames that may still use them have completed.
+///
+/// Shared by the objects created on a device (e.g. pipelines and texture arrays), and collected once per frame by
+/// `Device::collect`.
+#[derive(Clone)]
+pub(crate) struct DeferredDestroyQueue {
+    device: Arc<graal::Device>,
+    retired: Arc<Mutex<RetiredObjects>>,
+}
+
+impl DeferredDestroyQueue {
+    pub(crate) fn new(device: Arc<graal::Device>) -> DeferredDestroyQueue {
+        DeferredDestroyQueue {
+            device: device.clone(),
+            retired: Arc::new(Mutex::new(RetiredObjects {
+                device,
+                objects: vec![],
+                frame: 0,
+            })),
+        }
+    }
+
+    /// Returns the device on which the objects are created.
+    pub(crate) fn device(&self) -> &Arc<graal::Device> {
+        &self.device
+    }
+
+    /// Schedules the destruction of an object, once the frames submitted until now have completed.
+    pub(crate) fn destroy(&self, object: RetiredObject) {
+        let mut retired = self.retired.lock().unwrap();
+        let frame = retired.frame;
+        retired.objects.push((frame, object));
+    }
+
+    /// Destroys the objects retired at least `MAX_FRAMES_IN_FLIGHT` frames ago.
+    ///
+    /// Should be called once per frame.
+    pub(crate) fn collect(&self) {
+        let mut retired = self.retired.lock().unwrap();
+        retired.frame += 1;
+        let frame = retired.frame;
+        for object in take_expired(&mut retired.objects, frame) {
+            unsafe { destroy_object(&self.device, object) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{take_expired, MAX_FRAMES_IN_FLIGHT};
+
+    #[test]
+    fn test_take_expired() {
+        let mut retired = vec![(0, 'a'), (1, 'b'), (2, 'c')];
+        assert!(take_expired(&mut retired, MAX_FRAMES_IN_FLIGHT - 1).is_empty());
+        assert_eq!(take_expired(&mut retired, MAX_FRAMES_IN_FLIGHT), vec!['a']);
+        let mut expired = take_expired(&mut retired, MAX_FRAMES_IN_FLIGHT + 2);
+        expired.sort();
+        assert_eq!(expired, vec!['b', 'c']);
+        assert!(retired.is_empty());
+    }
+}
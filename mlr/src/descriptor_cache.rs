@@ -0,0 +1,356 @@
+//! Descriptor set caching
+use crate::{
+    arguments::DescriptorSetBuilder, sampler::SamplerCache, stats::StatsCounters, utils::set_debug_object_name, vk,
+    Arguments,
+};
+use std::{any::TypeId, collections::HashMap, ffi::c_void, ptr, sync::Arc};
+
+/// Number of frames after which a descriptor set that is not used anymore can be freed.
+pub(crate) const MAX_FRAMES_IN_FLIGHT: u64 = 3;
+
+/// Number of descriptor sets allocated from each descriptor pool.
+const SETS_PER_POOL: u32 = 256;
+
+/// Number of descriptors of each type in a descriptor pool.
+const POOL_SIZES: &[vk::DescriptorPoolSize] = &[
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::SAMPLER,
+        descriptor_count: SETS_PER_POOL,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: 4 * SETS_PER_POOL,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::SAMPLED_IMAGE,
+        descriptor_count: 4 * SETS_PER_POOL,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_IMAGE,
+        descriptor_count: SETS_PER_POOL,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_count: 2 * SETS_PER_POOL,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+        descriptor_count: SETS_PER_POOL,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count: 2 * SETS_PER_POOL,
+    },
+    vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+        descriptor_count: SETS_PER_POOL,
+    },
+];
+
+/// A resource referenced by a descriptor.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DescriptorResource {
+    Image(graal::ImageId),
+    Buffer {
+        id: graal::BufferId,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    },
+    /// A sampler, identified by the type ID of its `SamplerType`.
+    Sampler(TypeId),
+}
+
+/// Descriptor set layout + resources referenced by each binding.
+type CacheKey = (vk::DescriptorSetLayout, Vec<(u32, DescriptorResource)>);
+
+/// Returns the key of the descriptor set written with the specified arguments, or `None` if it can't be reused.
+fn cache_key<A: Arguments + ?Sized>(layout: vk::DescriptorSetLayout, args: &A) -> Option<CacheKey> {
+    let mut resources = vec![];
+    if args.descriptor_resources(&mut resources) {
+        Some((layout, resources))
+    } else {
+        None
+    }
+}
+
+struct DescriptorSet {
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    image_views: Vec<vk::ImageView>,
+}
+
+/// Descriptor sets written with the contents of `Arguments`, reused across frames.
+///
+/// Descriptor sets are keyed by layout and by the resources that they reference. They are evicted once one of
+/// those resources has been destroyed.
+pub(crate) struct DescriptorSetCache {
+    device: Arc<graal::Device>,
+    sampler_cache: Arc<SamplerCache>,
+    stats: Arc<StatsCounters>,
+    pools: Vec<vk::DescriptorPool>,
+    entries: HashMap<CacheKey, DescriptorSet>,
+    /// Descriptor sets not in use anymore, with the frame on which they were retired.
+    retired: Vec<(u64, DescriptorSet)>,
+    /// Number of calls to `collect`.
+    frame: u64,
+}
+
+impl DescriptorSetCache {
+    pub(crate) fn new(
+        device: Arc<graal::Device>,
+        sampler_cache: Arc<SamplerCache>,
+        stats: Arc<StatsCounters>,
+    ) -> DescriptorSetCache {
+        DescriptorSetCache {
+            device,
+            sampler_cache,
+            stats,
+            pools: vec![],
+            entries: HashMap::new(),
+            retired: vec![],
+            frame: 0,
+        }
+    }
+
+    unsafe fn create_pool(&mut self) -> vk::DescriptorPool {
+        let create_info = vk::DescriptorPoolCreateInfo {
+            flags: vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+            max_sets: SETS_PER_POOL,
+            pool_size_count: POOL_SIZES.len() as u32,
+            p_pool_sizes: POOL_SIZES.as_ptr(),
+            ..Default::default()
+        };
+        let pool = self
+            .device
+            .device
+            .create_descriptor_pool(&create_info, None)
+            .expect("failed to create descriptor pool");
+        self.pools.push(pool);
+        pool
+    }
+
+    /// Allocates a descriptor set. `variable_count` is the number of descriptors in the variable-count
+    /// binding of the layout, if there's one.
+    unsafe fn allocate(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+        variable_count: Option<u32>,
+    ) -> (vk::DescriptorPool, vk::DescriptorSet) {
+        self.stats.count_descriptor_set_allocation();
+        let variable_count_allocate_info =
+            variable_count
+                .as_ref()
+                .map(|count| vk::DescriptorSetVariableDescriptorCountAllocateInfo {
+                    descriptor_set_count: 1,
+                    p_descriptor_counts: count,
+                    ..Default::default()
+                });
+        let allocate_from = |device: &graal::Device, pool: vk::DescriptorPool| {
+            let allocate_info = vk::DescriptorSetAllocateInfo {
+                p_next: variable_count_allocate_info
+                    .as_ref()
+                    .map_or(ptr::null(), |info| info as *const _ as *const c_void),
+                descriptor_pool: pool,
+                descriptor_set_count: 1,
+                p_set_layouts: &layout,
+                ..Default::default()
+            };
+            device
+                .device
+                .allocate_descriptor_sets(&allocate_info)
+                .map(|sets| sets[0])
+        };
+
+        // try the most recent pool first, then create a new one if it's full
+        if let Some(&pool) = self.pools.last() {
+            if let Ok(set) = allocate_from(&self.device, pool) {
+                return (pool, set);
+            }
+        }
+        let pool = self.create_pool();
+        let set = allocate_from(&self.device, pool).expect("failed to allocate descriptor set");
+        (pool, set)
+    }
+
+    /// Returns a descriptor set with the specified layout containing the descriptors of the arguments.
+    ///
+    /// If a descriptor set referencing the same resources was already written, it is returned directly.
+    /// New descriptor sets are named after the type of the arguments.
+    pub(crate) unsafe fn get_or_write<A: Arguments + ?Sized>(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+        args: &mut A,
+    ) -> vk::DescriptorSet {
+        let key = cache_key(layout, &*args);
+        if let Some(entry) = key.as_ref().and_then(|key| self.entries.get(key)) {
+            return entry.set;
+        }
+
+        let (pool, set) = self.allocate(layout, args.variable_descriptor_count());
+        set_debug_object_name(&self.device, set, std::any::type_name::<A>());
+        let mut builder = DescriptorSetBuilder::new_persistent(set, &self.sampler_cache);
+        args.update_descriptor_set(&self.device, &mut builder, None);
+        let image_views = builder.finish(&self.device);
+        let entry = DescriptorSet { pool, set, image_views };
+
+        if let Some(key) = key {
+            self.entries.insert(key, entry);
+        } else {
+            // used only in the current frame
+            self.retire(entry);
+        }
+        set
+    }
+
+    fn retire(&mut self, entry: DescriptorSet) {
+        for &image_view in entry.image_views.iter() {
+            // deletion is deferred until the frames using the view have completed
+            unsafe { self.device.destroy_image_view(image_view) }
+        }
+        self.retired.push((self.frame, entry));
+    }
+
+    fn is_alive(&self, resource: &DescriptorResource) -> bool {
+        match *resource {
+            DescriptorResource::Image(id) => self.device.get_image_state(id).is_some(),
+            DescriptorResource::Buffer { id, .. } => self.device.get_buffer_state(id).is_some(),
+            DescriptorResource::Sampler(_) => true,
+        }
+    }
+
+    /// Evicts the descriptor sets that reference destroyed resources, and frees the descriptor sets
+    /// retired at least `MAX_FRAMES_IN_FLIGHT` frames ago.
+    ///
+    /// Should be called once per frame.
+    pub(crate) fn collect(&mut self) {
+        self.frame += 1;
+
+        let dead_keys: Vec<_> = self
+            .entries
+            .keys()
+            .filter(|(_, resources)| !resources.iter().all(|(_, r)| self.is_alive(r)))
+            .cloned()
+            .collect();
+        for key in dead_keys {
+            tracing::trace!("evicting descriptor set {:?}", key);
+            let entry = self.entries.remove(&key).unwrap();
+            self.retire(entry);
+        }
+
+        let frame = self.frame;
+        let device = &self.device;
+        self.retired.retain(|(retired_frame, entry)| {
+            if retired_frame + MAX_FRAMES_IN_FLIGHT <= frame {
+                unsafe {
+                    device
+                        .device
+                        .free_descriptor_sets(entry.pool, &[entry.set])
+                        .expect("failed to free descriptor set");
+                }
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+impl Drop for DescriptorSetCache {
+    fn drop(&mut self) {
+        unsafe {
+            for (_, entry) in self.entries.drain() {
+                for image_view in entry.image_views {
+                    self.device.destroy_image_view(image_view);
+                }
+            }
+            // retired image views have already been scheduled for deletion; destroying the pools frees the sets
+            for &pool in self.pools.iter() {
+                self.device.device.destroy_descriptor_pool(pool, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_key, DescriptorResource};
+    use crate::{
+        arguments::{DescriptorSetBuilder, ResourceAccess},
+        sampler::{Linear_ClampToEdge, Nearest_ClampToEdge},
+        vk::{self, Handle},
+        Arguments,
+    };
+    use std::any::TypeId;
+
+    /// Arguments referencing samplers only, which don't need a device.
+    struct SamplerArguments {
+        samplers: Vec<TypeId>,
+        cacheable: bool,
+    }
+
+    impl ResourceAccess for SamplerArguments {
+        fn register(&self, _pass: &mut graal::PassBuilder<()>) {}
+    }
+
+    impl Arguments for SamplerArguments {
+        fn unique_type_id(&self) -> Option<TypeId> {
+            None
+        }
+
+        fn get_descriptor_set_layout_bindings(&self) -> &[vk::DescriptorSetLayoutBinding] {
+            &[]
+        }
+
+        unsafe fn update_descriptor_set(
+            &mut self,
+            _device: &graal::Device,
+            _descriptor_set_builder: &mut DescriptorSetBuilder,
+            _update_template: Option<vk::DescriptorUpdateTemplate>,
+        ) {
+        }
+
+        fn descriptor_resources(&self, resources: &mut Vec<(u32, DescriptorResource)>) -> bool {
+            for (binding, &sampler) in self.samplers.iter().enumerate() {
+                resources.push((binding as u32, DescriptorResource::Sampler(sampler)));
+            }
+            self.cacheable
+        }
+    }
+
+    #[test]
+    fn test_cache_key_equality() {
+        let layout_a = vk::DescriptorSetLayout::from_raw(1);
+        let layout_b = vk::DescriptorSetLayout::from_raw(2);
+        let linear = TypeId::of::<Linear_ClampToEdge>();
+        let nearest = TypeId::of::<Nearest_ClampToEdge>();
+        let args = |samplers: &[TypeId]| SamplerArguments {
+            samplers: samplers.to_vec(),
+            cacheable: true,
+        };
+
+        // same layout and resources: the descriptor set is reused
+        assert_eq!(
+            cache_key(layout_a, &args(&[linear, nearest])),
+            cache_key(layout_a, &args(&[linear, nearest]))
+        );
+        // different layouts, resources, or bindings of the resources
+        assert_ne!(
+            cache_key(layout_a, &args(&[linear])),
+            cache_key(layout_b, &args(&[linear]))
+        );
+        assert_ne!(
+            cache_key(layout_a, &args(&[linear])),
+            cache_key(layout_a, &args(&[nearest]))
+        );
+        assert_ne!(
+            cache_key(layout_a, &args(&[linear, nearest])),
+            cache_key(layout_a, &args(&[nearest, linear]))
+        );
+
+        let uncacheable = SamplerArguments {
+            samplers: vec![linear],
+            cacheable: false,
+        };
+        assert_eq!(cache_key(layout_a, &uncacheable), None);
+    }
+}
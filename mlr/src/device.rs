@@ -1,5 +1,10 @@
 use crate::{
-    sampler::{Sampler, SamplerInner, SamplerType},
+    deferred::DeferredDestroyQueue,
+    descriptor_cache::DescriptorSetCache,
+    pipeline::{GraphicsPipelineConfig, PipelineInterfaceDesc, RawGraphicsPipeline},
+    pipeline_cache::GraphicsPipelineCache,
+    sampler::SamplerCache,
+    stats::{FrameStats, StatsCounters},
     vk, Arguments,
 };
 use mlr::arguments::StaticArguments;
@@ -9,19 +14,25 @@ use std::{
     borrow::BorrowMut,
     cell::Cell,
     collections::{HashMap, VecDeque},
+    ffi::c_void,
+    ptr,
     sync::{Arc, Mutex},
 };
 
 pub(crate) struct DeviceInner {
     current_frame: graal::FrameNumber,
     descriptor_set_layout_by_typeid: HashMap<TypeId, vk::DescriptorSetLayout>,
-    sampler_by_typeid: HashMap<TypeId, vk::Sampler>,
+    descriptor_set_cache: DescriptorSetCache,
 }
 
 #[derive(Clone)]
 pub struct Device {
     pub(crate) backend: Arc<graal::Device>,
     pub(crate) inner: Arc<Mutex<DeviceInner>>,
+    pub(crate) sampler_cache: Arc<SamplerCache>,
+    pub(crate) pipeline_cache: Arc<GraphicsPipelineCache>,
+    pub(crate) deferred: DeferredDestroyQueue,
+    pub(crate) stats: Arc<StatsCounters>,
 }
 
 impl Device {
@@ -36,33 +47,30 @@ impl Device {
     }
 
     /// Creates a descriptor set layout.
+    ///
+    /// `binding_flags` contains the flags of each binding, or is empty if no binding has flags.
     pub(crate) unsafe fn get_or_create_descriptor_set_layout(
         &self,
         type_id: Option<TypeId>,
         bindings: &[vk::DescriptorSetLayoutBinding],
+        binding_flags: &[vk::DescriptorBindingFlags],
     ) -> vk::DescriptorSetLayout {
         let device = &self.backend.device;
         let mut inner = self.inner.lock().unwrap();
 
-        if let Some(type_id) = type_id {
-            // look in the map to see if we have already created the layout for the given type.
-            *inner
-                .descriptor_set_layout_by_typeid
-                .entry(type_id)
-                .or_insert_with(|| unsafe {
-                    // not in the map, create layout and insert it into the map
-                    let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
-                        binding_count: bindings.len() as u32,
-                        p_bindings: bindings.as_ptr(),
-                        ..Default::default()
-                    };
-                    device
-                        .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
-                        .expect("failed to create descriptor set layout")
-                })
-        } else {
-            // no associated typeid, create an "anonymous" descriptor set layout
+        let create_layout = || unsafe {
+            let binding_flags_create_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo {
+                binding_count: binding_flags.len() as u32,
+                p_binding_flags: binding_flags.as_ptr(),
+                ..Default::default()
+            };
             let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+                p_next: if binding_flags.is_empty() {
+                    ptr::null()
+                } else {
+                    assert_eq!(binding_flags.len(), bindings.len());
+                    &binding_flags_create_info as *const _ as *const c_void
+                },
                 binding_count: bindings.len() as u32,
                 p_bindings: bindings.as_ptr(),
                 ..Default::default()
@@ -70,6 +78,17 @@ impl Device {
             device
                 .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
                 .expect("failed to create descriptor set layout")
+        };
+
+        if let Some(type_id) = type_id {
+            // look in the map to see if we have already created the layout for the given type.
+            *inner
+                .descriptor_set_layout_by_typeid
+                .entry(type_id)
+                .or_insert_with(create_layout)
+        } else {
+            // no associated typeid, create an "anonymous" descriptor set layout
+            create_layout()
         }
     }
 
@@ -77,36 +96,71 @@ impl Device {
     pub(crate) unsafe fn get_or_create_descriptor_set_layout_for_type<T: StaticArguments>(
         &self,
     ) -> vk::DescriptorSetLayout {
-        self.get_or_create_descriptor_set_layout(Some(T::TYPE_ID), T::LAYOUT)
+        self.get_or_create_descriptor_set_layout(Some(T::TYPE_ID), T::LAYOUT, &[])
     }
 
-    /*/// Creates a sampler object from a type implementing `ToSampler`.
+    /// Returns a descriptor set containing the descriptors of the specified arguments, along with its layout.
     ///
-    /// The returned object lives as long as the context is alive.
-    pub fn get_or_create_sampler(
-        &mut self,
-        sampler: impl SamplerType,
-    ) -> Sampler {
-        let device = &self.backend.device;
+    /// Descriptor sets are cached: if the arguments reference the same resources as a previous call,
+    /// the descriptor set written by that call is returned.
+    pub unsafe fn get_or_create_descriptor_set<A: Arguments>(
+        &self,
+        args: &mut A,
+    ) -> (vk::DescriptorSetLayout, vk::DescriptorSet) {
+        let layout = self.get_or_create_descriptor_set_layout(
+            args.unique_type_id(),
+            args.get_descriptor_set_layout_bindings(),
+            args.get_descriptor_set_layout_binding_flags(),
+        );
         let mut inner = self.inner.lock().unwrap();
-        let mut samplers = &mut inner.samplers;
-        let mut sampler_by_typeid = &mut inner.sampler_by_typeid;
-        if let Some(type_id) = sampler.unique_type_id() {
-            *sampler_by_typeid
-                .entry(type_id)
-                .or_insert_with(|| {
-                    let sampler = sampler.to_sampler(device);
-                    let id = samplers.insert(sampler);
-                    Sampler(Arc::new(SamplerInner {
-                        device: self.clone(),
-                        id,
-                        sampler: Default::default()
-                    }))
-                })
-        } else {
-            todo!()
-        }
-    }*/
+        let set = inner.descriptor_set_cache.get_or_write(layout, args);
+        (layout, set)
+    }
+
+    /// Evicts cached descriptor sets referencing destroyed resources, frees descriptor sets that are not
+    /// used anymore, and destroys the objects dropped by the application (e.g. pipelines) once the frames that
+    /// may still use them have completed.
+    ///
+    /// Should be called once per frame, after the frame has been finished. Returns the statistics of the work
+    /// recorded on this device since the previous call.
+    pub fn collect(&self) -> FrameStats {
+        self.inner.lock().unwrap().descriptor_set_cache.collect();
+        self.deferred.collect();
+        self.stats.take()
+    }
+
+    /// Returns the sampler objects shared by the descriptors created on this device.
+    pub fn sampler_cache(&self) -> &Arc<SamplerCache> {
+        &self.sampler_cache
+    }
+
+    /// Returns a sampler object with the specified parameters.
+    ///
+    /// Samplers are shared: calls with the same parameters return the same object, which lives as long as
+    /// the device.
+    pub fn get_or_create_sampler(&self, create_info: &vk::SamplerCreateInfo) -> vk::Sampler {
+        self.sampler_cache.get_or_create(create_info)
+    }
+
+    /// Returns the graphics pipelines shared by the users of this device.
+    pub fn pipeline_cache(&self) -> &Arc<GraphicsPipelineCache> {
+        &self.pipeline_cache
+    }
+
+    /// Returns a graphics pipeline with the specified parameters.
+    ///
+    /// Pipelines are shared: calls with the same shaders, interface and state return the same object.
+    ///
+    /// # Safety
+    ///
+    /// Same as `RawGraphicsPipeline::new`.
+    pub unsafe fn get_or_create_graphics_pipeline(
+        &self,
+        config: &GraphicsPipelineConfig,
+        interface: &PipelineInterfaceDesc,
+    ) -> Arc<RawGraphicsPipeline> {
+        self.pipeline_cache.get_or_create(config, interface)
+    }
 
     /*pub(crate) fn destroy_sampler(&self, id: SamplerId) {
         let mut inner = self.inner.lock().unwrap();
@@ -149,14 +203,25 @@ impl Device {
 
 pub unsafe fn create_device_and_context(present_surface: Option<vk::SurfaceKHR>) -> (Device, graal::Context) {
     let (backend_device, backend_context) = graal::create_device_and_context(present_surface);
+    let sampler_cache = Arc::new(SamplerCache::new(backend_device.clone()));
+    let deferred = DeferredDestroyQueue::new(backend_device.clone());
+    let stats = Arc::new(StatsCounters::default());
 
     (
         Device {
             inner: Arc::new(Mutex::new(DeviceInner {
                 current_frame: Default::default(),
                 descriptor_set_layout_by_typeid: Default::default(),
-                sampler_by_typeid: Default::default(),
+                descriptor_set_cache: DescriptorSetCache::new(
+                    backend_device.clone(),
+                    sampler_cache.clone(),
+                    stats.clone(),
+                ),
             })),
+            sampler_cache,
+            pipeline_cache: Arc::new(GraphicsPipelineCache::new(deferred.clone())),
+            deferred,
+            stats,
             backend: backend_device,
         },
         backend_context,
@@ -0,0 +1,384 @@
+//! Sorting and batching of draw calls
+use crate::{
+    device::Device,
+    pipeline::RawGraphicsPipeline,
+    push_constants::PushConstants,
+    render_pass::{argument_push_constants, check_push_constant_range, push_constant_bytes, RenderPass},
+    vertex::{IndexBufferView, Mesh, VertexBufferView, VertexData},
+    vk::{self, Handle},
+    Arguments,
+};
+
+/// Order in which the draws of a `DrawList` are recorded.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DrawOrder {
+    /// Groups draws by pipeline, then orders them by sort key within each group. This minimizes state changes,
+    /// and should be used for opaque geometry.
+    ByPipeline,
+    /// Orders draws by sort key only (e.g. back-to-front for transparent geometry).
+    ByKey,
+}
+
+enum DrawCall {
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    },
+    DrawIndexed {
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    },
+}
+
+/// Returns the key by which draws are sorted, in the specified order.
+fn draw_sort_key(order: DrawOrder, pipeline: vk::Pipeline, sort_key: u64) -> (u64, u64) {
+    match order {
+        DrawOrder::ByPipeline => (pipeline.as_raw(), sort_key),
+        DrawOrder::ByKey => (0, sort_key),
+    }
+}
+
+/// State bound in the render pass while recording the draws of a list, to skip redundant bindings.
+///
+/// Each method records a binding and returns whether it must actually be bound.
+#[derive(Default)]
+struct BoundState {
+    pipeline: Option<vk::Pipeline>,
+    descriptor_sets: Vec<Option<(vk::DescriptorSet, Vec<u32>)>>,
+    vertex_buffers: Vec<Option<(vk::Buffer, vk::DeviceSize)>>,
+    index_buffer: Option<(vk::Buffer, vk::DeviceSize, vk::IndexType)>,
+}
+
+impl BoundState {
+    fn bind_pipeline(&mut self, pipeline: vk::Pipeline) -> bool {
+        if self.pipeline == Some(pipeline) {
+            return false;
+        }
+        self.pipeline = Some(pipeline);
+        // the new pipeline may have a different layout: rebind all descriptor sets
+        self.descriptor_sets.clear();
+        true
+    }
+
+    fn bind_descriptor_set(&mut self, set_index: u32, set: vk::DescriptorSet, dynamic_offsets: &[u32]) -> bool {
+        let index = set_index as usize;
+        if self.descriptor_sets.len() <= index {
+            self.descriptor_sets.resize(index + 1, None);
+        }
+        if let Some((bound_set, bound_offsets)) = &self.descriptor_sets[index] {
+            if *bound_set == set && bound_offsets[..] == *dynamic_offsets {
+                return false;
+            }
+        }
+        self.descriptor_sets[index] = Some((set, dynamic_offsets.to_vec()));
+        true
+    }
+
+    fn bind_vertex_buffer(&mut self, binding: u32, buffer: vk::Buffer, offset: vk::DeviceSize) -> bool {
+        let index = binding as usize;
+        if self.vertex_buffers.len() <= index {
+            self.vertex_buffers.resize(index + 1, None);
+        }
+        if self.vertex_buffers[index] == Some((buffer, offset)) {
+            return false;
+        }
+        self.vertex_buffers[index] = Some((buffer, offset));
+        true
+    }
+
+    fn bind_index_buffer(&mut self, index_buffer: (vk::Buffer, vk::DeviceSize, vk::IndexType)) -> bool {
+        if self.index_buffer == Some(index_buffer) {
+            return false;
+        }
+        self.index_buffer = Some(index_buffer);
+        true
+    }
+}
+
+/// A draw request, with its descriptors and buffers already resolved to Vulkan handles.
+struct DrawItem<'a> {
+    pipeline: &'a RawGraphicsPipeline,
+    sort_key: u64,
+    /// (set index, descriptor set, dynamic offsets)
+    descriptor_sets: Vec<(u32, vk::DescriptorSet, Vec<u32>)>,
+    /// (stages, offset, data)
+    push_constants: Vec<(vk::ShaderStageFlags, u32, Vec<u8>)>,
+    /// (binding, buffer, offset)
+    vertex_buffers: Vec<(u32, vk::Buffer, vk::DeviceSize)>,
+    index_buffer: Option<(vk::Buffer, vk::DeviceSize, vk::IndexType)>,
+    call: DrawCall,
+}
+
+/// Collects draw requests, and records them into a render pass sorted to minimize state changes.
+///
+/// Arguments are resolved to descriptor sets and resources are registered on the render pass as draws are
+/// added. When the list is finished, draws are sorted according to the `DrawOrder` and recorded, skipping
+/// redundant pipeline, descriptor set and buffer bindings. Draws with equal keys keep their submission order.
+pub struct DrawList<'a> {
+    pass: &'a mut RenderPass,
+    device: &'a Device,
+    order: DrawOrder,
+    items: Vec<DrawItem<'a>>,
+}
+
+impl<'a> DrawList<'a> {
+    /// Creates an empty draw list that records into the specified render pass.
+    pub fn new(pass: &'a mut RenderPass, device: &'a Device, order: DrawOrder) -> DrawList<'a> {
+        DrawList {
+            pass,
+            device,
+            order,
+            items: vec![],
+        }
+    }
+
+    /// Starts a draw request with the specified pipeline and sort key (e.g. a material ID or a quantized depth).
+    ///
+    /// The request is added to the list by one of the draw methods of the returned builder.
+    pub fn draw<'l>(&'l mut self, pipeline: &'a RawGraphicsPipeline, sort_key: u64) -> DrawBuilder<'l, 'a> {
+        DrawBuilder {
+            list: self,
+            pipeline,
+            sort_key,
+            descriptor_sets: vec![],
+            push_constants: vec![],
+            vertex_buffers: vec![],
+            index_buffer: None,
+        }
+    }
+
+    /// Returns the number of draws in the list.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns whether the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Sorts the draws and records them into the render pass.
+    pub fn finish(self) {
+        let DrawList {
+            pass, order, mut items, ..
+        } = self;
+
+        // stable sort: draws with equal keys keep their submission order
+        items.sort_by_key(|item| draw_sort_key(order, item.pipeline.pipeline(), item.sort_key));
+
+        let mut bound = BoundState::default();
+        for item in items {
+            if bound.bind_pipeline(item.pipeline.pipeline()) {
+                pass.bind_pipeline(item.pipeline);
+            }
+
+            for (set_index, set, dynamic_offsets) in item.descriptor_sets {
+                if bound.bind_descriptor_set(set_index, set, &dynamic_offsets) {
+                    pass.bind_descriptor_set(set_index, set, dynamic_offsets);
+                }
+            }
+
+            for (stages, offset, data) in item.push_constants {
+                pass.set_push_constant_bytes(stages, offset, data);
+            }
+
+            for (binding, buffer, offset) in item.vertex_buffers {
+                if bound.bind_vertex_buffer(binding, buffer, offset) {
+                    pass.bind_vertex_buffer_handle(binding, buffer, offset);
+                }
+            }
+
+            if let Some(index_buffer) = item.index_buffer {
+                if bound.bind_index_buffer(index_buffer) {
+                    let (buffer, offset, index_type) = index_buffer;
+                    pass.bind_index_buffer_handle(buffer, offset, index_type);
+                }
+            }
+
+            match item.call {
+                DrawCall::Draw {
+                    vertex_count,
+                    instance_count,
+                    first_vertex,
+                    first_instance,
+                } => pass.draw(vertex_count, instance_count, first_vertex, first_instance),
+                DrawCall::DrawIndexed {
+                    index_count,
+                    instance_count,
+                    first_index,
+                    vertex_offset,
+                    first_instance,
+                } => pass.draw_indexed(index_count, instance_count, first_index, vertex_offset, first_instance),
+            }
+        }
+    }
+}
+
+/// Builder for a draw request of a `DrawList`.
+pub struct DrawBuilder<'l, 'a> {
+    list: &'l mut DrawList<'a>,
+    pipeline: &'a RawGraphicsPipeline,
+    sort_key: u64,
+    descriptor_sets: Vec<(u32, vk::DescriptorSet, Vec<u32>)>,
+    push_constants: Vec<(vk::ShaderStageFlags, u32, Vec<u8>)>,
+    vertex_buffers: Vec<(u32, vk::Buffer, vk::DeviceSize)>,
+    index_buffer: Option<(vk::Buffer, vk::DeviceSize, vk::IndexType)>,
+}
+
+impl<'l, 'a> DrawBuilder<'l, 'a> {
+    /// Binds arguments to the specified descriptor set of the pipeline (see `RenderPass::bind_arguments`).
+    ///
+    /// Push constants contained in the arguments replace the ones set previously on this draw.
+    pub fn arguments<A: Arguments>(mut self, set_index: u32, args: &mut A, dynamic_offsets: &[u32]) -> Self {
+        let set = self.list.pass.prepare_arguments(
+            self.list.device,
+            self.pipeline.descriptor_bindings(),
+            set_index,
+            args,
+            dynamic_offsets,
+        );
+        self.descriptor_sets.push((set_index, set, dynamic_offsets.to_vec()));
+        if let Some(push_constants) = argument_push_constants(args, self.pipeline.push_constant_range()) {
+            self.push_constants.push(push_constants);
+        }
+        self
+    }
+
+    /// Sets the push constants of the draw.
+    pub fn push_constants<T: PushConstants>(mut self, data: &T) -> Self {
+        let stages = check_push_constant_range(T::range(), self.pipeline.push_constant_range());
+        self.push_constants.push((stages, T::OFFSET, push_constant_bytes(data)));
+        self
+    }
+
+    /// Binds a vertex buffer to the specified binding.
+    pub fn vertex_buffer<V: VertexData>(mut self, binding: u32, vertices: &VertexBufferView<V>) -> Self {
+        self.list.pass.register_vertex_buffer(vertices.buffer);
+        self.vertex_buffers
+            .push((binding, vertices.buffer.handle(), vertices.offset));
+        self
+    }
+
+    /// Binds an index buffer.
+    pub fn index_buffer(mut self, indices: &IndexBufferView) -> Self {
+        self.list.pass.register_index_buffer(indices.buffer);
+        self.index_buffer = Some((indices.buffer.handle(), indices.offset, indices.format.to_vk()));
+        self
+    }
+
+    /// Adds a non-indexed draw to the list (see `RenderPass::draw`).
+    pub fn draw(self, vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32) {
+        self.finish(DrawCall::Draw {
+            vertex_count,
+            instance_count,
+            first_vertex,
+            first_instance,
+        })
+    }
+
+    /// Adds an indexed draw to the list (see `RenderPass::draw_indexed`).
+    ///
+    /// An index buffer must be bound with `index_buffer`.
+    pub fn draw_indexed(
+        self,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    ) {
+        assert!(self.index_buffer.is_some(), "no index buffer bound");
+        self.finish(DrawCall::DrawIndexed {
+            index_count,
+            instance_count,
+            first_index,
+            vertex_offset,
+            first_instance,
+        })
+    }
+
+    /// Adds a draw of `instance_count` instances of a mesh, with its vertices bound to binding #0
+    /// (see `RenderPass::draw_mesh`).
+    pub fn draw_mesh<V: VertexData>(self, mesh: &Mesh<V>, instance_count: u32) {
+        if instance_count == 0 {
+            return;
+        }
+        let this = self.vertex_buffer(0, &mesh.vertices);
+        if let Some(ref indices) = mesh.indices {
+            this.index_buffer(indices)
+                .draw_indexed(indices.len, instance_count, 0, 0, 0);
+        } else {
+            this.draw(mesh.vertices.len, instance_count, 0, 0);
+        }
+    }
+
+    fn finish(self, call: DrawCall) {
+        self.list.items.push(DrawItem {
+            pipeline: self.pipeline,
+            sort_key: self.sort_key,
+            descriptor_sets: self.descriptor_sets,
+            push_constants: self.push_constants,
+            vertex_buffers: self.vertex_buffers,
+            index_buffer: self.index_buffer,
+            call,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{draw_sort_key, BoundState, DrawOrder};
+    use crate::vk::{self, Handle};
+
+    #[test]
+    fn test_draw_sort_order() {
+        let a = vk::Pipeline::from_raw(2);
+        let b = vk::Pipeline::from_raw(1);
+        // (pipeline, sort key, submission index)
+        let draws = [(a, 1, 0), (b, 3, 1), (a, 0, 2), (b, 1, 3), (a, 1, 4)];
+
+        let sorted = |order| {
+            let mut draws = draws.to_vec();
+            draws.sort_by_key(|&(pipeline, key, _)| draw_sort_key(order, pipeline, key));
+            draws.into_iter().map(|(_, _, index)| index).collect::<Vec<_>>()
+        };
+        // grouped by pipeline, then by key; equal keys keep their submission order
+        assert_eq!(sorted(DrawOrder::ByPipeline), vec![3, 1, 2, 0, 4]);
+        assert_eq!(sorted(DrawOrder::ByKey), vec![2, 0, 3, 4, 1]);
+    }
+
+    #[test]
+    fn test_redundant_bindings() {
+        let pipeline_a = vk::Pipeline::from_raw(1);
+        let pipeline_b = vk::Pipeline::from_raw(2);
+        let set = vk::DescriptorSet::from_raw(1);
+        let buffer = vk::Buffer::from_raw(1);
+
+        let mut bound = BoundState::default();
+        assert!(bound.bind_pipeline(pipeline_a));
+        assert!(bound.bind_descriptor_set(1, set, &[]));
+        assert!(bound.bind_vertex_buffer(0, buffer, 0));
+        assert!(bound.bind_index_buffer((buffer, 256, vk::IndexType::UINT16)));
+
+        // same state: merged with the previous draw
+        assert!(!bound.bind_pipeline(pipeline_a));
+        assert!(!bound.bind_descriptor_set(1, set, &[]));
+        assert!(!bound.bind_vertex_buffer(0, buffer, 0));
+        assert!(!bound.bind_index_buffer((buffer, 256, vk::IndexType::UINT16)));
+
+        // different dynamic offsets or buffer offsets must be rebound
+        assert!(bound.bind_descriptor_set(1, set, &[64]));
+        assert!(bound.bind_vertex_buffer(0, buffer, 128));
+        assert!(!bound.bind_vertex_buffer(0, buffer, 128));
+
+        // changing the pipeline invalidates the descriptor sets, but not the buffers
+        assert!(bound.bind_pipeline(pipeline_b));
+        assert!(bound.bind_descriptor_set(1, set, &[64]));
+        assert!(!bound.bind_vertex_buffer(0, buffer, 128));
+    }
+}
@@ -0,0 +1,57 @@
+//! Fragment output interfaces (sets of render target attachments)
+use crate::{
+    device::Device,
+    render_pass::{
+        AttachmentLoadOp, AttachmentStoreOp, ClearColorValue, RenderPass, RenderPassColorAttachment,
+        RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    },
+    vk,
+};
+
+/// Describes the attachments written by the fragment stage of a graphics pipeline.
+///
+/// Usually derived on a struct of image attachments with `#[derive(FragmentOutputInterface)]`:
+/// the formats of the attachments and their load/store operations are specified with `#[attachment(...)]`
+/// attributes on each field.
+///
+/// # Safety
+///
+/// The formats of the attachments returned by `color_attachments` and `depth_stencil_attachment` must match
+/// `COLOR_ATTACHMENT_FORMATS`, `DEPTH_ATTACHMENT_FORMAT` and `STENCIL_ATTACHMENT_FORMAT`.
+pub unsafe trait FragmentOutputInterface {
+    /// Formats of the color attachments, in attachment order.
+    const COLOR_ATTACHMENT_FORMATS: &'static [vk::Format];
+    /// Format of the depth attachment, if any.
+    const DEPTH_ATTACHMENT_FORMAT: Option<vk::Format>;
+    /// Format of the stencil attachment, if any.
+    ///
+    /// For combined depth/stencil formats (e.g. `D24_UNORM_S8_UINT`), the depth/stencil attachment is used as
+    /// both the depth and the stencil attachment, and this is the same as `DEPTH_ATTACHMENT_FORMAT`.
+    const STENCIL_ATTACHMENT_FORMAT: Option<vk::Format>;
+
+    /// Returns the load and store operations of the color attachments, in attachment order.
+    fn color_attachment_ops() -> Vec<(AttachmentLoadOp<ClearColorValue>, AttachmentStoreOp)>;
+
+    /// Returns the load and store operations of the depth/stencil attachment, if any.
+    fn depth_stencil_attachment_ops() -> Option<(AttachmentLoadOp<f32>, AttachmentStoreOp)>;
+
+    /// Returns the color attachments, along with their load and store operations.
+    fn color_attachments(&self) -> Vec<RenderPassColorAttachment>;
+
+    /// Returns the depth attachment, along with its load and store operations.
+    fn depth_stencil_attachment(&self) -> Option<RenderPassDepthStencilAttachment>;
+
+    /// Starts a render pass that renders to the attachments.
+    fn begin_render_pass(&self, device: &Device, name: &str, render_area: vk::Rect2D) -> RenderPass {
+        let color_attachments = self.color_attachments();
+        RenderPass::new(
+            device,
+            name,
+            &RenderPassDescriptor {
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: self.depth_stencil_attachment(),
+                render_area,
+            },
+        )
+    }
+}
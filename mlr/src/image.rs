@@ -1,12 +1,21 @@
-use crate::{arguments::SampledImage2D, sampler::SamplerType, vk};
+use crate::{
+    arguments::{SampledImage2D, StorageImage2D},
+    device::Device,
+    sampler::SamplerType,
+    utils::set_debug_object_name,
+    vk,
+};
 use mlr::arguments::CombinedImageSampler2D;
-use std::sync::Arc;
+use std::{fs, io, path::Path, ptr, sync::Arc};
+use thiserror::Error;
 
 #[derive(Debug)]
 pub struct ImageAny {
     pub(crate) device: Arc<graal::Device>,
     pub(crate) image: graal::ImageInfo,
     pub(crate) format: graal::vk::Format,
+    pub(crate) extent: graal::vk::Extent3D,
+    pub(crate) mip_levels: u32,
 }
 
 impl ImageAny {
@@ -31,15 +40,43 @@ impl ImageAny {
     }
 
     /// Returns the format of this image.
+    /// Sets the debug name of the image, shown by debugging tools like RenderDoc.
+    pub fn set_name(&self, name: &str) {
+        set_debug_object_name(&self.device, self.image.handle, name)
+    }
+
     pub fn format(&self) -> graal::vk::Format {
         self.format
     }
 
+    /// Returns the size of the base mip level of this image.
+    pub fn extent(&self) -> graal::vk::Extent3D {
+        self.extent
+    }
+
+    /// Returns the number of mip levels of this image.
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// Schedules a pass that fills mip levels 1 and above from the contents of the base level.
+    ///
+    /// The image must have been created with the `TRANSFER_SRC` and `TRANSFER_DST` usages.
+    /// Does nothing if the image has only one mip level.
+    pub fn generate_mipmaps(&self, frame: &mut graal::Frame<'static, ()>) {
+        add_mipmap_generation_pass(frame, self)
+    }
+
     ///
     pub fn to_sampled_image_2d(&self) -> SampledImage2D {
         SampledImage2D { image: self }
     }
 
+    /// Returns a storage image descriptor for this image.
+    pub fn to_storage_image_2d(&self) -> StorageImage2D {
+        StorageImage2D { image: self }
+    }
+
     pub fn to_combined_image_sampler_2d<S: SamplerType>(&self, sampler: S) -> CombinedImageSampler2D<S> {
         CombinedImageSampler2D { image: self, sampler }
     }
@@ -58,6 +95,8 @@ impl Device {
             device: self.backend.clone(),
             image,
             format: create_info.format,
+            extent: create_info.extent,
+            mip_levels: create_info.mip_levels,
         }
     }
 }*/
@@ -67,3 +106,459 @@ impl Drop for ImageAny {
         self.device.destroy_image(self.image.id)
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+
+/// Error while loading an image file.
+#[derive(Debug, Error)]
+pub enum LoadImageError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to decode image: {0}")]
+    Decode(String),
+    #[error("failed to parse KTX2 file: {0}")]
+    Ktx2(#[from] ktx2::ParseError),
+    #[error("unsupported image: {0}")]
+    Unsupported(String),
+}
+
+/// Converts an OIIO type descriptor into its preferred vulkan image format.
+///
+/// Returns a tuple consisting of the vulkan image format and the pixel stride (size of one pixel in bytes).
+/// 3-channel images are loaded into 4-channel formats, since RGB formats have limited support in vulkan.
+#[cfg(feature = "openimageio")]
+fn oiio_typedesc_to_vk_format(typedesc: &openimageio::TypeDesc, num_channels: usize) -> Option<(vk::Format, usize)> {
+    let (vk_format, bpp) = match (typedesc, num_channels) {
+        (&openimageio::TypeDesc::U8, 1) => (vk::Format::R8_UNORM, 1usize),
+        (&openimageio::TypeDesc::U8, 2) => (vk::Format::R8G8_UNORM, 2usize),
+        (&openimageio::TypeDesc::U8, 3) => (vk::Format::R8G8B8A8_UNORM, 4usize),
+        (&openimageio::TypeDesc::U8, 4) => (vk::Format::R8G8B8A8_UNORM, 4usize),
+        (&openimageio::TypeDesc::U16, 1) => (vk::Format::R16_UNORM, 2usize),
+        (&openimageio::TypeDesc::U16, 2) => (vk::Format::R16G16_UNORM, 4usize),
+        (&openimageio::TypeDesc::U16, 3) => (vk::Format::R16G16B16A16_UNORM, 8usize),
+        (&openimageio::TypeDesc::U16, 4) => (vk::Format::R16G16B16A16_UNORM, 8usize),
+        (&openimageio::TypeDesc::HALF, 1) => (vk::Format::R16_SFLOAT, 2usize),
+        (&openimageio::TypeDesc::HALF, 2) => (vk::Format::R16G16_SFLOAT, 4usize),
+        (&openimageio::TypeDesc::HALF, 3) => (vk::Format::R16G16B16A16_SFLOAT, 8usize),
+        (&openimageio::TypeDesc::HALF, 4) => (vk::Format::R16G16B16A16_SFLOAT, 8usize),
+        (&openimageio::TypeDesc::FLOAT, 1) => (vk::Format::R32_SFLOAT, 4usize),
+        (&openimageio::TypeDesc::FLOAT, 2) => (vk::Format::R32G32_SFLOAT, 8usize),
+        (&openimageio::TypeDesc::FLOAT, 3) => (vk::Format::R32G32B32A32_SFLOAT, 16usize),
+        (&openimageio::TypeDesc::FLOAT, 4) => (vk::Format::R32G32B32A32_SFLOAT, 16usize),
+        _ => return None,
+    };
+    Some((vk_format, bpp))
+}
+
+/// Creates a 2D image that can be sampled, with the specified number of mip levels.
+pub(crate) fn create_texture(
+    device: &Arc<graal::Device>,
+    name: &str,
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+) -> ImageAny {
+    let extent = vk::Extent3D {
+        width,
+        height,
+        depth: 1,
+    };
+    let image = device.create_image(
+        name,
+        graal::MemoryLocation::GpuOnly,
+        &graal::ImageResourceCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            usage: vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            format,
+            extent,
+            mip_levels,
+            array_layers: 1,
+            samples: 1,
+            tiling: Default::default(),
+        },
+    );
+    ImageAny {
+        device: device.clone(),
+        image,
+        format,
+        extent,
+        mip_levels,
+    }
+}
+
+/// Creates a mapped staging buffer.
+pub(crate) fn create_staging_buffer(device: &graal::Device, byte_size: u64) -> graal::BufferInfo {
+    device.create_buffer(
+        "staging",
+        graal::MemoryLocation::CpuToGpu,
+        &graal::BufferResourceCreateInfo {
+            usage: vk::BufferUsageFlags::TRANSFER_SRC,
+            byte_size,
+            map_on_create: true,
+        },
+    )
+}
+
+/// Adds a pass that copies the contents of a staging buffer to the mip levels of an image.
+///
+/// `levels` contains the byte offset of each mip level in the staging buffer, starting from level 0.
+/// The staging buffer is deleted once the pass has completed.
+fn add_upload_pass(
+    frame: &mut graal::Frame<'static, ()>,
+    device: &graal::Device,
+    staging_buffer: graal::BufferInfo,
+    image: &ImageAny,
+    levels: Vec<u64>,
+) {
+    let staging_buffer_handle = staging_buffer.handle;
+    let image_handle = image.handle();
+    let extent = image.extent;
+    let pass = graal::PassBuilder::new()
+        .name("image upload")
+        .image_dependency(
+            image.id(),
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        )
+        .buffer_dependency(
+            staging_buffer.id,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+        )
+        .record_callback(Box::new(move |context, _, command_buffer| unsafe {
+            let device = context.vulkan_device();
+            let regions: Vec<_> = levels
+                .iter()
+                .enumerate()
+                .map(|(level, &offset)| vk::BufferImageCopy {
+                    buffer_offset: offset,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: level as u32,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                    image_extent: vk::Extent3D {
+                        width: (extent.width >> level).max(1),
+                        height: (extent.height >> level).max(1),
+                        depth: 1,
+                    },
+                })
+                .collect();
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer_handle,
+                image_handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+        }));
+    frame.add_pass(pass);
+    // deletion is deferred until the upload pass has completed
+    device.destroy_buffer(staging_buffer.id);
+}
+
+/// Adds a pass that fills mip levels 1 and above of an image by successively blitting each level
+/// into the next one.
+fn add_mipmap_generation_pass(frame: &mut graal::Frame<'static, ()>, image: &ImageAny) {
+    let image_handle = image.handle();
+    let extent = image.extent;
+    let mip_levels = image.mip_levels;
+    if mip_levels <= 1 {
+        return;
+    }
+
+    let pass = graal::PassBuilder::new()
+        .name("mipmap generation")
+        .image_dependency(
+            image.id(),
+            vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )
+        .record_callback(Box::new(move |context, _, command_buffer| unsafe {
+            let device = context.vulkan_device();
+            let level_to_src = |level: u32| vk::ImageMemoryBarrier {
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: image_handle,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: level,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                ..Default::default()
+            };
+            let level_offset = |level: u32| vk::Offset3D {
+                x: (extent.width >> level).max(1) as i32,
+                y: (extent.height >> level).max(1) as i32,
+                z: 1,
+            };
+
+            for level in 1..mip_levels {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[level_to_src(level - 1)],
+                );
+                let subresource = |mip_level| vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                };
+                device.cmd_blit_image(
+                    command_buffer,
+                    image_handle,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image_handle,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[vk::ImageBlit {
+                        src_subresource: subresource(level - 1),
+                        src_offsets: [vk::Offset3D::default(), level_offset(level - 1)],
+                        dst_subresource: subresource(level),
+                        dst_offsets: [vk::Offset3D::default(), level_offset(level)],
+                    }],
+                    vk::Filter::LINEAR,
+                );
+            }
+
+            // leave all levels in the same layout
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[level_to_src(mip_levels - 1)],
+            );
+        }));
+    frame.add_pass(pass);
+}
+
+/// Loads an image file supported by OpenImageIO (PNG, JPEG, HDR, EXR...) and generates its mip chain.
+#[cfg(feature = "openimageio")]
+fn load_with_oiio(
+    device: &Device,
+    frame: &mut graal::Frame<'static, ()>,
+    path: &Path,
+) -> Result<ImageAny, LoadImageError> {
+    let image_input = openimageio::ImageInput::open(path).map_err(|e| LoadImageError::Decode(e.to_string()))?;
+    let spec = image_input.spec();
+    let num_channels = spec.num_channels();
+    let format_typedesc = spec.format();
+    let width = spec.width();
+    let height = spec.height();
+    let (format, bytes_per_pixel) = oiio_typedesc_to_vk_format(&format_typedesc, num_channels).ok_or_else(|| {
+        LoadImageError::Unsupported(format!("{} channels of type {:?}", num_channels, format_typedesc))
+    })?;
+
+    let image = create_texture(
+        device.backend(),
+        &path.to_string_lossy(),
+        format,
+        width,
+        height,
+        graal::get_mip_level_count(width, height),
+    );
+
+    // read image data directly into the staging buffer
+    let byte_size = width as u64 * height as u64 * bytes_per_pixel as u64;
+    let staging_buffer = create_staging_buffer(device.backend(), byte_size);
+    unsafe {
+        let ptr = staging_buffer.mapped_ptr.unwrap().as_ptr() as *mut u8;
+        if num_channels == 3 {
+            // the alpha channel is not read from the file
+            ptr::write_bytes(ptr, 0, byte_size as usize);
+        }
+        image_input
+            .read_unchecked(0, 0, 0..num_channels, format_typedesc, ptr, bytes_per_pixel)
+            .map_err(|e| LoadImageError::Decode(e.to_string()))?;
+    }
+
+    device.stats.count_bytes_uploaded(byte_size);
+    add_upload_pass(frame, device.backend(), staging_buffer, &image, vec![0]);
+    image.generate_mipmaps(frame);
+    Ok(image)
+}
+
+/// Returns the vulkan image format of a decoded PNG image, and the size of one pixel in bytes.
+///
+/// Like `oiio_typedesc_to_vk_format`, 3-channel images are loaded into 4-channel formats.
+#[cfg(not(feature = "openimageio"))]
+fn png_vk_format(bit_depth: png::BitDepth, num_channels: usize) -> Option<(vk::Format, usize)> {
+    Some(match (bit_depth, num_channels) {
+        (png::BitDepth::Eight, 1) => (vk::Format::R8_UNORM, 1usize),
+        (png::BitDepth::Eight, 2) => (vk::Format::R8G8_UNORM, 2usize),
+        (png::BitDepth::Eight, 3) => (vk::Format::R8G8B8A8_UNORM, 4usize),
+        (png::BitDepth::Eight, 4) => (vk::Format::R8G8B8A8_UNORM, 4usize),
+        (png::BitDepth::Sixteen, 1) => (vk::Format::R16_UNORM, 2usize),
+        (png::BitDepth::Sixteen, 2) => (vk::Format::R16G16_UNORM, 4usize),
+        (png::BitDepth::Sixteen, 3) => (vk::Format::R16G16B16A16_UNORM, 8usize),
+        (png::BitDepth::Sixteen, 4) => (vk::Format::R16G16B16A16_UNORM, 8usize),
+        _ => return None,
+    })
+}
+
+/// Loads a PNG file and generates its mip chain.
+#[cfg(not(feature = "openimageio"))]
+fn load_png(device: &Device, frame: &mut graal::Frame<'static, ()>, path: &Path) -> Result<ImageAny, LoadImageError> {
+    let mut decoder = png::Decoder::new(fs::File::open(path)?);
+    // palette images and bit depths below 8 are expanded to 8 bits per channel
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|e| LoadImageError::Decode(e.to_string()))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut data)
+        .map_err(|e| LoadImageError::Decode(e.to_string()))?;
+    let num_channels = info.color_type.samples();
+    let (format, bytes_per_pixel) = png_vk_format(info.bit_depth, num_channels).ok_or_else(|| {
+        LoadImageError::Unsupported(format!("{} channels of {:?} bits", num_channels, info.bit_depth))
+    })?;
+
+    let image = create_texture(
+        device.backend(),
+        &path.to_string_lossy(),
+        format,
+        info.width,
+        info.height,
+        graal::get_mip_level_count(info.width, info.height),
+    );
+
+    let byte_size = info.width as u64 * info.height as u64 * bytes_per_pixel as u64;
+    let staging_buffer = create_staging_buffer(device.backend(), byte_size);
+    let dst = unsafe {
+        std::slice::from_raw_parts_mut(
+            staging_buffer.mapped_ptr.unwrap().as_ptr() as *mut u8,
+            byte_size as usize,
+        )
+    };
+    let sample_size = bytes_per_pixel / if num_channels == 3 { 4 } else { num_channels };
+    let src_row_size = info.width as usize * num_channels * sample_size;
+    let dst_row_size = info.width as usize * bytes_per_pixel;
+    for y in 0..info.height as usize {
+        let src_row = &data[y * info.line_size..y * info.line_size + src_row_size];
+        let dst_row = &mut dst[y * dst_row_size..(y + 1) * dst_row_size];
+        for (src_pixel, dst_pixel) in src_row
+            .chunks_exact(num_channels * sample_size)
+            .zip(dst_row.chunks_exact_mut(bytes_per_pixel))
+        {
+            // the alpha channel added to RGB images is opaque
+            dst_pixel.fill(0xFF);
+            for (src_sample, dst_sample) in src_pixel
+                .chunks_exact(sample_size)
+                .zip(dst_pixel.chunks_exact_mut(sample_size))
+            {
+                dst_sample.copy_from_slice(src_sample);
+                // 16-bit samples are stored in big-endian order
+                if cfg!(target_endian = "little") {
+                    dst_sample.reverse();
+                }
+            }
+        }
+    }
+
+    device.stats.count_bytes_uploaded(byte_size);
+    add_upload_pass(frame, device.backend(), staging_buffer, &image, vec![0]);
+    image.generate_mipmaps(frame);
+    Ok(image)
+}
+
+/// Loads a KTX2 file, with the mip levels that it contains.
+fn load_ktx2(device: &Device, frame: &mut graal::Frame<'static, ()>, path: &Path) -> Result<ImageAny, LoadImageError> {
+    let data = fs::read(path)?;
+    let reader = ktx2::Reader::new(&data[..])?;
+    let header = reader.header();
+
+    if header.supercompression_scheme.is_some() {
+        return Err(LoadImageError::Unsupported("supercompressed KTX2 file".to_string()));
+    }
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        return Err(LoadImageError::Unsupported(
+            "KTX2 file is not a 2D texture (3D, array or cubemap)".to_string(),
+        ));
+    }
+    let format = match header.format {
+        Some(format) => vk::Format::from_raw(format.0.get() as i32),
+        None => {
+            return Err(LoadImageError::Unsupported(
+                "KTX2 file without a vulkan format".to_string(),
+            ))
+        }
+    };
+
+    let levels: Vec<&[u8]> = reader.levels().collect();
+    let image = create_texture(
+        device.backend(),
+        &path.to_string_lossy(),
+        format,
+        header.pixel_width,
+        header.pixel_height,
+        levels.len() as u32,
+    );
+
+    // copy all levels into the staging buffer, with offsets aligned to 16 bytes
+    // (a multiple of the texel block size of all formats)
+    let mut offsets = Vec::with_capacity(levels.len());
+    let mut byte_size = 0;
+    for level in levels.iter() {
+        offsets.push(byte_size);
+        byte_size = (byte_size + level.len() as u64 + 15) & !15;
+    }
+    let staging_buffer = create_staging_buffer(device.backend(), byte_size);
+    unsafe {
+        let ptr = staging_buffer.mapped_ptr.unwrap().as_ptr() as *mut u8;
+        for (level, &offset) in levels.iter().zip(offsets.iter()) {
+            ptr::copy_nonoverlapping(level.as_ptr(), ptr.add(offset as usize), level.len());
+        }
+    }
+
+    device.stats.count_bytes_uploaded(byte_size);
+    add_upload_pass(frame, device.backend(), staging_buffer, &image, offsets);
+    Ok(image)
+}
+
+/// Loads an image file into a new image, and uploads it to the GPU in passes added to the frame.
+///
+/// PNG files are loaded with a full mip chain, generated on the GPU. With the `openimageio` feature, so are JPEG,
+/// HDR and the other formats supported by OpenImageIO. KTX2 files (identified by their extension) are loaded with
+/// the mip levels that they contain.
+/// Use `ImageAny::to_sampled_image_2d` to bind the loaded image to a shader. The image is named after the file.
+pub fn load_from_file(
+    device: &Device,
+    frame: &mut graal::Frame<'static, ()>,
+    path: impl AsRef<Path>,
+) -> Result<ImageAny, LoadImageError> {
+    let path = path.as_ref();
+    let is_ktx2 = path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("ktx2"));
+    if is_ktx2 {
+        return load_ktx2(device, frame, path);
+    }
+
+    #[cfg(feature = "openimageio")]
+    {
+        load_with_oiio(device, frame, path)
+    }
+    #[cfg(not(feature = "openimageio"))]
+    {
+        load_png(device, frame, path)
+    }
+}
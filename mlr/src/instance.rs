@@ -0,0 +1,92 @@
+//! Per-instance data
+use crate::{
+    buffer::Buffer,
+    device::Device,
+    vertex::{VertexAttribute, VertexBufferView, VertexData},
+    vk,
+};
+
+/// Per-instance affine transform, stored as the first three rows of a 4x4 matrix.
+///
+/// Occupies three consecutive vertex attribute locations (one `vec4` per row).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct InstanceTransform {
+    pub rows: [[f32; 4]; 3],
+}
+
+impl InstanceTransform {
+    pub const IDENTITY: InstanceTransform = InstanceTransform {
+        rows: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]],
+    };
+
+    /// Creates a transform from a column-major 4x4 matrix (the last row is ignored).
+    pub fn from_cols_array_2d(m: &[[f32; 4]; 4]) -> InstanceTransform {
+        InstanceTransform {
+            rows: [
+                [m[0][0], m[1][0], m[2][0], m[3][0]],
+                [m[0][1], m[1][1], m[2][1], m[3][1]],
+                [m[0][2], m[1][2], m[2][2], m[3][2]],
+            ],
+        }
+    }
+}
+
+unsafe impl VertexData for InstanceTransform {
+    const INPUT_RATE: vk::VertexInputRate = vk::VertexInputRate::INSTANCE;
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute {
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: 0,
+            location: None,
+        },
+        VertexAttribute {
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: 16,
+            location: None,
+        },
+        VertexAttribute {
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: 32,
+            location: None,
+        },
+    ];
+}
+
+/// A host-visible buffer of per-instance data, usually rebuilt every frame.
+pub struct InstanceBuffer<I: VertexData> {
+    buffer: Buffer<I>,
+}
+
+impl<I: VertexData> InstanceBuffer<I> {
+    /// Creates a buffer containing the per-instance data produced by the iterator.
+    pub fn from_iter(device: &Device, instances: impl IntoIterator<Item = I>) -> InstanceBuffer<I> {
+        let instances: Vec<I> = instances.into_iter().collect();
+        InstanceBuffer {
+            buffer: Buffer::from_slice(device, vk::BufferUsageFlags::VERTEX_BUFFER, &instances),
+        }
+    }
+
+    /// Number of instances in the buffer.
+    pub fn len(&self) -> u32 {
+        self.buffer.len() as u32
+    }
+
+    /// Returns a view of the instance data, for use in draw calls.
+    pub fn view(&self) -> VertexBufferView<I> {
+        self.buffer.vertex_view()
+    }
+}
+
+/// Builds a buffer of instance transforms from an iterator of column-major 4x4 matrices.
+pub fn instance_transforms(
+    device: &Device,
+    transforms: impl IntoIterator<Item = [[f32; 4]; 4]>,
+) -> InstanceBuffer<InstanceTransform> {
+    InstanceBuffer::from_iter(
+        device,
+        transforms
+            .into_iter()
+            .map(|m| InstanceTransform::from_cols_array_2d(&m)),
+    )
+}
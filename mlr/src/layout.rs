@@ -0,0 +1,203 @@
+//! Layout of types in GLSL uniform (std140) and storage (std430) blocks
+
+/// Alignment and size of a type in a GLSL block.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TypeLayout {
+    pub align: usize,
+    pub size: usize,
+}
+
+impl TypeLayout {
+    pub const fn new(align: usize, size: usize) -> TypeLayout {
+        TypeLayout { align, size }
+    }
+}
+
+/// Offset and size of a member of a block or struct, along with the layout of its own members if it's a struct.
+///
+/// The offsets in `members` are relative to the start of the member.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MemberLayout<M> {
+    pub offset: usize,
+    pub size: usize,
+    pub members: M,
+}
+
+/// Layout of a column-major float matrix in a GLSL block.
+///
+/// `stride` is the distance in bytes between the starts of two consecutive columns, and corresponds to the
+/// `MatrixStride` decoration of SPIR-V block members. Columns are padded when `stride` is larger than the
+/// size of a column (e.g. the `vec3` columns of a `mat3` occupy 16 bytes).
+///
+/// The Rust representations of `[[f32; 3]; 3]` and `glam::Mat3` don't have this padding: data copied as-is
+/// into a block should use `glam::Mat3A` or `[[f32; 4]; 3]` instead.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MatrixLayout {
+    pub columns: usize,
+    pub rows: usize,
+    pub stride: usize,
+}
+
+impl MatrixLayout {
+    /// Returns the layout of a matrix with the specified number of columns and rows under std140 rules.
+    ///
+    /// Matrices are laid out like arrays of columns, whose stride is rounded up to the size of a `vec4`.
+    pub const fn std140(columns: usize, rows: usize) -> MatrixLayout {
+        MatrixLayout {
+            columns,
+            rows,
+            stride: 16,
+        }
+    }
+
+    /// Returns the layout of a matrix with the specified number of columns and rows under std430 rules.
+    ///
+    /// The column stride is the alignment of the column vector (`vec3` columns are still padded to 16 bytes).
+    pub const fn std430(columns: usize, rows: usize) -> MatrixLayout {
+        MatrixLayout {
+            columns,
+            rows,
+            stride: if rows == 2 { 8 } else { 16 },
+        }
+    }
+
+    /// Returns the alignment and size of the matrix.
+    pub const fn type_layout(&self) -> TypeLayout {
+        TypeLayout::new(self.stride, self.stride * self.columns)
+    }
+}
+
+/// Types that can appear in GLSL uniform and storage blocks.
+///
+/// Implemented for scalars, vectors (arrays of 2 to 4 scalars) and float matrices (arrays of columns, with
+/// `MatrixLayout` members).
+/// `#[derive(StructLayout)]` implements it for structs whose fields all implement it, computing the offsets of
+/// nested structs recursively.
+pub trait BlockLayout {
+    /// Layout of the members of the type if it's a struct, `MatrixLayout` if it's a matrix, `()` otherwise.
+    type Members: Copy;
+    /// Alignment and size under std140 rules (uniform blocks).
+    const STD140: TypeLayout;
+    /// Alignment and size under std430 rules (storage blocks and push constants).
+    const STD430: TypeLayout;
+    /// Layout of the members under std140 rules.
+    const STD140_MEMBERS: Self::Members;
+    /// Layout of the members under std430 rules.
+    const STD430_MEMBERS: Self::Members;
+}
+
+const fn round_up(value: usize, align: usize) -> usize {
+    (value + align - 1) / align * align
+}
+
+/// Returns the offsets of consecutive members of a struct.
+pub const fn member_offsets<const N: usize>(members: [TypeLayout; N]) -> [usize; N] {
+    let mut offsets = [0; N];
+    let mut end = 0;
+    let mut i = 0;
+    while i < N {
+        offsets[i] = round_up(end, members[i].align);
+        end = offsets[i] + members[i].size;
+        i += 1;
+    }
+    offsets
+}
+
+const fn struct_layout(members: &[TypeLayout], min_align: usize) -> TypeLayout {
+    let mut align = min_align;
+    let mut end = 0;
+    let mut i = 0;
+    while i < members.len() {
+        if members[i].align > align {
+            align = members[i].align;
+        }
+        end = round_up(end, members[i].align) + members[i].size;
+        i += 1;
+    }
+    TypeLayout::new(align, round_up(end, align))
+}
+
+/// Returns the layout of a struct with the specified members under std140 rules.
+///
+/// The alignment of structs is rounded up to the alignment of a `vec4`.
+pub const fn std140_struct_layout(members: &[TypeLayout]) -> TypeLayout {
+    struct_layout(members, 16)
+}
+
+/// Returns the layout of a struct with the specified members under std430 rules.
+pub const fn std430_struct_layout(members: &[TypeLayout]) -> TypeLayout {
+    struct_layout(members, 1)
+}
+
+macro_rules! impl_block_layout {
+    ($($t:ty => ($align140:expr, $size140:expr), ($align430:expr, $size430:expr);)*) => {
+        $(impl BlockLayout for $t {
+            type Members = ();
+            const STD140: TypeLayout = TypeLayout::new($align140, $size140);
+            const STD430: TypeLayout = TypeLayout::new($align430, $size430);
+            const STD140_MEMBERS: () = ();
+            const STD430_MEMBERS: () = ();
+        })*
+    };
+}
+
+impl_block_layout! {
+    f32 => (4, 4), (4, 4);
+    i32 => (4, 4), (4, 4);
+    u32 => (4, 4), (4, 4);
+    [f32; 2] => (8, 8), (8, 8);
+    [f32; 3] => (16, 12), (16, 12);
+    [f32; 4] => (16, 16), (16, 16);
+    [i32; 2] => (8, 8), (8, 8);
+    [i32; 3] => (16, 12), (16, 12);
+    [i32; 4] => (16, 16), (16, 16);
+    [u32; 2] => (8, 8), (8, 8);
+    [u32; 3] => (16, 12), (16, 12);
+    [u32; 4] => (16, 16), (16, 16);
+}
+
+macro_rules! impl_matrix_block_layout {
+    ($($t:ty => $columns:expr, $rows:expr;)*) => {
+        $(impl BlockLayout for $t {
+            type Members = MatrixLayout;
+            const STD140: TypeLayout = MatrixLayout::std140($columns, $rows).type_layout();
+            const STD430: TypeLayout = MatrixLayout::std430($columns, $rows).type_layout();
+            const STD140_MEMBERS: MatrixLayout = MatrixLayout::std140($columns, $rows);
+            const STD430_MEMBERS: MatrixLayout = MatrixLayout::std430($columns, $rows);
+        })*
+    };
+}
+
+// matrices are arrays of columns: `[[f32; ROWS]; COLUMNS]`
+impl_matrix_block_layout! {
+    [[f32; 2]; 2] => 2, 2;
+    [[f32; 3]; 3] => 3, 3;
+    [[f32; 4]; 4] => 4, 4;
+    [[f32; 3]; 2] => 2, 3;
+    [[f32; 4]; 2] => 2, 4;
+    [[f32; 2]; 3] => 3, 2;
+    [[f32; 4]; 3] => 3, 4;
+    [[f32; 2]; 4] => 4, 2;
+    [[f32; 3]; 4] => 4, 3;
+}
+
+#[cfg(feature = "glam")]
+impl_block_layout! {
+    glam::Vec2 => (8, 8), (8, 8);
+    glam::Vec3 => (16, 12), (16, 12);
+    glam::Vec4 => (16, 16), (16, 16);
+    glam::IVec2 => (8, 8), (8, 8);
+    glam::IVec3 => (16, 12), (16, 12);
+    glam::IVec4 => (16, 16), (16, 16);
+    glam::UVec2 => (8, 8), (8, 8);
+    glam::UVec3 => (16, 12), (16, 12);
+    glam::UVec4 => (16, 16), (16, 16);
+}
+
+#[cfg(feature = "glam")]
+impl_matrix_block_layout! {
+    glam::Mat2 => 2, 2;
+    glam::Mat3 => 3, 3;
+    glam::Mat3A => 3, 3;
+    glam::Mat4 => 4, 4;
+}
@@ -4,22 +4,59 @@ pub mod buffer;
 pub mod image;
 //pub mod pipeline;
 pub mod arguments;
+pub mod bindless;
+mod deferred;
+pub mod descriptor_cache;
+pub mod draw_list;
+pub mod fragment_output;
 //mod device;
 mod device;
+pub mod instance;
+pub mod layout;
 pub mod pipeline;
+pub mod pipeline_cache;
+pub mod push_constants;
+mod reflect;
+pub mod render_pass;
 pub mod sampler;
 pub mod shader;
+pub mod stats;
+pub mod upload;
 pub mod utils;
+pub mod variants;
 pub mod vertex;
 
 // macro support
 extern crate self as mlr;
 
 pub use arguments::{
-    ArgumentBlock, Arguments, CombinedImageSampler2D, DescriptorBinding, ResourceAccess, SampledImage2D, UniformBuffer,
+    ArgumentBlock, Arguments, CombinedImageSampler2D, DescriptorBinding, DynamicUniformBuffer, ResourceAccess,
+    SampledImage2D, StorageImage2D, UniformBuffer,
 };
+pub use bindless::{TextureArray, TextureIndex};
+pub use buffer::{Buffer, DrawIndexedIndirectCommandBuffer, DrawIndirectCommandBuffer};
+pub use device::{create_device_and_context, Device};
+pub use draw_list::{DrawList, DrawOrder};
+pub use fragment_output::FragmentOutputInterface;
 pub use graal::{self, vk};
+pub use instance::{InstanceBuffer, InstanceTransform};
 pub use kyute_common::atom::Atom;
-pub use mlr_macros::{Arguments, StructLayout, VertexData};
-pub use pipeline::{GraphicsPipelineBuilder, GraphicsPipelineConfig};
-pub use vertex::{VertexAttribute, VertexData};
+pub use mlr_macros::{check_interface, Arguments, FragmentOutputInterface, PushConstants, StructLayout, VertexData};
+pub use pipeline::{
+    BlendComponent, BlendMode, BlendState, ColorTargetState, ColorWrites, CompareFunction, ComputePipeline,
+    ComputePipelineBuilder, DepthBiasState, DepthState, DepthStencilState, DynamicStates, GraphicsPipelineBuilder,
+    GraphicsPipelineConfig, StaticState, StencilFaceState, StencilOperation, StencilState,
+};
+pub use pipeline_cache::GraphicsPipelineCache;
+pub use push_constants::PushConstants;
+pub use render_pass::{
+    AttachmentLoadOp, AttachmentStoreOp, ClearColorValue, RenderPass, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor,
+};
+pub use stats::FrameStats;
+pub use upload::UploadContext;
+pub use variants::PipelineVariants;
+pub use vertex::{
+    IndexBufferView, IndexData, Mesh, Norm, VertexAttribute, VertexAttributeType, VertexBufferView, VertexData,
+    VertexInputLayout,
+};
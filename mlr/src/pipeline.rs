@@ -1,8 +1,19 @@
-use crate::{shader::ShaderModule, vk::GraphicsPipelineCreateInfo, Arguments, VertexAttribute, VertexData};
+use crate::{
+    deferred::{DeferredDestroyQueue, RetiredObject},
+    fragment_output::FragmentOutputInterface,
+    push_constants::PushConstants,
+    reflect::DescriptorBindingInfo,
+    render_pass::{argument_push_constants, check_dynamic_offsets, depth_stencil_aspects},
+    shader,
+    shader::{ArgumentsMismatchError, ShaderModule},
+    utils::set_debug_object_name,
+    vk::GraphicsPipelineCreateInfo,
+    Arguments, VertexAttribute, VertexData, VertexInputLayout,
+};
 use bitflags::bitflags;
 use graal::vk;
 use mlr::device::Device;
-use std::{ffi::c_void, marker::PhantomData, mem, os::raw::c_char, ptr, sync::Arc};
+use std::{ffi::c_void, marker::PhantomData, os::raw::c_char, ptr, sync::Arc};
 
 //--------------------------------------------------------------------------------------------------
 
@@ -49,7 +60,7 @@ impl GraphicsShaderStages {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum CompareFunction {
     Never,
     Less,
@@ -62,6 +73,19 @@ pub enum CompareFunction {
 }
 
 impl CompareFunction {
+    fn to_vk(self) -> vk::CompareOp {
+        match self {
+            CompareFunction::Never => vk::CompareOp::NEVER,
+            CompareFunction::Less => vk::CompareOp::LESS,
+            CompareFunction::Equal => vk::CompareOp::EQUAL,
+            CompareFunction::LessEqual => vk::CompareOp::LESS_OR_EQUAL,
+            CompareFunction::Greater => vk::CompareOp::GREATER,
+            CompareFunction::NotEqual => vk::CompareOp::NOT_EQUAL,
+            CompareFunction::GreaterEqual => vk::CompareOp::GREATER_OR_EQUAL,
+            CompareFunction::Always => vk::CompareOp::ALWAYS,
+        }
+    }
+
     /// Returns true if the comparison depends on the reference value.
     pub fn needs_ref_value(self) -> bool {
         match self {
@@ -71,7 +95,7 @@ impl CompareFunction {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct StencilFaceState {
     pub compare: CompareFunction,
     pub fail_op: StencilOperation,
@@ -95,6 +119,18 @@ impl StencilFaceState {
             || self.depth_fail_op == StencilOperation::Replace
             || self.pass_op == StencilOperation::Replace
     }
+
+    fn to_vk(&self, stencil: &StencilState) -> vk::StencilOpState {
+        vk::StencilOpState {
+            fail_op: self.fail_op.to_vk(),
+            pass_op: self.pass_op.to_vk(),
+            depth_fail_op: self.depth_fail_op.to_vk(),
+            compare_op: self.compare.to_vk(),
+            compare_mask: stencil.read_mask,
+            write_mask: stencil.write_mask,
+            reference: stencil.reference,
+        }
+    }
 }
 
 impl Default for StencilFaceState {
@@ -103,7 +139,7 @@ impl Default for StencilFaceState {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum StencilOperation {
     Keep,
     Zero,
@@ -115,15 +151,41 @@ pub enum StencilOperation {
     DecrementWrap,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl StencilOperation {
+    fn to_vk(self) -> vk::StencilOp {
+        match self {
+            StencilOperation::Keep => vk::StencilOp::KEEP,
+            StencilOperation::Zero => vk::StencilOp::ZERO,
+            StencilOperation::Replace => vk::StencilOp::REPLACE,
+            StencilOperation::Invert => vk::StencilOp::INVERT,
+            StencilOperation::IncrementClamp => vk::StencilOp::INCREMENT_AND_CLAMP,
+            StencilOperation::DecrementClamp => vk::StencilOp::DECREMENT_AND_CLAMP,
+            StencilOperation::IncrementWrap => vk::StencilOp::INCREMENT_AND_WRAP,
+            StencilOperation::DecrementWrap => vk::StencilOp::DECREMENT_AND_WRAP,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct StencilState {
     pub front: StencilFaceState,
     pub back: StencilFaceState,
     pub read_mask: u32,
     pub write_mask: u32,
+    /// Reference value used by the stencil test and the `Replace` operation.
+    pub reference: u32,
 }
 
 impl StencilState {
+    /// Stencil test disabled.
+    pub const DISABLED: StencilState = StencilState {
+        front: StencilFaceState::IGNORE,
+        back: StencilFaceState::IGNORE,
+        read_mask: 0,
+        write_mask: 0,
+        reference: 0,
+    };
+
     /// Returns true if the stencil test is enabled.
     pub fn is_enabled(&self) -> bool {
         (self.front != StencilFaceState::IGNORE || self.back != StencilFaceState::IGNORE)
@@ -135,19 +197,121 @@ impl StencilState {
     }
     /// Returns true if the stencil state uses the reference value for testing.
     pub fn needs_ref_value(&self) -> bool {
-        todo!()
-        //self.front.needs_ref_value() || self.back.needs_ref_value()
+        self.front.needs_ref_value() || self.back.needs_ref_value()
+    }
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        Self::DISABLED
+    }
+}
+
+/// Depth test configuration.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DepthState {
+    /// Whether fragments are tested against the depth buffer.
+    pub test_enabled: bool,
+    /// Whether the depth of the fragments that pass the test is written to the depth buffer.
+    pub write_enabled: bool,
+    pub compare: CompareFunction,
+    /// Range of the depth bounds test (`min`, `max`), or `None` to disable it.
+    pub bounds: Option<(f32, f32)>,
+}
+
+impl DepthState {
+    /// No depth test, no depth writes.
+    pub const DISABLED: DepthState = DepthState {
+        test_enabled: false,
+        write_enabled: false,
+        compare: CompareFunction::Always,
+        bounds: None,
+    };
+
+    /// Usual depth test for opaque geometry: keeps the closest fragments and writes their depth.
+    pub const LESS_WRITE: DepthState = DepthState {
+        test_enabled: true,
+        write_enabled: true,
+        compare: CompareFunction::Less,
+        bounds: None,
+    };
+
+    /// Same as `LESS_WRITE`, but fragments at the same depth as the stored value also pass.
+    pub const LESS_EQUAL_WRITE: DepthState = DepthState {
+        test_enabled: true,
+        write_enabled: true,
+        compare: CompareFunction::LessEqual,
+        bounds: None,
+    };
+
+    /// Depth test without depth writes, e.g. for transparent geometry or after a depth pre-pass.
+    pub const READ_ONLY: DepthState = DepthState {
+        test_enabled: true,
+        write_enabled: false,
+        compare: CompareFunction::LessEqual,
+        bounds: None,
+    };
+
+    /// Returns this state with a different comparison function.
+    pub const fn with_compare(mut self, compare: CompareFunction) -> DepthState {
+        self.compare = compare;
+        self
+    }
+
+    /// Returns this state with the depth bounds test enabled (requires the `depthBounds` device feature).
+    pub const fn with_bounds(mut self, min: f32, max: f32) -> DepthState {
+        self.bounds = Some((min, max));
+        self
+    }
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self::LESS_WRITE
     }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct DepthStencilState {
-    pub depth_write_enabled: bool,
-    pub depth_compare: vk::CompareOp,
+    pub depth: DepthState,
     pub stencil: StencilState,
     pub bias: DepthBiasState,
 }
 
+impl DepthStencilState {
+    /// Depth-only configuration, without stencil test and depth bias.
+    pub const fn depth(depth: DepthState) -> DepthStencilState {
+        DepthStencilState {
+            depth,
+            stencil: StencilState::DISABLED,
+            bias: DepthBiasState::NONE,
+        }
+    }
+
+    fn to_vk(&self) -> vk::PipelineDepthStencilStateCreateInfo {
+        let (min_depth_bounds, max_depth_bounds) = self.depth.bounds.unwrap_or((0.0, 1.0));
+        vk::PipelineDepthStencilStateCreateInfo {
+            flags: Default::default(),
+            depth_test_enable: self.depth.test_enabled as vk::Bool32,
+            depth_write_enable: self.depth.write_enabled as vk::Bool32,
+            depth_compare_op: self.depth.compare.to_vk(),
+            depth_bounds_test_enable: self.depth.bounds.is_some() as vk::Bool32,
+            stencil_test_enable: self.stencil.is_enabled() as vk::Bool32,
+            front: self.stencil.front.to_vk(&self.stencil),
+            back: self.stencil.back.to_vk(&self.stencil),
+            min_depth_bounds,
+            max_depth_bounds,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for DepthStencilState {
+    fn default() -> Self {
+        DepthStencilState::depth(DepthState::default())
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct DepthBiasState {
     pub constant: i32,
@@ -155,19 +319,95 @@ pub struct DepthBiasState {
     pub clamp: f32,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl DepthBiasState {
+    /// No depth bias.
+    pub const NONE: DepthBiasState = DepthBiasState {
+        constant: 0,
+        slope_scale: 0.0,
+        clamp: 0.0,
+    };
+
+    /// Returns true if the depth bias is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.constant != 0 || self.slope_scale != 0.0
+    }
+}
+
+impl Default for DepthBiasState {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct BlendComponent {
     pub src_factor: vk::BlendFactor,
     pub dst_factor: vk::BlendFactor,
     pub operation: vk::BlendOp,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl BlendComponent {
+    /// `src * src_factor + dst * dst_factor`
+    pub const fn add(src_factor: vk::BlendFactor, dst_factor: vk::BlendFactor) -> BlendComponent {
+        BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: vk::BlendOp::ADD,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct BlendState {
     pub color: BlendComponent,
     pub alpha: BlendComponent,
 }
 
+impl BlendState {
+    /// Straight (non-premultiplied) alpha blending.
+    pub const ALPHA_BLENDING: BlendState = BlendState {
+        color: BlendComponent::add(vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+        alpha: BlendComponent::add(vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+    };
+
+    /// Blending of colors premultiplied by alpha ("over" operator).
+    pub const PREMULTIPLIED_ALPHA_BLENDING: BlendState = BlendState {
+        color: BlendComponent::add(vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+        alpha: BlendComponent::add(vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+    };
+
+    /// Adds the fragment color to the target.
+    pub const ADDITIVE: BlendState = BlendState {
+        color: BlendComponent::add(vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+        alpha: BlendComponent::add(vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+    };
+}
+
+/// Common blending configurations of a color attachment.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BlendMode {
+    /// No blending, the fragment color replaces the target.
+    Opaque,
+    /// See `BlendState::ALPHA_BLENDING`.
+    AlphaBlend,
+    /// See `BlendState::PREMULTIPLIED_ALPHA_BLENDING`.
+    Premultiplied,
+    /// See `BlendState::ADDITIVE`.
+    Additive,
+}
+
+impl BlendMode {
+    /// Returns the blend state corresponding to this mode, or `None` if blending is disabled.
+    pub fn blend_state(self) -> Option<BlendState> {
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::AlphaBlend => Some(BlendState::ALPHA_BLENDING),
+            BlendMode::Premultiplied => Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            BlendMode::Additive => Some(BlendState::ADDITIVE),
+        }
+    }
+}
+
 bitflags::bitflags! {
     /// Color write mask. Disabled color channels will not be written to.
     #[repr(transparent)]
@@ -187,12 +427,129 @@ bitflags::bitflags! {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Blending configuration of a color attachment.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct ColorTargetState {
     pub blend: Option<BlendState>,
     pub write_mask: ColorWrites,
 }
 
+impl ColorTargetState {
+    /// Opaque color target writing to all channels.
+    pub const OPAQUE: ColorTargetState = ColorTargetState {
+        blend: None,
+        write_mask: ColorWrites::ALL,
+    };
+
+    /// Color target writing to all channels with the specified blend mode.
+    pub fn new(mode: BlendMode) -> ColorTargetState {
+        ColorTargetState {
+            blend: mode.blend_state(),
+            write_mask: ColorWrites::ALL,
+        }
+    }
+
+    /// Returns this state with a different color write mask.
+    pub fn with_write_mask(mut self, write_mask: ColorWrites) -> ColorTargetState {
+        self.write_mask = write_mask;
+        self
+    }
+
+    fn to_vk(&self) -> vk::PipelineColorBlendAttachmentState {
+        let color_write_mask = vk::ColorComponentFlags::from_raw(self.write_mask.bits);
+        if let Some(blend) = self.blend {
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: vk::TRUE,
+                src_color_blend_factor: blend.color.src_factor,
+                dst_color_blend_factor: blend.color.dst_factor,
+                color_blend_op: blend.color.operation,
+                src_alpha_blend_factor: blend.alpha.src_factor,
+                dst_alpha_blend_factor: blend.alpha.dst_factor,
+                alpha_blend_op: blend.alpha.operation,
+                color_write_mask,
+            }
+        } else {
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: vk::FALSE,
+                color_write_mask,
+                ..Default::default()
+            }
+        }
+    }
+}
+
+impl Default for ColorTargetState {
+    fn default() -> Self {
+        Self::OPAQUE
+    }
+}
+
+impl From<BlendMode> for ColorTargetState {
+    fn from(mode: BlendMode) -> Self {
+        ColorTargetState::new(mode)
+    }
+}
+
+bitflags::bitflags! {
+    /// Pipeline states that are specified at draw time (see `RenderPass::set_viewport` etc.)
+    /// instead of when the pipeline is created.
+    #[repr(transparent)]
+    pub struct DynamicStates: u32 {
+        const VIEWPORT = 1 << 0;
+        const SCISSOR = 1 << 1;
+        const LINE_WIDTH = 1 << 2;
+        const BLEND_CONSTANTS = 1 << 3;
+    }
+}
+
+impl DynamicStates {
+    fn to_vk(self) -> Vec<vk::DynamicState> {
+        let mut states = Vec::new();
+        if self.contains(DynamicStates::VIEWPORT) {
+            states.push(vk::DynamicState::VIEWPORT);
+        }
+        if self.contains(DynamicStates::SCISSOR) {
+            states.push(vk::DynamicState::SCISSOR);
+        }
+        if self.contains(DynamicStates::LINE_WIDTH) {
+            states.push(vk::DynamicState::LINE_WIDTH);
+        }
+        if self.contains(DynamicStates::BLEND_CONSTANTS) {
+            states.push(vk::DynamicState::BLEND_CONSTANTS);
+        }
+        states
+    }
+}
+
+impl Default for DynamicStates {
+    /// Viewport and scissor are dynamic, so that resizing the render targets doesn't require new pipelines.
+    fn default() -> Self {
+        DynamicStates::VIEWPORT | DynamicStates::SCISSOR
+    }
+}
+
+/// Values of the states that are not dynamic, baked into the pipeline.
+#[derive(Copy, Clone, Debug)]
+pub struct StaticState {
+    /// Must be specified if the viewport is not dynamic.
+    pub viewport: Option<vk::Viewport>,
+    /// Must be specified if the scissor is not dynamic.
+    pub scissor: Option<vk::Rect2D>,
+    pub line_width: f32,
+    pub blend_constants: [f32; 4],
+}
+
+impl Default for StaticState {
+    fn default() -> Self {
+        StaticState {
+            viewport: None,
+            scissor: None,
+            line_width: 1.0,
+            blend_constants: [0.0; 4],
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum IndexFormat {
     Uint16,
@@ -253,6 +610,10 @@ pub enum PrimitiveTopology {
     LineStrip,
     TriangleList,
     TriangleStrip,
+    /// Patches of the specified number of control points, for tessellation shaders.
+    PatchList {
+        control_points: u32,
+    },
 }
 
 impl PrimitiveTopology {
@@ -263,11 +624,12 @@ impl PrimitiveTopology {
             PrimitiveTopology::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
             PrimitiveTopology::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
             PrimitiveTopology::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
+            PrimitiveTopology::PatchList { .. } => vk::PrimitiveTopology::PATCH_LIST,
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct PrimitiveState {
     pub topology: PrimitiveTopology,
     //pub strip_index_format: Option<IndexFormat>,
@@ -278,7 +640,7 @@ pub struct PrimitiveState {
     pub conservative: bool,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct MultisampleState {
     pub count: u32,
     pub mask: u64,
@@ -286,52 +648,250 @@ pub struct MultisampleState {
 }
 
 /// Create info for a graphics pipeline (excluding interfaces).
+///
+/// A pipeline has either a vertex shader, or a mesh shader and an optional task shader
+/// (`VK_EXT_mesh_shader`, which must be enabled on the device).
+///
+/// Vertex pipelines can have tessellation control and evaluation shaders, which must be specified together
+/// and require the `PatchList` topology, and a geometry shader.
 pub struct GraphicsPipelineConfig<'a> {
-    pub vertex_shader: &'a ShaderModule,
+    pub vertex_shader: Option<&'a ShaderModule>,
+    pub tess_control_shader: Option<&'a ShaderModule>,
+    pub tess_evaluation_shader: Option<&'a ShaderModule>,
+    pub geometry_shader: Option<&'a ShaderModule>,
+    pub task_shader: Option<&'a ShaderModule>,
+    pub mesh_shader: Option<&'a ShaderModule>,
     pub fragment_shader: &'a ShaderModule,
     pub primitive_state: PrimitiveState,
     pub multisample_state: MultisampleState,
     pub depth_stencil_state: Option<DepthStencilState>,
+    /// Blending configuration of each color attachment of the fragment output interface.
+    ///
+    /// If empty, all color attachments are opaque. Attachments with different configurations require
+    /// the `independentBlend` device feature.
     pub color_attachments: &'a [ColorTargetState],
+    /// States specified at draw time.
+    pub dynamic_states: DynamicStates,
+    /// Values of the states that are not in `dynamic_states`.
+    pub static_state: StaticState,
+    /// Push constant range of the pipeline layout (see `push_constants`).
+    pub push_constant_range: Option<vk::PushConstantRange>,
+}
+
+impl<'a> GraphicsPipelineConfig<'a> {
+    /// Declares push constants of the specified type in the pipeline layout.
+    ///
+    /// Can be called for several types at different offsets (see `PushConstants::OFFSET`): the pipeline then
+    /// declares a single range covering all of them, accessed by all their stages.
+    pub fn push_constants<T: PushConstants>(mut self) -> GraphicsPipelineConfig<'a> {
+        self.push_constant_range = Some(merge_push_constant_ranges(self.push_constant_range, T::range()));
+        self
+    }
 }
 
+/// Returns the smallest push constant range that contains `range` and `other` (if specified), accessed by the
+/// stages of both.
+pub(crate) fn merge_push_constant_ranges(
+    range: Option<vk::PushConstantRange>,
+    other: vk::PushConstantRange,
+) -> vk::PushConstantRange {
+    match range {
+        None => other,
+        Some(range) => {
+            let offset = range.offset.min(other.offset);
+            let end = (range.offset + range.size).max(other.offset + other.size);
+            vk::PushConstantRange {
+                stage_flags: range.stage_flags | other.stage_flags,
+                offset,
+                size: end - offset,
+            }
+        }
+    }
+}
+
+/// Checks that the push constant block of a shader is within the push constant range of the pipeline.
+fn check_push_constants(shader: &ShaderModule, range: Option<&vk::PushConstantRange>) {
+    if let Some(block_size) = shader.push_constant_size {
+        let range = range.expect("the shader uses push constants, but the pipeline doesn't declare push constants");
+        assert!(
+            block_size <= range.offset + range.size,
+            "push constant block of the shader ({} bytes) exceeds the push constant range of the pipeline ({} bytes)",
+            block_size,
+            range.offset + range.size
+        );
+    }
+}
+
+#[derive(Default)]
 pub struct PipelineInterfaceDesc<'a> {
-    vertex_bindings: &'a [vk::VertexInputBindingDescription],
-    vertex_attributes: &'a [vk::VertexInputAttributeDescription],
-    descriptor_set_layouts: &'a [vk::DescriptorSetLayout],
-    color_attachment_formats: &'a [vk::Format],
-    depth_attachment_format: Option<vk::Format>,
-    stencil_attachment_format: Option<vk::Format>,
+    pub(crate) vertex_bindings: &'a [vk::VertexInputBindingDescription],
+    pub(crate) vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+    pub(crate) descriptor_set_layouts: &'a [vk::DescriptorSetLayout],
+    pub(crate) color_attachment_formats: &'a [vk::Format],
+    pub(crate) depth_attachment_format: Option<vk::Format>,
+    pub(crate) stencil_attachment_format: Option<vk::Format>,
+}
+
+impl<'a> PipelineInterfaceDesc<'a> {
+    /// Sets the vertex input bindings and attributes, which can include per-instance bindings
+    /// (see `VertexInputLayout::per_instance`).
+    pub fn with_vertex_input(self, layout: &'a VertexInputLayout) -> PipelineInterfaceDesc<'a> {
+        PipelineInterfaceDesc {
+            vertex_bindings: &layout.bindings,
+            vertex_attributes: &layout.attributes,
+            ..self
+        }
+    }
+
+    /// Sets the layouts of the descriptor sets of the pipeline layout, in set order (e.g. the layout of a
+    /// `TextureArray`).
+    pub fn with_descriptor_set_layouts(self, layouts: &'a [vk::DescriptorSetLayout]) -> PipelineInterfaceDesc<'a> {
+        PipelineInterfaceDesc {
+            descriptor_set_layouts: layouts,
+            ..self
+        }
+    }
+
+    /// Sets the formats of the color attachments, in attachment order.
+    ///
+    /// Use this instead of `with_fragment_output` to describe attachments without a fragment output interface type.
+    pub fn with_color_attachment_formats(self, formats: &'a [vk::Format]) -> PipelineInterfaceDesc<'a> {
+        PipelineInterfaceDesc {
+            color_attachment_formats: formats,
+            ..self
+        }
+    }
+
+    /// Sets the format of the depth/stencil attachment.
+    ///
+    /// Formats with a stencil aspect are also used as the format of the stencil attachment, and `S8_UINT`
+    /// only as the format of the stencil attachment.
+    pub fn with_depth_attachment_format(self, format: Option<vk::Format>) -> PipelineInterfaceDesc<'a> {
+        let aspects = format.map_or(vk::ImageAspectFlags::empty(), depth_stencil_aspects);
+        PipelineInterfaceDesc {
+            depth_attachment_format: format.filter(|_| aspects.contains(vk::ImageAspectFlags::DEPTH)),
+            stencil_attachment_format: format.filter(|_| aspects.contains(vk::ImageAspectFlags::STENCIL)),
+            ..self
+        }
+    }
+
+    /// Sets the formats of the attachments from a fragment output interface.
+    pub fn with_fragment_output<FO: FragmentOutputInterface>(self) -> PipelineInterfaceDesc<'a> {
+        PipelineInterfaceDesc {
+            color_attachment_formats: FO::COLOR_ATTACHMENT_FORMATS,
+            depth_attachment_format: FO::DEPTH_ATTACHMENT_FORMAT,
+            stencil_attachment_format: FO::STENCIL_ATTACHMENT_FORMAT,
+            ..self
+        }
+    }
 }
 
 pub struct RawGraphicsPipeline {
     device: Arc<graal::Device>,
+    deferred: DeferredDestroyQueue,
     pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    push_constant_range: Option<vk::PushConstantRange>,
+    /// Descriptor bindings declared in all shader stages.
+    descriptor_bindings: Arc<Vec<DescriptorBindingInfo>>,
+    color_attachment_formats: Vec<vk::Format>,
+    depth_attachment_format: Option<vk::Format>,
+    stencil_attachment_format: Option<vk::Format>,
 }
 
 impl RawGraphicsPipeline {
+    /// Creates a graphics pipeline.
+    ///
+    /// The pipeline is destroyed once the frames that may still use it have completed after it is dropped
+    /// (see `Device::collect`).
     pub unsafe fn new(
-        device: &Arc<graal::Device>,
+        device: &Device,
         config: &GraphicsPipelineConfig,
         interface: &PipelineInterfaceDesc,
     ) -> RawGraphicsPipeline {
+        RawGraphicsPipeline::create(&device.deferred, config, interface)
+    }
+
+    pub(crate) unsafe fn create(
+        deferred: &DeferredDestroyQueue,
+        config: &GraphicsPipelineConfig,
+        interface: &PipelineInterfaceDesc,
+    ) -> RawGraphicsPipeline {
+        let device = deferred.device();
+        assert!(
+            config.vertex_shader.is_some() != config.mesh_shader.is_some(),
+            "a graphics pipeline must have either a vertex shader or a mesh shader"
+        );
+        assert!(
+            config.task_shader.is_none() || config.mesh_shader.is_some(),
+            "a task shader requires a mesh shader"
+        );
+        assert!(
+            config.tess_control_shader.is_some() == config.tess_evaluation_shader.is_some(),
+            "tessellation control and evaluation shaders must be specified together"
+        );
+        assert!(
+            config.vertex_shader.is_some()
+                || (config.tess_control_shader.is_none() && config.geometry_shader.is_none()),
+            "tessellation and geometry shaders require a vertex shader"
+        );
+        let patch_control_points = match config.primitive_state.topology {
+            PrimitiveTopology::PatchList { control_points } => Some(control_points),
+            _ => None,
+        };
+        assert!(
+            config.tess_control_shader.is_some() == patch_control_points.is_some(),
+            "tessellation shaders require the `PatchList` topology, and vice versa"
+        );
+
+        let push_constant_range = config.push_constant_range.as_ref();
+
         let mut pipeline_shader_stages = Vec::new();
-        pipeline_shader_stages.push(vk::PipelineShaderStageCreateInfo {
-            flags: vk::PipelineShaderStageCreateFlags::empty(),
-            stage: vk::ShaderStageFlags::VERTEX,
-            module: config.vertex_shader.get_or_create_shader_module(device),
-            p_name: b"main\0".as_ptr() as *const c_char,
-            p_specialization_info: ::std::ptr::null(),
-            ..Default::default()
-        });
-        pipeline_shader_stages.push(vk::PipelineShaderStageCreateInfo {
-            flags: vk::PipelineShaderStageCreateFlags::empty(),
-            stage: vk::ShaderStageFlags::FRAGMENT,
-            module: config.fragment_shader.get_or_create_shader_module(device),
-            p_name: b"main\0".as_ptr() as *const c_char,
-            p_specialization_info: ::std::ptr::null(),
-            ..Default::default()
-        });
+        let mut specialization_infos = Vec::new();
+        let mut descriptor_bindings = Vec::new();
+        let mut push_stage = |stage: vk::ShaderStageFlags, shader: &ShaderModule| {
+            check_push_constants(shader, push_constant_range);
+            descriptor_bindings.extend(shader.descriptor_bindings.iter().cloned());
+            specialization_infos.push(shader.specialization_info());
+            pipeline_shader_stages.push(vk::PipelineShaderStageCreateInfo {
+                flags: vk::PipelineShaderStageCreateFlags::empty(),
+                stage,
+                module: shader.shader_module,
+                p_name: b"main\0".as_ptr() as *const c_char,
+                p_specialization_info: ::std::ptr::null(),
+                ..Default::default()
+            });
+        };
+        if let Some(vertex_shader) = config.vertex_shader {
+            push_stage(vk::ShaderStageFlags::VERTEX, vertex_shader);
+        }
+        if let Some(tess_control_shader) = config.tess_control_shader {
+            push_stage(vk::ShaderStageFlags::TESSELLATION_CONTROL, tess_control_shader);
+        }
+        if let Some(tess_evaluation_shader) = config.tess_evaluation_shader {
+            push_stage(vk::ShaderStageFlags::TESSELLATION_EVALUATION, tess_evaluation_shader);
+        }
+        if let Some(geometry_shader) = config.geometry_shader {
+            push_stage(vk::ShaderStageFlags::GEOMETRY, geometry_shader);
+        }
+        if let Some(task_shader) = config.task_shader {
+            push_stage(vk::ShaderStageFlags::TASK_EXT, task_shader);
+        }
+        if let Some(mesh_shader) = config.mesh_shader {
+            push_stage(vk::ShaderStageFlags::MESH_EXT, mesh_shader);
+        }
+        push_stage(vk::ShaderStageFlags::FRAGMENT, config.fragment_shader);
+        // point to the specialization infos once they are all collected, since the vector may reallocate
+        for (stage, info) in pipeline_shader_stages.iter_mut().zip(specialization_infos.iter()) {
+            if let Some(info) = info {
+                stage.p_specialization_info = info;
+            }
+        }
+        descriptor_bindings.sort_by_key(|b| (b.set, b.binding));
+        descriptor_bindings.dedup();
+
+        // mesh pipelines have no vertex input or input assembly stages
+        let is_mesh_pipeline = config.mesh_shader.is_some();
 
         let vertex_input_state = vk::PipelineVertexInputStateCreateInfo {
             flags: vk::PipelineVertexInputStateCreateFlags::empty(),
@@ -349,17 +909,44 @@ impl RawGraphicsPipeline {
             ..Default::default()
         };
 
-        let tessellation_state = vk::PipelineTessellationStateCreateInfo::default();
+        let tessellation_state = vk::PipelineTessellationStateCreateInfo {
+            patch_control_points: patch_control_points.unwrap_or(0),
+            ..Default::default()
+        };
 
+        let dynamic_states = config.dynamic_states;
+        let static_state = &config.static_state;
+        let viewport = if dynamic_states.contains(DynamicStates::VIEWPORT) {
+            None
+        } else {
+            Some(
+                static_state
+                    .viewport
+                    .expect("the viewport must be specified if it is not a dynamic state"),
+            )
+        };
+        let scissor = if dynamic_states.contains(DynamicStates::SCISSOR) {
+            None
+        } else {
+            Some(
+                static_state
+                    .scissor
+                    .expect("the scissor must be specified if it is not a dynamic state"),
+            )
+        };
         let viewport_state = vk::PipelineViewportStateCreateInfo {
             flags: vk::PipelineViewportStateCreateFlags::empty(),
             viewport_count: 1,
-            p_viewports: ptr::null(),
+            p_viewports: viewport.as_ref().map_or(ptr::null(), |v| v as *const _),
             scissor_count: 1,
-            p_scissors: ptr::null(),
+            p_scissors: scissor.as_ref().map_or(ptr::null(), |s| s as *const _),
             ..Default::default()
         };
 
+        let depth_bias = config
+            .depth_stencil_state
+            .map(|dss| dss.bias)
+            .unwrap_or(DepthBiasState::NONE);
         let rasterization_state = vk::PipelineRasterizationStateCreateInfo {
             flags: Default::default(),
             depth_clamp_enable: vk::FALSE,
@@ -367,11 +954,11 @@ impl RawGraphicsPipeline {
             polygon_mode: config.primitive_state.polygon_mode.to_vk(),
             cull_mode: vk::CullModeFlags::NONE, // TODO
             front_face: config.primitive_state.front_face.to_vk(),
-            depth_bias_enable: vk::FALSE, // TODO
-            depth_bias_constant_factor: 0.0,
-            depth_bias_clamp: 0.0,
-            depth_bias_slope_factor: 0.0,
-            line_width: 1.0,
+            depth_bias_enable: depth_bias.is_enabled() as vk::Bool32,
+            depth_bias_constant_factor: depth_bias.constant as f32,
+            depth_bias_clamp: depth_bias.clamp,
+            depth_bias_slope_factor: depth_bias.slope_scale,
+            line_width: static_state.line_width,
             ..Default::default()
         };
 
@@ -399,75 +986,40 @@ impl RawGraphicsPipeline {
             ..Default::default()
         };
 
-        let depth_stencil_state = if let Some(ref dss) = config.depth_stencil_state {
-            vk::PipelineDepthStencilStateCreateInfo {
-                flags: Default::default(),
-                depth_test_enable: vk::TRUE,
-                depth_write_enable: if dss.depth_write_enabled { vk::TRUE } else { vk::FALSE },
-                depth_compare_op: dss.depth_compare,
-                depth_bounds_test_enable: 0,
-                stencil_test_enable: vk::FALSE, // TODO
-                front: Default::default(),
-                back: Default::default(),
-                min_depth_bounds: 0.0,
-                max_depth_bounds: 0.0,
-                ..Default::default()
-            }
+        let depth_stencil_state = config
+            .depth_stencil_state
+            .unwrap_or(DepthStencilState::depth(DepthState::DISABLED))
+            .to_vk();
+
+        let color_attachment_count = interface.color_attachment_formats.len();
+        let color_blend_attachments: Vec<_> = if config.color_attachments.is_empty() {
+            vec![ColorTargetState::OPAQUE.to_vk(); color_attachment_count]
         } else {
-            vk::PipelineDepthStencilStateCreateInfo {
-                flags: Default::default(),
-                depth_test_enable: vk::FALSE,
-                depth_write_enable: vk::FALSE,
-                depth_compare_op: Default::default(),
-                depth_bounds_test_enable: 0,
-                stencil_test_enable: 0,
-                front: Default::default(),
-                back: Default::default(),
-                min_depth_bounds: 0.0,
-                max_depth_bounds: 0.0,
-                ..Default::default()
+            assert_eq!(
+                config.color_attachments.len(),
+                color_attachment_count,
+                "the number of color target states doesn't match the number of color attachments of the interface"
+            );
+            if config.color_attachments.windows(2).any(|w| w[0] != w[1]) {
+                assert!(
+                    device.physical_device_features().independent_blend != 0,
+                    "color attachments with different blend states require the `independentBlend` device feature"
+                );
             }
+            config.color_attachments.iter().map(ColorTargetState::to_vk).collect()
         };
 
-        let mut color_blend_attachments = Vec::with_capacity(config.color_attachments.len());
-        for cts in config.color_attachments {
-            let color_blend_attachment = if let Some(blend) = cts.blend {
-                vk::PipelineColorBlendAttachmentState {
-                    blend_enable: vk::TRUE,
-                    src_color_blend_factor: blend.color.src_factor,
-                    dst_color_blend_factor: blend.color.dst_factor,
-                    color_blend_op: blend.color.operation,
-                    src_alpha_blend_factor: blend.alpha.src_factor,
-                    dst_alpha_blend_factor: blend.alpha.dst_factor,
-                    alpha_blend_op: blend.alpha.operation,
-                    color_write_mask: vk::ColorComponentFlags::from_raw(cts.write_mask.bits),
-                }
-            } else {
-                vk::PipelineColorBlendAttachmentState {
-                    blend_enable: vk::FALSE,
-                    src_color_blend_factor: Default::default(),
-                    dst_color_blend_factor: Default::default(),
-                    color_blend_op: Default::default(),
-                    src_alpha_blend_factor: Default::default(),
-                    dst_alpha_blend_factor: Default::default(),
-                    alpha_blend_op: Default::default(),
-                    color_write_mask: vk::ColorComponentFlags::from_raw(cts.write_mask.bits),
-                }
-            };
-            color_blend_attachments.push(color_blend_attachment);
-        }
-
         let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
             flags: Default::default(),
             logic_op_enable: vk::FALSE, // TODO
             logic_op: Default::default(),
             attachment_count: color_blend_attachments.len() as u32,
             p_attachments: color_blend_attachments.as_ptr(),
-            blend_constants: [0.0f32; 4],
+            blend_constants: static_state.blend_constants,
             ..Default::default()
         };
 
-        let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_states = dynamic_states.to_vk();
 
         let dynamic_state = vk::PipelineDynamicStateCreateInfo {
             flags: Default::default(),
@@ -482,8 +1034,8 @@ impl RawGraphicsPipeline {
                 flags: vk::PipelineLayoutCreateFlags::empty(),
                 set_layout_count: interface.descriptor_set_layouts.len() as u32,
                 p_set_layouts: interface.descriptor_set_layouts.as_ptr(),
-                push_constant_range_count: 0,
-                p_push_constant_ranges: ptr::null(),
+                push_constant_range_count: push_constant_range.is_some() as u32,
+                p_push_constant_ranges: push_constant_range.map_or(ptr::null(), |r| r as *const _),
                 ..Default::default()
             };
             device
@@ -507,9 +1059,21 @@ impl RawGraphicsPipeline {
             flags: vk::PipelineCreateFlags::empty(),
             stage_count: pipeline_shader_stages.len() as u32,
             p_stages: pipeline_shader_stages.as_ptr(),
-            p_vertex_input_state: &vertex_input_state,
-            p_input_assembly_state: &input_assembly_state,
-            p_tessellation_state: &tessellation_state,
+            p_vertex_input_state: if is_mesh_pipeline {
+                ptr::null()
+            } else {
+                &vertex_input_state
+            },
+            p_input_assembly_state: if is_mesh_pipeline {
+                ptr::null()
+            } else {
+                &input_assembly_state
+            },
+            p_tessellation_state: if patch_control_points.is_some() {
+                &tessellation_state
+            } else {
+                ptr::null()
+            },
             p_viewport_state: &viewport_state,
             p_rasterization_state: &rasterization_state,
             p_multisample_state: &multisample_state,
@@ -531,10 +1095,69 @@ impl RawGraphicsPipeline {
                 .expect("failed to create pipeline");
             RawGraphicsPipeline {
                 device: device.clone(),
+                deferred: deferred.clone(),
                 pipeline: pipelines[0],
+                layout: pipeline_layout,
+                push_constant_range: config.push_constant_range,
+                descriptor_bindings: Arc::new(descriptor_bindings),
+                color_attachment_formats: interface.color_attachment_formats.to_vec(),
+                depth_attachment_format: interface.depth_attachment_format,
+                stencil_attachment_format: interface.stencil_attachment_format,
             }
         }
     }
+
+    /// Sets the debug name of the pipeline, shown by debugging tools like RenderDoc.
+    pub fn set_name(&self, name: &str) {
+        set_debug_object_name(&self.device, self.pipeline, name)
+    }
+
+    /// Returns the formats of the color attachments that the pipeline renders to.
+    pub fn color_attachment_formats(&self) -> &[vk::Format] {
+        &self.color_attachment_formats
+    }
+
+    /// Returns the format of the depth attachment, if any.
+    pub fn depth_attachment_format(&self) -> Option<vk::Format> {
+        self.depth_attachment_format
+    }
+
+    /// Returns the format of the stencil attachment, if any.
+    pub fn stencil_attachment_format(&self) -> Option<vk::Format> {
+        self.stencil_attachment_format
+    }
+
+    /// Returns the vulkan pipeline handle.
+    pub fn pipeline(&self) -> vk::Pipeline {
+        self.pipeline
+    }
+
+    /// Returns the vulkan pipeline layout handle.
+    pub fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    /// Returns the push constant range of the pipeline layout.
+    pub fn push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        self.push_constant_range
+    }
+
+    /// Checks arguments bound to the specified descriptor set against the bindings declared in the shaders
+    /// of the pipeline (see `ShaderModule::check_arguments`).
+    pub fn check_arguments<A: Arguments + ?Sized>(&self, set: u32, args: &A) -> Result<(), ArgumentsMismatchError> {
+        shader::check_arguments(&self.descriptor_bindings, set, args)
+    }
+
+    pub(crate) fn descriptor_bindings(&self) -> &Arc<Vec<DescriptorBindingInfo>> {
+        &self.descriptor_bindings
+    }
+}
+
+impl Drop for RawGraphicsPipeline {
+    fn drop(&mut self) {
+        self.deferred.destroy(RetiredObject::Pipeline(self.pipeline));
+        self.deferred.destroy(RetiredObject::PipelineLayout(self.layout));
+    }
 }
 
 pub struct GraphicsPipeline<VertexInput, FragmentOutputColor, ShaderResources> {
@@ -562,8 +1185,8 @@ impl<VertexInput, FragmentOutputColor, ShaderResources>
         }
     }
 
-    /// Specifies the type of the fragment output interface (color attachments).
-    pub fn with_fragment_output<FO: FragmentOutputColorInterface>(
+    /// Specifies the type of the fragment output interface (color and depth attachments).
+    pub fn with_fragment_output<FO: FragmentOutputInterface>(
         self,
     ) -> GraphicsPipelineBuilder<VertexInput, FO, ShaderResources> {
         GraphicsPipelineBuilder {
@@ -588,21 +1211,21 @@ impl<VertexInput, FragmentOutputColor, ShaderResources>
     GraphicsPipelineBuilder<VertexInput, FragmentOutputColor, ShaderResources>
 where
     VertexInput: VertexInputInterface,
-    FragmentOutputColor: FragmentOutputColorInterface,
+    FragmentOutputColor: FragmentOutputInterface,
     ShaderResources: ShaderResourceInterface,
 {
     pub fn build(
         self,
-        device: &Arc<graal::Device>,
+        device: &Device,
         config: &GraphicsPipelineConfig,
     ) -> GraphicsPipeline<VertexInput, FragmentOutputColor, ShaderResources> {
         let interface_desc = PipelineInterfaceDesc {
             vertex_bindings: VertexInput::BINDINGS,
             vertex_attributes: VertexInput::ATTRIBUTES,
             descriptor_set_layouts: &[],
-            color_attachment_formats: FragmentOutputColorInterface::COLOR_ATTACHMENT_FORMATS,
-            depth_attachment_format: None,
-            stencil_attachment_format: None,
+            color_attachment_formats: FragmentOutputColor::COLOR_ATTACHMENT_FORMATS,
+            depth_attachment_format: FragmentOutputColor::DEPTH_ATTACHMENT_FORMAT,
+            stencil_attachment_format: FragmentOutputColor::STENCIL_ATTACHMENT_FORMAT,
         };
         unsafe {
             GraphicsPipeline {
@@ -624,6 +1247,216 @@ pub fn draw<VI, FO, SR>(
 ) where
     VI: VertexInputInterface,
     SR: ShaderResourceInterface,
-    FO: FragmentOutputColorInterface,
+    FO: FragmentOutputInterface,
 {
 }
+
+//--------------------------------------------------------------------------------------------------
+
+/// A compute pipeline.
+pub struct RawComputePipeline {
+    deferred: DeferredDestroyQueue,
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    push_constant_range: Option<vk::PushConstantRange>,
+}
+
+impl RawComputePipeline {
+    /// Creates a compute pipeline with the specified descriptor set layouts and push constant range.
+    ///
+    /// The pipeline is destroyed once the frames submitted before it is dropped have completed
+    /// (see `Device::collect`).
+    pub unsafe fn new(
+        device: &Device,
+        shader: &ShaderModule,
+        descriptor_set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_range: Option<vk::PushConstantRange>,
+    ) -> RawComputePipeline {
+        let vk_device = &device.backend.device;
+
+        check_push_constants(shader, push_constant_range.as_ref());
+
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: descriptor_set_layouts.len() as u32,
+            p_set_layouts: descriptor_set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_range.is_some() as u32,
+            p_push_constant_ranges: push_constant_range.as_ref().map_or(ptr::null(), |r| r as *const _),
+            ..Default::default()
+        };
+        let layout = vk_device
+            .create_pipeline_layout(&pipeline_layout_create_info, None)
+            .expect("failed to create pipeline layout");
+
+        let specialization_info = shader.specialization_info();
+        let create_info = vk::ComputePipelineCreateInfo {
+            flags: vk::PipelineCreateFlags::empty(),
+            stage: vk::PipelineShaderStageCreateInfo {
+                flags: vk::PipelineShaderStageCreateFlags::empty(),
+                stage: vk::ShaderStageFlags::COMPUTE,
+                module: shader.shader_module,
+                p_name: b"main\0".as_ptr() as *const c_char,
+                p_specialization_info: specialization_info
+                    .as_ref()
+                    .map_or(ptr::null(), |info| info as *const _),
+                ..Default::default()
+            },
+            layout,
+            base_pipeline_handle: Default::default(),
+            base_pipeline_index: 0,
+            ..Default::default()
+        };
+        let pipelines = vk_device
+            .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
+            .expect("failed to create pipeline");
+
+        RawComputePipeline {
+            deferred: device.deferred.clone(),
+            pipeline: pipelines[0],
+            layout,
+            push_constant_range,
+        }
+    }
+
+    /// Returns the vulkan pipeline handle.
+    pub fn pipeline(&self) -> vk::Pipeline {
+        self.pipeline
+    }
+
+    /// Returns the vulkan pipeline layout handle.
+    pub fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    /// Returns the push constant range of the pipeline layout.
+    pub fn push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        self.push_constant_range
+    }
+}
+
+impl Drop for RawComputePipeline {
+    fn drop(&mut self) {
+        self.deferred.destroy(RetiredObject::Pipeline(self.pipeline));
+        self.deferred.destroy(RetiredObject::PipelineLayout(self.layout));
+    }
+}
+
+/// A compute pipeline whose resources are bound with an `Arguments` struct, in descriptor set #0.
+///
+/// The pipeline is created on the first dispatch, with the descriptor set layout and push constant range
+/// of the arguments.
+pub struct ComputePipeline<A> {
+    device: Device,
+    name: String,
+    shader: ShaderModule,
+    raw: Option<RawComputePipeline>,
+    _arguments: PhantomData<fn(&A)>,
+}
+
+pub struct ComputePipelineBuilder<A> {
+    name: String,
+    _arguments: PhantomData<fn(&A)>,
+}
+
+impl ComputePipelineBuilder<()> {
+    /// Begin building a compute pipeline.
+    pub fn new() -> ComputePipelineBuilder<()> {
+        ComputePipelineBuilder {
+            name: "compute".to_string(),
+            _arguments: PhantomData,
+        }
+    }
+}
+
+impl<A> ComputePipelineBuilder<A> {
+    /// Sets the name of the passes created by `dispatch`, also used as the debug name of the pipeline.
+    pub fn name(mut self, name: &str) -> ComputePipelineBuilder<A> {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Specifies the type of the arguments of the compute shader.
+    pub fn with_arguments<A2: Arguments>(self) -> ComputePipelineBuilder<A2> {
+        ComputePipelineBuilder {
+            name: self.name,
+            _arguments: PhantomData,
+        }
+    }
+}
+
+impl<A: Arguments> ComputePipelineBuilder<A> {
+    pub fn build(self, device: &Device, shader: ShaderModule) -> ComputePipeline<A> {
+        ComputePipeline {
+            device: device.clone(),
+            name: self.name,
+            shader,
+            raw: None,
+            _arguments: PhantomData,
+        }
+    }
+}
+
+impl<A: Arguments> ComputePipeline<A> {
+    /// Dispatches compute work groups (`vkCmdDispatch`) with the specified arguments, in a new pass
+    /// added to the frame.
+    ///
+    /// The arguments are bound in a descriptor set from the descriptor set cache of the device, with the
+    /// specified offsets for their dynamic descriptors, and their push constants (if any) are set before
+    /// the dispatch.
+    pub fn dispatch(
+        &mut self,
+        frame: &mut graal::Frame<'static, ()>,
+        args: &mut A,
+        dynamic_offsets: &[u32],
+        group_counts: [u32; 3],
+    ) {
+        let device = &self.device;
+        let shader = &self.shader;
+        let name = &self.name;
+        check_dynamic_offsets(args, dynamic_offsets);
+        let raw = self.raw.get_or_insert_with(|| unsafe {
+            if let Err(err) = shader.check_arguments(0, args) {
+                panic!("{}: {}", name, err);
+            }
+            let descriptor_set_layout = device.get_or_create_descriptor_set_layout(
+                args.unique_type_id(),
+                args.get_descriptor_set_layout_bindings(),
+                args.get_descriptor_set_layout_binding_flags(),
+            );
+            let raw = RawComputePipeline::new(device, shader, &[descriptor_set_layout], args.push_constant_range());
+            set_debug_object_name(&device.backend, raw.pipeline, name);
+            raw
+        });
+
+        let mut pass = graal::PassBuilder::new().name(&self.name);
+        args.register(&mut pass);
+        // SAFETY: TODO
+        let (_, set) = unsafe { device.get_or_create_descriptor_set(args) };
+        let push_constants = argument_push_constants(args, raw.push_constant_range());
+
+        let pipeline = raw.pipeline();
+        let layout = raw.layout();
+        let dynamic_offsets = dynamic_offsets.to_vec();
+        let [group_count_x, group_count_y, group_count_z] = group_counts;
+        let pass = pass.record_callback(Box::new(move |context, _, command_buffer| unsafe {
+            let device = context.vulkan_device();
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                layout,
+                0,
+                &[set],
+                &dynamic_offsets,
+            );
+            if let Some((stages, offset, data)) = &push_constants {
+                device.cmd_push_constants(command_buffer, layout, *stages, *offset, data);
+            }
+            device.cmd_dispatch(command_buffer, group_count_x, group_count_y, group_count_z);
+        }));
+
+        frame.add_pass(pass);
+        self.device.stats.count_pipeline_binds(1);
+        self.device.stats.count_dispatch();
+    }
+}
@@ -0,0 +1,273 @@
+//! Graphics pipeline caching
+use crate::{
+    deferred::DeferredDestroyQueue,
+    pipeline::{
+        ColorTargetState, CompareFunction, DepthStencilState, DynamicStates, GraphicsPipelineConfig, MultisampleState,
+        PipelineInterfaceDesc, PrimitiveState, RawGraphicsPipeline, StaticState, StencilState,
+    },
+    vk,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Hashable version of `DepthStencilState` (floats are stored as their bit patterns).
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct DepthStencilKey {
+    depth_test_enabled: bool,
+    depth_write_enabled: bool,
+    depth_compare: CompareFunction,
+    depth_bounds: Option<(u32, u32)>,
+    stencil: StencilState,
+    bias_constant: i32,
+    bias_slope_scale: u32,
+    bias_clamp: u32,
+}
+
+impl<'a> From<&'a DepthStencilState> for DepthStencilKey {
+    fn from(state: &'a DepthStencilState) -> Self {
+        DepthStencilKey {
+            depth_test_enabled: state.depth.test_enabled,
+            depth_write_enabled: state.depth.write_enabled,
+            depth_compare: state.depth.compare,
+            depth_bounds: state.depth.bounds.map(|(min, max)| (min.to_bits(), max.to_bits())),
+            stencil: state.stencil,
+            bias_constant: state.bias.constant,
+            bias_slope_scale: state.bias.slope_scale.to_bits(),
+            bias_clamp: state.bias.clamp.to_bits(),
+        }
+    }
+}
+
+/// Returns the bit patterns of the values of the static states that are not dynamic.
+fn static_state_bits(static_state: &StaticState, dynamic_states: DynamicStates) -> Vec<u32> {
+    let mut bits = Vec::new();
+    if !dynamic_states.contains(DynamicStates::VIEWPORT) {
+        if let Some(v) = static_state.viewport {
+            bits.extend(
+                [v.x, v.y, v.width, v.height, v.min_depth, v.max_depth]
+                    .iter()
+                    .map(|f| f.to_bits()),
+            );
+        }
+    }
+    if !dynamic_states.contains(DynamicStates::SCISSOR) {
+        if let Some(s) = static_state.scissor {
+            bits.extend_from_slice(&[s.offset.x as u32, s.offset.y as u32, s.extent.width, s.extent.height]);
+        }
+    }
+    if !dynamic_states.contains(DynamicStates::LINE_WIDTH) {
+        bits.push(static_state.line_width.to_bits());
+    }
+    if !dynamic_states.contains(DynamicStates::BLEND_CONSTANTS) {
+        bits.extend(static_state.blend_constants.iter().map(|f| f.to_bits()));
+    }
+    bits
+}
+
+/// Everything that determines the pipeline object created from a `GraphicsPipelineConfig` and a
+/// `PipelineInterfaceDesc`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct GraphicsPipelineKey {
+    /// Vertex, tessellation control, tessellation evaluation, geometry, task, mesh and fragment shaders.
+    shaders: [Option<vk::ShaderModule>; 7],
+    /// (binding, stride, input rate)
+    vertex_bindings: Vec<(u32, u32, vk::VertexInputRate)>,
+    /// (location, binding, format, offset)
+    vertex_attributes: Vec<(u32, u32, vk::Format, u32)>,
+    descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    color_attachment_formats: Vec<vk::Format>,
+    depth_attachment_format: Option<vk::Format>,
+    stencil_attachment_format: Option<vk::Format>,
+    primitive_state: PrimitiveState,
+    multisample_state: MultisampleState,
+    depth_stencil_state: Option<DepthStencilKey>,
+    color_attachments: Vec<ColorTargetState>,
+    dynamic_states: DynamicStates,
+    /// Bit patterns of the values of the static states that are not dynamic.
+    static_state: Vec<u32>,
+    /// (stages, offset, size)
+    push_constant_range: Option<(vk::ShaderStageFlags, u32, u32)>,
+}
+
+impl GraphicsPipelineKey {
+    fn new(config: &GraphicsPipelineConfig, interface: &PipelineInterfaceDesc) -> GraphicsPipelineKey {
+        GraphicsPipelineKey {
+            shaders: [
+                config.vertex_shader.map(|s| s.shader_module),
+                config.tess_control_shader.map(|s| s.shader_module),
+                config.tess_evaluation_shader.map(|s| s.shader_module),
+                config.geometry_shader.map(|s| s.shader_module),
+                config.task_shader.map(|s| s.shader_module),
+                config.mesh_shader.map(|s| s.shader_module),
+                Some(config.fragment_shader.shader_module),
+            ],
+            vertex_bindings: interface
+                .vertex_bindings
+                .iter()
+                .map(|b| (b.binding, b.stride, b.input_rate))
+                .collect(),
+            vertex_attributes: interface
+                .vertex_attributes
+                .iter()
+                .map(|a| (a.location, a.binding, a.format, a.offset))
+                .collect(),
+            descriptor_set_layouts: interface.descriptor_set_layouts.to_vec(),
+            color_attachment_formats: interface.color_attachment_formats.to_vec(),
+            depth_attachment_format: interface.depth_attachment_format,
+            stencil_attachment_format: interface.stencil_attachment_format,
+            primitive_state: config.primitive_state,
+            multisample_state: config.multisample_state,
+            depth_stencil_state: config.depth_stencil_state.as_ref().map(DepthStencilKey::from),
+            color_attachments: config.color_attachments.to_vec(),
+            dynamic_states: config.dynamic_states,
+            static_state: static_state_bits(&config.static_state, config.dynamic_states),
+            push_constant_range: config.push_constant_range.map(|r| (r.stage_flags, r.offset, r.size)),
+        }
+    }
+}
+
+/// Graphics pipelines shared between all users of a device, keyed by their shaders, vertex layout,
+/// attachment formats and fixed-function state.
+///
+/// Requesting a pipeline with the same parameters twice returns the same object. Pipelines are kept alive by
+/// the cache until `clear` is called.
+pub struct GraphicsPipelineCache {
+    deferred: DeferredDestroyQueue,
+    pipelines: Mutex<HashMap<GraphicsPipelineKey, Arc<RawGraphicsPipeline>>>,
+}
+
+impl GraphicsPipelineCache {
+    pub(crate) fn new(deferred: DeferredDestroyQueue) -> GraphicsPipelineCache {
+        GraphicsPipelineCache {
+            deferred,
+            pipelines: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a pipeline created with the specified parameters, creating it if it doesn't exist yet.
+    ///
+    /// # Safety
+    ///
+    /// Same as `RawGraphicsPipeline::new`.
+    pub unsafe fn get_or_create(
+        &self,
+        config: &GraphicsPipelineConfig,
+        interface: &PipelineInterfaceDesc,
+    ) -> Arc<RawGraphicsPipeline> {
+        let key = GraphicsPipelineKey::new(config, interface);
+        let mut pipelines = self.pipelines.lock().unwrap();
+        pipelines
+            .entry(key)
+            .or_insert_with(|| {
+                tracing::trace!("creating graphics pipeline");
+                Arc::new(RawGraphicsPipeline::create(&self.deferred, config, interface))
+            })
+            .clone()
+    }
+
+    /// Creates the pipelines for all the specified parameters in advance (e.g. at load time), so that
+    /// they are not compiled in the middle of a frame.
+    ///
+    /// # Safety
+    ///
+    /// Same as `RawGraphicsPipeline::new`.
+    pub unsafe fn prewarm<'a, 'b: 'a>(
+        &self,
+        variants: impl IntoIterator<Item = (&'a GraphicsPipelineConfig<'b>, &'a PipelineInterfaceDesc<'b>)>,
+    ) {
+        for (config, interface) in variants {
+            self.get_or_create(config, interface);
+        }
+    }
+
+    /// Returns the number of cached pipelines.
+    pub fn len(&self) -> usize {
+        self.pipelines.lock().unwrap().len()
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all pipelines from the cache.
+    ///
+    /// Pipelines still referenced elsewhere stay alive.
+    pub fn clear(&self) {
+        self.pipelines.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{static_state_bits, DepthStencilKey};
+    use crate::{
+        pipeline::{DepthBiasState, DepthState, DepthStencilState, DynamicStates, StaticState},
+        vk,
+    };
+
+    #[test]
+    fn test_static_state_key() {
+        let state = StaticState::default();
+        let viewport = StaticState {
+            viewport: Some(vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: 640.0,
+                height: 480.0,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            }),
+            ..state
+        };
+        let dynamic = DynamicStates::default();
+        let dynamic_line_width = DynamicStates::default() | DynamicStates::LINE_WIDTH;
+
+        // dynamic states don't contribute to the key
+        assert_eq!(
+            static_state_bits(&state, dynamic),
+            static_state_bits(&viewport, dynamic)
+        );
+        assert_ne!(
+            static_state_bits(&state, DynamicStates::empty()),
+            static_state_bits(&viewport, DynamicStates::empty())
+        );
+
+        let thick = StaticState {
+            line_width: 2.0,
+            ..state
+        };
+        assert_ne!(static_state_bits(&state, dynamic), static_state_bits(&thick, dynamic));
+        assert_eq!(
+            static_state_bits(&state, dynamic_line_width),
+            static_state_bits(&thick, dynamic_line_width)
+        );
+    }
+
+    #[test]
+    fn test_depth_stencil_key() {
+        let state = DepthStencilState::default();
+        assert_eq!(
+            DepthStencilKey::from(&state),
+            DepthStencilKey::from(&DepthStencilState::default())
+        );
+
+        let biased = DepthStencilState {
+            bias: DepthBiasState {
+                constant: 0,
+                slope_scale: 1.5,
+                clamp: 0.0,
+            },
+            ..state
+        };
+        assert_ne!(DepthStencilKey::from(&state), DepthStencilKey::from(&biased));
+
+        let bounded = DepthStencilState::depth(DepthState {
+            bounds: Some((0.0, 0.5)),
+            ..DepthState::default()
+        });
+        assert_ne!(DepthStencilKey::from(&state), DepthStencilKey::from(&bounded));
+    }
+}
@@ -0,0 +1,35 @@
+//! Push constants
+use crate::vk;
+
+/// Types that can be uploaded as push constants.
+///
+/// Use `#[derive(PushConstants)]` on `repr(C)` structs. The layout of the struct must match the layout of
+/// the push constant block declared in the shaders, starting at `OFFSET` (specified with
+/// `#[push_constants(offset = N)]`).
+///
+/// # Safety
+///
+/// `SIZE` must not be larger than the size of the type.
+pub unsafe trait PushConstants: Copy + 'static {
+    /// Size of the push constant data in bytes. Must be a multiple of 4.
+    const SIZE: u32;
+    /// Shader stages that access the push constants.
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::ALL;
+    /// Offset of the data in the push constant block of the shaders. Must be a multiple of 4.
+    const OFFSET: u32 = 0;
+
+    /// Returns the push constant range to declare in the pipeline layout.
+    fn range() -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags: Self::STAGES,
+            offset: Self::OFFSET,
+            size: Self::SIZE,
+        }
+    }
+}
+
+/// Returns the bytes of push constant data (the first `T::SIZE` bytes of `data`).
+pub fn push_constant_data<T: PushConstants>(data: &T) -> &[u8] {
+    // SAFETY: `T::SIZE` is not larger than `T` (see `PushConstants`)
+    unsafe { std::slice::from_raw_parts(data as *const T as *const u8, T::SIZE as usize) }
+}
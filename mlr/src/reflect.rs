@@ -0,0 +1,366 @@
+//! Minimal SPIR-V reflection
+use crate::{shader::SpecializationConstantType, vk};
+use std::collections::{HashMap, HashSet};
+
+const MAGIC: u32 = 0x07230203;
+const HEADER_LEN: usize = 5;
+
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+enum Type {
+    Scalar { byte_size: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+/// Types, constants and decorations of a SPIR-V module.
+#[derive(Default)]
+struct Module {
+    types: HashMap<u32, Type>,
+    /// Types of 32-bit scalars and booleans, for specialization constants.
+    scalar_types: HashMap<u32, SpecializationConstantType>,
+    constants: HashMap<u32, u32>,
+    /// (result type, id) of each scalar specialization constant
+    spec_constants: Vec<(u32, u32)>,
+    spec_ids: HashMap<u32, u32>,
+    array_strides: HashMap<u32, u32>,
+    /// (struct, member) -> offset
+    member_offsets: HashMap<(u32, u32), u32>,
+    /// (struct, member) -> matrix stride
+    member_matrix_strides: HashMap<(u32, u32), u32>,
+    /// (pointer type, id, storage class) of each variable
+    variables: Vec<(u32, u32, u32)>,
+    bindings: HashMap<u32, u32>,
+    descriptor_sets: HashMap<u32, u32>,
+    blocks: HashSet<u32>,
+    buffer_blocks: HashSet<u32>,
+}
+
+impl Module {
+    fn parse(spirv: &[u32]) -> Option<Module> {
+        if spirv.len() < HEADER_LEN || spirv[0] != MAGIC {
+            return None;
+        }
+
+        let mut module = Module::default();
+        let mut words = &spirv[HEADER_LEN..];
+        while !words.is_empty() {
+            let word_count = (words[0] >> 16) as usize;
+            let opcode = words[0] & 0xFFFF;
+            if word_count == 0 || word_count > words.len() {
+                return None;
+            }
+            let ops = &words[1..word_count];
+            match opcode {
+                OP_TYPE_BOOL if !ops.is_empty() => {
+                    module.scalar_types.insert(ops[0], SpecializationConstantType::Bool);
+                }
+                OP_TYPE_INT | OP_TYPE_FLOAT if ops.len() >= 2 => {
+                    module.types.insert(ops[0], Type::Scalar { byte_size: ops[1] / 8 });
+                    if ops[1] == 32 {
+                        let ty = match opcode {
+                            OP_TYPE_FLOAT => SpecializationConstantType::Float,
+                            _ if ops.len() >= 3 && ops[2] != 0 => SpecializationConstantType::Int,
+                            _ => SpecializationConstantType::UInt,
+                        };
+                        module.scalar_types.insert(ops[0], ty);
+                    }
+                }
+                OP_TYPE_VECTOR if ops.len() >= 3 => {
+                    module.types.insert(
+                        ops[0],
+                        Type::Vector {
+                            component: ops[1],
+                            count: ops[2],
+                        },
+                    );
+                }
+                OP_TYPE_MATRIX if ops.len() >= 3 => {
+                    module.types.insert(
+                        ops[0],
+                        Type::Matrix {
+                            column: ops[1],
+                            count: ops[2],
+                        },
+                    );
+                }
+                OP_TYPE_IMAGE if ops.len() >= 7 => {
+                    module.types.insert(
+                        ops[0],
+                        Type::Image {
+                            dim: ops[2],
+                            sampled: ops[6],
+                        },
+                    );
+                }
+                OP_TYPE_SAMPLER if !ops.is_empty() => {
+                    module.types.insert(ops[0], Type::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE if !ops.is_empty() => {
+                    module.types.insert(ops[0], Type::SampledImage);
+                }
+                OP_TYPE_RUNTIME_ARRAY if ops.len() >= 2 => {
+                    module.types.insert(ops[0], Type::RuntimeArray { element: ops[1] });
+                }
+                OP_TYPE_ARRAY if ops.len() >= 3 => {
+                    // the length is the id of a constant, resolved when computing sizes
+                    module.types.insert(
+                        ops[0],
+                        Type::Array {
+                            element: ops[1],
+                            length: ops[2],
+                        },
+                    );
+                }
+                OP_TYPE_STRUCT if !ops.is_empty() => {
+                    module.types.insert(
+                        ops[0],
+                        Type::Struct {
+                            members: ops[1..].to_vec(),
+                        },
+                    );
+                }
+                OP_TYPE_POINTER if ops.len() >= 3 => {
+                    module.types.insert(ops[0], Type::Pointer { pointee: ops[2] });
+                }
+                OP_CONSTANT if ops.len() >= 3 => {
+                    module.constants.insert(ops[1], ops[2]);
+                }
+                OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE | OP_SPEC_CONSTANT if ops.len() >= 2 => {
+                    module.spec_constants.push((ops[0], ops[1]));
+                }
+                OP_VARIABLE if ops.len() >= 3 => {
+                    module.variables.push((ops[0], ops[1], ops[2]));
+                }
+                OP_DECORATE if ops.len() >= 2 => match ops[1] {
+                    DECORATION_SPEC_ID if ops.len() >= 3 => {
+                        module.spec_ids.insert(ops[0], ops[2]);
+                    }
+                    DECORATION_BLOCK => {
+                        module.blocks.insert(ops[0]);
+                    }
+                    DECORATION_BUFFER_BLOCK => {
+                        module.buffer_blocks.insert(ops[0]);
+                    }
+                    DECORATION_ARRAY_STRIDE if ops.len() >= 3 => {
+                        module.array_strides.insert(ops[0], ops[2]);
+                    }
+                    DECORATION_BINDING if ops.len() >= 3 => {
+                        module.bindings.insert(ops[0], ops[2]);
+                    }
+                    DECORATION_DESCRIPTOR_SET if ops.len() >= 3 => {
+                        module.descriptor_sets.insert(ops[0], ops[2]);
+                    }
+                    _ => {}
+                },
+                OP_MEMBER_DECORATE if ops.len() >= 4 => match ops[2] {
+                    DECORATION_OFFSET => {
+                        module.member_offsets.insert((ops[0], ops[1]), ops[3]);
+                    }
+                    DECORATION_MATRIX_STRIDE => {
+                        module.member_matrix_strides.insert((ops[0], ops[1]), ops[3]);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+            words = &words[word_count..];
+        }
+        Some(module)
+    }
+
+    /// Returns the size in bytes of a type, following the explicit layout decorations.
+    fn type_size(&self, ty: u32, matrix_stride: Option<u32>) -> Option<u32> {
+        match *self.types.get(&ty)? {
+            Type::Scalar { byte_size } => Some(byte_size),
+            Type::Vector { component, count } => Some(self.type_size(component, None)? * count),
+            Type::Matrix { column, count } => match matrix_stride {
+                Some(stride) => Some(stride * count),
+                None => Some(self.type_size(column, None)? * count),
+            },
+            Type::Array { element, length } => {
+                let length = *self.constants.get(&length)?;
+                let stride = match self.array_strides.get(&ty) {
+                    Some(&stride) => stride,
+                    None => self.type_size(element, matrix_stride)?,
+                };
+                Some(stride * length)
+            }
+            Type::Struct { ref members } => {
+                let mut size = 0;
+                for (i, &member) in members.iter().enumerate() {
+                    let key = (ty, i as u32);
+                    let offset = self.member_offsets.get(&key).cloned().unwrap_or(0);
+                    let member_size = self.type_size(member, self.member_matrix_strides.get(&key).cloned())?;
+                    size = size.max(offset + member_size);
+                }
+                Some(size)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the descriptor type, descriptor count (`None` for runtime arrays), and block size (for uniform
+    /// buffers) of a variable with the specified type and storage class.
+    fn descriptor_info(&self, ty: u32, storage_class: u32) -> Option<(vk::DescriptorType, Option<u32>, Option<u32>)> {
+        let (ty, count) = match *self.types.get(&ty)? {
+            Type::Array { element, length } => (element, Some(*self.constants.get(&length)?)),
+            Type::RuntimeArray { element } => (element, None),
+            _ => (ty, Some(1)),
+        };
+        let (descriptor_type, block_size) = match *self.types.get(&ty)? {
+            Type::Sampler => (vk::DescriptorType::SAMPLER, None),
+            Type::SampledImage => (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, None),
+            Type::Image { dim, sampled } => match (dim, sampled) {
+                (DIM_SUBPASS_DATA, _) => (vk::DescriptorType::INPUT_ATTACHMENT, None),
+                (DIM_BUFFER, 2) => (vk::DescriptorType::STORAGE_TEXEL_BUFFER, None),
+                (DIM_BUFFER, _) => (vk::DescriptorType::UNIFORM_TEXEL_BUFFER, None),
+                (_, 2) => (vk::DescriptorType::STORAGE_IMAGE, None),
+                _ => (vk::DescriptorType::SAMPLED_IMAGE, None),
+            },
+            Type::Struct { .. } => match storage_class {
+                STORAGE_CLASS_UNIFORM if self.buffer_blocks.contains(&ty) => (vk::DescriptorType::STORAGE_BUFFER, None),
+                STORAGE_CLASS_UNIFORM => (vk::DescriptorType::UNIFORM_BUFFER, self.type_size(ty, None)),
+                STORAGE_CLASS_STORAGE_BUFFER => (vk::DescriptorType::STORAGE_BUFFER, None),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some((descriptor_type, count, block_size))
+    }
+}
+
+/// A descriptor binding declared in a shader.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct DescriptorBindingInfo {
+    pub(crate) set: u32,
+    pub(crate) binding: u32,
+    pub(crate) descriptor_type: vk::DescriptorType,
+    /// Number of descriptors, or `None` for runtime-sized arrays.
+    pub(crate) count: Option<u32>,
+    /// Size in bytes of the uniform block, for uniform buffers.
+    pub(crate) block_size: Option<u32>,
+}
+
+/// Returns the descriptor bindings declared in a SPIR-V module, sorted by set and binding number.
+pub(crate) fn descriptor_bindings(spirv: &[u32]) -> Vec<DescriptorBindingInfo> {
+    let module = match Module::parse(spirv) {
+        Some(module) => module,
+        None => return vec![],
+    };
+    let mut bindings: Vec<_> = module
+        .variables
+        .iter()
+        .filter_map(|&(result_type, id, storage_class)| {
+            if !matches!(
+                storage_class,
+                STORAGE_CLASS_UNIFORM_CONSTANT | STORAGE_CLASS_UNIFORM | STORAGE_CLASS_STORAGE_BUFFER
+            ) {
+                return None;
+            }
+            let pointee = match *module.types.get(&result_type)? {
+                Type::Pointer { pointee } => pointee,
+                _ => return None,
+            };
+            let (descriptor_type, count, block_size) = module.descriptor_info(pointee, storage_class)?;
+            Some(DescriptorBindingInfo {
+                set: module.descriptor_sets.get(&id).cloned().unwrap_or(0),
+                binding: *module.bindings.get(&id)?,
+                descriptor_type,
+                count,
+                block_size,
+            })
+        })
+        .collect();
+    bindings.sort_by_key(|b| (b.set, b.binding));
+    bindings
+}
+
+/// A specialization constant declared in a shader.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct SpecializationConstantInfo {
+    /// Constant ID (`layout(constant_id = N)` in GLSL).
+    pub(crate) id: u32,
+    /// Type of the constant, or `None` if it's not a 32-bit scalar or a boolean.
+    pub(crate) ty: Option<SpecializationConstantType>,
+}
+
+/// Returns the specialization constants declared in a SPIR-V module, sorted by constant ID.
+pub(crate) fn specialization_constants(spirv: &[u32]) -> Vec<SpecializationConstantInfo> {
+    let module = match Module::parse(spirv) {
+        Some(module) => module,
+        None => return vec![],
+    };
+    let mut constants: Vec<_> = module
+        .spec_constants
+        .iter()
+        .filter_map(|&(result_type, id)| {
+            Some(SpecializationConstantInfo {
+                id: *module.spec_ids.get(&id)?,
+                ty: module.scalar_types.get(&result_type).cloned(),
+            })
+        })
+        .collect();
+    constants.sort_by_key(|c| c.id);
+    constants
+}
+
+/// Returns the size in bytes of the push constant block declared in a SPIR-V module, or `None` if the module
+/// doesn't declare push constants (or can't be parsed).
+///
+/// The size includes the offset of the first member, i.e. it's the end of the range of push constants
+/// accessed by the shader.
+pub(crate) fn push_constant_block_size(spirv: &[u32]) -> Option<u32> {
+    let module = Module::parse(spirv)?;
+    module.variables.iter().find_map(|&(result_type, _, storage_class)| {
+        if storage_class != STORAGE_CLASS_PUSH_CONSTANT {
+            return None;
+        }
+        match *module.types.get(&result_type)? {
+            Type::Pointer { pointee } => module.type_size(pointee, None),
+            _ => None,
+        }
+    })
+}
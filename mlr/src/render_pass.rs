@@ -0,0 +1,1019 @@
+//! Render passes and draw commands
+use crate::{
+    arguments::ResourceAccess,
+    bindless::TextureArray,
+    buffer::{BufferAny, DrawIndexedIndirectCommandBuffer, DrawIndirectCommandBuffer},
+    device::Device,
+    image::ImageAny,
+    pipeline::RawGraphicsPipeline,
+    push_constants::{push_constant_data, PushConstants},
+    reflect::DescriptorBindingInfo,
+    shader,
+    stats::StatsCounters,
+    vertex::{IndexBufferView, Mesh, VertexBufferView, VertexData},
+    vk, Arguments,
+};
+use std::{mem, ops::Range, sync::Arc};
+
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AttachmentLoadOp<V> {
+    Load,
+    Clear { value: V },
+    DontCare,
+}
+
+impl<V> AttachmentLoadOp<V> {
+    fn to_vk(&self) -> vk::AttachmentLoadOp {
+        match self {
+            AttachmentLoadOp::Load => vk::AttachmentLoadOp::LOAD,
+            AttachmentLoadOp::Clear { .. } => vk::AttachmentLoadOp::CLEAR,
+            AttachmentLoadOp::DontCare => vk::AttachmentLoadOp::DONT_CARE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AttachmentStoreOp {
+    Store,
+    DontCare,
+}
+
+impl AttachmentStoreOp {
+    fn to_vk(&self) -> vk::AttachmentStoreOp {
+        match self {
+            AttachmentStoreOp::Store => vk::AttachmentStoreOp::STORE,
+            AttachmentStoreOp::DontCare => vk::AttachmentStoreOp::DONT_CARE,
+        }
+    }
+}
+
+/// Clear value of a color attachment.
+///
+/// The variant must match the numeric format of the attachment: `Float` for float, UNORM and SNORM formats,
+/// `Int` and `Uint` for SINT and UINT formats (e.g. object IDs in a G-buffer).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClearColorValue {
+    Float([f32; 4]),
+    Int([i32; 4]),
+    Uint([u32; 4]),
+}
+
+impl ClearColorValue {
+    fn to_vk(&self) -> vk::ClearColorValue {
+        match *self {
+            ClearColorValue::Float(float32) => vk::ClearColorValue { float32 },
+            ClearColorValue::Int(int32) => vk::ClearColorValue { int32 },
+            ClearColorValue::Uint(uint32) => vk::ClearColorValue { uint32 },
+        }
+    }
+}
+
+impl From<[f32; 4]> for ClearColorValue {
+    fn from(value: [f32; 4]) -> Self {
+        ClearColorValue::Float(value)
+    }
+}
+
+impl From<[i32; 4]> for ClearColorValue {
+    fn from(value: [i32; 4]) -> Self {
+        ClearColorValue::Int(value)
+    }
+}
+
+impl From<[u32; 4]> for ClearColorValue {
+    fn from(value: [u32; 4]) -> Self {
+        ClearColorValue::Uint(value)
+    }
+}
+
+/// Basically taken from wgpu.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderPassColorAttachment<'a> {
+    /// TODO subresources
+    pub attachment: &'a ImageAny,
+    pub load_op: AttachmentLoadOp<ClearColorValue>,
+    pub store_op: AttachmentStoreOp,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RenderPassDepthStencilAttachment<'a> {
+    /// TODO subresources
+    pub attachment: &'a ImageAny,
+    /// TODO separate depth/stencil ops
+    pub load_op: AttachmentLoadOp<f32>,
+    pub store_op: AttachmentStoreOp,
+}
+
+/// Basically taken from wgpu.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderPassDescriptor<'a, 'b> {
+    pub color_attachments: &'b [RenderPassColorAttachment<'a>],
+    pub depth_stencil_attachment: Option<RenderPassDepthStencilAttachment<'a>>,
+    /// Rendered area, also used as the viewport and scissor rectangle.
+    pub render_area: vk::Rect2D,
+}
+
+//--------------------------------------------------------------------------------------------------
+
+/// Commands recorded in a render pass.
+enum Command {
+    BindPipeline {
+        pipeline: vk::Pipeline,
+    },
+    BindDescriptorSet {
+        layout: vk::PipelineLayout,
+        set_index: u32,
+        set: vk::DescriptorSet,
+        dynamic_offsets: Vec<u32>,
+    },
+    BindVertexBuffer {
+        binding: u32,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+    },
+    BindIndexBuffer {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        index_type: vk::IndexType,
+    },
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    },
+    DrawIndexed {
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    },
+    DrawIndirect {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32,
+    },
+    DrawIndexedIndirect {
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32,
+    },
+    DrawMeshTasks {
+        group_count_x: u32,
+        group_count_y: u32,
+        group_count_z: u32,
+    },
+    PushConstants {
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        data: Vec<u8>,
+    },
+    SetViewport {
+        viewport: vk::Viewport,
+    },
+    SetScissor {
+        scissor: vk::Rect2D,
+    },
+    SetLineWidth {
+        line_width: f32,
+    },
+    SetBlendConstants {
+        blend_constants: [f32; 4],
+    },
+}
+
+struct Attachment {
+    image_view: vk::ImageView,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    clear_value: vk::ClearValue,
+}
+
+impl Attachment {
+    fn to_vk(&self, image_layout: vk::ImageLayout) -> vk::RenderingAttachmentInfo {
+        vk::RenderingAttachmentInfo {
+            image_view: self.image_view,
+            image_layout,
+            load_op: self.load_op,
+            store_op: self.store_op,
+            clear_value: self.clear_value,
+            ..Default::default()
+        }
+    }
+}
+
+/// Creates an image view for an attachment, which is deleted once the frame is finished.
+fn create_attachment_view(
+    device: &graal::Device,
+    image: &ImageAny,
+    aspect_mask: vk::ImageAspectFlags,
+) -> vk::ImageView {
+    // SAFETY: TODO
+    unsafe {
+        let create_info = vk::ImageViewCreateInfo {
+            flags: vk::ImageViewCreateFlags::empty(),
+            image: image.handle(),
+            view_type: vk::ImageViewType::TYPE_2D,
+            format: image.format(),
+            components: vk::ComponentMapping::default(),
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            ..Default::default()
+        };
+        let image_view = device
+            .device
+            .create_image_view(&create_info, None)
+            .expect("could not create image view");
+        // immediately schedule deletion since it will be used only in this frame
+        device.destroy_image_view(image_view);
+        image_view
+    }
+}
+
+/// Returns the aspects of a depth/stencil attachment format.
+pub(crate) fn depth_stencil_aspects(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::DEPTH,
+    }
+}
+
+/// Returns the layout of a depth/stencil attachment with the specified aspects.
+fn depth_stencil_layout(aspects: vk::ImageAspectFlags) -> vk::ImageLayout {
+    if aspects == vk::ImageAspectFlags::DEPTH {
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+    } else if aspects == vk::ImageAspectFlags::STENCIL {
+        vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL
+    } else {
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+    }
+}
+
+/// Panics if the range of the push constants to set is not within the push constant range of a pipeline, or is
+/// accessed by stages not declared in it.
+///
+/// Returns the stages to specify when setting the push constants: all the stages of the range of the pipeline.
+pub(crate) fn check_push_constant_range(
+    expected: vk::PushConstantRange,
+    range: Option<vk::PushConstantRange>,
+) -> vk::ShaderStageFlags {
+    let range = range.expect("the bound pipeline doesn't declare push constants");
+    assert!(
+        expected.offset >= range.offset
+            && expected.offset + expected.size <= range.offset + range.size
+            && range.stage_flags.contains(expected.stage_flags),
+        "push constant type doesn't match the push constant range of the bound pipeline"
+    );
+    range.stage_flags
+}
+
+/// Panics if the number of dynamic offsets doesn't match the number of dynamic descriptors of arguments.
+pub(crate) fn check_dynamic_offsets<A: Arguments>(args: &A, dynamic_offsets: &[u32]) {
+    let dynamic_descriptor_count: u32 = args
+        .get_descriptor_set_layout_bindings()
+        .iter()
+        .filter(|b| {
+            b.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+                || b.descriptor_type == vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+        })
+        .map(|b| b.descriptor_count)
+        .sum();
+    assert_eq!(
+        dynamic_offsets.len(),
+        dynamic_descriptor_count as usize,
+        "the number of dynamic offsets doesn't match the number of dynamic descriptors"
+    );
+}
+
+/// Returns the push constants contained in arguments (`#[argument(push_constant)]`), after checking them
+/// against the push constant range of a pipeline.
+///
+/// Returns the stages, offset and bytes of the push constants.
+pub(crate) fn argument_push_constants<A: Arguments>(
+    args: &A,
+    range: Option<vk::PushConstantRange>,
+) -> Option<(vk::ShaderStageFlags, u32, Vec<u8>)> {
+    let expected = args.push_constant_range()?;
+    let stages = check_push_constant_range(expected, range);
+    let data = args
+        .push_constant_data()
+        .expect("arguments declare a push constant range but no data");
+    Some((stages, expected.offset, data.to_vec()))
+}
+
+/// Returns the bytes of a push constant block.
+pub(crate) fn push_constant_bytes<T: PushConstants>(data: &T) -> Vec<u8> {
+    push_constant_data(data).to_vec()
+}
+
+/// A render pass (with dynamic rendering) in which draw commands are recorded.
+///
+/// Commands are recorded on the command buffer when the frame is submitted.
+pub struct RenderPass {
+    device: Arc<graal::Device>,
+    /// Statistics of the device, updated when the pass is finished.
+    stats: Arc<StatsCounters>,
+    pass: graal::PassBuilder<'static, ()>,
+    render_area: vk::Rect2D,
+    color_attachments: Vec<Attachment>,
+    /// Depth/stencil attachment, with its aspects.
+    depth_attachment: Option<(Attachment, vk::ImageAspectFlags)>,
+    /// Formats of the color attachments and of the depth attachment, to validate bound pipelines.
+    color_attachment_formats: Vec<vk::Format>,
+    depth_attachment_format: Option<vk::Format>,
+    stencil_attachment_format: Option<vk::Format>,
+    commands: Vec<Command>,
+    /// Layout and push constant range of the currently bound pipeline.
+    pipeline_layout: Option<(vk::PipelineLayout, Option<vk::PushConstantRange>)>,
+    /// Descriptor bindings declared in the shaders of the currently bound pipeline.
+    pipeline_descriptor_bindings: Option<Arc<Vec<DescriptorBindingInfo>>>,
+    /// `VK_EXT_mesh_shader` device functions, loaded on first use.
+    mesh_shader_fn: Option<vk::ExtMeshShaderFn>,
+}
+
+impl RenderPass {
+    /// Starts a new render pass.
+    pub fn new(device: &Device, name: &str, desc: &RenderPassDescriptor) -> RenderPass {
+        let mut pass = graal::PassBuilder::new().name(name);
+
+        let mut color_attachments = Vec::with_capacity(desc.color_attachments.len());
+        for a in desc.color_attachments.iter() {
+            pass.add_image_dependency(
+                a.attachment.id(),
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            );
+            let clear_value = match a.load_op {
+                AttachmentLoadOp::Clear { value } => vk::ClearValue { color: value.to_vk() },
+                _ => vk::ClearValue::default(),
+            };
+            color_attachments.push(Attachment {
+                image_view: create_attachment_view(device.backend(), a.attachment, vk::ImageAspectFlags::COLOR),
+                load_op: a.load_op.to_vk(),
+                store_op: a.store_op.to_vk(),
+                clear_value,
+            });
+        }
+
+        let depth_attachment = desc.depth_stencil_attachment.as_ref().map(|a| {
+            let aspects = depth_stencil_aspects(a.attachment.format());
+            let layout = depth_stencil_layout(aspects);
+            pass.add_image_dependency(
+                a.attachment.id(),
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                layout,
+                layout,
+            );
+            let clear_value = match a.load_op {
+                AttachmentLoadOp::Clear { value } => vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: value,
+                        stencil: 0,
+                    },
+                },
+                _ => vk::ClearValue::default(),
+            };
+            let attachment = Attachment {
+                image_view: create_attachment_view(device.backend(), a.attachment, aspects),
+                load_op: a.load_op.to_vk(),
+                store_op: a.store_op.to_vk(),
+                clear_value,
+            };
+            (attachment, aspects)
+        });
+        // formats of the depth and stencil attachments, as declared by pipelines
+        let attachment_format = |aspect: vk::ImageAspectFlags| {
+            desc.depth_stencil_attachment
+                .as_ref()
+                .map(|a| a.attachment.format())
+                .filter(|format| depth_stencil_aspects(*format).contains(aspect))
+        };
+
+        RenderPass {
+            device: device.backend().clone(),
+            stats: device.stats.clone(),
+            pass,
+            render_area: desc.render_area,
+            color_attachments,
+            depth_attachment,
+            color_attachment_formats: desc.color_attachments.iter().map(|a| a.attachment.format()).collect(),
+            depth_attachment_format: attachment_format(vk::ImageAspectFlags::DEPTH),
+            stencil_attachment_format: attachment_format(vk::ImageAspectFlags::STENCIL),
+            commands: vec![],
+            pipeline_layout: None,
+            pipeline_descriptor_bindings: None,
+            mesh_shader_fn: None,
+        }
+    }
+
+    /// Binds a graphics pipeline for subsequent draw commands.
+    ///
+    /// The attachment formats of the pipeline must match the attachments of the render pass.
+    pub fn bind_pipeline(&mut self, pipeline: &RawGraphicsPipeline) {
+        assert_eq!(
+            pipeline.color_attachment_formats(),
+            &self.color_attachment_formats[..],
+            "color attachment formats of the pipeline don't match the attachments of the render pass"
+        );
+        assert_eq!(
+            pipeline.depth_attachment_format(),
+            self.depth_attachment_format,
+            "depth attachment format of the pipeline doesn't match the depth attachment of the render pass"
+        );
+        assert_eq!(
+            pipeline.stencil_attachment_format(),
+            self.stencil_attachment_format,
+            "stencil attachment format of the pipeline doesn't match the stencil attachment of the render pass"
+        );
+        self.pipeline_layout = Some((pipeline.layout(), pipeline.push_constant_range()));
+        self.pipeline_descriptor_bindings = Some(pipeline.descriptor_bindings().clone());
+        self.commands.push(Command::BindPipeline {
+            pipeline: pipeline.pipeline(),
+        });
+    }
+
+    /// Binds arguments to the specified descriptor set of the bound pipeline.
+    ///
+    /// `dynamic_offsets` contains the offset of each dynamic descriptor of the arguments
+    /// (e.g. `DynamicUniformBuffer`), in binding order. Offsets must be multiples of
+    /// `minUniformBufferOffsetAlignment`. Since descriptor sets are cached, binding the same arguments
+    /// with different dynamic offsets for each draw doesn't write new descriptor sets.
+    ///
+    /// If the arguments contain push constants (`#[argument(push_constant)]`), they are set as well.
+    ///
+    /// In debug builds, panics if the arguments don't match the descriptor bindings declared in the shaders of
+    /// the bound pipeline.
+    pub fn bind_arguments<A: Arguments>(
+        &mut self,
+        device: &Device,
+        set_index: u32,
+        args: &mut A,
+        dynamic_offsets: &[u32],
+    ) {
+        let bindings = self.pipeline_descriptor_bindings.clone().expect("no pipeline bound");
+        let set = self.prepare_arguments(device, &bindings, set_index, args, dynamic_offsets);
+        self.bind_descriptor_set(set_index, set, dynamic_offsets.to_vec());
+        if let Some((stages, offset, data)) = argument_push_constants(args, self.pipeline_layout.and_then(|(_, r)| r)) {
+            self.set_push_constant_bytes(stages, offset, data);
+        }
+    }
+
+    /// Validates arguments against the descriptor bindings of a pipeline, registers the resources they
+    /// reference, and returns the descriptor set containing them.
+    pub(crate) fn prepare_arguments<A: Arguments>(
+        &mut self,
+        device: &Device,
+        bindings: &[DescriptorBindingInfo],
+        set_index: u32,
+        args: &mut A,
+        dynamic_offsets: &[u32],
+    ) -> vk::DescriptorSet {
+        check_dynamic_offsets(args, dynamic_offsets);
+        if cfg!(debug_assertions) {
+            if let Err(err) = shader::check_arguments(bindings, set_index, args) {
+                panic!("{}", err);
+            }
+        }
+
+        args.register(&mut self.pass);
+        // SAFETY: TODO
+        let (_, set) = unsafe { device.get_or_create_descriptor_set(args) };
+        set
+    }
+
+    /// Binds a descriptor set to the specified set index of the bound pipeline.
+    pub(crate) fn bind_descriptor_set(&mut self, set_index: u32, set: vk::DescriptorSet, dynamic_offsets: Vec<u32>) {
+        let (layout, _) = self.pipeline_layout.expect("no pipeline bound");
+        self.commands.push(Command::BindDescriptorSet {
+            layout,
+            set_index,
+            set,
+            dynamic_offsets,
+        });
+    }
+
+    /// Binds a texture table to the specified descriptor set of the bound pipeline.
+    ///
+    /// All the textures in the table are registered as read by the pass.
+    pub fn bind_texture_array(&mut self, set_index: u32, textures: &TextureArray) {
+        let (layout, _) = self.pipeline_layout.expect("no pipeline bound");
+        textures.register(&mut self.pass);
+        self.commands.push(Command::BindDescriptorSet {
+            layout,
+            set_index,
+            set: textures.descriptor_set(),
+            dynamic_offsets: vec![],
+        });
+    }
+
+    /// Sets the push constants for subsequent draw commands (`vkCmdPushConstants`).
+    ///
+    /// The bound pipeline must have been created with push constants of type `T`
+    /// (see `GraphicsPipelineConfig::push_constants`). They are written at `T::OFFSET` in the push constant
+    /// block.
+    pub fn push_constants<T: PushConstants>(&mut self, data: &T) {
+        let (_, range) = self.pipeline_layout.expect("no pipeline bound");
+        let stages = check_push_constant_range(T::range(), range);
+        self.set_push_constant_bytes(stages, T::OFFSET, push_constant_bytes(data));
+    }
+
+    /// Sets push constants already validated against the range of the bound pipeline.
+    pub(crate) fn set_push_constant_bytes(&mut self, stages: vk::ShaderStageFlags, offset: u32, data: Vec<u8>) {
+        let (layout, _) = self.pipeline_layout.expect("no pipeline bound");
+        self.commands.push(Command::PushConstants {
+            layout,
+            stages,
+            offset,
+            data,
+        });
+    }
+
+    /// Binds a vertex buffer to the specified binding.
+    pub fn bind_vertex_buffer<V: VertexData>(&mut self, binding: u32, vertices: &VertexBufferView<V>) {
+        self.register_vertex_buffer(vertices.buffer);
+        self.bind_vertex_buffer_handle(binding, vertices.buffer.handle(), vertices.offset);
+    }
+
+    /// Binds an index buffer for subsequent indexed draw commands.
+    pub fn bind_index_buffer(&mut self, indices: &IndexBufferView) {
+        self.register_index_buffer(indices.buffer);
+        self.bind_index_buffer_handle(indices.buffer.handle(), indices.offset, indices.format.to_vk());
+    }
+
+    pub(crate) fn register_vertex_buffer(&mut self, buffer: &BufferAny) {
+        self.pass.add_buffer_dependency(
+            buffer.id(),
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+        );
+    }
+
+    pub(crate) fn register_index_buffer(&mut self, buffer: &BufferAny) {
+        self.pass.add_buffer_dependency(
+            buffer.id(),
+            vk::AccessFlags::INDEX_READ,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+        );
+    }
+
+    pub(crate) fn bind_vertex_buffer_handle(&mut self, binding: u32, buffer: vk::Buffer, offset: vk::DeviceSize) {
+        self.commands.push(Command::BindVertexBuffer {
+            binding,
+            buffer,
+            offset,
+        });
+    }
+
+    pub(crate) fn bind_index_buffer_handle(
+        &mut self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        index_type: vk::IndexType,
+    ) {
+        self.commands.push(Command::BindIndexBuffer {
+            buffer,
+            offset,
+            index_type,
+        });
+    }
+
+    /// vkCmdDraw
+    pub fn draw(&mut self, vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32) {
+        self.commands.push(Command::Draw {
+            vertex_count,
+            instance_count,
+            first_vertex,
+            first_instance,
+        })
+    }
+
+    /// vkCmdDrawIndexed
+    pub fn draw_indexed(
+        &mut self,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    ) {
+        self.commands.push(Command::DrawIndexed {
+            index_count,
+            instance_count,
+            first_index,
+            vertex_offset,
+            first_instance,
+        })
+    }
+
+    /// Draws with the parameters read from a range of commands in a buffer (`vkCmdDrawIndirect`).
+    ///
+    /// Drawing more than one command requires the `multiDrawIndirect` device feature.
+    pub fn draw_indirect(&mut self, commands: &DrawIndirectCommandBuffer, range: Range<u32>) {
+        assert!(
+            range.end as usize <= commands.len(),
+            "command range out of bounds of the indirect buffer"
+        );
+        if range.is_empty() {
+            return;
+        }
+        self.pass.add_buffer_dependency(
+            commands.untyped().id(),
+            vk::AccessFlags::INDIRECT_COMMAND_READ,
+            vk::PipelineStageFlags::DRAW_INDIRECT,
+        );
+        let stride = mem::size_of::<vk::DrawIndirectCommand>() as u32;
+        self.commands.push(Command::DrawIndirect {
+            buffer: commands.untyped().handle(),
+            offset: (range.start * stride) as vk::DeviceSize,
+            draw_count: range.end - range.start,
+            stride,
+        })
+    }
+
+    /// Indexed version of `draw_indirect` (`vkCmdDrawIndexedIndirect`).
+    ///
+    /// An index buffer must be bound.
+    pub fn draw_indexed_indirect(&mut self, commands: &DrawIndexedIndirectCommandBuffer, range: Range<u32>) {
+        assert!(
+            range.end as usize <= commands.len(),
+            "command range out of bounds of the indirect buffer"
+        );
+        if range.is_empty() {
+            return;
+        }
+        self.pass.add_buffer_dependency(
+            commands.untyped().id(),
+            vk::AccessFlags::INDIRECT_COMMAND_READ,
+            vk::PipelineStageFlags::DRAW_INDIRECT,
+        );
+        let stride = mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        self.commands.push(Command::DrawIndexedIndirect {
+            buffer: commands.untyped().handle(),
+            offset: (range.start * stride) as vk::DeviceSize,
+            draw_count: range.end - range.start,
+            stride,
+        })
+    }
+
+    /// Draws `instance_count` instances of a mesh, with its vertices bound to binding #0.
+    ///
+    /// Per-instance vertex buffers can be bound to other bindings with `bind_vertex_buffer` before the call.
+    pub fn draw_mesh<V: VertexData>(&mut self, mesh: &Mesh<V>, instance_count: u32) {
+        if instance_count == 0 {
+            return;
+        }
+        self.bind_vertex_buffer(0, &mesh.vertices);
+        if let Some(ref indices) = mesh.indices {
+            self.bind_index_buffer(indices);
+            self.draw_indexed(indices.len, instance_count, 0, 0, 0);
+        } else {
+            self.draw(mesh.vertices.len, instance_count, 0, 0);
+        }
+    }
+
+    /// Draws instances of a mesh.
+    ///
+    /// The vertices of the mesh are bound to binding #0, and the per-instance data to binding #1
+    /// (see `VertexInputLayout::per_vertex` and `VertexInputLayout::per_instance`).
+    /// `instances` is the range of elements of the instance buffer to draw.
+    pub fn draw_instanced<V: VertexData, I: VertexData>(
+        &mut self,
+        mesh: &Mesh<V>,
+        instance_buffer: &VertexBufferView<I>,
+        instances: Range<u32>,
+    ) {
+        assert!(
+            instances.end <= instance_buffer.len,
+            "instance range out of bounds of the instance buffer"
+        );
+        if instances.is_empty() {
+            return;
+        }
+
+        self.bind_vertex_buffer(0, &mesh.vertices);
+        self.bind_vertex_buffer(1, instance_buffer);
+
+        let instance_count = instances.end - instances.start;
+        if let Some(ref indices) = mesh.indices {
+            self.bind_index_buffer(indices);
+            self.draw_indexed(indices.len, instance_count, 0, 0, instances.start);
+        } else {
+            self.draw(mesh.vertices.len, instance_count, 0, instances.start);
+        }
+    }
+
+    /// Sets the viewport for subsequent draw commands.
+    ///
+    /// The viewport covers the whole render area by default. Only has an effect on pipelines
+    /// created with `DynamicStates::VIEWPORT`.
+    pub fn set_viewport(&mut self, viewport: vk::Viewport) {
+        self.commands.push(Command::SetViewport { viewport });
+    }
+
+    /// Sets the scissor rectangle for subsequent draw commands.
+    ///
+    /// The scissor rectangle is the render area by default. Only has an effect on pipelines
+    /// created with `DynamicStates::SCISSOR`.
+    pub fn set_scissor(&mut self, scissor: vk::Rect2D) {
+        self.commands.push(Command::SetScissor { scissor });
+    }
+
+    /// Sets the width of rasterized lines for subsequent draw commands.
+    ///
+    /// Only has an effect on pipelines created with `DynamicStates::LINE_WIDTH`.
+    pub fn set_line_width(&mut self, line_width: f32) {
+        self.commands.push(Command::SetLineWidth { line_width });
+    }
+
+    /// Sets the blend constants for subsequent draw commands.
+    ///
+    /// Only has an effect on pipelines created with `DynamicStates::BLEND_CONSTANTS`.
+    pub fn set_blend_constants(&mut self, blend_constants: [f32; 4]) {
+        self.commands.push(Command::SetBlendConstants { blend_constants });
+    }
+
+    /// Dispatches mesh shader (or task shader) work groups (`vkCmdDrawMeshTasksEXT`).
+    ///
+    /// The bound pipeline must have been created with a mesh shader, and `VK_EXT_mesh_shader` must be
+    /// enabled on the device.
+    pub fn draw_mesh_tasks(&mut self, group_count_x: u32, group_count_y: u32, group_count_z: u32) {
+        if self.mesh_shader_fn.is_none() {
+            let vk_device = &self.device.device;
+            self.mesh_shader_fn = Some(vk::ExtMeshShaderFn::load(|name| unsafe {
+                mem::transmute((vk_device.fp_v1_0().get_device_proc_addr)(
+                    vk_device.handle(),
+                    name.as_ptr(),
+                ))
+            }));
+        }
+        self.commands.push(Command::DrawMeshTasks {
+            group_count_x,
+            group_count_y,
+            group_count_z,
+        });
+    }
+
+    /// Finishes recording the render pass and adds it to the frame.
+    pub fn finish(self, frame: &mut graal::Frame<'static, ()>) {
+        let RenderPass {
+            pass,
+            render_area,
+            color_attachments,
+            depth_attachment,
+            commands,
+            mesh_shader_fn,
+            stats,
+            ..
+        } = self;
+
+        stats.count_pipeline_binds(
+            commands
+                .iter()
+                .filter(|cmd| matches!(cmd, Command::BindPipeline { .. }))
+                .count() as u64,
+        );
+        stats.count_draw_calls(
+            commands
+                .iter()
+                .filter(|cmd| {
+                    matches!(
+                        cmd,
+                        Command::Draw { .. }
+                            | Command::DrawIndexed { .. }
+                            | Command::DrawIndirect { .. }
+                            | Command::DrawIndexedIndirect { .. }
+                            | Command::DrawMeshTasks { .. }
+                    )
+                })
+                .count() as u64,
+        );
+
+        let pass = pass.record_callback(Box::new(move |context, _, command_buffer| unsafe {
+            let device = context.vulkan_device();
+
+            let color_attachment_infos: Vec<_> = color_attachments
+                .iter()
+                .map(|a| a.to_vk(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+                .collect();
+            let depth_stencil_attachment_info = depth_attachment
+                .as_ref()
+                .map(|(a, aspects)| (a.to_vk(depth_stencil_layout(*aspects)), *aspects));
+            // the same attachment is used for the depth and stencil aspects of combined formats
+            let attachment_info = |aspect: vk::ImageAspectFlags| match depth_stencil_attachment_info {
+                Some((ref info, aspects)) if aspects.contains(aspect) => info as *const _,
+                _ => std::ptr::null(),
+            };
+
+            let rendering_info = vk::RenderingInfo {
+                render_area,
+                layer_count: 1,
+                view_mask: 0,
+                color_attachment_count: color_attachment_infos.len() as u32,
+                p_color_attachments: color_attachment_infos.as_ptr(),
+                p_depth_attachment: attachment_info(vk::ImageAspectFlags::DEPTH),
+                p_stencil_attachment: attachment_info(vk::ImageAspectFlags::STENCIL),
+                ..Default::default()
+            };
+
+            device.cmd_begin_rendering(command_buffer, &rendering_info);
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    x: render_area.offset.x as f32,
+                    y: render_area.offset.y as f32,
+                    width: render_area.extent.width as f32,
+                    height: render_area.extent.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                }],
+            );
+            device.cmd_set_scissor(command_buffer, 0, &[render_area]);
+
+            for cmd in commands.iter() {
+                match *cmd {
+                    Command::BindPipeline { pipeline } => {
+                        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                    }
+                    Command::BindDescriptorSet {
+                        layout,
+                        set_index,
+                        set,
+                        ref dynamic_offsets,
+                    } => {
+                        device.cmd_bind_descriptor_sets(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            layout,
+                            set_index,
+                            &[set],
+                            dynamic_offsets,
+                        );
+                    }
+                    Command::BindVertexBuffer {
+                        binding,
+                        buffer,
+                        offset,
+                    } => {
+                        device.cmd_bind_vertex_buffers(command_buffer, binding, &[buffer], &[offset]);
+                    }
+                    Command::BindIndexBuffer {
+                        buffer,
+                        offset,
+                        index_type,
+                    } => {
+                        device.cmd_bind_index_buffer(command_buffer, buffer, offset, index_type);
+                    }
+                    Command::Draw {
+                        vertex_count,
+                        instance_count,
+                        first_vertex,
+                        first_instance,
+                    } => {
+                        device.cmd_draw(
+                            command_buffer,
+                            vertex_count,
+                            instance_count,
+                            first_vertex,
+                            first_instance,
+                        );
+                    }
+                    Command::DrawIndexed {
+                        index_count,
+                        instance_count,
+                        first_index,
+                        vertex_offset,
+                        first_instance,
+                    } => {
+                        device.cmd_draw_indexed(
+                            command_buffer,
+                            index_count,
+                            instance_count,
+                            first_index,
+                            vertex_offset,
+                            first_instance,
+                        );
+                    }
+                    Command::DrawIndirect {
+                        buffer,
+                        offset,
+                        draw_count,
+                        stride,
+                    } => {
+                        device.cmd_draw_indirect(command_buffer, buffer, offset, draw_count, stride);
+                    }
+                    Command::DrawIndexedIndirect {
+                        buffer,
+                        offset,
+                        draw_count,
+                        stride,
+                    } => {
+                        device.cmd_draw_indexed_indirect(command_buffer, buffer, offset, draw_count, stride);
+                    }
+                    Command::DrawMeshTasks {
+                        group_count_x,
+                        group_count_y,
+                        group_count_z,
+                    } => {
+                        let mesh_shader_fn = mesh_shader_fn.as_ref().unwrap();
+                        (mesh_shader_fn.cmd_draw_mesh_tasks_ext)(
+                            command_buffer,
+                            group_count_x,
+                            group_count_y,
+                            group_count_z,
+                        );
+                    }
+                    Command::PushConstants {
+                        layout,
+                        stages,
+                        offset,
+                        ref data,
+                    } => {
+                        device.cmd_push_constants(command_buffer, layout, stages, offset, data);
+                    }
+                    Command::SetViewport { viewport } => {
+                        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+                    }
+                    Command::SetScissor { scissor } => {
+                        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+                    }
+                    Command::SetLineWidth { line_width } => {
+                        device.cmd_set_line_width(command_buffer, line_width);
+                    }
+                    Command::SetBlendConstants { ref blend_constants } => {
+                        device.cmd_set_blend_constants(command_buffer, blend_constants);
+                    }
+                }
+            }
+
+            device.cmd_end_rendering(command_buffer);
+        }));
+
+        frame.add_pass(pass);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_push_constant_range;
+    use crate::{pipeline::merge_push_constant_ranges, vk};
+
+    #[test]
+    fn test_push_constant_ranges() {
+        let vertex = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: 64,
+        };
+        let fragment = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 64,
+            size: 16,
+        };
+
+        let range = merge_push_constant_ranges(Some(vertex), fragment);
+        assert_eq!(range.offset, 0);
+        assert_eq!(range.size, 80);
+        assert_eq!(
+            range.stage_flags,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        );
+
+        // both ranges are set with the stages of the whole range, at their own offset
+        assert_eq!(check_push_constant_range(vertex, Some(range)), range.stage_flags);
+        assert_eq!(check_push_constant_range(fragment, Some(range)), range.stage_flags);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_push_constant_range_out_of_bounds() {
+        let vertex = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: 64,
+        };
+        let outside = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 64,
+            size: 16,
+        };
+        check_push_constant_range(outside, Some(vertex));
+    }
+}
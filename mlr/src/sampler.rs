@@ -3,13 +3,15 @@ use crate::vk::SamplerCreateInfo;
 use graal::{vk, SamplerId};
 use std::{
     any::{Any, TypeId},
-    sync::Arc,
+    collections::HashMap,
+    sync::{Arc, Mutex},
 };
 
 // TODO should be called just "sampler"
 pub trait SamplerType: Copy {
     fn unique_type_id(&self) -> Option<TypeId>;
-    fn to_sampler(&self, device: &graal::ash::Device) -> vk::Sampler;
+    /// Returns the parameters of the sampler object.
+    fn create_info(&self) -> vk::SamplerCreateInfo;
 }
 
 macro_rules! impl_static_sampler_type {
@@ -21,8 +23,8 @@ macro_rules! impl_static_sampler_type {
                 Some(::std::any::TypeId::of::<Self>())
             }
 
-            fn to_sampler(&self, device: &graal::ash::Device) -> vk::Sampler {
-                const SAMPLER_CREATE_INFO: vk::SamplerCreateInfo = vk::SamplerCreateInfo {
+            fn create_info(&self) -> vk::SamplerCreateInfo {
+                vk::SamplerCreateInfo {
                     s_type: vk::StructureType::SAMPLER_CREATE_INFO,
                     p_next: std::ptr::null(),
                     flags: vk::SamplerCreateFlags::empty(),
@@ -41,10 +43,6 @@ macro_rules! impl_static_sampler_type {
                     max_lod: 0.0,
                     border_color: vk::BorderColor::INT_OPAQUE_BLACK,
                     unnormalized_coordinates: 0,
-                };
-                unsafe {
-                    // bail out if we can't create a simple sampler object with no particular extensions
-                    device.create_sampler(&SAMPLER_CREATE_INFO, None).expect("failed to create static sampler")
                 }
             }
         }
@@ -68,3 +66,97 @@ impl Drop for SamplerInner {
 
 #[derive(Clone)]
 pub struct Sampler(pub(crate) Arc<SamplerInner>);
+
+//--------------------------------------------------------------------------------------------------
+
+/// Hashable representation of the parameters in a `vk::SamplerCreateInfo`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+struct SamplerKey {
+    flags: vk::SamplerCreateFlags,
+    mag_filter: vk::Filter,
+    min_filter: vk::Filter,
+    mipmap_mode: vk::SamplerMipmapMode,
+    address_mode_u: vk::SamplerAddressMode,
+    address_mode_v: vk::SamplerAddressMode,
+    address_mode_w: vk::SamplerAddressMode,
+    mip_lod_bias: u32,
+    anisotropy_enable: vk::Bool32,
+    max_anisotropy: u32,
+    compare_enable: vk::Bool32,
+    compare_op: vk::CompareOp,
+    min_lod: u32,
+    max_lod: u32,
+    border_color: vk::BorderColor,
+    unnormalized_coordinates: vk::Bool32,
+}
+
+impl<'a> From<&'a vk::SamplerCreateInfo> for SamplerKey {
+    fn from(create_info: &'a SamplerCreateInfo) -> Self {
+        SamplerKey {
+            flags: create_info.flags,
+            mag_filter: create_info.mag_filter,
+            min_filter: create_info.min_filter,
+            mipmap_mode: create_info.mipmap_mode,
+            address_mode_u: create_info.address_mode_u,
+            address_mode_v: create_info.address_mode_v,
+            address_mode_w: create_info.address_mode_w,
+            mip_lod_bias: create_info.mip_lod_bias.to_bits(),
+            anisotropy_enable: create_info.anisotropy_enable,
+            max_anisotropy: create_info.max_anisotropy.to_bits(),
+            compare_enable: create_info.compare_enable,
+            compare_op: create_info.compare_op,
+            min_lod: create_info.min_lod.to_bits(),
+            max_lod: create_info.max_lod.to_bits(),
+            border_color: create_info.border_color,
+            unnormalized_coordinates: create_info.unnormalized_coordinates,
+        }
+    }
+}
+
+/// Sampler objects shared between all users of a device, keyed by their parameters.
+///
+/// Samplers are destroyed along with the cache.
+pub struct SamplerCache {
+    device: Arc<graal::Device>,
+    samplers: Mutex<HashMap<SamplerKey, vk::Sampler>>,
+}
+
+impl SamplerCache {
+    pub(crate) fn new(device: Arc<graal::Device>) -> SamplerCache {
+        SamplerCache {
+            device,
+            samplers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a sampler object with the specified parameters, creating it if it doesn't exist yet.
+    ///
+    /// Extension structures (`p_next`) are not supported.
+    pub fn get_or_create(&self, create_info: &vk::SamplerCreateInfo) -> vk::Sampler {
+        assert!(
+            create_info.p_next.is_null(),
+            "sampler create info extension structures are not supported"
+        );
+        let mut samplers = self.samplers.lock().unwrap();
+        *samplers.entry(create_info.into()).or_insert_with(|| unsafe {
+            tracing::trace!("creating sampler {:?}", create_info);
+            self.device
+                .device
+                .create_sampler(create_info, None)
+                .expect("failed to create sampler")
+        })
+    }
+
+    /// Returns the sampler object for a `SamplerType`.
+    pub fn get_or_create_for_type(&self, sampler: &impl SamplerType) -> vk::Sampler {
+        self.get_or_create(&sampler.create_info())
+    }
+}
+
+impl Drop for SamplerCache {
+    fn drop(&mut self) {
+        for (_, sampler) in self.samplers.get_mut().unwrap().drain() {
+            self.device.destroy_sampler(sampler)
+        }
+    }
+}
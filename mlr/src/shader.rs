@@ -1,11 +1,16 @@
 //! Shader
+use crate::{
+    reflect,
+    reflect::{DescriptorBindingInfo, SpecializationConstantInfo},
+    Arguments,
+};
 use graal::{
     vk,
     vk::{AccessFlags, ImageLayout, PipelineStageFlags},
     BufferId, ImageId, ResourceGroupId, ResourceId,
 };
 use graal_spirv::typedesc;
-use std::sync::Arc;
+use std::{collections::HashMap, fmt, mem, sync::Arc};
 use thiserror::Error;
 
 /// Error during shader module creation.
@@ -13,17 +18,279 @@ use thiserror::Error;
 pub enum CreateShaderError {
     #[error(transparent)]
     Vulkan(#[from] vk::Result),
+    #[error("the shader doesn't declare a specialization constant with ID {0}")]
+    UnknownSpecializationConstant(u32),
+    #[error("specialization constant {id}: the shader declares a constant of type {shader:?} but the value is of type {value:?}")]
+    SpecializationConstantType {
+        id: u32,
+        /// Type declared in the shader, or `None` if it's not supported.
+        shader: Option<SpecializationConstantType>,
+        value: SpecializationConstantType,
+    },
+}
+
+/// Type of a specialization constant.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SpecializationConstantType {
+    Bool,
+    Int,
+    UInt,
+    Float,
+}
+
+/// Value of a specialization constant.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpecializationConstant {
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+}
+
+impl SpecializationConstant {
+    /// Returns the type of the value.
+    pub fn ty(&self) -> SpecializationConstantType {
+        match self {
+            SpecializationConstant::Bool(_) => SpecializationConstantType::Bool,
+            SpecializationConstant::Int(_) => SpecializationConstantType::Int,
+            SpecializationConstant::UInt(_) => SpecializationConstantType::UInt,
+            SpecializationConstant::Float(_) => SpecializationConstantType::Float,
+        }
+    }
+
+    /// Returns the representation of the value in the specialization data (booleans are 32-bit `VkBool32`).
+    fn to_bits(&self) -> u32 {
+        match *self {
+            SpecializationConstant::Bool(v) => v as u32,
+            SpecializationConstant::Int(v) => v as u32,
+            SpecializationConstant::UInt(v) => v,
+            SpecializationConstant::Float(v) => v.to_bits(),
+        }
+    }
+}
+
+impl From<bool> for SpecializationConstant {
+    fn from(v: bool) -> Self {
+        SpecializationConstant::Bool(v)
+    }
+}
+
+impl From<i32> for SpecializationConstant {
+    fn from(v: i32) -> Self {
+        SpecializationConstant::Int(v)
+    }
+}
+
+impl From<u32> for SpecializationConstant {
+    fn from(v: u32) -> Self {
+        SpecializationConstant::UInt(v)
+    }
+}
+
+impl From<f32> for SpecializationConstant {
+    fn from(v: f32) -> Self {
+        SpecializationConstant::Float(v)
+    }
+}
+
+/// Mismatch between a descriptor binding declared in a shader and the arguments bound to it.
+#[derive(Clone, Debug, Error)]
+pub enum BindingMismatch {
+    #[error("binding {binding} of set {set} is used by the shader but not provided by the arguments")]
+    Missing { set: u32, binding: u32 },
+    #[error("binding {binding} of set {set}: the shader expects {shader:?} but the arguments provide {arguments:?}")]
+    DescriptorType {
+        set: u32,
+        binding: u32,
+        shader: vk::DescriptorType,
+        arguments: vk::DescriptorType,
+    },
+    #[error(
+        "binding {binding} of set {set}: the shader expects {shader} descriptors but the arguments provide {arguments}"
+    )]
+    DescriptorCount {
+        set: u32,
+        binding: u32,
+        shader: u32,
+        arguments: u32,
+    },
+    #[error("binding {binding} of set {set}: the uniform block is {shader} bytes but the bound buffer range is only {arguments} bytes")]
+    BufferSize {
+        set: u32,
+        binding: u32,
+        shader: u64,
+        arguments: u64,
+    },
+}
+
+/// Error returned when arguments don't match the descriptor bindings declared in a shader.
+#[derive(Clone, Debug)]
+pub struct ArgumentsMismatchError {
+    pub mismatches: Vec<BindingMismatch>,
+}
+
+impl fmt::Display for ArgumentsMismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "arguments don't match the shader interface:")?;
+        for mismatch in self.mismatches.iter() {
+            write!(f, "\n- {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ArgumentsMismatchError {}
+
+/// Checks arguments bound to a descriptor set against the descriptor bindings declared in shaders.
+pub(crate) fn check_arguments<A: Arguments + ?Sized>(
+    bindings: &[DescriptorBindingInfo],
+    set: u32,
+    args: &A,
+) -> Result<(), ArgumentsMismatchError> {
+    let layout = args.get_descriptor_set_layout_bindings();
+    let mut buffer_ranges = vec![];
+    args.buffer_ranges(&mut buffer_ranges);
+
+    let mut mismatches = vec![];
+    for b in bindings.iter().filter(|b| b.set == set) {
+        let binding = b.binding;
+        let provided = match layout.iter().find(|l| l.binding == binding) {
+            Some(provided) => provided,
+            None => {
+                mismatches.push(BindingMismatch::Missing { set, binding });
+                continue;
+            }
+        };
+        if !is_compatible_descriptor_type(b.descriptor_type, provided.descriptor_type) {
+            mismatches.push(BindingMismatch::DescriptorType {
+                set,
+                binding,
+                shader: b.descriptor_type,
+                arguments: provided.descriptor_type,
+            });
+            continue;
+        }
+        if let Some(count) = b.count {
+            if provided.descriptor_count < count {
+                mismatches.push(BindingMismatch::DescriptorCount {
+                    set,
+                    binding,
+                    shader: count,
+                    arguments: provided.descriptor_count,
+                });
+            }
+        }
+        if let Some(block_size) = b.block_size {
+            if let Some(&(_, range)) = buffer_ranges.iter().find(|(b, _)| *b == binding) {
+                if range != vk::WHOLE_SIZE && range < block_size as u64 {
+                    mismatches.push(BindingMismatch::BufferSize {
+                        set,
+                        binding,
+                        shader: block_size as u64,
+                        arguments: range,
+                    });
+                }
+            }
+        }
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(ArgumentsMismatchError { mismatches })
+    }
+}
+
+/// Whether a descriptor of type `arguments` can be bound to a shader binding of type `shader`.
+///
+/// Used by `check_interface!`.
+#[doc(hidden)]
+pub const fn is_compatible_descriptor_type(shader: vk::DescriptorType, arguments: vk::DescriptorType) -> bool {
+    // `as_raw` because `PartialEq` can't be used in const fns
+    let (shader, arguments) = (shader.as_raw(), arguments.as_raw());
+    shader == arguments
+        || (shader == vk::DescriptorType::UNIFORM_BUFFER.as_raw()
+            && arguments == vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC.as_raw())
+        || (shader == vk::DescriptorType::STORAGE_BUFFER.as_raw()
+            && arguments == vk::DescriptorType::STORAGE_BUFFER_DYNAMIC.as_raw())
+}
+
+/// Returns the layout binding with the specified binding number.
+///
+/// Used by `check_interface!`.
+#[doc(hidden)]
+pub const fn find_layout_binding(
+    bindings: &[vk::DescriptorSetLayoutBinding],
+    binding: u32,
+) -> Option<&vk::DescriptorSetLayoutBinding> {
+    let mut i = 0;
+    while i < bindings.len() {
+        if bindings[i].binding == binding {
+            return Some(&bindings[i]);
+        }
+        i += 1;
+    }
+    None
 }
 
 /// Wrapper over a vulkan ShaderModule.
 pub struct ShaderModule {
     device: Arc<graal::Device>,
     pub(crate) shader_module: vk::ShaderModule,
+    /// Size of the push constant block declared in the shader, if any.
+    pub(crate) push_constant_size: Option<u32>,
+    /// Descriptor bindings declared in the shader.
+    pub(crate) descriptor_bindings: Vec<DescriptorBindingInfo>,
+    /// Specialization constants declared in the shader.
+    specialization_constants: Vec<SpecializationConstantInfo>,
+    /// Specialization map entries and data, used when creating pipelines with this shader.
+    specialization_map_entries: Vec<vk::SpecializationMapEntry>,
+    specialization_data: Vec<u32>,
 }
 
 impl ShaderModule {
     /// Creates a new shader from SPIR-V bytecode.
     pub fn from_spirv(device: &Arc<graal::Device>, spirv: &[u32]) -> Result<ShaderModule, CreateShaderError> {
+        Self::from_spirv_specialized(device, spirv, &HashMap::new())
+    }
+
+    /// Creates a new shader from SPIR-V bytecode, with values for specialization constants, keyed by
+    /// constant ID.
+    ///
+    /// The values are used by the pipelines created with the shader, so that the same SPIR-V can be used
+    /// to create different pipeline variants. Returns an error if the shader doesn't declare a constant with
+    /// the specified ID and type. Constants without a value keep their default value.
+    pub fn from_spirv_specialized(
+        device: &Arc<graal::Device>,
+        spirv: &[u32],
+        constants: &HashMap<u32, SpecializationConstant>,
+    ) -> Result<ShaderModule, CreateShaderError> {
+        let specialization_constants = reflect::specialization_constants(spirv);
+
+        let mut values: Vec<_> = constants.iter().map(|(&id, &value)| (id, value)).collect();
+        values.sort_by_key(|&(id, _)| id);
+        let mut specialization_map_entries = Vec::with_capacity(values.len());
+        let mut specialization_data = Vec::with_capacity(values.len());
+        for (id, value) in values {
+            let declared = specialization_constants
+                .iter()
+                .find(|c| c.id == id)
+                .ok_or(CreateShaderError::UnknownSpecializationConstant(id))?;
+            if declared.ty != Some(value.ty()) {
+                return Err(CreateShaderError::SpecializationConstantType {
+                    id,
+                    shader: declared.ty,
+                    value: value.ty(),
+                });
+            }
+            specialization_map_entries.push(vk::SpecializationMapEntry {
+                constant_id: id,
+                offset: (specialization_data.len() * mem::size_of::<u32>()) as u32,
+                size: mem::size_of::<u32>(),
+            });
+            specialization_data.push(value.to_bits());
+        }
+
         let device = device.clone();
         let vk_device = &device.device;
 
@@ -39,7 +306,50 @@ impl ShaderModule {
             )?
         };
 
-        Ok(ShaderModule { device, shader_module })
+        Ok(ShaderModule {
+            device,
+            shader_module,
+            push_constant_size: reflect::push_constant_block_size(spirv),
+            descriptor_bindings: reflect::descriptor_bindings(spirv),
+            specialization_constants,
+            specialization_map_entries,
+            specialization_data,
+        })
+    }
+
+    /// Returns the IDs of the specialization constants declared in the shader.
+    pub fn specialization_constant_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.specialization_constants.iter().map(|c| c.id)
+    }
+
+    /// Returns the specialization info to use when creating a pipeline stage with this shader, or `None` if
+    /// no specialization constants were specified.
+    ///
+    /// The returned structure points to data owned by the shader module.
+    pub(crate) fn specialization_info(&self) -> Option<vk::SpecializationInfo> {
+        if self.specialization_map_entries.is_empty() {
+            return None;
+        }
+        Some(vk::SpecializationInfo {
+            map_entry_count: self.specialization_map_entries.len() as u32,
+            p_map_entries: self.specialization_map_entries.as_ptr(),
+            data_size: self.specialization_data.len() * mem::size_of::<u32>(),
+            p_data: self.specialization_data.as_ptr() as *const _,
+        })
+    }
+
+    /// Returns the size of the push constant block declared in the shader, or `None` if the shader doesn't
+    /// use push constants.
+    pub fn push_constant_size(&self) -> Option<u32> {
+        self.push_constant_size
+    }
+
+    /// Checks that arguments bound to the specified descriptor set provide all the bindings that the shader
+    /// declares in this set, with matching descriptor types, descriptor counts and uniform buffer sizes.
+    ///
+    /// Bindings provided by the arguments but not used by the shader are allowed.
+    pub fn check_arguments<A: Arguments + ?Sized>(&self, set: u32, args: &A) -> Result<(), ArgumentsMismatchError> {
+        check_arguments(&self.descriptor_bindings, set, args)
     }
 }
 
@@ -0,0 +1,100 @@
+//! Per-frame statistics
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of the work recorded during a frame.
+///
+/// Commands are counted when they are recorded by mlr (e.g. draws when `RenderPass::finish` is called),
+/// not when they execute on the GPU. Statistics are collected per device, and returned by `Device::collect`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct FrameStats {
+    /// Draw commands, including indirect and mesh task draws (an indirect draw counts as one).
+    pub draw_calls: u64,
+    /// Compute dispatches.
+    pub dispatches: u64,
+    /// Graphics and compute pipeline binds.
+    pub pipeline_binds: u64,
+    /// Descriptor sets allocated from descriptor pools.
+    pub descriptor_set_allocations: u64,
+    /// Bytes written to staging or host-visible buffers for transfer to the GPU.
+    pub bytes_uploaded: u64,
+}
+
+/// Counters of the work recorded on a device.
+///
+/// Shared by the objects that record work on the device, and taken once per frame by `Device::collect`.
+#[derive(Default)]
+pub(crate) struct StatsCounters {
+    draw_calls: AtomicU64,
+    dispatches: AtomicU64,
+    pipeline_binds: AtomicU64,
+    descriptor_set_allocations: AtomicU64,
+    bytes_uploaded: AtomicU64,
+}
+
+impl StatsCounters {
+    pub(crate) fn count_draw_calls(&self, count: u64) {
+        self.draw_calls.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_dispatch(&self) {
+        self.dispatches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_pipeline_binds(&self, count: u64) {
+        self.pipeline_binds.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_descriptor_set_allocation(&self) {
+        self.descriptor_set_allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_bytes_uploaded(&self, byte_size: u64) {
+        self.bytes_uploaded.fetch_add(byte_size, Ordering::Relaxed);
+    }
+
+    /// Returns the statistics collected since the last call, and resets the counters.
+    pub(crate) fn take(&self) -> FrameStats {
+        FrameStats {
+            draw_calls: self.draw_calls.swap(0, Ordering::Relaxed),
+            dispatches: self.dispatches.swap(0, Ordering::Relaxed),
+            pipeline_binds: self.pipeline_binds.swap(0, Ordering::Relaxed),
+            descriptor_set_allocations: self.descriptor_set_allocations.swap(0, Ordering::Relaxed),
+            bytes_uploaded: self.bytes_uploaded.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameStats, StatsCounters};
+
+    #[test]
+    fn test_take_stats() {
+        let a = StatsCounters::default();
+        let b = StatsCounters::default();
+        a.count_draw_calls(3);
+        a.count_pipeline_binds(2);
+        a.count_bytes_uploaded(256);
+        b.count_dispatch();
+        b.count_descriptor_set_allocation();
+
+        assert_eq!(
+            a.take(),
+            FrameStats {
+                draw_calls: 3,
+                pipeline_binds: 2,
+                bytes_uploaded: 256,
+                ..Default::default()
+            }
+        );
+        assert_eq!(a.take(), FrameStats::default());
+        assert_eq!(
+            b.take(),
+            FrameStats {
+                dispatches: 1,
+                descriptor_set_allocations: 1,
+                ..Default::default()
+            }
+        );
+    }
+}
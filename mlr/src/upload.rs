@@ -0,0 +1,263 @@
+//! Batched uploads of buffer and image data
+use crate::{
+    buffer::BufferAny,
+    device::Device,
+    image::{create_staging_buffer, create_texture, ImageAny},
+    stats::StatsCounters,
+    vk,
+};
+use std::{mem, ptr, sync::Arc};
+
+/// Size of the staging buffers allocated by an `UploadContext`. Larger uploads get their own staging buffer.
+const STAGING_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Alignment of the data in staging buffers (a multiple of the texel block size of all formats).
+const STAGING_ALIGNMENT: u64 = 16;
+
+/// Returns the range of `size` bytes allocated in a staging chunk of `chunk_size` bytes whose first free byte is
+/// at `offset`, as the offset of the data and the next free (aligned) offset. Returns `None` if the data doesn't fit.
+fn staging_range(offset: u64, chunk_size: u64, size: u64) -> Option<(u64, u64)> {
+    if offset + size > chunk_size {
+        return None;
+    }
+    let next = (offset + size + STAGING_ALIGNMENT - 1) & !(STAGING_ALIGNMENT - 1);
+    Some((offset, next))
+}
+
+/// A staging buffer, filled linearly.
+struct StagingChunk {
+    buffer: graal::BufferInfo,
+    size: u64,
+    offset: u64,
+}
+
+enum UploadCopy {
+    Buffer {
+        dst: vk::Buffer,
+        region: vk::BufferCopy,
+    },
+    Image {
+        dst: vk::Image,
+        region: vk::BufferImageCopy,
+    },
+}
+
+/// Batches uploads of buffer and image data into a single transfer pass per frame.
+///
+/// Data is copied immediately into staging buffers owned by the context, and transferred on the GPU by the pass
+/// added to the frame by `flush`. Resources returned by the `upload_*` methods can be used in all passes added
+/// to the frame after the call to `flush`.
+pub struct UploadContext {
+    device: Arc<graal::Device>,
+    /// Statistics of the device, updated when data is staged.
+    stats: Arc<StatsCounters>,
+    chunks: Vec<StagingChunk>,
+    /// Copies of the current batch, with the staging buffer to copy from.
+    copies: Vec<(vk::Buffer, UploadCopy)>,
+    buffer_dependencies: Vec<graal::BufferId>,
+    image_dependencies: Vec<graal::ImageId>,
+}
+
+impl UploadContext {
+    pub fn new(device: &Device) -> UploadContext {
+        UploadContext {
+            device: device.backend().clone(),
+            stats: device.stats.clone(),
+            chunks: vec![],
+            copies: vec![],
+            buffer_dependencies: vec![],
+            image_dependencies: vec![],
+        }
+    }
+
+    /// Copies data into the staging arena, and returns the staging buffer and the offset of the data in it.
+    fn stage(&mut self, data: &[u8]) -> (vk::Buffer, u64) {
+        let size = data.len() as u64;
+        let range = self
+            .chunks
+            .last()
+            .and_then(|chunk| staging_range(chunk.offset, chunk.size, size));
+        let (offset, next_offset) = match range {
+            Some(range) => range,
+            None => {
+                let chunk_size = size.max(STAGING_CHUNK_SIZE);
+                self.chunks.push(StagingChunk {
+                    buffer: create_staging_buffer(&self.device, chunk_size),
+                    size: chunk_size,
+                    offset: 0,
+                });
+                staging_range(0, chunk_size, size).unwrap()
+            }
+        };
+
+        let chunk = self.chunks.last_mut().unwrap();
+        // SAFETY: the staging buffer is mapped and large enough
+        unsafe {
+            let ptr = chunk.buffer.mapped_ptr.unwrap().as_ptr() as *mut u8;
+            ptr::copy_nonoverlapping(data.as_ptr(), ptr.add(offset as usize), data.len());
+        }
+        chunk.offset = next_offset;
+        self.stats.count_bytes_uploaded(size);
+        (chunk.buffer.handle, offset)
+    }
+
+    /// Schedules a copy of data at the specified byte offset in an existing buffer.
+    ///
+    /// The buffer must have been created with the `TRANSFER_DST` usage.
+    pub fn upload_to_buffer<T: Copy + 'static>(&mut self, buffer: &BufferAny, offset: vk::DeviceSize, data: &[T]) {
+        if data.is_empty() {
+            return;
+        }
+        // SAFETY: T is Copy, so it's plain data
+        let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data)) };
+        let (staging_buffer, src_offset) = self.stage(bytes);
+        self.copies.push((
+            staging_buffer,
+            UploadCopy::Buffer {
+                dst: buffer.handle(),
+                region: vk::BufferCopy {
+                    src_offset,
+                    dst_offset: offset,
+                    size: bytes.len() as u64,
+                },
+            },
+        ));
+        if !self.buffer_dependencies.contains(&buffer.id()) {
+            self.buffer_dependencies.push(buffer.id());
+        }
+    }
+
+    /// Creates a device-local buffer initialized with the contents of a slice.
+    pub fn upload_buffer<T: Copy + 'static>(&mut self, usage: vk::BufferUsageFlags, data: &[T]) -> BufferAny {
+        // zero-sized buffers are not allowed
+        let byte_size = mem::size_of_val(data).max(1);
+        let buffer = BufferAny::new(
+            &self.device,
+            "",
+            graal::MemoryLocation::GpuOnly,
+            graal::BufferResourceCreateInfo {
+                usage: usage | vk::BufferUsageFlags::TRANSFER_DST,
+                byte_size: byte_size as u64,
+                map_on_create: false,
+            },
+        );
+        self.upload_to_buffer(&buffer, 0, data);
+        buffer
+    }
+
+    /// Creates a 2D image with one mip level, initialized with tightly-packed pixel data.
+    ///
+    /// The image can be sampled, and used as the source or destination of transfers (e.g. to generate mipmaps
+    /// after the upload).
+    pub fn upload_image_2d(&mut self, format: vk::Format, width: u32, height: u32, data: &[u8]) -> ImageAny {
+        let image = create_texture(&self.device, "", format, width, height, 1);
+        let (staging_buffer, buffer_offset) = self.stage(data);
+        self.copies.push((
+            staging_buffer,
+            UploadCopy::Image {
+                dst: image.handle(),
+                region: vk::BufferImageCopy {
+                    buffer_offset,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                    image_extent: vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                },
+            },
+        ));
+        self.image_dependencies.push(image.id());
+        image
+    }
+
+    /// Adds a pass that performs all the uploads scheduled since the last call to `flush`.
+    ///
+    /// The staging buffers are deleted once the pass has completed.
+    pub fn flush(&mut self, frame: &mut graal::Frame<'static, ()>) {
+        if self.copies.is_empty() {
+            return;
+        }
+
+        let mut pass = graal::PassBuilder::new().name("upload");
+        for &buffer in self.buffer_dependencies.iter() {
+            pass.add_buffer_dependency(
+                buffer,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::PipelineStageFlags::TRANSFER,
+            );
+        }
+        for &image in self.image_dependencies.iter() {
+            pass.add_image_dependency(
+                image,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+        }
+        for chunk in self.chunks.iter() {
+            pass.add_buffer_dependency(
+                chunk.buffer.id,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::PipelineStageFlags::TRANSFER,
+            );
+        }
+
+        let copies = mem::take(&mut self.copies);
+        let pass = pass.record_callback(Box::new(move |context, _, command_buffer| unsafe {
+            let device = context.vulkan_device();
+            for (staging_buffer, copy) in copies.iter() {
+                match *copy {
+                    UploadCopy::Buffer { dst, ref region } => {
+                        device.cmd_copy_buffer(command_buffer, *staging_buffer, dst, &[*region]);
+                    }
+                    UploadCopy::Image { dst, ref region } => {
+                        device.cmd_copy_buffer_to_image(
+                            command_buffer,
+                            *staging_buffer,
+                            dst,
+                            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                            &[*region],
+                        );
+                    }
+                }
+            }
+        }));
+        frame.add_pass(pass);
+
+        // deletion is deferred until the upload pass has completed
+        for chunk in self.chunks.drain(..) {
+            self.device.destroy_buffer(chunk.buffer.id);
+        }
+        self.buffer_dependencies.clear();
+        self.image_dependencies.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{staging_range, STAGING_ALIGNMENT, STAGING_CHUNK_SIZE};
+
+    #[test]
+    fn test_staging_range() {
+        // data is placed at the current offset, and the next offset is aligned
+        assert_eq!(staging_range(0, 256, 3), Some((0, STAGING_ALIGNMENT)));
+        assert_eq!(staging_range(16, 256, 16), Some((16, 32)));
+        assert_eq!(staging_range(32, 256, 0), Some((32, 32)));
+        // the last bytes of the chunk can be used
+        assert_eq!(staging_range(240, 256, 16), Some((240, 256)));
+        // a new chunk is needed
+        assert_eq!(staging_range(240, 256, 17), None);
+        // uploads larger than the chunk size don't fit in an empty chunk, and get their own
+        assert_eq!(staging_range(0, STAGING_CHUNK_SIZE, STAGING_CHUNK_SIZE + 1), None);
+    }
+}
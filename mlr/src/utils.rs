@@ -1 +1,317 @@
+//! Miscellaneous utilities
+use crate::{image::ImageAny, vk};
+use graal::ash::extensions::ext::DebugUtils;
+use std::{
+    ffi::CString,
+    fs::File,
+    future::Future,
+    io::BufWriter,
+    path::Path,
+    pin::Pin,
+    ptr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+    time::Duration,
+};
+use thiserror::Error;
 
+/// Offset and size of a field in a `repr(C)` struct (see `#[derive(StructLayout)]`).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FieldLayout {
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Sets the name of a Vulkan object (`VK_EXT_debug_utils`), shown by debugging tools like RenderDoc.
+///
+/// Does nothing if the name is empty.
+pub(crate) fn set_debug_object_name<H: vk::Handle>(device: &graal::Device, handle: H, name: &str) {
+    if name.is_empty() {
+        return;
+    }
+    let debug_utils = DebugUtils::new(graal::get_vulkan_entry(), graal::get_vulkan_instance());
+    let object_name = CString::new(name).expect("debug name contains a null character");
+    unsafe {
+        let result = debug_utils.set_debug_utils_object_name(
+            device.device.handle(),
+            &vk::DebugUtilsObjectNameInfoEXT {
+                object_type: H::TYPE,
+                object_handle: handle.as_raw(),
+                p_object_name: object_name.as_ptr(),
+                ..Default::default()
+            },
+        );
+        if let Err(err) = result {
+            tracing::warn!("failed to set debug name of {:?} object: {}", H::TYPE, err);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the size in bytes of a texel of the specified format, for the formats supported by
+/// `read_image_to_cpu`.
+fn texel_size(format: vk::Format) -> Option<u32> {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SRGB | vk::Format::R8_UINT => Some(1),
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB | vk::Format::R16_SFLOAT | vk::Format::R16_UINT => Some(2),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_SFLOAT
+        | vk::Format::R32_UINT => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_SFLOAT => Some(8),
+        vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        _ => None,
+    }
+}
+
+/// Pixels of an image read back from the GPU.
+#[derive(Clone, Debug)]
+pub struct CpuImage {
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed texel data, row by row.
+    pub data: Vec<u8>,
+}
+
+/// Error returned by `CpuImage::save_png`.
+#[derive(Debug, Error)]
+pub enum SaveImageError {
+    #[error("cannot save image with format {0:?} to PNG")]
+    UnsupportedFormat(vk::Format),
+    #[error(transparent)]
+    Encoding(#[from] png::EncodingError),
+}
+
+impl CpuImage {
+    /// Returns the bytes of the texel at the specified position.
+    pub fn texel(&self, x: u32, y: u32) -> &[u8] {
+        let size = self.data.len() / (self.width * self.height) as usize;
+        let offset = (y * self.width + x) as usize * size;
+        &self.data[offset..offset + size]
+    }
+
+    /// Saves the image to a PNG file.
+    ///
+    /// Only 8-bit formats with one, two or four components are supported.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), SaveImageError> {
+        let (color_type, bgra) = match self.format {
+            vk::Format::R8_UNORM | vk::Format::R8_SRGB => (png::ColorType::Grayscale, false),
+            vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB => (png::ColorType::GrayscaleAlpha, false),
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => (png::ColorType::Rgba, false),
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => (png::ColorType::Rgba, true),
+            format => return Err(SaveImageError::UnsupportedFormat(format)),
+        };
+
+        let file = File::create(path).map_err(png::EncodingError::from)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        if bgra {
+            let mut rgba = self.data.clone();
+            for texel in rgba.chunks_exact_mut(4) {
+                texel.swap(0, 2);
+            }
+            writer.write_image_data(&rgba)?;
+        } else {
+            writer.write_image_data(&self.data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Interval at which the readback thread checks whether the readback event has been signalled.
+const READBACK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Default)]
+struct ReadbackStatus {
+    signalled: bool,
+    /// Waker of the task waiting on the future.
+    waker: Option<Waker>,
+    /// Buffer of a future dropped before the copy has completed, destroyed by the readback thread.
+    orphaned_buffer: Option<graal::BufferId>,
+}
+
+/// Waits for the readback event on a separate thread, since Vulkan events can't be waited on by the host,
+/// then destroys the event and wakes the task waiting on the future.
+fn spawn_readback_thread(device: Arc<graal::Device>, event: vk::Event, status: Arc<Mutex<ReadbackStatus>>) {
+    thread::Builder::new()
+        .name("image readback".to_string())
+        .spawn(move || {
+            loop {
+                let signalled = unsafe {
+                    device
+                        .device
+                        .get_event_status(event)
+                        .expect("failed to query readback event status")
+                };
+                if signalled {
+                    break;
+                }
+                thread::sleep(READBACK_POLL_INTERVAL);
+            }
+            unsafe {
+                device.device.destroy_event(event, None);
+            }
+            let mut status = status.lock().unwrap();
+            status.signalled = true;
+            if let Some(buffer) = status.orphaned_buffer.take() {
+                unsafe { device.destroy_buffer(buffer) }
+            }
+            if let Some(waker) = status.waker.take() {
+                waker.wake();
+            }
+        })
+        .expect("failed to spawn readback thread");
+}
+
+/// Future returned by `read_image_to_cpu`.
+struct ReadbackFuture {
+    device: Arc<graal::Device>,
+    /// Set by the readback thread once the readback pass has completed.
+    status: Arc<Mutex<ReadbackStatus>>,
+    buffer: graal::BufferInfo,
+    image: Option<CpuImage>,
+}
+
+impl Future for ReadbackFuture {
+    type Output = CpuImage;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<CpuImage> {
+        {
+            let mut status = self.status.lock().unwrap();
+            if !status.signalled {
+                status.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+
+        let mut image = self.image.take().expect("readback future polled after completion");
+        unsafe {
+            let ptr = self.buffer.mapped_ptr.unwrap().as_ptr() as *const u8;
+            ptr::copy_nonoverlapping(ptr, image.data.as_mut_ptr(), image.data.len());
+        }
+        Poll::Ready(image)
+    }
+}
+
+impl Drop for ReadbackFuture {
+    fn drop(&mut self) {
+        let mut status = self.status.lock().unwrap();
+        if status.signalled {
+            unsafe { self.device.destroy_buffer(self.buffer.id) }
+        } else {
+            // the GPU may still write to the buffer: let the readback thread destroy it
+            status.orphaned_buffer = Some(self.buffer.id);
+        }
+    }
+}
+
+/// Adds a pass to the frame that copies the first mip level of an image to host memory, and returns
+/// a future that resolves to the contents of the image once the copy has completed on the GPU.
+///
+/// The frame must be submitted for the future to make progress: a thread waits for the copy to complete and
+/// wakes the task waiting on the future. Only single-layer color images with uncompressed formats of common
+/// sizes are supported. This is intended for tests and screenshots, not for streaming data back every frame.
+pub fn read_image_to_cpu(frame: &mut graal::Frame<'static, ()>, image: &ImageAny) -> impl Future<Output = CpuImage> {
+    let device = image.device.clone();
+    let format = image.format();
+    let extent = image.extent();
+    let texel_size = texel_size(format).unwrap_or_else(|| panic!("unsupported readback format: {:?}", format));
+    let byte_size = extent.width as u64 * extent.height as u64 * texel_size as u64;
+
+    let buffer = device.create_buffer(
+        "readback",
+        graal::MemoryLocation::GpuToCpu,
+        &graal::BufferResourceCreateInfo {
+            usage: vk::BufferUsageFlags::TRANSFER_DST,
+            byte_size,
+            map_on_create: true,
+        },
+    );
+    let event = unsafe {
+        device
+            .device
+            .create_event(&vk::EventCreateInfo::default(), None)
+            .expect("failed to create readback event")
+    };
+    let status = Arc::new(Mutex::new(ReadbackStatus::default()));
+    spawn_readback_thread(device.clone(), event, status.clone());
+
+    let buffer_handle = buffer.handle;
+    let image_handle = image.handle();
+    let pass = graal::PassBuilder::new()
+        .name("image readback")
+        .image_dependency(
+            image.id(),
+            vk::AccessFlags::TRANSFER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )
+        .buffer_dependency(
+            buffer.id,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+        )
+        .record_callback(Box::new(move |context, _, command_buffer| unsafe {
+            let device = context.vulkan_device();
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image_handle,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer_handle,
+                &[vk::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                    image_extent: vk::Extent3D {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    },
+                }],
+            );
+            // make the copy visible to the host
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier {
+                    src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    dst_access_mask: vk::AccessFlags::HOST_READ,
+                    ..Default::default()
+                }],
+                &[],
+                &[],
+            );
+            device.cmd_set_event(command_buffer, event, vk::PipelineStageFlags::TRANSFER);
+        }));
+    frame.add_pass(pass);
+
+    ReadbackFuture {
+        device,
+        status,
+        buffer,
+        image: Some(CpuImage {
+            format,
+            width: extent.width,
+            height: extent.height,
+            data: vec![0; byte_size as usize],
+        }),
+    }
+}
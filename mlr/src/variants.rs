@@ -0,0 +1,170 @@
+//! Pipeline variants
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Write},
+    hash::Hash,
+    ops::BitAnd,
+};
+
+struct Variant<P> {
+    pipeline: P,
+    /// Value of the use counter the last time this variant was requested.
+    last_used: u64,
+}
+
+/// Lazily-built cache of pipeline permutations, keyed by a set of feature flags (usually a `bitflags` type,
+/// e.g. `SKINNED | ALPHA_TEST`).
+///
+/// All variants are built by the same function from a set of flags. The function typically compiles
+/// a shared shader source with the defines returned by `variant_defines`, or sets specialization constants
+/// from the flags.
+///
+/// When the number of cached variants exceeds the capacity, the least recently used variants are evicted.
+pub struct PipelineVariants<F, P> {
+    variants: HashMap<F, Variant<P>>,
+    create: Box<dyn FnMut(F) -> P>,
+    capacity: usize,
+    use_counter: u64,
+}
+
+impl<F, P> PipelineVariants<F, P>
+where
+    F: Copy + Eq + Hash + Debug,
+{
+    /// Creates an empty variant cache that keeps at most `capacity` variants alive.
+    pub fn new(capacity: usize, create: impl FnMut(F) -> P + 'static) -> PipelineVariants<F, P> {
+        assert!(capacity > 0, "invalid capacity");
+        PipelineVariants {
+            variants: HashMap::new(),
+            create: Box::new(create),
+            capacity,
+            use_counter: 0,
+        }
+    }
+
+    /// Returns the pipeline for the specified flags, building it if necessary.
+    pub fn get(&mut self, flags: F) -> &P {
+        self.use_counter += 1;
+        let counter = self.use_counter;
+
+        if !self.variants.contains_key(&flags) {
+            if self.variants.len() >= self.capacity {
+                self.evict_lru();
+            }
+            tracing::trace!("building pipeline variant {:?}", flags);
+            let pipeline = (self.create)(flags);
+            self.variants.insert(
+                flags,
+                Variant {
+                    pipeline,
+                    last_used: counter,
+                },
+            );
+        }
+
+        let variant = self.variants.get_mut(&flags).unwrap();
+        variant.last_used = counter;
+        &variant.pipeline
+    }
+
+    /// Builds the variants for all the specified flags in advance (e.g. at load time).
+    pub fn prewarm(&mut self, flags: impl IntoIterator<Item = F>) {
+        for f in flags {
+            self.get(f);
+        }
+    }
+
+    /// Returns whether the variant for the specified flags is currently cached.
+    pub fn contains(&self, flags: F) -> bool {
+        self.variants.contains_key(&flags)
+    }
+
+    /// Returns the number of cached variants.
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    /// Removes all cached variants.
+    pub fn clear(&mut self) {
+        self.variants.clear();
+    }
+
+    fn evict_lru(&mut self) {
+        if let Some(flags) = self
+            .variants
+            .iter()
+            .min_by_key(|(_, v)| v.last_used)
+            .map(|(flags, _)| *flags)
+        {
+            tracing::trace!("evicting pipeline variant {:?}", flags);
+            self.variants.remove(&flags);
+        }
+    }
+}
+
+/// Returns `#define` lines for each named flag contained in `flags`, to prepend to a shader source.
+///
+/// # Example
+///
+/// ```ignore
+/// let defines = variant_defines(flags, &[(Flags::SKINNED, "SKINNED"), (Flags::ALPHA_TEST, "ALPHA_TEST")]);
+/// ```
+pub fn variant_defines<F>(flags: F, names: &[(F, &str)]) -> String
+where
+    F: Copy + Eq + BitAnd<Output = F>,
+{
+    let mut defines = String::new();
+    for &(flag, name) in names {
+        if flags & flag == flag {
+            writeln!(defines, "#define {} 1", name).unwrap();
+        }
+    }
+    defines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{variant_defines, PipelineVariants};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_lru_eviction() {
+        let built = Rc::new(RefCell::new(Vec::new()));
+        let mut variants = PipelineVariants::new(2, {
+            let built = built.clone();
+            move |flags: u32| {
+                built.borrow_mut().push(flags);
+                flags * 10
+            }
+        });
+
+        assert_eq!(*variants.get(1), 10);
+        assert_eq!(*variants.get(2), 20);
+        // cached: not rebuilt, and 1 becomes the most recently used
+        assert_eq!(*variants.get(1), 10);
+        assert_eq!(*built.borrow(), vec![1, 2]);
+
+        // past capacity: the least recently used variant (2) is evicted
+        assert_eq!(*variants.get(3), 30);
+        assert_eq!(variants.len(), 2);
+        assert!(variants.contains(1));
+        assert!(!variants.contains(2));
+        assert!(variants.contains(3));
+
+        // evicted variants are rebuilt on demand, evicting 1
+        assert_eq!(*variants.get(2), 20);
+        assert!(!variants.contains(1));
+        assert_eq!(*built.borrow(), vec![1, 2, 3, 2]);
+    }
+
+    #[test]
+    fn test_variant_defines() {
+        let names = [(0b01u32, "SKINNED"), (0b10, "ALPHA_TEST")];
+        assert_eq!(
+            variant_defines(0b11, &names),
+            "#define SKINNED 1\n#define ALPHA_TEST 1\n"
+        );
+        assert_eq!(variant_defines(0b10, &names), "#define ALPHA_TEST 1\n");
+        assert_eq!(variant_defines(0, &names), "");
+    }
+}
@@ -1,10 +1,11 @@
 //! Vertex-related types
 use crate::{
-    buffer::BufferData,
+    buffer::{BufferAny, BufferData},
     vk::{VertexInputAttributeDescription, VertexInputBindingDescription},
 };
 use graal::vk;
 use graal_spirv::typedesc::TypeDesc;
+use std::{marker::PhantomData, mem};
 
 /// Describes the type of indices contained in an index buffer.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -15,26 +16,190 @@ pub enum IndexFormat {
     U32,
 }
 
+impl IndexFormat {
+    pub(crate) fn to_vk(&self) -> vk::IndexType {
+        match self {
+            IndexFormat::U16 => vk::IndexType::UINT16,
+            IndexFormat::U32 => vk::IndexType::UINT32,
+        }
+    }
+}
+
+/// Types that can be used as vertex indices.
+pub unsafe trait IndexData: Copy + 'static {
+    const FORMAT: IndexFormat;
+}
+
+unsafe impl IndexData for u16 {
+    const FORMAT: IndexFormat = IndexFormat::U16;
+}
+
+unsafe impl IndexData for u32 {
+    const FORMAT: IndexFormat = IndexFormat::U32;
+}
+
 /// Description of a vertex attribute within a vertex buffer layout.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct VertexAttribute {
-    /// Index of the attribute in the buffer layout.
-    index: usize,
-    /// Offset of the attribute within a vertex entry.
-    offset: usize,
     /// Format of the attribute.
-    format: vk::Format,
+    pub format: vk::Format,
+    /// Offset of the attribute within a vertex entry.
+    pub offset: u32,
+    /// Explicit shader location of the attribute.
+    ///
+    /// If `None`, the attribute is assigned the location following the one of the previous attribute.
+    pub location: Option<u32>,
+}
+
+/// Types that describe the layout of an element of a vertex buffer.
+///
+/// Usually derived with `#[derive(VertexData)]`.
+///
+/// # Safety
+///
+/// `ATTRIBUTES` must describe attributes that are within the bounds of `Self`.
+pub unsafe trait VertexData: Copy + 'static {
+    /// Attributes of the vertex element.
+    ///
+    /// Attributes without an explicit location are assigned consecutive locations in declaration order.
+    const ATTRIBUTES: &'static [VertexAttribute];
+    /// Whether the elements are consumed per vertex or per instance.
+    ///
+    /// Set with `#[vertex(input_rate = "instance")]` on the struct when deriving `VertexData`.
+    const INPUT_RATE: vk::VertexInputRate = vk::VertexInputRate::VERTEX;
+}
+
+/// Types that can be used as vertex attributes, with their corresponding vertex format.
+///
+/// Used by `#[derive(VertexData)]` to determine the format of each field. Implement this trait on newtypes
+/// to use them in vertex structs.
+///
+/// # Safety
+///
+/// `FORMAT` must describe the memory representation of `Self`.
+pub unsafe trait VertexAttributeType: Copy + 'static {
+    /// Vertex format of the attribute.
+    const FORMAT: vk::Format;
+    /// Size of the attribute in bytes.
+    const SIZE: usize = mem::size_of::<Self>();
+    /// Alignment of the attribute in bytes.
+    const ALIGN: usize = mem::align_of::<Self>();
+}
+
+/// Wrapper for integer attributes normalized to `[0,1]` (unsigned) or `[-1,1]` (signed) in shaders.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Norm<T>(pub T);
+
+macro_rules! impl_norm {
+    ($($t:ty => $min:expr;)*) => {
+        $(impl Norm<$t> {
+            /// Converts a float to its normalized representation, clamping it to the representable range.
+            pub fn from_f32(value: f32) -> Norm<$t> {
+                Norm((value.clamp($min, 1.0) * <$t>::MAX as f32).round() as $t)
+            }
+
+            /// Returns the float value that the attribute has in shaders.
+            pub fn to_f32(self) -> f32 {
+                (self.0 as f32 / <$t>::MAX as f32).max($min)
+            }
+        }
+
+        impl From<f32> for Norm<$t> {
+            fn from(value: f32) -> Norm<$t> {
+                Norm::<$t>::from_f32(value)
+            }
+        })*
+    };
+}
+
+impl_norm! {
+    u8 => 0.0;
+    i8 => -1.0;
+    u16 => 0.0;
+    i16 => -1.0;
+}
+
+macro_rules! impl_vertex_attribute_type {
+    ($($t:ty => $format:ident;)*) => {
+        $(unsafe impl VertexAttributeType for $t {
+            const FORMAT: vk::Format = vk::Format::$format;
+        })*
+    };
+}
+
+impl_vertex_attribute_type! {
+    f32 => R32_SFLOAT;
+    [f32; 2] => R32G32_SFLOAT;
+    [f32; 3] => R32G32B32_SFLOAT;
+    [f32; 4] => R32G32B32A32_SFLOAT;
+    i32 => R32_SINT;
+    [i32; 2] => R32G32_SINT;
+    [i32; 3] => R32G32B32_SINT;
+    [i32; 4] => R32G32B32A32_SINT;
+    u32 => R32_UINT;
+    [u32; 2] => R32G32_UINT;
+    [u32; 3] => R32G32B32_UINT;
+    [u32; 4] => R32G32B32A32_UINT;
+    u16 => R16_UINT;
+    [u16; 2] => R16G16_UINT;
+    [u16; 4] => R16G16B16A16_UINT;
+    i16 => R16_SINT;
+    [i16; 2] => R16G16_SINT;
+    [i16; 4] => R16G16B16A16_SINT;
+    u8 => R8_UINT;
+    [u8; 2] => R8G8_UINT;
+    [u8; 4] => R8G8B8A8_UINT;
+    Norm<u8> => R8_UNORM;
+    [Norm<u8>; 2] => R8G8_UNORM;
+    [Norm<u8>; 3] => R8G8B8_UNORM;
+    [Norm<u8>; 4] => R8G8B8A8_UNORM;
+    Norm<i8> => R8_SNORM;
+    [Norm<i8>; 2] => R8G8_SNORM;
+    [Norm<i8>; 3] => R8G8B8_SNORM;
+    [Norm<i8>; 4] => R8G8B8A8_SNORM;
+    Norm<u16> => R16_UNORM;
+    [Norm<u16>; 2] => R16G16_UNORM;
+    [Norm<u16>; 3] => R16G16B16_UNORM;
+    [Norm<u16>; 4] => R16G16B16A16_UNORM;
+    Norm<i16> => R16_SNORM;
+    [Norm<i16>; 2] => R16G16_SNORM;
+    [Norm<i16>; 3] => R16G16B16_SNORM;
+    [Norm<i16>; 4] => R16G16B16A16_SNORM;
 }
 
-/// Returns the size of bytes of a vertex attribute of the given format.
+#[cfg(feature = "glam")]
+impl_vertex_attribute_type! {
+    glam::Vec2 => R32G32_SFLOAT;
+    glam::Vec3 => R32G32B32_SFLOAT;
+    glam::Vec4 => R32G32B32A32_SFLOAT;
+    glam::IVec2 => R32G32_SINT;
+    glam::IVec3 => R32G32B32_SINT;
+    glam::IVec4 => R32G32B32A32_SINT;
+    glam::UVec2 => R32G32_UINT;
+    glam::UVec3 => R32G32B32_UINT;
+    glam::UVec4 => R32G32B32A32_UINT;
+}
+
+/// Returns the size in bytes of a vertex attribute of the given format.
 pub fn vertex_format_byte_size(format: vk::Format) -> usize {
     match format {
-        vk::Format::R32G32B32_SFLOAT => 24,
-        vk::Format::R32G32B32A32_SFLOAT => 32,
-        vk::Format::R32G32_SFLOAT => 16,
-        vk::Format::R32_SFLOAT => 8,
-        vk::Format::R16G16_UNORM => 4,
-        vk::Format::R16G16B16A16_UNORM => 8,
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT | vk::Format::R8_SINT => 1,
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SNORM | vk::Format::R8G8_UINT | vk::Format::R8G8_SINT => 2,
+        vk::Format::R8G8B8_UNORM | vk::Format::R8G8B8_SNORM => 3,
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SNORM | vk::Format::R8G8B8A8_UINT => 4,
+        vk::Format::R8G8B8A8_SINT => 4,
+        vk::Format::R16_UNORM | vk::Format::R16_SNORM | vk::Format::R16_UINT | vk::Format::R16_SINT => 2,
+        vk::Format::R16_SFLOAT => 2,
+        vk::Format::R16G16_UNORM | vk::Format::R16G16_SNORM | vk::Format::R16G16_UINT => 4,
+        vk::Format::R16G16_SINT | vk::Format::R16G16_SFLOAT => 4,
+        vk::Format::R16G16B16_UNORM | vk::Format::R16G16B16_SNORM => 6,
+        vk::Format::R16G16B16A16_UNORM | vk::Format::R16G16B16A16_SNORM | vk::Format::R16G16B16A16_UINT => 8,
+        vk::Format::R16G16B16A16_SINT | vk::Format::R16G16B16A16_SFLOAT => 8,
+        vk::Format::R32_SFLOAT | vk::Format::R32_UINT | vk::Format::R32_SINT => 4,
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_UINT | vk::Format::R32G32_SINT => 8,
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT => 12,
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT | vk::Format::R32G32B32A32_SINT => 16,
         _ => todo!("unsupported vertex format"),
     }
 }
@@ -59,9 +224,9 @@ impl VertexBufferLayoutBuilder {
     pub fn push_attribute(&mut self, format: vk::Format) -> VertexAttribute {
         let byte_size = vertex_format_byte_size(format);
         let attr = VertexAttribute {
-            index: self.attributes.len(),
-            offset: byte_size,
+            offset: self.current_offset as u32,
             format,
+            location: None,
         };
         self.attributes.push(attr);
         // FIXME: alignment
@@ -100,3 +265,109 @@ impl VertexBufferLayout {
         &self.attributes
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+
+/// Vertex input bindings and attributes of a graphics pipeline.
+///
+/// Bindings are assigned in the order they are added. Attribute locations are assigned consecutively
+/// across bindings, unless specified explicitly with `VertexAttribute::location`.
+#[derive(Clone, Debug, Default)]
+pub struct VertexInputLayout {
+    pub bindings: Vec<VertexInputBindingDescription>,
+    pub attributes: Vec<VertexInputAttributeDescription>,
+}
+
+impl VertexInputLayout {
+    /// Creates an empty vertex input layout.
+    pub fn new() -> VertexInputLayout {
+        VertexInputLayout::default()
+    }
+
+    /// Adds a binding with the specified input rate and the attributes of `V`.
+    pub fn binding<V: VertexData>(mut self, input_rate: vk::VertexInputRate) -> VertexInputLayout {
+        let binding = self.bindings.len() as u32;
+        let mut location = self.attributes.iter().map(|a| a.location + 1).max().unwrap_or(0);
+        self.bindings.push(VertexInputBindingDescription {
+            binding,
+            stride: std::mem::size_of::<V>() as u32,
+            input_rate,
+        });
+        for attr in V::ATTRIBUTES.iter() {
+            if let Some(explicit_location) = attr.location {
+                location = explicit_location;
+            }
+            assert!(
+                self.attributes.iter().all(|a| a.location != location),
+                "vertex attribute location {} is used more than once",
+                location
+            );
+            self.attributes.push(VertexInputAttributeDescription {
+                location,
+                binding,
+                format: attr.format,
+                offset: attr.offset,
+            });
+            location += 1;
+        }
+        self
+    }
+
+    /// Adds a binding with data of type `V`, at the input rate of `V` (`VertexData::INPUT_RATE`).
+    ///
+    /// Per-vertex and per-instance bindings can be mixed in the same layout.
+    pub fn buffer<V: VertexData>(self) -> VertexInputLayout {
+        self.binding::<V>(V::INPUT_RATE)
+    }
+
+    /// Adds a binding with per-vertex data of type `V`.
+    pub fn per_vertex<V: VertexData>(self) -> VertexInputLayout {
+        self.binding::<V>(vk::VertexInputRate::VERTEX)
+    }
+
+    /// Adds a binding with per-instance data of type `V`.
+    pub fn per_instance<V: VertexData>(self) -> VertexInputLayout {
+        self.binding::<V>(vk::VertexInputRate::INSTANCE)
+    }
+}
+
+/// A view over a buffer containing elements of type `V`, used as vertex or instance data.
+#[derive(Copy, Clone, Debug)]
+pub struct VertexBufferView<'a, V: VertexData> {
+    pub buffer: &'a BufferAny,
+    /// Byte offset of the first element in the buffer.
+    pub offset: vk::DeviceSize,
+    /// Number of elements.
+    pub len: u32,
+    pub(crate) _phantom: PhantomData<fn() -> V>,
+}
+
+impl<'a, V: VertexData> VertexBufferView<'a, V> {
+    /// Creates a view over `len` elements starting at byte offset `offset` in the buffer.
+    pub fn new(buffer: &'a BufferAny, offset: vk::DeviceSize, len: u32) -> VertexBufferView<'a, V> {
+        VertexBufferView {
+            buffer,
+            offset,
+            len,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// A view over a buffer containing vertex indices.
+#[derive(Copy, Clone, Debug)]
+pub struct IndexBufferView<'a> {
+    pub buffer: &'a BufferAny,
+    /// Byte offset of the first index in the buffer.
+    pub offset: vk::DeviceSize,
+    pub format: IndexFormat,
+    /// Number of indices.
+    pub len: u32,
+}
+
+/// Vertices and (optionally) indices of a mesh.
+#[derive(Copy, Clone, Debug)]
+pub struct Mesh<'a, V: VertexData> {
+    pub vertices: VertexBufferView<'a, V>,
+    pub indices: Option<IndexBufferView<'a>>,
+}